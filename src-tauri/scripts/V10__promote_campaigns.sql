@@ -0,0 +1,19 @@
+-- 营销推广：推广模板即一次营销活动，推广记录为每次发送
+ALTER TABLE promote_template ADD COLUMN store_id INTEGER;
+ALTER TABLE promote_template ADD COLUMN title TEXT;
+-- 目标人群筛选条件（JSON）
+ALTER TABLE promote_template ADD COLUMN audience TEXT;
+-- 每条推送的成本，用于计算投入产出
+ALTER TABLE promote_template ADD COLUMN unit_cost REAL DEFAULT 0;
+-- 发送后多少天内的消费计入活动效果
+ALTER TABLE promote_template ADD COLUMN attribution_days INTEGER DEFAULT 7;
+CREATE INDEX idx_promote_template_store_id ON promote_template (store_id);
+
+ALTER TABLE promote_record ADD COLUMN store_id INTEGER;
+ALTER TABLE promote_record ADD COLUMN total_count INTEGER DEFAULT 0;
+ALTER TABLE promote_record ADD COLUMN success_count INTEGER DEFAULT 0;
+ALTER TABLE promote_record ADD COLUMN cost REAL DEFAULT 0;
+CREATE INDEX idx_promote_record_store_id ON promote_record (store_id);
+CREATE INDEX idx_promote_record_temp_id ON promote_record (temp_id);
+
+INSERT INTO dict_data (dict_sort, dict_label, dict_value, dict_type, css_class, list_class, is_default, status, create_time, update_time, remark) VALUES (1, '活动通知', '01', 'sys_promote_type', null, 'success', 'N', '0', '2025-01-01 00:00:00', '', null);
@@ -0,0 +1,31 @@
+-- 会员分群规则，定时计算后以标签写入 user_tags.auto_tags
+CREATE TABLE segment_rules
+(
+    rule_id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id       INTEGER NOT NULL,
+    tag_name       TEXT    NOT NULL,
+    rule_type      TEXT    NOT NULL,
+    threshold      REAL,
+    days           INTEGER,
+    enabled        BOOLEAN NOT NULL DEFAULT 1,
+    matched_count  INTEGER NOT NULL DEFAULT 0,
+    last_run_time  INTEGER,
+    create_time    INTEGER,
+    update_time    INTEGER,
+    remark         TEXT
+);
+CREATE INDEX idx_segment_rules_store_id ON segment_rules (store_id);
+
+-- 规则计算出的标签，与手动维护的 tags 分开存放
+ALTER TABLE user_tags ADD COLUMN auto_tags TEXT;
+
+
+-- 为已有门店创建默认规则，新门店首次登录时由 SegmentRule::seed_defaults 创建
+INSERT INTO segment_rules (store_id, tag_name, rule_type, threshold, days, create_time, update_time)
+SELECT l.id, r.tag_name, r.rule_type, r.threshold, r.days,
+       CAST(strftime('%s', 'now') AS INTEGER) * 1000, CAST(strftime('%s', 'now') AS INTEGER) * 1000
+FROM local_users l,
+     (SELECT '高消费' AS tag_name, 'SpendOver' AS rule_type, 500 AS threshold, 90 AS days
+      UNION ALL SELECT '有储值余额', 'HasBalance', 0, NULL
+      UNION ALL SELECT '沉睡会员', 'NoVisit', NULL, 60
+      UNION ALL SELECT '黑名单', 'Blacklisted', NULL, NULL) r;
@@ -0,0 +1,31 @@
+-- 积分流水：user_integral_record 按流水结构重建，原表改名保留历史记录
+ALTER TABLE user_integral_record RENAME TO user_integral_record_legacy;
+DROP INDEX IF EXISTS idx_user_integral_record_user_id;
+CREATE INDEX idx_user_integral_record_legacy_user_id ON user_integral_record_legacy (user_id);
+
+CREATE TABLE user_integral_record
+(
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id      INTEGER NOT NULL,
+    user_id       INTEGER NOT NULL,
+    -- 变动积分，增加为正，扣减为负
+    change_points INTEGER NOT NULL,
+    -- 变动后余额
+    balance       INTEGER NOT NULL,
+    reason        TEXT    NOT NULL,
+    -- 关联单据，如订单号、支付单号
+    ref_id        TEXT,
+    -- 增加的积分中尚未被扣减或过期的部分，按先到期先扣减
+    remaining     INTEGER NOT NULL DEFAULT 0,
+    expire_time   INTEGER,
+    remark        TEXT,
+    create_time   INTEGER NOT NULL
+);
+CREATE INDEX idx_user_integral_record_user_id ON user_integral_record (user_id);
+CREATE INDEX idx_user_integral_record_expire ON user_integral_record (store_id, expire_time);
+
+-- 已有积分记为期初余额，不设过期时间
+INSERT INTO user_integral_record (store_id, user_id, change_points, balance, reason, remaining, create_time)
+SELECT store_id, user_id, integral, integral, 'Opening', integral, CAST(strftime('%s', 'now') AS INTEGER) * 1000
+FROM users
+WHERE integral > 0;
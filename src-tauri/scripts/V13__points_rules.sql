@@ -0,0 +1,21 @@
+-- 积分兑换规则：积分抵扣现金或兑换卡券
+CREATE TABLE points_rules
+(
+    rule_id     INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id    INTEGER NOT NULL,
+    rule_name   TEXT    NOT NULL,
+    rule_type   TEXT    NOT NULL DEFAULT 'Discount',
+    -- 每次兑换所需积分
+    points      INTEGER NOT NULL,
+    -- 抵扣规则：每次兑换抵扣的金额
+    amount      REAL,
+    -- 兑换卡券规则：兑换的卡券
+    coupon_id   INTEGER,
+    -- 抵扣规则：单笔订单最多抵扣的比例（百分比），为空时不限制
+    max_ratio   REAL,
+    enabled     BOOLEAN NOT NULL DEFAULT 1,
+    create_time INTEGER,
+    update_time INTEGER,
+    remark      TEXT
+);
+CREATE INDEX idx_points_rules_store_id ON points_rules (store_id);
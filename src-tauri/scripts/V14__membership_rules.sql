@@ -0,0 +1,22 @@
+-- 会员等级规则与权益
+-- 参与自动评级的等级，员工等级不参与
+ALTER TABLE membership_level ADD COLUMN auto_assign BOOLEAN NOT NULL DEFAULT 0;
+-- 升级条件，满足任一条件即可，均为空时为基础等级
+ALTER TABLE membership_level ADD COLUMN spend_threshold REAL;
+ALTER TABLE membership_level ADD COLUMN points_threshold INTEGER;
+ALTER TABLE membership_level ADD COLUMN balance_threshold REAL;
+-- 等级权益：价格折扣（百分比）、积分倍数、免费存放天数
+ALTER TABLE membership_level ADD COLUMN price_discount REAL;
+ALTER TABLE membership_level ADD COLUMN points_multiplier REAL;
+ALTER TABLE membership_level ADD COLUMN free_storage_days INTEGER;
+
+UPDATE membership_level SET auto_assign = 1 WHERE level_code = 'vip-pt';
+UPDATE membership_level
+SET auto_assign = 1, spend_threshold = 1000, price_discount = 98, points_multiplier = 1.2, free_storage_days = 7
+WHERE level_code = 'vip-by';
+UPDATE membership_level
+SET auto_assign = 1, spend_threshold = 3000, price_discount = 95, points_multiplier = 1.5, free_storage_days = 15
+WHERE level_code = 'vip-hj';
+UPDATE membership_level
+SET auto_assign = 1, spend_threshold = 10000, price_discount = 90, points_multiplier = 2, free_storage_days = 30
+WHERE level_code = 'vip-zs';
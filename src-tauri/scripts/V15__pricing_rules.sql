@@ -0,0 +1,39 @@
+-- 计价规则：按优先级从小到大依次计算
+CREATE TABLE pricing_rules
+(
+    rule_id             INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id            INTEGER NOT NULL,
+    rule_name           TEXT    NOT NULL,
+    rule_type           TEXT    NOT NULL,
+    priority            INTEGER NOT NULL DEFAULT 100,
+    stacking            TEXT    NOT NULL DEFAULT 'Stack',
+    -- 适用条件，为空时不限制
+    service_requirement TEXT,
+    category_id         INTEGER,
+    style_id            INTEGER,
+    -- 规则参数，含义见规则类型
+    value               REAL,
+    quantity            INTEGER,
+    -- 生效时间（毫秒）
+    start_time          INTEGER,
+    end_time            INTEGER,
+    enabled             BOOLEAN NOT NULL DEFAULT 1,
+    create_time         INTEGER,
+    update_time         INTEGER,
+    remark              TEXT
+);
+CREATE INDEX idx_pricing_rules_store_id ON pricing_rules (store_id);
+
+-- 订单计价明细（JSON），结账时保存
+ALTER TABLE orders ADD COLUMN price_breakdown TEXT;
+
+-- 原有的加急 ×2、单洗 ×1.5 及会员等级折扣转为已有门店的默认规则，
+-- 新门店首次登录时由 PricingRule::seed_defaults 创建
+INSERT INTO pricing_rules (store_id, rule_name, rule_type, priority, service_requirement, value, create_time, update_time)
+SELECT l.id, r.rule_name, r.rule_type, r.priority, r.service_requirement, r.value,
+       CAST(strftime('%s', 'now') AS INTEGER) * 1000, CAST(strftime('%s', 'now') AS INTEGER) * 1000
+FROM local_users l,
+     (SELECT '加急' AS rule_name, 'ServiceMultiplier' AS rule_type, 10 AS priority,
+             'Emergency' AS service_requirement, 2 AS value
+      UNION ALL SELECT '单洗', 'ServiceMultiplier', 10, 'SingleWash', 1.5
+      UNION ALL SELECT '会员折扣', 'LevelDiscount', 50, NULL, NULL) r;
@@ -0,0 +1,2 @@
+-- 卡券在各衣物上的分摊明细（JSON）
+ALTER TABLE coupon_usages ADD COLUMN allocations TEXT;
@@ -0,0 +1,2 @@
+-- 卡券是否可与其他类型的卡券叠加使用，储值卡始终可叠加
+ALTER TABLE coupons ADD COLUMN stackable BOOLEAN NOT NULL DEFAULT 0;
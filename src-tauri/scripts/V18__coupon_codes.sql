@@ -0,0 +1,30 @@
+-- 卡券兑换码批次
+CREATE TABLE coupon_code_batches
+(
+    batch_id    INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id    INTEGER NOT NULL,
+    coupon_id   INTEGER NOT NULL,
+    batch_name  TEXT    NOT NULL,
+    quantity    INTEGER NOT NULL,
+    -- 兑换截止时间，为空时以卡券有效期为准
+    expire_time TIMESTAMP DEFAULT NULL,
+    create_time INTEGER,
+    remark      TEXT
+);
+CREATE INDEX idx_coupon_code_batches_store_id ON coupon_code_batches (store_id);
+
+-- 卡券兑换码：每个兑换码只能兑换一次
+CREATE TABLE coupon_codes
+(
+    code_id     INTEGER PRIMARY KEY AUTOINCREMENT,
+    batch_id    INTEGER NOT NULL,
+    store_id    INTEGER NOT NULL,
+    code        TEXT    NOT NULL,
+    status      TEXT    NOT NULL DEFAULT 'Unused',
+    -- 兑换后写入兑换的会员和会员卡券
+    user_id     INTEGER,
+    uc_id       INTEGER,
+    redeem_time INTEGER,
+    UNIQUE (store_id, code)
+);
+CREATE INDEX idx_coupon_codes_batch_id ON coupon_codes (batch_id);
@@ -0,0 +1,45 @@
+-- 储值卡余额中的赠送部分，不可退；available_value - bonus_value 为本金
+ALTER TABLE user_coupons ADD COLUMN bonus_value DOUBLE DEFAULT 0;
+-- 已有储值卡按面值（usage_value）超出售价（coupon_value）的比例折算剩余余额中的赠送部分，
+-- 避免升级后赠送余额被当作本金退还
+UPDATE user_coupons
+SET bonus_value = ROUND(available_value * (c.usage_value - c.coupon_value) / c.usage_value, 2)
+FROM coupons c
+WHERE c.coupon_id = user_coupons.coupon_id
+  AND c.coupon_type = 'StoredValueCard'
+  AND c.usage_value > c.coupon_value
+  AND user_coupons.available_value > 0;
+-- 储值卡扣款中的赠送余额部分，退款时一并退还
+ALTER TABLE coupon_usages ADD COLUMN bonus_amount REAL DEFAULT 0;
+
+-- 充值赠送档位：单次充值满 min_amount 赠送 bonus_amount，取满足条件的最高档
+CREATE TABLE topup_tiers
+(
+    tier_id      INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id     INTEGER NOT NULL,
+    tier_name    TEXT    NOT NULL,
+    min_amount   REAL    NOT NULL,
+    bonus_amount REAL    NOT NULL DEFAULT 0,
+    enabled      BOOLEAN NOT NULL DEFAULT 1,
+    create_time  INTEGER,
+    update_time  INTEGER,
+    remark       TEXT
+);
+CREATE INDEX idx_topup_tiers_store_id ON topup_tiers (store_id);
+
+-- 储值卡充值记录
+CREATE TABLE stored_value_topups
+(
+    topup_id     INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id     INTEGER NOT NULL,
+    uc_id        INTEGER NOT NULL,
+    user_id      INTEGER NOT NULL,
+    -- 充值本金
+    amount       REAL    NOT NULL,
+    bonus_amount REAL    NOT NULL DEFAULT 0,
+    tier_id      INTEGER,
+    pay_id       TEXT,
+    create_time  INTEGER,
+    remark       TEXT
+);
+CREATE INDEX idx_stored_value_topups_uc_id ON stored_value_topups (uc_id);
@@ -0,0 +1,53 @@
+-- 家庭组：组内指定成员可使用共享给家庭的卡券，成员间可转移储值余额
+CREATE TABLE family_groups
+(
+    group_id    INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id    INTEGER NOT NULL,
+    group_name  TEXT    NOT NULL,
+    -- 户主，创建时自动加入家庭组
+    owner_id    INTEGER NOT NULL,
+    create_time INTEGER,
+    update_time INTEGER,
+    remark      TEXT
+);
+CREATE INDEX idx_family_groups_store_id ON family_groups (store_id);
+
+-- 家庭成员，每个会员只能加入一个家庭组
+CREATE TABLE family_members
+(
+    group_id  INTEGER NOT NULL,
+    user_id   INTEGER NOT NULL UNIQUE,
+    -- 是否可使用共享卡券支付
+    can_pay   BOOLEAN NOT NULL DEFAULT 0,
+    join_time INTEGER,
+    PRIMARY KEY (group_id, user_id)
+);
+
+-- 共享给家庭成员使用的会员卡券
+CREATE TABLE family_shared_coupons
+(
+    group_id    INTEGER NOT NULL,
+    uc_id       INTEGER NOT NULL UNIQUE,
+    create_time INTEGER,
+    PRIMARY KEY (group_id, uc_id)
+);
+
+-- 储值余额转移记录
+CREATE TABLE stored_value_transfers
+(
+    transfer_id  INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id     INTEGER NOT NULL,
+    from_uc_id   INTEGER NOT NULL,
+    to_uc_id     INTEGER NOT NULL,
+    from_user_id INTEGER NOT NULL,
+    to_user_id   INTEGER NOT NULL,
+    amount       REAL    NOT NULL,
+    -- 随转移金额按比例转出的赠送余额
+    bonus_amount REAL    NOT NULL DEFAULT 0,
+    create_time  INTEGER,
+    remark       TEXT
+);
+CREATE INDEX idx_stored_value_transfers_from ON stored_value_transfers (from_uc_id);
+CREATE INDEX idx_stored_value_transfers_to ON stored_value_transfers (to_uc_id);
+
+INSERT INTO dict_data (dict_sort, dict_label, dict_value, dict_type, css_class, list_class, is_default, status, create_time, update_time, remark) VALUES (4, '储值变动通知', '4', 'sys_temp_type', null, 'info', 'N', '0', '2025-01-01 00:00:00', '', '储值余额转移等变动通知');
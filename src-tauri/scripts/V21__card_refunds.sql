@@ -0,0 +1,28 @@
+-- 储值卡、次卡退卡记录：只退还剩余本金，赠送部分不退，按配置扣除手续费
+CREATE TABLE card_refunds
+(
+    refund_id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id         INTEGER NOT NULL,
+    uc_id            INTEGER NOT NULL UNIQUE,
+    user_id          INTEGER NOT NULL,
+    coupon_type      TEXT    NOT NULL,
+    -- 退卡前的余额，次卡为剩余次数
+    balance          REAL    NOT NULL DEFAULT 0,
+    -- 作废的赠送部分，次卡为赠送次数
+    bonus_amount     REAL    NOT NULL DEFAULT 0,
+    -- 可退本金
+    principal_amount REAL    NOT NULL DEFAULT 0,
+    fee_amount       REAL    NOT NULL DEFAULT 0,
+    -- 实退金额 = 可退本金 - 手续费
+    refund_amount    REAL    NOT NULL DEFAULT 0,
+    payment_method   TEXT,
+    pay_id           TEXT,
+    create_time      INTEGER,
+    remark           TEXT
+);
+CREATE INDEX idx_card_refunds_store_id ON card_refunds (store_id);
+
+INSERT INTO configs (config_name, config_key, config_value, config_type, create_by, create_time, update_by, update_time, remark)
+VALUES ('退卡手续费比例', 'card_refund_fee_rate', '0', 'Y', 'admin', null, '', null, '单位：%，按可退本金计算');
+INSERT INTO configs (config_name, config_key, config_value, config_type, create_by, create_time, update_by, update_time, remark)
+VALUES ('退卡最低手续费', 'card_refund_fee_min', '0', 'Y', 'admin', null, '', null, '单位：元，不超过可退本金');
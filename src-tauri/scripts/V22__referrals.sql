@@ -0,0 +1,25 @@
+-- 会员推荐：推荐人及会员自己的推荐码
+ALTER TABLE users ADD COLUMN referrer_id INTEGER;
+ALTER TABLE users ADD COLUMN referral_code TEXT;
+CREATE UNIQUE INDEX idx_users_referral_code ON users (store_id, referral_code);
+
+-- 推荐奖励记录：被推荐人首单支付后发放，每个被推荐人只处理一次
+CREATE TABLE referral_rewards
+(
+    reward_id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id           INTEGER NOT NULL,
+    referrer_id        INTEGER NOT NULL,
+    referee_id         INTEGER NOT NULL UNIQUE,
+    -- 被推荐人手机号，同一手机号只能领取一次奖励
+    referee_phone      TEXT,
+    referrer_points    INTEGER NOT NULL DEFAULT 0,
+    referrer_coupon_id INTEGER,
+    referee_points     INTEGER NOT NULL DEFAULT 0,
+    referee_coupon_id  INTEGER,
+    -- Rewarded: 已发放，Rejected: 未通过校验
+    status             TEXT    NOT NULL,
+    create_time        INTEGER,
+    remark             TEXT
+);
+CREATE INDEX idx_referral_rewards_referrer_id ON referral_rewards (referrer_id);
+CREATE INDEX idx_referral_rewards_referee_phone ON referral_rewards (store_id, referee_phone);
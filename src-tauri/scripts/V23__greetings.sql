@@ -0,0 +1,22 @@
+-- 会员生日及入会日期（YYYY-MM-DD），入会日期默认取注册时间
+ALTER TABLE users ADD COLUMN birthday TEXT;
+ALTER TABLE users ADD COLUMN join_date TEXT;
+UPDATE users SET join_date = date(create_time / 1000, 'unixepoch', 'localtime')
+WHERE typeof(create_time) = 'integer';
+
+-- 生日、入会周年祝福记录：每个会员每年每种祝福只发放一次
+CREATE TABLE greeting_gifts
+(
+    store_id    INTEGER NOT NULL,
+    user_id     INTEGER NOT NULL,
+    kind        TEXT    NOT NULL,
+    year        INTEGER NOT NULL,
+    coupon_id   INTEGER,
+    notice_id   INTEGER,
+    create_time INTEGER,
+    -- 赠券失败等原因
+    remark      TEXT,
+    PRIMARY KEY (user_id, kind, year)
+);
+
+INSERT INTO dict_data (dict_sort, dict_label, dict_value, dict_type, css_class, list_class, is_default, status, create_time, update_time, remark) VALUES (5, '会员祝福', '5', 'sys_temp_type', null, 'info', 'N', '0', '2025-01-01 00:00:00', '', '生日及入会周年祝福');
@@ -0,0 +1,28 @@
+-- 美团、抖音团购券核销记录，按支付记录关联
+CREATE TABLE platform_vouchers
+(
+    id                INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id          INTEGER NOT NULL,
+    pay_id            TEXT    NOT NULL,
+    order_id          INTEGER,
+    -- 支付方式：Meituan / Douyin
+    platform          TEXT    NOT NULL,
+    voucher_code      TEXT    NOT NULL,
+    -- 平台核销单号，撤销核销时使用
+    platform_order_id TEXT,
+    -- 抖音券 ID，撤销核销时使用
+    certificate_id    TEXT,
+    deal_title        TEXT,
+    -- 券面价值
+    face_amount       REAL    NOT NULL DEFAULT 0,
+    -- 结算金额，按用户实付计算
+    settlement_amount REAL    NOT NULL DEFAULT 0,
+    -- Consumed: 已核销，Cancelled: 已撤销
+    status            TEXT    NOT NULL,
+    raw_response      TEXT,
+    create_time       INTEGER,
+    update_time       INTEGER,
+    remark            TEXT
+);
+CREATE INDEX idx_platform_vouchers_pay_id ON platform_vouchers (pay_id);
+CREATE INDEX idx_platform_vouchers_code ON platform_vouchers (store_id, voucher_code);
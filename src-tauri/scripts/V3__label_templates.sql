@@ -0,0 +1,19 @@
+-- 衣物标签模板
+-- fields 为 JSON 数组，描述每个元素的类型、内容、位置（单位 mm，原点为左下角）
+CREATE TABLE IF NOT EXISTS label_templates
+(
+    template_id   INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id      INTEGER NOT NULL,
+    template_name TEXT    NOT NULL,
+    page_width    REAL    NOT NULL DEFAULT 180,
+    page_height   REAL    NOT NULL DEFAULT 35,
+    font_path     TEXT             DEFAULT 'MSYH.TTC',
+    font_size     REAL    NOT NULL DEFAULT 10,
+    fields        TEXT    NOT NULL DEFAULT '[]',
+    is_default    BOOLEAN NOT NULL DEFAULT 0,
+    create_time   INTEGER,
+    update_time   INTEGER,
+    remark        TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_label_templates_store_id ON label_templates (store_id);
@@ -0,0 +1,6 @@
+-- 打印机指令类型及原始指令输出目标
+ALTER TABLE printers ADD COLUMN language TEXT NOT NULL DEFAULT 'Pdf';
+-- 为空时发送到系统打印机，tcp://ip:port 发送到网络端口，file://path 写入文件
+ALTER TABLE printers ADD COLUMN target TEXT;
+ALTER TABLE printers ADD COLUMN cut_paper BOOLEAN NOT NULL DEFAULT 1;
+ALTER TABLE printers ADD COLUMN open_drawer BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,25 @@
+-- 打印任务队列
+-- payload 为 JSON，标签任务保存衣物列表及模板，小票任务保存订单信息
+CREATE TABLE IF NOT EXISTS print_jobs
+(
+    job_id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id        INTEGER NOT NULL,
+    job_type        TEXT    NOT NULL,
+    ref_no          TEXT,
+    printer_name    TEXT,
+    -- 入队时的打印机配置（JSON），重试和补打按此配置发送
+    printer_config  TEXT,
+    payload         TEXT    NOT NULL,
+    status          TEXT    NOT NULL DEFAULT 'Pending',
+    attempts        INTEGER NOT NULL DEFAULT 0,
+    -- 标签任务已打印的张数，重试时从下一张继续
+    printed         INTEGER NOT NULL DEFAULT 0,
+    max_attempts    INTEGER NOT NULL DEFAULT 5,
+    last_error      TEXT,
+    next_retry_time INTEGER,
+    create_time     INTEGER,
+    update_time     INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_print_jobs_store_status ON print_jobs (store_id, status);
+CREATE INDEX IF NOT EXISTS idx_print_jobs_ref_no ON print_jobs (ref_no);
@@ -0,0 +1,3 @@
+-- 标签模板条码类型：Code39、Code128、QrCode、DataMatrix
+-- 原先固定打印 Code39，已有模板保持 Code39，避免按 Code39 设置的扫码枪无法识别
+ALTER TABLE label_templates ADD COLUMN barcode_type TEXT NOT NULL DEFAULT 'Code39';
@@ -0,0 +1,5 @@
+-- 会员通知偏好：首选渠道（Sms 或 InApp）为空时按模板的通知方式发送
+ALTER TABLE users ADD COLUMN notice_channel TEXT;
+ALTER TABLE users ADD COLUMN notice_opt_out BOOLEAN NOT NULL DEFAULT 0;
+
+INSERT INTO dict_data (dict_sort, dict_label, dict_value, dict_type, css_class, list_class, is_default, status, create_time, update_time, remark) VALUES (2, '站内消息', '2', 'sys_notice_method', null, 'info', 'N', '0', '2025-01-01 00:00:00', '', '站内消息通知');
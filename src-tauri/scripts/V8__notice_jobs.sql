@@ -0,0 +1,27 @@
+-- 订单全部上挂的时间（毫秒），用于未取件提醒
+ALTER TABLE orders ADD COLUMN ready_time INTEGER;
+UPDATE orders SET ready_time = update_time
+WHERE status = 'ReadyForPickup' AND typeof(update_time) = 'integer';
+
+-- 定时通知任务
+-- Once：在 next_run_time 向 user_ids（逗号分隔）发送一次
+-- Recurring：每 interval_days 天提醒一次上挂超过 overdue_days 天仍未取件的订单
+CREATE TABLE IF NOT EXISTS notice_jobs
+(
+    job_id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    store_id      INTEGER NOT NULL,
+    temp_id       INTEGER NOT NULL,
+    job_type      TEXT    NOT NULL,
+    user_ids      TEXT,
+    overdue_days  INTEGER,
+    interval_days INTEGER,
+    next_run_time INTEGER NOT NULL,
+    status        TEXT    NOT NULL DEFAULT 'Pending',
+    last_run_time INTEGER,
+    sent_count    INTEGER NOT NULL DEFAULT 0,
+    last_error    TEXT,
+    create_time   INTEGER,
+    update_time   INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_notice_jobs_store_status ON notice_jobs (store_id, status);
@@ -0,0 +1,15 @@
+-- 会员卡券的有效期，自动延期后写入；为空时以卡券的 valid_to 为准
+ALTER TABLE user_coupons ADD COLUMN expire_time TIMESTAMP DEFAULT NULL;
+
+-- 卡券到期提醒记录：同一到期时间的每个提醒阈值只提醒一次，延期后重新提醒
+CREATE TABLE coupon_expiry_notices
+(
+    uc_id          INTEGER   NOT NULL,
+    threshold_days INTEGER   NOT NULL,
+    expire_time    TIMESTAMP NOT NULL,
+    notice_id      INTEGER,
+    create_time    INTEGER,
+    PRIMARY KEY (uc_id, threshold_days, expire_time)
+);
+
+INSERT INTO dict_data (dict_sort, dict_label, dict_value, dict_type, css_class, list_class, is_default, status, create_time, update_time, remark) VALUES (3, '卡券到期提醒', '3', 'sys_temp_type', null, 'info', 'N', '0', '2025-01-01 00:00:00', '', '卡券到期提醒');
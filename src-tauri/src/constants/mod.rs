@@ -107,6 +107,8 @@ pub enum PaymentMethod {
     WechatPay,
     Meituan,
     Douyin,
+    /// 积分抵扣
+    Points,
 
     /// combination method
     CashAndStoredValueCard,
@@ -142,6 +144,7 @@ impl Display for PaymentMethod {
             PaymentMethod::WechatPay => write!(f, "WechatPay"),
             PaymentMethod::Meituan => write!(f, "Meituan"),
             PaymentMethod::Douyin => write!(f, "Douyin"),
+            PaymentMethod::Points => write!(f, "Points"),
             PaymentMethod::CashAndStoredValueCard => write!(f, "CashAndStoredValueCard"),
             PaymentMethod::AlipayAndStoredValueCard => write!(f, "AlipayAndStoredValueCard"),
             PaymentMethod::WechatPayAndStoredValueCard => write!(f, "WechatPayAndStoredValueCard"),
@@ -211,6 +214,57 @@ impl PaymentMethod {
             _ => None,
         }
     }
+
+    /// 卡券之外的支付方式，纯卡券支付时为空
+    pub fn cash_method(&self) -> Option<Self> {
+        match self {
+            Self::StoredValueCard | Self::DiscountCard | Self::SessionCard | Self::Points => None,
+            Self::CashAndStoredValueCard
+            | Self::CashAndDiscountCard
+            | Self::CashAndSpendAndSaveCard
+            | Self::CashAndDiscountCoupon
+            | Self::CashAndSessionCard => Some(Self::Cash),
+            Self::AlipayAndStoredValueCard
+            | Self::AlipayAndDiscountCard
+            | Self::AlipayAndSpendAndSaveCard
+            | Self::AlipayAndDiscountCoupon
+            | Self::AlipayAndSessionCard => Some(Self::Alipay),
+            Self::WechatPayAndStoredValueCard
+            | Self::WechatPayAndDiscountCard
+            | Self::WechatPayAndSpendAndSaveCard
+            | Self::WechatPayAndDiscountCoupon
+            | Self::WechatPayAndSessionCard => Some(Self::WechatPay),
+            other => Some(other.clone()),
+        }
+    }
+
+    /// 组合支付方式，cash 为空时为纯卡券支付
+    pub fn combine(cash: Option<&Self>, coupon_type: &CouponType) -> Self {
+        match (cash, coupon_type) {
+            (None, CouponType::StoredValueCard) => Self::StoredValueCard,
+            (None, CouponType::DiscountCard) => Self::DiscountCard,
+            (None, CouponType::SessionCard) => Self::SessionCard,
+            (Some(Self::Alipay), CouponType::StoredValueCard) => Self::AlipayAndStoredValueCard,
+            (Some(Self::Alipay), CouponType::DiscountCard) => Self::AlipayAndDiscountCard,
+            (Some(Self::Alipay), CouponType::SpendAndSaveCard) => Self::AlipayAndSpendAndSaveCard,
+            (Some(Self::Alipay), CouponType::DiscountCoupon) => Self::AlipayAndDiscountCoupon,
+            (Some(Self::Alipay), CouponType::SessionCard) => Self::AlipayAndSessionCard,
+            (Some(Self::WechatPay), CouponType::StoredValueCard) => {
+                Self::WechatPayAndStoredValueCard
+            }
+            (Some(Self::WechatPay), CouponType::DiscountCard) => Self::WechatPayAndDiscountCard,
+            (Some(Self::WechatPay), CouponType::SpendAndSaveCard) => {
+                Self::WechatPayAndSpendAndSaveCard
+            }
+            (Some(Self::WechatPay), CouponType::DiscountCoupon) => Self::WechatPayAndDiscountCoupon,
+            (Some(Self::WechatPay), CouponType::SessionCard) => Self::WechatPayAndSessionCard,
+            (_, CouponType::StoredValueCard) => Self::CashAndStoredValueCard,
+            (_, CouponType::DiscountCard) => Self::CashAndDiscountCard,
+            (_, CouponType::SpendAndSaveCard) => Self::CashAndSpendAndSaveCard,
+            (_, CouponType::DiscountCoupon) => Self::CashAndDiscountCoupon,
+            (_, CouponType::SessionCard) => Self::CashAndSessionCard,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
@@ -314,3 +368,406 @@ impl Display for TagType {
         }
     }
 }
+
+/// 打印机指令类型，Pdf 交给系统打印队列，其余直接发送原始指令
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum PrinterLanguage {
+    #[default]
+    Pdf,
+    EscPos,
+    Tspl,
+    Zpl,
+}
+
+impl Display for PrinterLanguage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrinterLanguage::Pdf => write!(f, "Pdf"),
+            PrinterLanguage::EscPos => write!(f, "EscPos"),
+            PrinterLanguage::Tspl => write!(f, "Tspl"),
+            PrinterLanguage::Zpl => write!(f, "Zpl"),
+        }
+    }
+}
+
+/// 标签条码类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum BarcodeType {
+    /// 原先固定使用 Code39，已有模板及内置模板保持不变
+    #[default]
+    Code39,
+    Code128,
+    QrCode,
+    DataMatrix,
+}
+
+impl Display for BarcodeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BarcodeType::Code39 => write!(f, "Code39"),
+            BarcodeType::Code128 => write!(f, "Code128"),
+            BarcodeType::QrCode => write!(f, "QrCode"),
+            BarcodeType::DataMatrix => write!(f, "DataMatrix"),
+        }
+    }
+}
+
+/// 打印任务类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum PrintJobType {
+    #[default]
+    Label,
+    Receipt,
+}
+
+impl Display for PrintJobType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrintJobType::Label => write!(f, "Label"),
+            PrintJobType::Receipt => write!(f, "Receipt"),
+        }
+    }
+}
+
+/// 打印任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum PrintJobStatus {
+    #[default]
+    Pending,
+    Printing,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl Display for PrintJobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrintJobStatus::Pending => write!(f, "Pending"),
+            PrintJobStatus::Printing => write!(f, "Printing"),
+            PrintJobStatus::Completed => write!(f, "Completed"),
+            PrintJobStatus::Failed => write!(f, "Failed"),
+            PrintJobStatus::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+/// 通知发送渠道
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum NoticeChannel {
+    /// 服务端短信接口，目前只有取件通知模板（/sms/hangup）
+    Sms,
+    /// 站内消息，写入 messages 表
+    InApp,
+    /// 仅写入本地日志，用于测试
+    Log,
+}
+
+impl NoticeChannel {
+    /// 按通知方式字典值（sys_notice_method）选择渠道，
+    /// 小程序通知走站内消息，由小程序同步给会员
+    pub fn from_method(method: Option<&str>) -> Self {
+        match method {
+            Some("1") | Some("2") => Self::InApp,
+            _ => Self::Sms,
+        }
+    }
+
+    /// 对应的通知方式字典值，日志渠道不改变记录的通知方式
+    pub fn method(&self) -> Option<&'static str> {
+        match self {
+            Self::Sms => Some("0"),
+            Self::InApp => Some("2"),
+            Self::Log => None,
+        }
+    }
+
+    /// 渠道能否发送该类通知：服务端短信只支持取件通知
+    pub fn is_available(&self, pickup: bool) -> bool {
+        match self {
+            Self::Sms => pickup,
+            Self::InApp | Self::Log => true,
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "Sms" => Some(Self::Sms),
+            "InApp" => Some(Self::InApp),
+            "Log" => Some(Self::Log),
+            _ => None,
+        }
+    }
+}
+
+impl Display for NoticeChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NoticeChannel::Sms => write!(f, "Sms"),
+            NoticeChannel::InApp => write!(f, "InApp"),
+            NoticeChannel::Log => write!(f, "Log"),
+        }
+    }
+}
+
+/// 定时通知任务类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum NoticeJobType {
+    /// 在指定时间向指定会员发送一次
+    #[default]
+    Once,
+    /// 定期提醒上挂后超过指定天数仍未取件的订单
+    Recurring,
+}
+
+impl Display for NoticeJobType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NoticeJobType::Once => write!(f, "Once"),
+            NoticeJobType::Recurring => write!(f, "Recurring"),
+        }
+    }
+}
+
+/// 定时通知任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum NoticeJobStatus {
+    #[default]
+    Pending,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl Display for NoticeJobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NoticeJobStatus::Pending => write!(f, "Pending"),
+            NoticeJobStatus::Running => write!(f, "Running"),
+            NoticeJobStatus::Completed => write!(f, "Completed"),
+            NoticeJobStatus::Failed => write!(f, "Failed"),
+            NoticeJobStatus::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+/// 会员分群规则类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum SegmentRuleType {
+    /// 最近 days 天内实付金额超过 threshold
+    #[default]
+    SpendOver,
+    /// 储值卡余额超过 threshold
+    HasBalance,
+    /// 最近 days 天内没有下单
+    NoVisit,
+    /// 黑名单会员
+    Blacklisted,
+}
+
+impl Display for SegmentRuleType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SegmentRuleType::SpendOver => write!(f, "SpendOver"),
+            SegmentRuleType::HasBalance => write!(f, "HasBalance"),
+            SegmentRuleType::NoVisit => write!(f, "NoVisit"),
+            SegmentRuleType::Blacklisted => write!(f, "Blacklisted"),
+        }
+    }
+}
+
+/// 积分变动原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum PointsReason {
+    /// 启用积分流水前的期初余额
+    Opening,
+    /// 订单支付赠送
+    #[default]
+    OrderPayment,
+    /// 订单退款扣回
+    Refund,
+    /// 积分兑换/抵扣
+    Redemption,
+    /// 手动调整
+    Manual,
+    /// 积分过期
+    Expiry,
+    /// 推荐奖励
+    Referral,
+}
+
+impl Display for PointsReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PointsReason::Opening => write!(f, "Opening"),
+            PointsReason::OrderPayment => write!(f, "OrderPayment"),
+            PointsReason::Refund => write!(f, "Refund"),
+            PointsReason::Redemption => write!(f, "Redemption"),
+            PointsReason::Manual => write!(f, "Manual"),
+            PointsReason::Expiry => write!(f, "Expiry"),
+            PointsReason::Referral => write!(f, "Referral"),
+        }
+    }
+}
+
+/// 积分兑换规则类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum PointsRuleType {
+    /// 结账时每 points 积分抵扣 amount 元
+    #[default]
+    Discount,
+    /// 每 points 积分兑换一张卡券
+    Coupon,
+}
+
+impl Display for PointsRuleType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PointsRuleType::Discount => write!(f, "Discount"),
+            PointsRuleType::Coupon => write!(f, "Coupon"),
+        }
+    }
+}
+
+/// 计价规则类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum PricingRuleType {
+    /// 服务要求倍率：基础价 × value
+    #[default]
+    ServiceMultiplier,
+    /// 品类/款式加价：每件加 value 元
+    Surcharge,
+    /// 会员等级折扣：按会员等级的价格折扣计算
+    LevelDiscount,
+    /// 数量优惠：每 quantity 件合计 value 元
+    QuantityBreak,
+    /// 限时优惠：活动时间内按 value 折（百分比）
+    TimePromotion,
+}
+
+impl Display for PricingRuleType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PricingRuleType::ServiceMultiplier => write!(f, "ServiceMultiplier"),
+            PricingRuleType::Surcharge => write!(f, "Surcharge"),
+            PricingRuleType::LevelDiscount => write!(f, "LevelDiscount"),
+            PricingRuleType::QuantityBreak => write!(f, "QuantityBreak"),
+            PricingRuleType::TimePromotion => write!(f, "TimePromotion"),
+        }
+    }
+}
+
+/// 计价规则叠加方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum PricingStacking {
+    /// 与其他规则叠加
+    #[default]
+    Stack,
+    /// 命中的衣物不再应用优先级更低的规则
+    Exclusive,
+}
+
+impl Display for PricingStacking {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PricingStacking::Stack => write!(f, "Stack"),
+            PricingStacking::Exclusive => write!(f, "Exclusive"),
+        }
+    }
+}
+
+/// 卡券兑换码状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum CouponCodeStatus {
+    /// 未兑换
+    #[default]
+    Unused,
+    /// 已兑换
+    Redeemed,
+    /// 已作废
+    Voided,
+}
+
+impl Display for CouponCodeStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CouponCodeStatus::Unused => write!(f, "Unused"),
+            CouponCodeStatus::Redeemed => write!(f, "Redeemed"),
+            CouponCodeStatus::Voided => write!(f, "Voided"),
+        }
+    }
+}
+
+/// 推荐奖励处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum ReferralRewardStatus {
+    /// 已发放
+    #[default]
+    Rewarded,
+    /// 未通过防刷校验，不发放
+    Rejected,
+}
+
+impl Display for ReferralRewardStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReferralRewardStatus::Rewarded => write!(f, "Rewarded"),
+            ReferralRewardStatus::Rejected => write!(f, "Rejected"),
+        }
+    }
+}
+
+/// 会员祝福类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum GreetingKind {
+    /// 生日
+    #[default]
+    Birthday,
+    /// 入会周年
+    Anniversary,
+}
+
+impl Display for GreetingKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GreetingKind::Birthday => write!(f, "Birthday"),
+            GreetingKind::Anniversary => write!(f, "Anniversary"),
+        }
+    }
+}
+
+/// 美团、抖音等平台团购券的核销状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum VoucherStatus {
+    /// 已核销
+    #[default]
+    Consumed,
+    /// 已撤销核销
+    Cancelled,
+}
+
+impl Display for VoucherStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VoucherStatus::Consumed => write!(f, "Consumed"),
+            VoucherStatus::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
@@ -0,0 +1,860 @@
+use std::collections::BTreeMap;
+
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::constants::{CouponType, PaymentMethod, PaymentStatus};
+use crate::db::family_groups::FamilyGroup;
+use crate::db::order_clothes::OrderCloth;
+use crate::db::orders::{Order, PaymentReq, TimeBasedCoupon};
+use crate::db::payments::{CouponAllocation, CouponUsage, Payment, PaymentMethodDetail};
+use crate::db::pricing_rules::PriceBreakdown;
+use crate::db::user_coupons::UserCoupon;
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+/// 卡券组合的结算结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct CheckoutPlan {
+    pub total_amount: f64,
+    /// 顾客实际花费：现金及储值卡、折扣卡余额
+    pub cost_amount: f64,
+    /// 需另行支付的金额
+    pub cash_amount: f64,
+    /// 优惠及次卡抵扣的金额
+    pub discount_amount: f64,
+    pub coupon_usages: Vec<CouponUsage>,
+    /// 储值卡、折扣卡的支付明细，不含现金部分
+    pub payment_method_details: Vec<PaymentMethodDetail>,
+}
+
+/// 推荐的结算方案及对应的支付请求
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckoutRecommendation {
+    pub plan: CheckoutPlan,
+    pub payment_req: PaymentReq,
+}
+
+fn coupon_type(uc: &UserCoupon) -> Option<CouponType> {
+    uc.coupon.as_ref().and_then(|c| c.coupon_type.clone())
+}
+
+fn title(uc: &UserCoupon) -> String {
+    uc.coupon
+        .as_ref()
+        .and_then(|c| c.coupon_title.clone())
+        .unwrap_or_default()
+}
+
+fn is_voucher(uc: &UserCoupon) -> bool {
+    matches!(
+        coupon_type(uc),
+        Some(CouponType::DiscountCoupon | CouponType::SpendAndSaveCard)
+    )
+}
+
+/// 各衣物的实付金额，订单级优惠和调价按比例分摊到衣物
+fn line_amounts(clothes: &[OrderCloth], breakdown: &PriceBreakdown) -> Vec<(String, Decimal)> {
+    let mut lines = clothes
+        .iter()
+        .zip(&breakdown.items)
+        .map(|(cloth, item)| {
+            (
+                cloth.cloth_id.clone().unwrap_or_default(),
+                Decimal::from_f64(item.price).unwrap_or_default(),
+            )
+        })
+        .collect::<Vec<_>>();
+    // 衣物均为零价时（如价格标签直接改价）平均分摊
+    if lines.iter().all(|(_, price)| *price <= Decimal::ZERO) {
+        lines
+            .iter_mut()
+            .for_each(|(_, price)| *price = Decimal::ONE);
+    }
+    let total = Decimal::from_f64(breakdown.total).unwrap_or_default();
+    CouponAllocation::split(total, &lines)
+        .into_iter()
+        .map(|line| {
+            (
+                line.cloth_id,
+                Decimal::from_f64(line.amount).unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+/// 按分摊结果扣减各衣物的待付金额，返回分摊明细
+fn allocate(
+    lines: &mut [(String, Decimal)],
+    indexes: &[usize],
+    amount: Decimal,
+) -> Vec<CouponAllocation> {
+    let selected = indexes
+        .iter()
+        .map(|i| lines[*i].clone())
+        .collect::<Vec<_>>();
+    let allocations = CouponAllocation::split(amount, &selected);
+    for (i, allocation) in indexes.iter().zip(&allocations) {
+        lines[*i].1 -= Decimal::from_f64(allocation.amount).unwrap_or_default();
+    }
+    allocations
+}
+
+fn sum(lines: &[(String, Decimal)], indexes: &[usize]) -> Decimal {
+    indexes.iter().map(|i| lines[*i].1).sum()
+}
+
+impl CheckoutPlan {
+    /// 校验卡券叠加规则：优惠券每次一张，折扣卡折扣系数需相同，
+    /// 不同类型的卡券叠加时除储值卡外均需允许叠加
+    fn check_stacking(coupons: &[UserCoupon]) -> Result<()> {
+        if coupons.iter().filter(|uc| is_voucher(uc)).count() > 1 {
+            return Err(Error::bad_request("每次支付只能使用一张优惠券"));
+        }
+
+        let rates = coupons
+            .iter()
+            .filter(|uc| coupon_type(uc) == Some(CouponType::DiscountCard))
+            .map(|uc| {
+                uc.coupon
+                    .as_ref()
+                    .and_then(|c| c.usage_value)
+                    .ok_or(Error::internal("获取折扣卡折扣系数失败"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if rates.iter().any(|rate| (rate - rates[0]).abs() > 0.01) {
+            return Err(Error::bad_request("只能同时使用相同折扣系数的折扣卡"));
+        }
+
+        let types = coupons.iter().filter_map(coupon_type).collect::<Vec<_>>();
+        if types.iter().any(|t| *t != types[0]) {
+            if let Some(uc) = coupons.iter().find(|uc| {
+                coupon_type(uc) != Some(CouponType::StoredValueCard)
+                    && !uc.coupon.as_ref().is_some_and(|c| c.stackable)
+            }) {
+                return Err(Error::bad_request(format!(
+                    "卡券 <{}> 不能与其他类型的卡券叠加使用",
+                    title(uc)
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn push_usage(
+        &mut self,
+        uc: &UserCoupon,
+        coupon_type: CouponType,
+        applied: Decimal,
+        allocations: Vec<CouponAllocation>,
+    ) -> &mut CouponUsage {
+        self.coupon_usages.push(CouponUsage {
+            coupon_id: uc.uc_id.unwrap_or_default(),
+            coupon_type,
+            applied_amount: applied.to_f64().unwrap_or_default(),
+            allocations,
+            ..Default::default()
+        });
+        self.coupon_usages.last_mut().unwrap()
+    }
+
+    fn push_detail(&mut self, method: PaymentMethod, amount: Decimal) {
+        self.payment_method_details.push(PaymentMethodDetail {
+            method: Some(method),
+            amount: amount.to_f64().unwrap_or_default(),
+            payment_status: Some(PaymentStatus::Paid),
+            ..Default::default()
+        });
+    }
+
+    /// 按次卡、优惠券、折扣卡、储值卡的顺序结算订单，卡券余额的变化写回 coupons
+    pub fn compute(
+        coupons: &mut [UserCoupon],
+        clothes: &[OrderCloth],
+        breakdown: &PriceBreakdown,
+    ) -> Result<Self> {
+        Self::compute_orders(coupons, &[(clothes, breakdown)])
+    }
+
+    /// 合并结算多个订单，每张卡券在所有订单中只结算一次
+    pub fn compute_orders(
+        coupons: &mut [UserCoupon],
+        orders: &[(&[OrderCloth], &PriceBreakdown)],
+    ) -> Result<Self> {
+        let now = utils::get_now();
+        for uc in coupons.iter() {
+            let coupon = uc
+                .coupon
+                .as_ref()
+                .ok_or(Error::internal("get coupon failed"))?;
+            if Some(now) > coupon.valid_to {
+                return Err(Error::bad_request(format!("优惠券 <{}> 已过期", title(uc))));
+            }
+            if uc.is_refunded() {
+                return Err(Error::bad_request(format!("卡券 <{}> 已退卡", title(uc))));
+            }
+        }
+        Self::check_stacking(coupons)?;
+
+        let total = orders
+            .iter()
+            .map(|(_, breakdown)| Decimal::from_f64(breakdown.total).unwrap_or_default())
+            .sum::<Decimal>();
+        let mut plan = Self {
+            total_amount: total.to_f64().unwrap_or_default(),
+            ..Default::default()
+        };
+        let clothes = orders
+            .iter()
+            .flat_map(|(clothes, _)| clothes.iter())
+            .collect::<Vec<_>>();
+        // 各衣物的待付金额，随各类卡券的抵扣逐步减少
+        let mut lines = orders
+            .iter()
+            .flat_map(|(clothes, breakdown)| line_amounts(clothes, breakdown))
+            .collect::<Vec<_>>();
+        let all = (0..lines.len()).collect::<Vec<_>>();
+        let payable = |lines: &[(String, Decimal)], applicable: &dyn Fn(&OrderCloth) -> bool| {
+            all.iter()
+                .copied()
+                .filter(|i| lines[*i].1 > Decimal::ZERO && applicable(clothes[*i]))
+                .collect::<Vec<_>>()
+        };
+
+        // 次卡：每件衣物使用一次，优先抵扣金额低的衣物
+        let mut sessions = coupons
+            .iter_mut()
+            .filter(|uc| coupon_type(uc) == Some(CouponType::SessionCard))
+            .peekable();
+        if sessions.peek().is_some() {
+            let mut used = false;
+            for uc in sessions {
+                let usable = uc.available_value.unwrap_or_default().max(0.) as usize;
+                let Some(coupon) = uc.coupon.as_ref() else {
+                    continue;
+                };
+                let mut covered = payable(&lines, &|cloth| coupon.applicable_to(cloth));
+                covered.sort_by(|a, b| lines[*a].1.cmp(&lines[*b].1));
+                covered.truncate(usable);
+                if covered.is_empty() {
+                    continue;
+                }
+
+                let allocations = covered
+                    .iter()
+                    .map(|i| CouponAllocation {
+                        cloth_id: lines[*i].0.clone(),
+                        amount: 1.0,
+                    })
+                    .collect();
+                for i in &covered {
+                    lines[*i].1 = Decimal::ZERO;
+                }
+                uc.available_value = Some((usable - covered.len()) as f64);
+                let count = Decimal::from(covered.len());
+                plan.push_usage(uc, CouponType::SessionCard, count, allocations);
+                used = true;
+            }
+            if !used {
+                return Err(Error::bad_request("所选次卡次数不足或不适用于本订单的衣物"));
+            }
+        }
+
+        // 优惠券：优惠只分摊到适用的衣物
+        if let Some(uc) = coupons.iter_mut().find(|uc| is_voucher(uc)) {
+            let coupon = uc
+                .coupon
+                .clone()
+                .ok_or(Error::internal("Invalid coupon data"))?;
+            let title = coupon.coupon_title.clone().unwrap_or_default();
+            let eligible = payable(&lines, &|cloth| coupon.applicable_to(cloth));
+            let eligible_amount = sum(&lines, &eligible);
+            if eligible_amount <= Decimal::ZERO {
+                return Err(Error::bad_request(format!(
+                    "优惠券 <{title}> 不适用于本订单的衣物"
+                )));
+            }
+
+            // 校验卡券最低消费，只计算适用衣物的金额
+            if let Some(min_spend) = coupon.min_spend {
+                if eligible_amount < Decimal::from_f64(min_spend).unwrap_or_default() {
+                    return Err(Error::bad_request(format!(
+                        "优惠券 <{title}> 需适用衣物满 {min_spend:.2} 元才能使用，当前 {eligible_amount:.2} 元"
+                    )));
+                }
+            }
+
+            let uc_count = uc
+                .uc_count
+                .as_mut()
+                .ok_or(Error::internal("获取用户优惠券数量失败"))?;
+            if *uc_count > 0 {
+                *uc_count -= 1;
+            } else {
+                return Err(Error::bad_request("优惠券数量不足"));
+            }
+
+            let usage_value =
+                Decimal::from_f64(coupon.usage_value.unwrap_or_default()).unwrap_or_default();
+            let discount_amount = if coupon.coupon_type == Some(CouponType::DiscountCoupon) {
+                if coupon.usage_value.is_none() || coupon.usage_limit.is_none() {
+                    return Err(Error::internal("get coupon usage value failed"));
+                }
+                // 折扣券逻辑
+                let discount = Decimal::ONE - usage_value / Decimal::ONE_HUNDRED;
+                let discounted = (eligible_amount * discount)
+                    .round_dp_with_strategy(2, RoundingStrategy::MidpointTowardZero);
+                let usage_limit =
+                    Decimal::from_f64(coupon.usage_limit.unwrap_or_default()).unwrap_or_default();
+                discounted.min(usage_limit)
+            } else {
+                // 满减券逻辑，优惠不超过适用衣物的金额
+                usage_value.min(eligible_amount)
+            };
+            tracing::debug!(
+                "[支付] 优惠券: {}, 适用金额: {}, 优惠金额: {}",
+                title,
+                eligible_amount,
+                discount_amount
+            );
+
+            let allocations = allocate(&mut lines, &eligible, discount_amount);
+            let coupon_type = coupon.coupon_type.unwrap_or_default();
+            plan.push_usage(uc, coupon_type, discount_amount, allocations);
+        }
+
+        // 折扣卡：只对所有卡都适用的衣物打折，折扣后的金额从卡内余额扣除
+        let mut cards = coupons
+            .iter_mut()
+            .filter(|uc| coupon_type(uc) == Some(CouponType::DiscountCard))
+            .collect::<Vec<_>>();
+        if !cards.is_empty() {
+            let rate = cards[0]
+                .coupon
+                .as_ref()
+                .and_then(|c| c.usage_value)
+                .ok_or(Error::internal("获取折扣卡折扣系数失败"))?;
+            let eligible = payable(&lines, &|cloth| {
+                cards.iter().all(|card| {
+                    card.coupon
+                        .as_ref()
+                        .is_some_and(|coupon| coupon.applicable_to(cloth))
+                })
+            });
+            let eligible_amount = sum(&lines, &eligible);
+            if eligible_amount <= Decimal::ZERO {
+                return Err(Error::bad_request(format!(
+                    "折扣卡 <{}> 不适用于本订单的衣物",
+                    title(&*cards[0])
+                )));
+            }
+
+            let discounted = (eligible_amount * Decimal::from_f64(rate).unwrap_or_default()
+                / Decimal::ONE_HUNDRED)
+                .round_dp_with_strategy(2, RoundingStrategy::MidpointTowardZero);
+            tracing::debug!(
+                "[支付] 折扣卡: 适用金额: {}, 折扣系数: {}%, 折扣后金额: {}",
+                eligible_amount,
+                rate,
+                discounted
+            );
+            allocate(&mut lines, &eligible, eligible_amount - discounted);
+
+            // 按余额升序使用折扣卡
+            cards.sort_by(|a, b| {
+                let balance = |uc: &UserCoupon| uc.available_value.unwrap_or_default();
+                balance(a).total_cmp(&balance(b))
+            });
+            for card in cards {
+                let balance =
+                    Decimal::from_f64(card.available_value.unwrap_or_default()).unwrap_or_default();
+                let due = sum(&lines, &eligible);
+                if balance <= Decimal::ZERO || due <= Decimal::ZERO {
+                    continue;
+                }
+                let applied = balance.min(due);
+                card.available_value = Some((balance - applied).to_f64().unwrap_or_default());
+                let allocations = allocate(&mut lines, &eligible, applied);
+                plan.push_usage(card, CouponType::DiscountCard, applied, allocations);
+                plan.push_detail(PaymentMethod::DiscountCard, applied);
+            }
+        }
+
+        // 储值卡：只支付适用衣物的剩余金额。限定范围的卡先用，以免被通用卡占用适用衣物，
+        // 同类按余额升序使用
+        let mut cards = coupons
+            .iter_mut()
+            .filter(|uc| coupon_type(uc) == Some(CouponType::StoredValueCard))
+            .collect::<Vec<_>>();
+        let restricted = |uc: &UserCoupon| uc.coupon.as_ref().is_some_and(|c| c.is_restricted());
+        let balance = |uc: &UserCoupon| uc.available_value.unwrap_or_default();
+        cards.sort_by(|a, b| {
+            restricted(b)
+                .cmp(&restricted(a))
+                .then_with(|| balance(a).total_cmp(&balance(b)))
+        });
+        for card in cards {
+            let balance =
+                Decimal::from_f64(card.available_value.unwrap_or_default()).unwrap_or_default();
+            let Some(coupon) = card.coupon.clone() else {
+                continue;
+            };
+            let unpaid = payable(&lines, &|cloth| coupon.applicable_to(cloth));
+            let due = sum(&lines, &unpaid);
+            if balance <= Decimal::ZERO || due <= Decimal::ZERO {
+                continue;
+            }
+            let applied = balance.min(due);
+            let bonus = card.deduct(applied);
+            let allocations = allocate(&mut lines, &unpaid, applied);
+            plan.push_usage(card, CouponType::StoredValueCard, applied, allocations)
+                .bonus_amount = bonus.to_f64().unwrap_or_default();
+            plan.push_detail(PaymentMethod::StoredValueCard, applied);
+        }
+
+        let cash = lines.iter().map(|(_, due)| *due).sum::<Decimal>();
+        let cost = cash
+            + plan
+                .payment_method_details
+                .iter()
+                .map(|detail| Decimal::from_f64(detail.amount).unwrap_or_default())
+                .sum::<Decimal>();
+        plan.cash_amount = cash.to_f64().unwrap_or_default();
+        plan.cost_amount = cost.to_f64().unwrap_or_default();
+        plan.discount_amount = (total - cost).to_f64().unwrap_or_default();
+        Ok(plan)
+    }
+
+    /// 写入支付明细，剩余金额由支付方式中的现金部分支付
+    pub fn apply(self, payment: &mut Payment) -> Result<()> {
+        let payment_method = payment
+            .payment_method
+            .as_ref()
+            .ok_or(Error::bad_request("支付方式不能为空"))?;
+        let cash_method = payment_method.cash_method();
+        let payment_id = payment.pay_id.clone().unwrap_or_default();
+
+        for mut usage in self.coupon_usages {
+            usage.payment_id = payment_id.clone();
+            payment.coupon_usages.push(usage);
+        }
+        let mut details = self.payment_method_details;
+        if self.cash_amount > 0.0 {
+            details.push(PaymentMethodDetail {
+                method: cash_method,
+                amount: self.cash_amount,
+                payment_status: Some(PaymentStatus::Paid),
+                ..Default::default()
+            });
+        }
+        for mut detail in details {
+            detail.store_id = payment.store_id;
+            detail.payment_id = payment_id.clone();
+            detail.creat_time = payment.create_time;
+            payment.payment_method_details.push(detail);
+        }
+        Ok(())
+    }
+
+    /// 枚举会员卡券的可用组合，选出顾客花费最少的方案；
+    /// 花费相同时优先少付现金，其次少用卡券
+    pub fn best(
+        coupons: &[UserCoupon],
+        clothes: &[OrderCloth],
+        breakdown: &PriceBreakdown,
+    ) -> Self {
+        let now = utils::get_now();
+        let usable = coupons
+            .iter()
+            .filter(|uc| {
+                let Some(coupon) = uc.coupon.as_ref().filter(|_| !uc.is_refunded()) else {
+                    return false;
+                };
+                let valid = coupon.valid_from.is_none_or(|from| from <= now)
+                    && coupon.valid_to.is_none_or(|to| now <= to);
+                let available = match coupon.coupon_type {
+                    Some(CouponType::DiscountCoupon | CouponType::SpendAndSaveCard) => {
+                        uc.uc_count.unwrap_or_default() > 0
+                    }
+                    Some(CouponType::SessionCard) => uc.available_value.unwrap_or_default() >= 1.,
+                    _ => uc.available_value.unwrap_or_default() > 0.,
+                };
+                valid && available
+            })
+            .collect::<Vec<_>>();
+
+        let of_type = |kind: CouponType| {
+            usable
+                .iter()
+                .copied()
+                .filter(|uc| coupon_type(uc) == Some(kind.clone()))
+                .collect::<Vec<_>>()
+        };
+        let sessions = of_type(CouponType::SessionCard);
+        let stored = of_type(CouponType::StoredValueCard);
+        let vouchers = usable
+            .iter()
+            .copied()
+            .filter(|uc| is_voucher(uc))
+            .collect::<Vec<_>>();
+        // 折扣卡按折扣系数分组，同组可同时使用
+        let mut discount_groups = BTreeMap::<i64, Vec<&UserCoupon>>::new();
+        for uc in of_type(CouponType::DiscountCard) {
+            let rate = uc.coupon.as_ref().and_then(|c| c.usage_value);
+            if let Some(rate) = rate {
+                discount_groups
+                    .entry((rate * 100.).round() as i64)
+                    .or_default()
+                    .push(uc);
+            }
+        }
+
+        let options = |group: Vec<Vec<&UserCoupon>>| {
+            let mut options = vec![Vec::new()];
+            options.extend(group.into_iter().filter(|g| !g.is_empty()));
+            options
+        };
+        let session_options = options(vec![sessions]);
+        let voucher_options = options(vouchers.into_iter().map(|uc| vec![uc]).collect());
+        let discount_options = options(discount_groups.into_values().collect());
+        let stored_options = options(vec![stored]);
+
+        let key = |plan: &Self| {
+            let cents = |amount: f64| (amount * 100.).round() as i64;
+            (
+                cents(plan.cost_amount),
+                cents(plan.cash_amount),
+                plan.coupon_usages.len(),
+            )
+        };
+        let mut best: Option<Self> = None;
+        for s in &session_options {
+            for v in &voucher_options {
+                for d in &discount_options {
+                    for sv in &stored_options {
+                        let mut combo = s
+                            .iter()
+                            .chain(v)
+                            .chain(d)
+                            .chain(sv)
+                            .map(|uc| (*uc).clone())
+                            .collect::<Vec<_>>();
+                        let Ok(plan) = Self::compute(&mut combo, clothes, breakdown) else {
+                            continue;
+                        };
+                        if best.as_ref().is_none_or(|best| key(&plan) < key(best)) {
+                            best = Some(plan);
+                        }
+                    }
+                }
+            }
+        }
+        best.unwrap_or_else(|| Self {
+            total_amount: breakdown.total,
+            cost_amount: breakdown.total,
+            cash_amount: breakdown.total,
+            ..Default::default()
+        })
+    }
+
+    /// 生成结账用的支付请求，支付方式取实际付款的卡（储值卡、折扣卡）与现金的组合；
+    /// 只用了优惠券或次卡时，取优惠券或次卡
+    pub fn payment_req(&self, order: &Order, cash_method: PaymentMethod) -> PaymentReq {
+        let used = |types: &[CouponType]| {
+            self.coupon_usages
+                .iter()
+                .find(|usage| types.contains(&usage.coupon_type))
+                .map(|usage| usage.coupon_type.clone())
+        };
+        let tender =
+            used(&[CouponType::DiscountCard]).or_else(|| used(&[CouponType::StoredValueCard]));
+        let cash = (self.cash_amount > 0.0).then_some(&cash_method);
+        let payment_method = match (&tender, cash) {
+            (Some(coupon_type), _) => PaymentMethod::combine(cash, coupon_type),
+            (None, None) if used(&[CouponType::SessionCard]).is_some() => {
+                PaymentMethod::SessionCard
+            }
+            (None, _) => match used(&[CouponType::DiscountCoupon, CouponType::SpendAndSaveCard])
+                .or_else(|| used(&[CouponType::SessionCard]))
+            {
+                Some(coupon_type) => PaymentMethod::combine(Some(&cash_method), &coupon_type),
+                None => cash_method.clone(),
+            },
+        };
+
+        let uc_ids = self
+            .coupon_usages
+            .iter()
+            .filter(|usage| usage.coupon_type != CouponType::SessionCard)
+            .map(|usage| usage.coupon_id)
+            .collect::<Vec<_>>();
+        let time_based = self
+            .coupon_usages
+            .iter()
+            .filter(|usage| usage.coupon_type == CouponType::SessionCard)
+            .map(|usage| TimeBasedCoupon {
+                uc_id: usage.coupon_id,
+                count: usage.applied_amount as i32,
+            })
+            .collect::<Vec<_>>();
+
+        PaymentReq {
+            payment: Some(Payment {
+                total_amount: Some(self.total_amount),
+                payment_method: Some(payment_method),
+                store_id: order.store_id,
+                ..Default::default()
+            }),
+            uc_ids: (!uc_ids.is_empty()).then_some(uc_ids),
+            time_based: (!time_based.is_empty()).then_some(time_based),
+            orders: Some(vec![Order {
+                order_id: order.order_id,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }
+    }
+}
+
+/// 结账推荐：按会员持有的卡券计算最省钱的组合及对应的支付请求
+#[tauri::command]
+pub async fn get_checkout_recommendation(
+    state: State<'_, AppState>,
+    order_id: i64,
+    payment_method: Option<PaymentMethod>,
+) -> Result<CheckoutRecommendation> {
+    let store_id = utils::get_user_id(&state).await?;
+    let pool = &state.pool;
+    let order = Order::get_by_id(pool, store_id, order_id)
+        .await?
+        .ok_or(Error::not_found("订单不存在"))?;
+    if order.payment_status == Some(PaymentStatus::Paid) {
+        return Err(Error::bad_request("订单已支付"));
+    }
+    let user_id = order.user_id.ok_or(Error::bad_request("订单未关联会员"))?;
+
+    let clothes = OrderCloth::get_by_order_id(pool, order_id).await?;
+    let breakdown = Order::price_breakdown(pool, &order, &clothes).await?;
+    // 会员自己的卡券及家庭共享给会员的卡券
+    let mut coupons = UserCoupon::find_by_user_id(pool, store_id, user_id).await?;
+    coupons.extend(FamilyGroup::shared_coupons(pool, store_id, user_id).await?);
+
+    let plan = CheckoutPlan::best(&coupons, &clothes, &breakdown);
+    let cash_method = payment_method
+        .and_then(|method| method.cash_method())
+        .unwrap_or(PaymentMethod::Cash);
+    let payment_req = plan.payment_req(&order, cash_method);
+    Ok(CheckoutRecommendation { plan, payment_req })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::coupons::Coupon;
+    use crate::db::pricing_rules::ItemPrice;
+    use crate::db::user_coupons::UC_STATUS_REFUNDED;
+
+    fn cloth(cloth_id: &str, category_id: i64) -> OrderCloth {
+        OrderCloth {
+            cloth_id: Some(cloth_id.to_string()),
+            category_id: Some(category_id),
+            ..Default::default()
+        }
+    }
+
+    fn breakdown(prices: &[(&str, f64)]) -> PriceBreakdown {
+        PriceBreakdown {
+            items: prices
+                .iter()
+                .map(|(cloth_id, price)| ItemPrice {
+                    cloth_id: Some(cloth_id.to_string()),
+                    price: *price,
+                    ..Default::default()
+                })
+                .collect(),
+            total: prices.iter().map(|(_, price)| price).sum(),
+            ..Default::default()
+        }
+    }
+
+    fn user_coupon(uc_id: i64, coupon: Coupon, available_value: f64) -> UserCoupon {
+        UserCoupon {
+            uc_id: Some(uc_id),
+            available_value: Some(available_value),
+            uc_count: Some(1),
+            coupon: Some(Coupon {
+                stackable: true,
+                ..coupon
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn coupon(coupon_type: CouponType, usage_value: f64) -> Coupon {
+        Coupon {
+            coupon_type: Some(coupon_type),
+            coupon_title: Some("卡券".to_string()),
+            usage_value: Some(usage_value),
+            usage_limit: Some(100.),
+            valid_to: Some(utils::get_now() + chrono::Duration::days(1)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compute_stacked() {
+        let clothes = vec![cloth("a", 1), cloth("b", 2)];
+        let breakdown = breakdown(&[("a", 60.), ("b", 40.)]);
+        let mut coupons = vec![
+            // 满 50 减 10，只适用于品类 1
+            user_coupon(
+                1,
+                Coupon {
+                    min_spend: Some(50.),
+                    applicable_category: Some("1".to_string()),
+                    ..coupon(CouponType::SpendAndSaveCard, 10.)
+                },
+                0.,
+            ),
+            user_coupon(2, coupon(CouponType::StoredValueCard, 0.), 30.),
+        ];
+
+        let plan = CheckoutPlan::compute(&mut coupons, &clothes, &breakdown).unwrap();
+        assert_eq!(plan.coupon_usages[0].applied_amount, 10.);
+        assert_eq!(
+            plan.coupon_usages[0].allocations,
+            vec![CouponAllocation {
+                cloth_id: "a".to_string(),
+                amount: 10.
+            }]
+        );
+        assert_eq!(plan.coupon_usages[1].applied_amount, 30.);
+        assert_eq!(plan.cash_amount, 60.);
+        assert_eq!(plan.cost_amount, 90.);
+        assert_eq!(coupons[0].uc_count, Some(0));
+        assert_eq!(coupons[1].available_value, Some(0.));
+
+        // 不可叠加的卡券不能与其他类型同时使用
+        let mut coupons = vec![
+            UserCoupon {
+                coupon: Some(coupon(CouponType::DiscountCoupon, 80.)),
+                uc_count: Some(1),
+                ..Default::default()
+            },
+            user_coupon(2, coupon(CouponType::StoredValueCard, 0.), 30.),
+        ];
+        assert!(CheckoutPlan::compute(&mut coupons, &clothes, &breakdown).is_err());
+    }
+
+    #[test]
+    fn test_stored_value_applicability() {
+        let clothes = vec![cloth("a", 1), cloth("b", 2)];
+        let breakdown = breakdown(&[("a", 60.), ("b", 40.)]);
+        let mut coupons = vec![
+            user_coupon(1, coupon(CouponType::StoredValueCard, 0.), 20.),
+            // 只适用于品类 1，先于通用卡使用
+            user_coupon(
+                2,
+                Coupon {
+                    applicable_category: Some("1".to_string()),
+                    ..coupon(CouponType::StoredValueCard, 0.)
+                },
+                100.,
+            ),
+        ];
+
+        let plan = CheckoutPlan::compute(&mut coupons, &clothes, &breakdown).unwrap();
+        assert_eq!(plan.coupon_usages[0].coupon_id, 2);
+        assert_eq!(plan.coupon_usages[0].applied_amount, 60.);
+        assert_eq!(
+            plan.coupon_usages[0].allocations,
+            vec![CouponAllocation {
+                cloth_id: "a".to_string(),
+                amount: 60.
+            }]
+        );
+        assert_eq!(plan.coupon_usages[1].applied_amount, 20.);
+        assert_eq!(plan.cash_amount, 20.);
+        assert_eq!(coupons[1].available_value, Some(40.));
+    }
+
+    #[test]
+    fn test_best_plan() {
+        let clothes = vec![cloth("a", 1), cloth("b", 1)];
+        let breakdown = breakdown(&[("a", 50.), ("b", 50.)]);
+        let coupons = vec![
+            user_coupon(1, coupon(CouponType::DiscountCoupon, 90.), 0.),
+            user_coupon(2, coupon(CouponType::DiscountCoupon, 70.), 0.),
+            user_coupon(3, coupon(CouponType::SessionCard, 0.), 1.),
+            user_coupon(4, coupon(CouponType::StoredValueCard, 0.), 200.),
+        ];
+
+        // 次卡抵扣一件，剩余 50 元用 7 折券后由储值卡支付
+        let plan = CheckoutPlan::best(&coupons, &clothes, &breakdown);
+        assert_eq!(plan.cost_amount, 35.);
+        assert_eq!(plan.cash_amount, 0.);
+        let ids = plan
+            .coupon_usages
+            .iter()
+            .map(|usage| usage.coupon_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![3, 2, 4]);
+
+        let req = plan.payment_req(&Order::default(), PaymentMethod::Cash);
+        assert_eq!(
+            req.payment.unwrap().payment_method,
+            Some(PaymentMethod::StoredValueCard)
+        );
+    }
+
+    #[test]
+    fn test_compute_orders() {
+        let first = vec![cloth("a", 1)];
+        let second = vec![cloth("b", 1)];
+        let first_breakdown = breakdown(&[("a", 50.)]);
+        let second_breakdown = breakdown(&[("b", 30.)]);
+        let mut coupons = vec![
+            user_coupon(1, coupon(CouponType::SessionCard, 0.), 1.),
+            user_coupon(2, coupon(CouponType::DiscountCoupon, 70.), 0.),
+        ];
+
+        // 两个订单合并结算：次卡抵扣较便宜的一件，优惠券只用一次
+        let plan = CheckoutPlan::compute_orders(
+            &mut coupons,
+            &[(&first, &first_breakdown), (&second, &second_breakdown)],
+        )
+        .unwrap();
+        assert_eq!(plan.total_amount, 80.);
+        assert_eq!(plan.cost_amount, 35.);
+        let ids = plan
+            .coupon_usages
+            .iter()
+            .map(|usage| usage.coupon_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(coupons[0].available_value, Some(0.));
+    }
+
+    #[test]
+    fn test_refunded_card() {
+        let clothes = vec![cloth("a", 1)];
+        let breakdown = breakdown(&[("a", 50.)]);
+        // 退卡后通过退款恢复了余额，也不能再使用
+        let refunded = UserCoupon {
+            status: Some(UC_STATUS_REFUNDED.to_string()),
+            ..user_coupon(1, coupon(CouponType::StoredValueCard, 0.), 100.)
+        };
+
+        let plan = CheckoutPlan::best(&[refunded.clone()], &clothes, &breakdown);
+        assert!(plan.coupon_usages.is_empty());
+        assert_eq!(plan.cash_amount, 50.);
+
+        let mut coupons = vec![refunded];
+        assert!(CheckoutPlan::compute(&mut coupons, &clothes, &breakdown).is_err());
+    }
+}
@@ -123,6 +123,33 @@ impl Config {
         .await?;
         Ok(result)
     }
+
+    /// 按键名写入参数值，不存在时以系统内置参数新建
+    pub async fn set_value(
+        pool: &Pool<Sqlite>,
+        key: &str,
+        name: &str,
+        value: String,
+    ) -> Result<()> {
+        match Self::get_config_by_key(pool, key).await? {
+            Some(mut config) => {
+                config.config_value = Some(value);
+                config.update(pool).await?;
+            }
+            None => {
+                Config {
+                    config_name: Some(name.to_string()),
+                    config_key: Some(key.to_string()),
+                    config_value: Some(value),
+                    config_type: Some("Y".to_string()),
+                    ..Default::default()
+                }
+                .create(pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Config {
@@ -0,0 +1,586 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    FromRow, Pool, QueryBuilder, Sqlite,
+    types::chrono::{DateTime, FixedOffset, Utc},
+};
+use tauri::State;
+
+use crate::constants::{BarcodeType, CouponCodeStatus};
+use crate::db::coupons::{Coupon, CouponIdCount};
+use crate::db::{Curd, PageParams, PageResult, Validator};
+use crate::error::{Error, Result};
+use crate::printer::graphics;
+use crate::state::AppState;
+use crate::utils;
+use crate::utils::chrono_serde::{deserialize_date, serialize_date};
+
+/// 兑换码字符集，去掉了容易混淆的 0/O、1/I
+const CODE_CHARS: &[u8] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+const CODE_LEN: usize = 12;
+/// 单个批次最多生成的兑换码数量
+const MAX_BATCH_QUANTITY: i64 = 10000;
+/// 二维码每个模块的像素数
+const QR_MODULE_PIXELS: u32 = 8;
+
+/// 兑换码批次
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct CouponCodeBatch {
+    pub batch_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub coupon_id: Option<i64>,
+    pub batch_name: Option<String>,
+    pub quantity: i64,
+    /// 兑换截止日期（含当天），为空时以卡券有效期为准
+    #[serde(
+        deserialize_with = "deserialize_date",
+        serialize_with = "serialize_date"
+    )]
+    pub expire_time: Option<DateTime<FixedOffset>>,
+    pub create_time: Option<i64>,
+    pub remark: Option<String>,
+}
+
+/// 兑换码
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct CouponCode {
+    pub code_id: Option<i64>,
+    pub batch_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub code: Option<String>,
+    pub status: Option<CouponCodeStatus>,
+    /// 兑换的会员
+    pub user_id: Option<i64>,
+    /// 兑换得到的会员卡券
+    pub uc_id: Option<i64>,
+    pub redeem_time: Option<i64>,
+}
+
+/// 批次兑换统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct CouponCodeStats {
+    pub batch_id: i64,
+    pub coupon_id: i64,
+    pub batch_name: String,
+    pub quantity: i64,
+    pub redeemed: i64,
+    pub voided: i64,
+    #[sqlx(skip)]
+    pub unused: i64,
+    /// 兑换率（百分比），作废的兑换码不计入
+    #[sqlx(skip)]
+    pub redeem_rate: f64,
+    pub last_redeem_time: Option<i64>,
+}
+
+/// 兑换码二维码，image 为 PNG 图片
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CouponCodeQrCode {
+    pub code: String,
+    pub image: Vec<u8>,
+}
+
+pub(crate) fn gen_code(len: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..len)
+        .map(|_| CODE_CHARS[rng.gen_range(0..CODE_CHARS.len())] as char)
+        .collect()
+}
+
+/// 统一兑换码格式：去掉分隔符和空白，转为大写
+pub(crate) fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+impl Validator for CouponCodeBatch {
+    fn validate(&self) -> Result<()> {
+        if self.coupon_id.is_none() {
+            return Err(Error::bad_request("请选择卡券"));
+        }
+        if self
+            .batch_name
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .is_empty()
+        {
+            return Err(Error::bad_request("批次名称不能为空"));
+        }
+        if !(1..=MAX_BATCH_QUANTITY).contains(&self.quantity) {
+            return Err(Error::bad_request(format!(
+                "每批兑换码数量需在 1 到 {MAX_BATCH_QUANTITY} 之间"
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Curd for CouponCodeBatch {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM coupon_code_batches WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM coupon_code_batches WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM coupon_code_batches WHERE batch_id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM coupon_code_batches WHERE batch_id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY batch_id DESC");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(coupon_id) = &self.coupon_id {
+            builder.push(" AND coupon_id = ").push_bind(coupon_id);
+        }
+    }
+}
+
+impl Curd for CouponCode {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM coupon_codes WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM coupon_codes WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM coupon_codes WHERE code_id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM coupon_codes WHERE code_id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY code_id");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(batch_id) = &self.batch_id {
+            builder.push(" AND batch_id = ").push_bind(batch_id);
+        }
+
+        if let Some(status) = &self.status {
+            builder.push(" AND status = ").push_bind(status);
+        }
+
+        if let Some(user_id) = &self.user_id {
+            builder.push(" AND user_id = ").push_bind(user_id);
+        }
+
+        if let Some(code) = &self.code {
+            builder
+                .push(" AND code LIKE ")
+                .push_bind(format!("%{}%", normalize_code(code)));
+        }
+    }
+}
+
+impl CouponCodeBatch {
+    /// 获取本门店的批次
+    async fn get_own(pool: &Pool<Sqlite>, store_id: i64, batch_id: i64) -> Result<Self> {
+        Self::get_by_id(pool, batch_id)
+            .await?
+            .filter(|batch| batch.store_id == Some(store_id))
+            .ok_or(Error::not_found("兑换码批次不存在"))
+    }
+
+    /// 创建批次并生成兑换码，兑换码在门店内唯一
+    pub async fn generate(&self, pool: &Pool<Sqlite>) -> Result<Self> {
+        let coupon = Coupon::get_by_id(pool, self.coupon_id.unwrap_or_default())
+            .await?
+            .filter(|coupon| {
+                coupon.store_id == self.store_id && coupon.del_flag.as_deref() != Some("2")
+            })
+            .ok_or(Error::not_found("卡券不存在"))?;
+        if coupon
+            .customer_sale_total
+            .is_some_and(|total| total != -1 && self.quantity > total as i64)
+        {
+            return Err(Error::bad_request("兑换码数量超过卡券库存"));
+        }
+
+        let mut tr = pool.begin().await?;
+        let batch: Self = sqlx::query_as(
+            "INSERT INTO coupon_code_batches (store_id, coupon_id, batch_name, quantity, expire_time,
+                create_time, remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(self.coupon_id)
+        .bind(self.batch_name.as_deref().map(str::trim))
+        .bind(self.quantity)
+        .bind(self.expire_time)
+        .bind(utils::get_timestamp())
+        .bind(&self.remark)
+        .fetch_one(&mut *tr)
+        .await?;
+
+        let mut generated = 0;
+        while generated < self.quantity {
+            // 与已有兑换码重复时忽略并重新生成
+            let result = sqlx::query(
+                "INSERT OR IGNORE INTO coupon_codes (batch_id, store_id, code, status)
+                VALUES (?, ?, ?, ?)",
+            )
+            .bind(batch.batch_id)
+            .bind(self.store_id)
+            .bind(gen_code(CODE_LEN))
+            .bind(CouponCodeStatus::Unused)
+            .execute(&mut *tr)
+            .await?;
+            generated += result.rows_affected() as i64;
+        }
+        tr.commit().await?;
+        Ok(batch)
+    }
+
+    /// 作废批次中未兑换的兑换码，返回作废的数量
+    pub async fn void(pool: &Pool<Sqlite>, store_id: i64, batch_id: i64) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE coupon_codes SET status = ? WHERE batch_id = ? AND store_id = ? AND status = ?",
+        )
+        .bind(CouponCodeStatus::Voided)
+        .bind(batch_id)
+        .bind(store_id)
+        .bind(CouponCodeStatus::Unused)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// 按批次统计兑换情况
+    pub async fn stats(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        coupon_id: Option<i64>,
+    ) -> Result<Vec<CouponCodeStats>> {
+        let mut builder = QueryBuilder::new(
+            "SELECT b.batch_id, b.coupon_id, b.batch_name, b.quantity,
+                COALESCE(SUM(c.status = ",
+        );
+        builder
+            .push_bind(CouponCodeStatus::Redeemed)
+            .push("), 0) AS redeemed, COALESCE(SUM(c.status = ")
+            .push_bind(CouponCodeStatus::Voided)
+            .push(
+                "), 0) AS voided,
+                MAX(c.redeem_time) AS last_redeem_time
+            FROM coupon_code_batches b
+            LEFT JOIN coupon_codes c ON c.batch_id = b.batch_id
+            WHERE b.store_id = ",
+            )
+            .push_bind(store_id);
+        if let Some(coupon_id) = coupon_id {
+            builder.push(" AND b.coupon_id = ").push_bind(coupon_id);
+        }
+        builder.push(" GROUP BY b.batch_id ORDER BY b.batch_id DESC");
+
+        let mut stats: Vec<CouponCodeStats> = builder.build_query_as().fetch_all(pool).await?;
+        for item in &mut stats {
+            item.fill();
+        }
+        Ok(stats)
+    }
+}
+
+impl CouponCodeStats {
+    fn fill(&mut self) {
+        self.unused = self.quantity - self.redeemed - self.voided;
+        let issued = self.quantity - self.voided;
+        if issued > 0 {
+            self.redeem_rate = (self.redeemed as f64 * 10000. / issued as f64).round() / 100.;
+        }
+    }
+}
+
+impl CouponCode {
+    async fn list_by_batch(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        batch_id: i64,
+        status: Option<CouponCodeStatus>,
+    ) -> Result<Vec<Self>> {
+        Self {
+            store_id: Some(store_id),
+            batch_id: Some(batch_id),
+            status,
+            ..Default::default()
+        }
+        .get_all(pool)
+        .await
+    }
+
+    async fn find_by_code(pool: &Pool<Sqlite>, store_id: i64, code: &str) -> Result<Option<Self>> {
+        let result = sqlx::query_as("SELECT * FROM coupon_codes WHERE store_id = ? AND code = ?")
+            .bind(store_id)
+            .bind(code)
+            .fetch_optional(pool)
+            .await?;
+        Ok(result)
+    }
+
+    /// 会员兑换卡券，每个兑换码只能兑换一次，返回会员卡券ID
+    pub async fn redeem(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        code: &str,
+    ) -> Result<i64> {
+        let member: Option<i64> = sqlx::query_scalar(
+            "SELECT user_id FROM users WHERE del_flag = '0' AND store_id = ? AND user_id = ?",
+        )
+        .bind(store_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+        if member.is_none() {
+            return Err(Error::not_found("会员不存在"));
+        }
+
+        let code = normalize_code(code);
+        let record = Self::find_by_code(pool, store_id, &code)
+            .await?
+            .ok_or(Error::not_found("兑换码不存在"))?;
+        match record.status {
+            Some(CouponCodeStatus::Redeemed) => return Err(Error::bad_request("兑换码已被使用")),
+            Some(CouponCodeStatus::Voided) => return Err(Error::bad_request("兑换码已作废")),
+            _ => {}
+        }
+
+        let batch =
+            CouponCodeBatch::get_own(pool, store_id, record.batch_id.unwrap_or_default()).await?;
+        let today = utils::get_now().date_naive();
+        if batch
+            .expire_time
+            .is_some_and(|expire| today > expire.date_naive())
+        {
+            return Err(Error::bad_request("兑换码已过兑换截止日期"));
+        }
+        let coupon_id = batch.coupon_id.unwrap_or_default();
+        let coupon = Coupon::get_by_id(pool, coupon_id)
+            .await?
+            .filter(|coupon| coupon.del_flag.as_deref() != Some("2"))
+            .ok_or(Error::not_found("兑换的卡券不存在或已删除"))?;
+        if coupon
+            .valid_to
+            .is_some_and(|valid_to| today > valid_to.date_naive())
+        {
+            return Err(Error::bad_request("兑换的卡券已过期"));
+        }
+
+        let mut tr = pool.begin().await?;
+        // 先占用兑换码，同一兑换码并发兑换时只有一个能成功
+        let result = sqlx::query(
+            "UPDATE coupon_codes SET status = ?, user_id = ?, redeem_time = ?
+            WHERE code_id = ? AND status = ?",
+        )
+        .bind(CouponCodeStatus::Redeemed)
+        .bind(user_id)
+        .bind(utils::get_timestamp())
+        .bind(record.code_id)
+        .bind(CouponCodeStatus::Unused)
+        .execute(&mut *tr)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::bad_request("兑换码已被使用"));
+        }
+
+        let info = CouponIdCount {
+            coupon_id,
+            count: 1,
+        };
+        let uc_id = Coupon::issue(pool, &mut tr, user_id, &info).await?;
+        sqlx::query("UPDATE coupon_codes SET uc_id = ? WHERE code_id = ?")
+            .bind(uc_id)
+            .bind(record.code_id)
+            .execute(&mut *tr)
+            .await?;
+        tr.commit().await?;
+        Ok(uc_id)
+    }
+
+    fn status_label(&self) -> &'static str {
+        match self.status.unwrap_or_default() {
+            CouponCodeStatus::Unused => "未兑换",
+            CouponCodeStatus::Redeemed => "已兑换",
+            CouponCodeStatus::Voided => "已作废",
+        }
+    }
+
+    /// 导出为 CSV，带 BOM 以便 Excel 正确识别中文
+    fn to_csv(codes: &[Self]) -> String {
+        let offset = utils::get_now().timezone();
+        let mut csv = String::from("\u{feff}兑换码,状态,会员ID,会员卡券ID,兑换时间\n");
+        for code in codes {
+            let redeem_time = code
+                .redeem_time
+                .and_then(DateTime::<Utc>::from_timestamp_millis)
+                .map(|time| {
+                    time.with_timezone(&offset)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                code.code.as_deref().unwrap_or_default(),
+                code.status_label(),
+                code.user_id.map(|id| id.to_string()).unwrap_or_default(),
+                code.uc_id.map(|id| id.to_string()).unwrap_or_default(),
+                redeem_time,
+            ));
+        }
+        csv
+    }
+}
+
+#[tauri::command]
+pub async fn get_coupon_code_batches(
+    state: State<'_, AppState>,
+    page_params: PageParams,
+    mut batch: CouponCodeBatch,
+) -> Result<PageResult<CouponCodeBatch>> {
+    batch.store_id = Some(utils::get_user_id(&state).await?);
+    batch.get_list(&state.pool, page_params).await
+}
+
+#[tauri::command]
+pub async fn generate_coupon_codes(
+    state: State<'_, AppState>,
+    mut batch: CouponCodeBatch,
+) -> Result<CouponCodeBatch> {
+    batch.validate()?;
+    batch.store_id = Some(utils::get_user_id(&state).await?);
+    batch.generate(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn void_coupon_codes(state: State<'_, AppState>, batch_id: i64) -> Result<u64> {
+    let store_id = utils::get_user_id(&state).await?;
+    CouponCodeBatch::void(&state.pool, store_id, batch_id).await
+}
+
+#[tauri::command]
+pub async fn get_coupon_codes(
+    state: State<'_, AppState>,
+    page_params: PageParams,
+    mut code: CouponCode,
+) -> Result<PageResult<CouponCode>> {
+    code.store_id = Some(utils::get_user_id(&state).await?);
+    code.get_list(&state.pool, page_params).await
+}
+
+/// 导出批次的兑换码为 CSV 文本，由前端保存为文件
+#[tauri::command]
+pub async fn export_coupon_codes_csv(state: State<'_, AppState>, batch_id: i64) -> Result<String> {
+    let store_id = utils::get_user_id(&state).await?;
+    CouponCodeBatch::get_own(&state.pool, store_id, batch_id).await?;
+    let codes = CouponCode::list_by_batch(&state.pool, store_id, batch_id, None).await?;
+    Ok(CouponCode::to_csv(&codes))
+}
+
+/// 生成批次中未兑换兑换码的二维码，用于打印
+#[tauri::command]
+pub async fn get_coupon_code_qrcodes(
+    state: State<'_, AppState>,
+    batch_id: i64,
+) -> Result<Vec<CouponCodeQrCode>> {
+    let store_id = utils::get_user_id(&state).await?;
+    CouponCodeBatch::get_own(&state.pool, store_id, batch_id).await?;
+    let codes = CouponCode::list_by_batch(
+        &state.pool,
+        store_id,
+        batch_id,
+        Some(CouponCodeStatus::Unused),
+    )
+    .await?;
+
+    codes
+        .into_iter()
+        .map(|code| {
+            let code = code.code.unwrap_or_default();
+            let matrix = graphics::encode_barcode(BarcodeType::QrCode, &code)?;
+            let image = graphics::encode_png(&matrix.to_image(QR_MODULE_PIXELS, 0))?;
+            Ok(CouponCodeQrCode { code, image })
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub async fn redeem_coupon_code(
+    state: State<'_, AppState>,
+    user_id: i64,
+    code: String,
+) -> Result<i64> {
+    let store_id = utils::get_user_id(&state).await?;
+    CouponCode::redeem(&state.pool, store_id, user_id, &code).await
+}
+
+#[tauri::command]
+pub async fn get_coupon_code_stats(
+    state: State<'_, AppState>,
+    coupon_id: Option<i64>,
+) -> Result<Vec<CouponCodeStats>> {
+    let store_id = utils::get_user_id(&state).await?;
+    CouponCodeBatch::stats(&state.pool, store_id, coupon_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_format() {
+        let code = gen_code(CODE_LEN);
+        assert_eq!(code.len(), CODE_LEN);
+        assert!(code.bytes().all(|c| CODE_CHARS.contains(&c)));
+        assert_eq!(normalize_code(" abcd-2345 efgh "), "ABCD2345EFGH");
+    }
+
+    #[test]
+    fn test_stats_fill() {
+        let mut stats = CouponCodeStats {
+            quantity: 10,
+            redeemed: 3,
+            voided: 2,
+            ..Default::default()
+        };
+        stats.fill();
+        assert_eq!(stats.unused, 5);
+        assert_eq!(stats.redeem_rate, 37.5);
+    }
+
+    #[tokio::test]
+    async fn test_stats_and_redeem_scope() {
+        use sqlx::{Executor, SqlitePool};
+
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        pool.execute(
+            r#"
+            CREATE TABLE users (user_id INTEGER PRIMARY KEY, store_id INTEGER, del_flag TEXT);
+            CREATE TABLE coupon_code_batches (
+                batch_id INTEGER PRIMARY KEY, store_id INTEGER, coupon_id INTEGER,
+                batch_name TEXT, quantity INTEGER
+            );
+            CREATE TABLE coupon_codes (
+                code_id INTEGER PRIMARY KEY, batch_id INTEGER, status TEXT, redeem_time INTEGER
+            );
+            INSERT INTO users VALUES (1, 1, '0'), (2, 2, '0');
+            INSERT INTO coupon_code_batches VALUES (1, 1, 1, '开业', 4);
+            INSERT INTO coupon_codes VALUES
+                (1, 1, 'Redeemed', 1735689600), (2, 1, 'Voided', NULL),
+                (3, 1, 'Unused', NULL), (4, 1, 'Unused', NULL);
+            "#,
+        )
+        .await
+        .unwrap();
+
+        let stats = CouponCodeBatch::stats(&pool, 1, None).await.unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!((stats[0].redeemed, stats[0].voided), (1, 1));
+        assert_eq!(stats[0].unused, 2);
+
+        // 不能为其他门店的会员兑换
+        assert!(CouponCode::redeem(&pool, 1, 2, "ABCD").await.is_err());
+    }
+}
@@ -9,6 +9,7 @@ use tauri::State;
 
 use crate::constants::{CouponType, PaymentMethod, PaymentOrderType, PaymentStatus};
 use crate::db::coupon_orders::CouponOrder;
+use crate::db::order_clothes::OrderCloth;
 use crate::db::payments::Payment;
 use crate::db::user_coupons::UserCoupon;
 use crate::db::{Curd, PageParams, PageResult, Validator};
@@ -18,7 +19,9 @@ use crate::utils;
 use crate::utils::chrono_serde::{deserialize_date, serialize_date};
 
 use super::payments::PaymentMethodDetail;
-use super::user::User;
+
+/// 自动延期字典值：是
+pub const AUTO_DELAY_YES: &str = "0";
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -54,6 +57,15 @@ pub struct Coupon {
     pub status: Option<String>,           // Coupon status (e.g., '0' for active, '1' for inactive)
     pub remark: Option<String>,           // Additional remarks
     pub desc: Option<String>,             // Additional description
+    /// 是否可与其他类型的卡券叠加使用
+    pub stackable: bool,
+}
+
+fn parse_ids(ids: Option<&str>) -> Vec<i64> {
+    ids.unwrap_or_default()
+        .split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect()
 }
 
 impl FromRow<'_, SqliteRow> for Coupon {
@@ -78,6 +90,7 @@ impl FromRow<'_, SqliteRow> for Coupon {
             applicable_category: row.try_get("applicable_category").unwrap_or_default(),
             applicable_style: row.try_get("applicable_style").unwrap_or_default(),
             applicable_cloths: row.try_get("applicable_cloths").unwrap_or_default(),
+            stackable: row.try_get("stackable").unwrap_or_default(),
             status: row.try_get("status").unwrap_or_default(),
             remark: row.try_get("remark").unwrap_or_default(),
             desc: row.try_get("desc").unwrap_or_default(),
@@ -211,8 +224,8 @@ impl Coupon {
         let query = r#"
         INSERT INTO coupons (store_id, coupon_number, coupon_type, coupon_title, desc, coupon_value, min_spend, customer_invalid,
                             customer_sale_total, customer_sale_count, valid_from, valid_to, auto_delay, usage_value,
-                            usage_limit, del_flag, applicable_category, applicable_style, applicable_cloths, status, remark)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *
+                            usage_limit, del_flag, applicable_category, applicable_style, applicable_cloths, stackable, status, remark)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *
     "#;
 
         tracing::debug!("coupon add: {:?}", self);
@@ -236,6 +249,7 @@ impl Coupon {
             .bind(&self.applicable_category)
             .bind(&self.applicable_style)
             .bind(&self.applicable_cloths)
+            .bind(self.stackable)
             .bind(&self.status)
             .bind(&self.remark)
             .fetch_one(&mut **tr)
@@ -286,6 +300,7 @@ impl Coupon {
             applicable_category = ?,
             applicable_style = ?,
             applicable_cloths = ?,
+            stackable = ?,
             status = ?,
             remark = ?,
             desc = ?
@@ -310,6 +325,7 @@ impl Coupon {
             .bind(&self.applicable_category)
             .bind(&self.applicable_style)
             .bind(&self.applicable_cloths)
+            .bind(self.stackable)
             .bind(&self.status)
             .bind(&self.remark)
             .bind(&self.desc)
@@ -319,69 +335,31 @@ impl Coupon {
 
         Ok(result.rows_affected() > 0)
     }
+}
 
-    // Add this new method to check expiring coupons
-    #[allow(dead_code)]
-    pub async fn check_expiring_coupons(pool: &Pool<Sqlite>) -> Result<()> {
-        let two_weeks_later = Utc::now() + chrono::Duration::weeks(2);
-
-        // Query expiring coupons of type '000'
-        let expiring_coupons = sqlx::query_as::<_, Self>(
-            r#"
-            SELECT c.* 
-            FROM coupons c
-            WHERE c.coupon_type = '000'
-            AND c.status = '0'
-            AND c.del_flag = '0'
-            AND c.valid_to <= ?
-            "#,
-        )
-        .bind(two_weeks_later)
-        .fetch_all(pool)
-        .await?;
-
-        for coupon in expiring_coupons {
-            // Query users with valid balance
-            let users_with_balance: Vec<User> = sqlx::query_as(
-                r#"
-                SELECT uc.user_id, uc.available_value as balance, u.phone_number, u.nick_name
-                FROM user_coupons uc
-                JOIN users u ON u.user_id = uc.user_id
-                WHERE uc.coupon_id = ?
-                AND uc.status = '0'
-                AND uc.available_value > 0
-                "#,
-            )
-            .bind(coupon.coupon_id)
-            .fetch_all(pool)
-            .await?;
-
-            // Send notifications to users
-            for user in users_with_balance {
-                let message = format!(
-                    "尊敬的{}，您的{}卡券（余额：{}元）即将于{}到期，请及时使用。",
-                    user.nick_name.unwrap_or_default(),
-                    coupon.coupon_title.as_deref().unwrap_or_default(),
-                    user.balance,
-                    coupon.valid_to.unwrap().format("%Y-%m-%d")
-                );
-
-                tracing::debug!("Sending message to user: {}", message);
-                // Spawn async task to send message
-                // if let Some(tel) = user.phonenumber  {
-
-                //     tokio::spawn(async move {
-                //         utils::send_sms(tel, message).await;
-                //     });
-                // }
-            }
-        }
+impl Coupon {
+    /// 是否限制了适用范围
+    pub fn is_restricted(&self) -> bool {
+        [
+            &self.applicable_category,
+            &self.applicable_style,
+            &self.applicable_cloths,
+        ]
+        .iter()
+        .any(|ids| !parse_ids(ids.as_deref()).is_empty())
+    }
 
-        Ok(())
+    /// 卡券是否适用于该衣物，适用品类、款式、衣物均为逗号分隔的ID，为空时不限制
+    pub fn applicable_to(&self, cloth: &OrderCloth) -> bool {
+        let matches = |ids: Option<&str>, id: Option<i64>| {
+            let ids = parse_ids(ids);
+            ids.is_empty() || id.is_some_and(|id| ids.contains(&id))
+        };
+        matches(self.applicable_category.as_deref(), cloth.category_id)
+            && matches(self.applicable_style.as_deref(), cloth.style_id)
+            && matches(self.applicable_cloths.as_deref(), cloth.clothing_id)
     }
-}
 
-impl Coupon {
     pub async fn insert(&mut self, tr: &mut Transaction<'_, Sqlite>) -> Result<Self> {
         // gen number
         let number = utils::gen_code(self.coupon_title.clone().unwrap());
@@ -466,6 +444,7 @@ impl Coupon {
         req: &CouponBuyReq,
         coupon: &Coupon,
         info: &CouponIdCount,
+        paid: bool,
     ) -> Result<i64> {
         let now = utils::get_now();
         let mut user_coupon = UserCoupon {
@@ -482,6 +461,8 @@ impl Coupon {
             status: Some("0".to_string()),
             remark: coupon.remark.clone(),
             coupon: None,
+            expire_time: None,
+            bonus_value: 0.,
         };
 
         // change available_value by coupon_type
@@ -491,6 +472,18 @@ impl Coupon {
             user_coupon.available_value = coupon.usage_value;
         } else if coupon.coupon_type == Some(CouponType::DiscountCard) {
             user_coupon.available_value = Some(coupon.coupon_value.unwrap() * info.count as f64);
+        } else if coupon.coupon_type == Some(CouponType::StoredValueCard) {
+            // 储值卡面值超出售价的部分为赠送余额，赠送的储值卡全部为赠送余额
+            let principal = if paid {
+                coupon.coupon_value.unwrap_or_default() * info.count as f64
+            } else {
+                0.
+            };
+            user_coupon.bonus_value =
+                (user_coupon.available_value.unwrap_or_default() - principal).max(0.);
+        } else if coupon.coupon_type == Some(CouponType::SessionCard) && !paid {
+            // 赠送的次卡全部为赠送次数，退卡时不可退
+            user_coupon.bonus_value = user_coupon.available_value.unwrap_or_default();
         }
 
         user_coupon.validate()?;
@@ -500,6 +493,26 @@ impl Coupon {
         Ok(result.uc_id.unwrap())
     }
 
+    /// 在已有事务中向会员发放卡券，返回会员卡券ID
+    pub async fn issue(
+        pool: &Pool<Sqlite>,
+        tr: &mut Transaction<'_, Sqlite>,
+        user_id: i64,
+        info: &CouponIdCount,
+    ) -> Result<i64> {
+        let mut coupon = Self::get_by_id(pool, info.coupon_id)
+            .await?
+            .ok_or(Error::not_found("Coupon not found"))?;
+
+        coupon.check_coupon(tr, info).await?;
+
+        let req = CouponBuyReq {
+            user_id,
+            ..Default::default()
+        };
+        Self::create_user_coupon(tr, &req, &coupon, info, false).await
+    }
+
     pub async fn gift(pool: &Pool<Sqlite>, coupon_buy_req: CouponBuyReq) -> Result<()> {
         let mut tr = pool.begin().await?;
         let mut coupon_names = vec![];
@@ -512,7 +525,7 @@ impl Coupon {
             // check coupon status and reduce available_value if necessary
             coupon.check_coupon(&mut tr, &info).await?;
 
-            Self::create_user_coupon(&mut tr, &coupon_buy_req, &coupon, &info).await?;
+            Self::create_user_coupon(&mut tr, &coupon_buy_req, &coupon, &info, false).await?;
 
             // concat coupon name
             coupon_names.push(format!("{} x {}", coupon.coupon_title.unwrap(), info.count));
@@ -549,7 +562,8 @@ impl Coupon {
             // check coupon status and reduce available_value if necessary
             coupon.check_coupon(&mut tr, &info).await?;
 
-            let uc_id = Self::create_user_coupon(&mut tr, &coupon_buy_req, &coupon, &info).await?;
+            let uc_id =
+                Self::create_user_coupon(&mut tr, &coupon_buy_req, &coupon, &info, true).await?;
 
             // concat coupon name
             uc_ids.push(uc_id);
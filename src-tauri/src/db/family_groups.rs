@@ -0,0 +1,635 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite};
+use tauri::State;
+
+use crate::db::user::User;
+use crate::db::user_coupons::UserCoupon;
+use crate::db::{Curd, PageParams, PageResult, Validator};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+/// 家庭组
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct FamilyGroup {
+    pub group_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub group_name: Option<String>,
+    /// 户主
+    pub owner_id: Option<i64>,
+    pub create_time: Option<i64>,
+    pub update_time: Option<i64>,
+    pub remark: Option<String>,
+    #[sqlx(skip)]
+    pub members: Vec<FamilyMember>,
+    /// 共享给家庭成员使用的卡券
+    #[sqlx(skip)]
+    pub shared_coupons: Vec<UserCoupon>,
+}
+
+/// 家庭成员
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct FamilyMember {
+    pub group_id: i64,
+    pub user_id: i64,
+    /// 是否可使用共享卡券支付
+    pub can_pay: bool,
+    pub join_time: Option<i64>,
+    #[sqlx(default)]
+    pub nick_name: Option<String>,
+    #[sqlx(default)]
+    pub phonenumber: Option<String>,
+}
+
+impl Validator for FamilyGroup {
+    fn validate(&self) -> Result<()> {
+        if self
+            .group_name
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .is_empty()
+        {
+            return Err(Error::bad_request("家庭组名称不能为空"));
+        }
+        if self.owner_id.is_none() {
+            return Err(Error::bad_request("请选择户主"));
+        }
+        Ok(())
+    }
+}
+
+impl Curd for FamilyGroup {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM family_groups WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM family_groups WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM family_groups WHERE group_id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM family_groups WHERE group_id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY group_id DESC");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(group_name) = &self.group_name {
+            builder
+                .push(" AND group_name LIKE ")
+                .push_bind(format!("%{}%", group_name));
+        }
+    }
+}
+
+/// 校验会员属于当前门店
+async fn check_user(pool: &Pool<Sqlite>, store_id: i64, user_id: i64) -> Result<User> {
+    User::get_by_id(pool, user_id)
+        .await?
+        .filter(|user| user.store_id == Some(store_id))
+        .ok_or(Error::not_found("会员不存在"))
+}
+
+impl FamilyGroup {
+    async fn get_own(pool: &Pool<Sqlite>, store_id: i64, group_id: i64) -> Result<Self> {
+        Self::get_by_id(pool, group_id)
+            .await?
+            .filter(|group| group.store_id == Some(store_id))
+            .ok_or(Error::not_found("家庭组不存在"))
+    }
+
+    /// 创建家庭组，户主自动加入并可使用共享卡券
+    pub async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self> {
+        let store_id = self.store_id.unwrap_or_default();
+        let owner_id = self.owner_id.unwrap_or_default();
+        check_user(pool, store_id, owner_id).await?;
+        if FamilyMember::find_by_user_id(pool, store_id, owner_id)
+            .await?
+            .is_some()
+        {
+            return Err(Error::bad_request("该会员已加入其他家庭组"));
+        }
+
+        let now = utils::get_timestamp();
+        let mut tr = pool.begin().await?;
+        let group: Self = sqlx::query_as(
+            "INSERT INTO family_groups (store_id, group_name, owner_id, create_time, update_time,
+                remark)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(store_id)
+        .bind(self.group_name.as_deref().map(str::trim))
+        .bind(owner_id)
+        .bind(now)
+        .bind(now)
+        .bind(&self.remark)
+        .fetch_one(&mut *tr)
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO family_members (group_id, user_id, can_pay, join_time) VALUES (?, ?, 1, ?)",
+        )
+        .bind(group.group_id)
+        .bind(owner_id)
+        .bind(now)
+        .execute(&mut *tr)
+        .await?;
+        tr.commit().await?;
+        Ok(group)
+    }
+
+    pub async fn update(&self, pool: &Pool<Sqlite>) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE family_groups SET group_name = ?, update_time = ?, remark = ?
+            WHERE group_id = ? AND store_id = ?",
+        )
+        .bind(self.group_name.as_deref().map(str::trim))
+        .bind(utils::get_timestamp())
+        .bind(&self.remark)
+        .bind(self.group_id)
+        .bind(self.store_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 解散家庭组，成员及共享卡券一并移除
+    pub async fn dissolve(pool: &Pool<Sqlite>, store_id: i64, group_id: i64) -> Result<()> {
+        Self::get_own(pool, store_id, group_id).await?;
+        let mut tr = pool.begin().await?;
+        for sql in [
+            "DELETE FROM family_shared_coupons WHERE group_id = ?",
+            "DELETE FROM family_members WHERE group_id = ?",
+            "DELETE FROM family_groups WHERE group_id = ?",
+        ] {
+            sqlx::query(sql).bind(group_id).execute(&mut *tr).await?;
+        }
+        tr.commit().await?;
+        Ok(())
+    }
+
+    /// 查询家庭组及其成员、共享卡券
+    pub async fn get_detail(pool: &Pool<Sqlite>, store_id: i64, group_id: i64) -> Result<Self> {
+        let mut group = Self::get_own(pool, store_id, group_id).await?;
+        group.members = sqlx::query_as(
+            "SELECT m.*, u.nick_name, u.phonenumber FROM family_members m
+            LEFT JOIN users u ON m.user_id = u.user_id
+            WHERE m.group_id = ?
+            ORDER BY m.join_time",
+        )
+        .bind(group_id)
+        .fetch_all(pool)
+        .await?;
+
+        let uc_ids: Vec<i64> =
+            sqlx::query_scalar("SELECT uc_id FROM family_shared_coupons WHERE group_id = ?")
+                .bind(group_id)
+                .fetch_all(pool)
+                .await?;
+        if !uc_ids.is_empty() {
+            group.shared_coupons = UserCoupon::find_by_uc_ids(pool, store_id, &uc_ids).await?;
+        }
+        Ok(group)
+    }
+
+    /// 共享或取消共享成员的卡券
+    pub async fn share_coupon(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        group_id: i64,
+        uc_id: i64,
+        shared: bool,
+    ) -> Result<()> {
+        Self::get_own(pool, store_id, group_id).await?;
+        if !shared {
+            sqlx::query("DELETE FROM family_shared_coupons WHERE group_id = ? AND uc_id = ?")
+                .bind(group_id)
+                .bind(uc_id)
+                .execute(pool)
+                .await?;
+            return Ok(());
+        }
+
+        let coupon = UserCoupon::find_by_uc_ids(pool, store_id, &[uc_id])
+            .await?
+            .pop()
+            .ok_or(Error::not_found("卡券不存在"))?;
+        let owner =
+            FamilyMember::find_by_user_id(pool, store_id, coupon.user_id.unwrap_or_default())
+                .await?;
+        if owner.is_none_or(|member| member.group_id != group_id) {
+            return Err(Error::bad_request("只能共享家庭成员的卡券"));
+        }
+        sqlx::query(
+            "INSERT OR IGNORE INTO family_shared_coupons (group_id, uc_id, create_time)
+            VALUES (?, ?, ?)",
+        )
+        .bind(group_id)
+        .bind(uc_id)
+        .bind(utils::get_timestamp())
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 会员可使用的共享卡券，不含会员自己的卡券
+    pub async fn shared_coupons(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: i64,
+    ) -> Result<Vec<UserCoupon>> {
+        let uc_ids = Self::shared_uc_ids(pool, user_id).await?;
+        if uc_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let coupons = UserCoupon::find_by_uc_ids(pool, store_id, &uc_ids).await?;
+        Ok(coupons
+            .into_iter()
+            .filter(|uc| uc.user_id != Some(user_id))
+            .collect())
+    }
+
+    async fn shared_uc_ids(pool: &Pool<Sqlite>, user_id: i64) -> Result<Vec<i64>> {
+        let result = sqlx::query_scalar(
+            "SELECT sc.uc_id FROM family_shared_coupons sc
+            INNER JOIN family_members m ON m.group_id = sc.group_id
+            WHERE m.user_id = ? AND m.can_pay = 1",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 校验会员可以使用这些卡券：自己的卡券，或家庭共享且有支付权限的卡券
+    pub async fn check_coupon_access(
+        pool: &Pool<Sqlite>,
+        user_id: i64,
+        coupons: &[UserCoupon],
+    ) -> Result<()> {
+        let others = coupons
+            .iter()
+            .filter(|uc| uc.user_id != Some(user_id))
+            .collect::<Vec<_>>();
+        if others.is_empty() {
+            return Ok(());
+        }
+
+        let shared = Self::shared_uc_ids(pool, user_id).await?;
+        if let Some(uc) = others
+            .iter()
+            .find(|uc| !uc.uc_id.is_some_and(|uc_id| shared.contains(&uc_id)))
+        {
+            let title = uc
+                .coupon
+                .as_ref()
+                .and_then(|c| c.coupon_title.as_deref())
+                .unwrap_or_default();
+            return Err(Error::bad_request(format!(
+                "卡券 <{title}> 不属于该会员，也未共享给该会员使用"
+            )));
+        }
+        Ok(())
+    }
+
+    /// 两个会员是否在同一家庭组
+    pub async fn same_family(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        other_id: i64,
+    ) -> Result<bool> {
+        let a = FamilyMember::find_by_user_id(pool, store_id, user_id).await?;
+        let b = FamilyMember::find_by_user_id(pool, store_id, other_id).await?;
+        Ok(matches!((a, b), (Some(a), Some(b)) if a.group_id == b.group_id))
+    }
+}
+
+impl FamilyMember {
+    /// 查询会员在本门店所在的家庭组成员记录
+    pub async fn find_by_user_id(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: i64,
+    ) -> Result<Option<Self>> {
+        let result = sqlx::query_as(
+            "SELECT m.* FROM family_members m
+            INNER JOIN family_groups g ON g.group_id = m.group_id
+            WHERE g.store_id = ? AND m.user_id = ?",
+        )
+        .bind(store_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 添加成员或修改成员的支付权限
+    pub async fn save(&self, pool: &Pool<Sqlite>, store_id: i64) -> Result<()> {
+        FamilyGroup::get_own(pool, store_id, self.group_id).await?;
+        check_user(pool, store_id, self.user_id).await?;
+        if let Some(member) = Self::find_by_user_id(pool, store_id, self.user_id).await? {
+            if member.group_id != self.group_id {
+                return Err(Error::bad_request("该会员已加入其他家庭组"));
+            }
+        }
+
+        sqlx::query(
+            "INSERT INTO family_members (group_id, user_id, can_pay, join_time)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(group_id, user_id) DO UPDATE SET can_pay = excluded.can_pay",
+        )
+        .bind(self.group_id)
+        .bind(self.user_id)
+        .bind(self.can_pay)
+        .bind(utils::get_timestamp())
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 移除成员，其共享的卡券同时取消共享；户主不能移除
+    pub async fn remove(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        group_id: i64,
+        user_id: i64,
+    ) -> Result<()> {
+        let group = FamilyGroup::get_own(pool, store_id, group_id).await?;
+        if group.owner_id == Some(user_id) {
+            return Err(Error::bad_request("户主不能移出家庭组，请解散家庭组"));
+        }
+
+        let mut tr = pool.begin().await?;
+        sqlx::query(
+            "DELETE FROM family_shared_coupons WHERE group_id = ?
+                AND uc_id IN (SELECT uc_id FROM user_coupons WHERE user_id = ?)",
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await?;
+        sqlx::query("DELETE FROM family_members WHERE group_id = ? AND user_id = ?")
+            .bind(group_id)
+            .bind(user_id)
+            .execute(&mut *tr)
+            .await?;
+        tr.commit().await?;
+        Ok(())
+    }
+}
+
+#[tauri::command]
+pub async fn get_family_groups(
+    state: State<'_, AppState>,
+    page_params: PageParams,
+    mut group: FamilyGroup,
+) -> Result<PageResult<FamilyGroup>> {
+    group.store_id = Some(utils::get_user_id(&state).await?);
+    group.get_list(&state.pool, page_params).await
+}
+
+#[tauri::command]
+pub async fn get_family_group(state: State<'_, AppState>, group_id: i64) -> Result<FamilyGroup> {
+    let store_id = utils::get_user_id(&state).await?;
+    FamilyGroup::get_detail(&state.pool, store_id, group_id).await
+}
+
+/// 查询会员所在的家庭组
+#[tauri::command]
+pub async fn get_family_group_by_user(
+    state: State<'_, AppState>,
+    user_id: i64,
+) -> Result<Option<FamilyGroup>> {
+    let store_id = utils::get_user_id(&state).await?;
+    match FamilyMember::find_by_user_id(&state.pool, store_id, user_id).await? {
+        Some(member) => Ok(Some(
+            FamilyGroup::get_detail(&state.pool, store_id, member.group_id).await?,
+        )),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+pub async fn create_family_group(
+    state: State<'_, AppState>,
+    mut group: FamilyGroup,
+) -> Result<FamilyGroup> {
+    group.validate()?;
+    group.store_id = Some(utils::get_user_id(&state).await?);
+    group.create(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn update_family_group(
+    state: State<'_, AppState>,
+    mut group: FamilyGroup,
+) -> Result<bool> {
+    group.validate()?;
+    group.store_id = Some(utils::get_user_id(&state).await?);
+    group.update(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn dissolve_family_group(state: State<'_, AppState>, group_id: i64) -> Result<()> {
+    let store_id = utils::get_user_id(&state).await?;
+    FamilyGroup::dissolve(&state.pool, store_id, group_id).await
+}
+
+#[tauri::command]
+pub async fn save_family_member(state: State<'_, AppState>, member: FamilyMember) -> Result<()> {
+    let store_id = utils::get_user_id(&state).await?;
+    member.save(&state.pool, store_id).await
+}
+
+#[tauri::command]
+pub async fn remove_family_member(
+    state: State<'_, AppState>,
+    group_id: i64,
+    user_id: i64,
+) -> Result<()> {
+    let store_id = utils::get_user_id(&state).await?;
+    FamilyMember::remove(&state.pool, store_id, group_id, user_id).await
+}
+
+#[tauri::command]
+pub async fn share_family_coupon(
+    state: State<'_, AppState>,
+    group_id: i64,
+    uc_id: i64,
+    shared: bool,
+) -> Result<()> {
+    let store_id = utils::get_user_id(&state).await?;
+    FamilyGroup::share_coupon(&state.pool, store_id, group_id, uc_id, shared).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::stored_value::{StoredValueTransfer, TransferReq};
+    use sqlx::{Executor, SqlitePool};
+
+    async fn setup() -> Pool<Sqlite> {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        pool.execute(
+            r#"
+            CREATE TABLE family_groups (group_id INTEGER PRIMARY KEY, store_id INTEGER,
+                group_name TEXT, owner_id INTEGER, create_time INTEGER, update_time INTEGER,
+                remark TEXT);
+            CREATE TABLE family_members (group_id INTEGER, user_id INTEGER UNIQUE,
+                can_pay BOOLEAN, join_time INTEGER, PRIMARY KEY (group_id, user_id));
+            CREATE TABLE family_shared_coupons (group_id INTEGER, uc_id INTEGER UNIQUE,
+                create_time INTEGER, PRIMARY KEY (group_id, uc_id));
+            CREATE TABLE coupons (coupon_id INTEGER PRIMARY KEY, coupon_type TEXT,
+                coupon_title TEXT, valid_to TEXT);
+            CREATE TABLE user_coupons (uc_id INTEGER PRIMARY KEY, store_id INTEGER,
+                user_id INTEGER, coupon_id INTEGER, create_time TEXT, obtain_at TEXT,
+                available_value REAL, uc_count INTEGER, pay_id INTEGER, uc_type TEXT, status TEXT,
+                remark TEXT, bonus_value REAL, expire_time TEXT);
+            CREATE TABLE stored_value_transfers (transfer_id INTEGER PRIMARY KEY, store_id INTEGER,
+                from_uc_id INTEGER, to_uc_id INTEGER, from_user_id INTEGER, to_user_id INTEGER,
+                amount REAL, bonus_amount REAL, create_time INTEGER, remark TEXT);
+
+            -- 门店 1 的家庭组：户主 1，成员 2 可用共享卡券支付，成员 3 不可；会员 5 不在家庭组。
+            -- 会员 4 是门店 2 家庭组的户主
+            INSERT INTO family_groups (group_id, store_id, group_name, owner_id)
+            VALUES (1, 1, '张家', 1), (2, 2, '李家', 4);
+            INSERT INTO family_members (group_id, user_id, can_pay)
+            VALUES (1, 1, 1), (1, 2, 1), (1, 3, 0), (2, 4, 1);
+            INSERT INTO coupons VALUES (1, 'StoredValueCard', '储值卡', NULL);
+            INSERT INTO user_coupons (uc_id, store_id, user_id, coupon_id, available_value,
+                uc_count, status, bonus_value)
+            VALUES (1, 1, 1, 1, 120, 1, '0', 20), (2, 1, 2, 1, 0, 1, '0', 0),
+                (3, 1, 5, 1, 100, 1, '0', 0), (4, 2, 4, 1, 100, 1, '0', 0);
+            "#,
+        )
+        .await
+        .unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_find_member_by_store() {
+        let pool = setup().await;
+        assert!(
+            FamilyMember::find_by_user_id(&pool, 1, 2)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        // 其他门店家庭组的成员不可见
+        assert!(
+            FamilyMember::find_by_user_id(&pool, 1, 4)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            FamilyMember::find_by_user_id(&pool, 2, 4)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_share_and_access() {
+        let pool = setup().await;
+        let coupons = UserCoupon::find_by_uc_ids(&pool, 1, &[1]).await.unwrap();
+        // 未共享前只有本人可以使用
+        assert!(
+            FamilyGroup::check_coupon_access(&pool, 1, &coupons)
+                .await
+                .is_ok()
+        );
+        assert!(
+            FamilyGroup::check_coupon_access(&pool, 2, &coupons)
+                .await
+                .is_err()
+        );
+
+        FamilyGroup::share_coupon(&pool, 1, 1, 1, true)
+            .await
+            .unwrap();
+        assert!(
+            FamilyGroup::check_coupon_access(&pool, 2, &coupons)
+                .await
+                .is_ok()
+        );
+        // 没有支付权限的成员和组外会员不能使用
+        assert!(
+            FamilyGroup::check_coupon_access(&pool, 3, &coupons)
+                .await
+                .is_err()
+        );
+        assert!(
+            FamilyGroup::check_coupon_access(&pool, 5, &coupons)
+                .await
+                .is_err()
+        );
+
+        // 只能共享本组成员的卡券，也不能共享其他门店的卡券
+        assert!(
+            FamilyGroup::share_coupon(&pool, 1, 1, 3, true)
+                .await
+                .is_err()
+        );
+        assert!(
+            FamilyGroup::share_coupon(&pool, 1, 1, 4, true)
+                .await
+                .is_err()
+        );
+        assert!(
+            FamilyGroup::share_coupon(&pool, 1, 2, 4, true)
+                .await
+                .is_err()
+        );
+
+        FamilyGroup::share_coupon(&pool, 1, 1, 1, false)
+            .await
+            .unwrap();
+        assert!(
+            FamilyGroup::check_coupon_access(&pool, 2, &coupons)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfer() {
+        let pool = setup().await;
+        let req = |from_uc_id, to_uc_id, amount| TransferReq {
+            from_uc_id,
+            to_uc_id,
+            amount,
+            remark: None,
+        };
+
+        // 转出 120 元中的 60 元，20 元赠送余额按比例转出 10 元
+        let (transfer, from, to) = StoredValueTransfer::transfer(&pool, 1, &req(1, 2, 60.))
+            .await
+            .unwrap();
+        assert_eq!(transfer.bonus_amount, 10.);
+        assert_eq!((from.available_value, from.bonus_value), (Some(60.), 10.));
+        assert_eq!((to.available_value, to.bonus_value), (Some(60.), 10.));
+
+        // 余额不足、组外会员和其他门店的卡都不能转移
+        assert!(
+            StoredValueTransfer::transfer(&pool, 1, &req(1, 2, 100.))
+                .await
+                .is_err()
+        );
+        assert!(
+            StoredValueTransfer::transfer(&pool, 1, &req(1, 3, 10.))
+                .await
+                .is_err()
+        );
+        assert!(
+            StoredValueTransfer::transfer(&pool, 1, &req(1, 4, 10.))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,463 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{FromRow, Pool, QueryBuilder, Row, Sqlite, Transaction};
+use tauri::State;
+
+use crate::constants::BarcodeType;
+use crate::db::{Curd, PageParams, PageResult, Validator};
+use crate::error::{Error, Result};
+use crate::printer::graphics;
+use crate::state::AppState;
+use crate::utils;
+
+/// 标签模板中可使用的变量，使用 `{变量名}` 引用
+pub const LABEL_VARIABLES: &[&str] = &[
+    "storeName",
+    "clothCode",
+    "clothName",
+    "clothColor",
+    "flaws",
+    "sum",
+    "num",
+    "receiveTime",
+    "dueDate",
+    "customerName",
+    "customerPhone",
+    "rackName",
+    "rackPosition",
+];
+
+const DEFAULT_FONT_PATH: &str = "MSYH.TTC";
+// 衣物编码为 yyMMdd + 当日序号，按 6 位序号估算最大长度
+const CLOTH_CODE_MAX_LEN: usize = 12;
+// 校验条码尺寸时其他变量按此长度估算
+const SAMPLE_VAR_LEN: usize = 8;
+
+/// 标签元素类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LabelFieldType {
+    #[default]
+    Text,
+    Barcode,
+}
+
+/// 标签元素，坐标单位为 mm，原点为左下角；文字的 y 为基线位置，条码的 y 为底边位置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct LabelField {
+    pub field_type: LabelFieldType,
+    /// 内容，可包含变量，例如 `瑕疵: {flaws}`
+    pub content: String,
+    pub x: f32,
+    pub y: f32,
+    /// 字号，为空时使用模板字号
+    pub font_size: Option<f32>,
+    /// 条码宽度
+    pub width: Option<f32>,
+    /// 条码高度
+    pub height: Option<f32>,
+}
+
+impl LabelField {
+    fn text(content: &str, x: f32, y: f32) -> Self {
+        Self {
+            field_type: LabelFieldType::Text,
+            content: content.to_string(),
+            x,
+            y,
+            ..Default::default()
+        }
+    }
+
+    fn barcode(content: &str, x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            field_type: LabelFieldType::Barcode,
+            content: content.to_string(),
+            x,
+            y,
+            width: Some(width),
+            height: Some(height),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct LabelTemplate {
+    pub template_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub template_name: Option<String>,
+    /// 纸张宽度(mm)
+    pub page_width: f32,
+    /// 纸张高度(mm)
+    pub page_height: f32,
+    /// 字体文件路径
+    pub font_path: Option<String>,
+    /// 默认字号
+    pub font_size: f32,
+    pub fields: Vec<LabelField>,
+    /// 条码元素使用的条码类型
+    pub barcode_type: BarcodeType,
+    /// 是否为门店默认模板
+    pub is_default: bool,
+    pub create_time: Option<i64>,
+    pub update_time: Option<i64>,
+    pub remark: Option<String>,
+}
+
+impl FromRow<'_, SqliteRow> for LabelTemplate {
+    fn from_row(row: &'_ SqliteRow) -> std::result::Result<Self, sqlx::Error> {
+        let fields: String = row.try_get("fields").unwrap_or_default();
+        let fields = serde_json::from_str(&fields).map_err(|e| sqlx::Error::ColumnDecode {
+            index: "fields".to_string(),
+            source: Box::new(e),
+        })?;
+
+        Ok(Self {
+            template_id: row.try_get("template_id").unwrap_or_default(),
+            store_id: row.try_get("store_id").unwrap_or_default(),
+            template_name: row.try_get("template_name").unwrap_or_default(),
+            page_width: row.try_get("page_width").unwrap_or_default(),
+            page_height: row.try_get("page_height").unwrap_or_default(),
+            font_path: row.try_get("font_path").unwrap_or_default(),
+            font_size: row.try_get("font_size").unwrap_or_default(),
+            fields,
+            barcode_type: row.try_get("barcode_type").unwrap_or_default(),
+            is_default: row.try_get("is_default").unwrap_or_default(),
+            create_time: row.try_get("create_time").unwrap_or_default(),
+            update_time: row.try_get("update_time").unwrap_or_default(),
+            remark: row.try_get("remark").unwrap_or_default(),
+        })
+    }
+}
+
+impl Curd for LabelTemplate {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM label_templates WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM label_templates WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM label_templates WHERE template_id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM label_templates WHERE template_id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY is_default DESC, template_id DESC");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(name) = &self.template_name {
+            builder
+                .push(" AND template_name LIKE ")
+                .push_bind(format!("%{}%", name));
+        }
+    }
+}
+
+impl Validator for LabelTemplate {
+    fn validate(&self) -> Result<()> {
+        if self.template_name.as_deref().unwrap_or_default().is_empty() {
+            return Err(Error::bad_request("模板名称不能为空"));
+        }
+
+        if self.page_width <= 0. || self.page_height <= 0. {
+            return Err(Error::bad_request("纸张尺寸必须大于0"));
+        }
+
+        if self.font_size <= 0. {
+            return Err(Error::bad_request("字号必须大于0"));
+        }
+
+        let re = Regex::new(r"\{(\w+)\}").unwrap();
+        for (i, field) in self.fields.iter().enumerate() {
+            let index = i + 1;
+            if field.content.trim().is_empty() {
+                return Err(Error::bad_request(format!("第{index}个元素内容不能为空")));
+            }
+
+            if field.x < 0.
+                || field.x > self.page_width
+                || field.y < 0.
+                || field.y > self.page_height
+            {
+                return Err(Error::bad_request(format!("第{index}个元素超出纸张范围")));
+            }
+
+            if let Some(var) = re
+                .captures_iter(&field.content)
+                .map(|c| c[1].to_string())
+                .find(|v| !LABEL_VARIABLES.contains(&v.as_str()))
+            {
+                return Err(Error::bad_request(format!(
+                    "第{index}个元素包含未知变量: {var}"
+                )));
+            }
+
+            if field.field_type == LabelFieldType::Barcode {
+                let (width, height) = (
+                    field.width.unwrap_or_default(),
+                    field.height.unwrap_or_default(),
+                );
+                if width <= 0. || height <= 0. {
+                    return Err(Error::bad_request(format!("第{index}个条码元素缺少尺寸")));
+                }
+                if field.x + width > self.page_width || field.y + height > self.page_height {
+                    return Err(Error::bad_request(format!("第{index}个条码超出纸张范围")));
+                }
+
+                // 按最长衣物编码估算条码尺寸，确保打印后能被扫描
+                let sample = re.replace_all(&field.content, |caps: &regex::Captures| {
+                    if &caps[1] == "clothCode" {
+                        "9".repeat(CLOTH_CODE_MAX_LEN)
+                    } else {
+                        "X".repeat(SAMPLE_VAR_LEN)
+                    }
+                });
+                let matrix =
+                    graphics::encode_barcode(self.barcode_type, &sample).map_err(|_| {
+                        Error::bad_request(format!(
+                            "第{index}个条码内容不支持{}",
+                            self.barcode_type
+                        ))
+                    })?;
+                let (min_width, min_height) = matrix.min_size();
+                if width < min_width || height < min_height {
+                    return Err(Error::bad_request(format!(
+                        "第{index}个条码尺寸不足，{}至少需要 {:.1}mm x {:.1}mm",
+                        self.barcode_type,
+                        min_width,
+                        min_height.max(1.)
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl LabelTemplate {
+    /// 内置模板，与原先固定的 180×35mm 布局一致，门店未配置模板时使用
+    pub fn builtin() -> Self {
+        Self {
+            template_name: Some("默认标签".to_string()),
+            page_width: 180.,
+            page_height: 35.,
+            font_path: Some(DEFAULT_FONT_PATH.to_string()),
+            font_size: 10.,
+            fields: vec![
+                LabelField::text("{storeName}", 6., 29.),
+                LabelField::text("{clothCode}", 6., 5.),
+                LabelField::barcode("{clothCode}", 6., 10., 70., 17.),
+                LabelField::text("{clothName} {clothColor} 总件数: {sum}:{num}", 90., 29.),
+                LabelField::text("瑕疵: {flaws}", 90., 21.),
+                LabelField::text(
+                    "收衣时间: {receiveTime}  衣柜位置: {rackName}-{rackPosition}",
+                    90.,
+                    13.,
+                ),
+                LabelField::text("客户: {customerName} 电话: {customerPhone}", 90., 5.),
+            ],
+            is_default: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn font_path(&self) -> &str {
+        self.font_path
+            .as_deref()
+            .filter(|p| !p.is_empty())
+            .unwrap_or(DEFAULT_FONT_PATH)
+    }
+
+    pub async fn create(&self, tx: &mut Transaction<'_, Sqlite>) -> Result<Self> {
+        let now = utils::get_timestamp();
+        let result = sqlx::query_as(
+            "INSERT INTO label_templates (store_id, template_name, page_width, page_height, font_path,
+                font_size, fields, barcode_type, is_default, create_time, update_time, remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(&self.template_name)
+        .bind(self.page_width)
+        .bind(self.page_height)
+        .bind(&self.font_path)
+        .bind(self.font_size)
+        .bind(serde_json::to_string(&self.fields)?)
+        .bind(self.barcode_type)
+        .bind(self.is_default)
+        .bind(now)
+        .bind(now)
+        .bind(&self.remark)
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(result)
+    }
+
+    pub async fn update(&self, tx: &mut Transaction<'_, Sqlite>) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE label_templates SET template_name = ?, page_width = ?, page_height = ?,
+                font_path = ?, font_size = ?, fields = ?, barcode_type = ?, is_default = ?,
+                update_time = ?, remark = ?
+            WHERE template_id = ? AND store_id = ?",
+        )
+        .bind(&self.template_name)
+        .bind(self.page_width)
+        .bind(self.page_height)
+        .bind(&self.font_path)
+        .bind(self.font_size)
+        .bind(serde_json::to_string(&self.fields)?)
+        .bind(self.barcode_type)
+        .bind(self.is_default)
+        .bind(utils::get_timestamp())
+        .bind(&self.remark)
+        .bind(self.template_id)
+        .bind(self.store_id)
+        .execute(&mut **tx)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 取消门店其他模板的默认状态
+    async fn clear_default(
+        tx: &mut Transaction<'_, Sqlite>,
+        store_id: i64,
+        except_id: Option<i64>,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE label_templates SET is_default = 0 WHERE store_id = ? AND template_id != ?",
+        )
+        .bind(store_id)
+        .bind(except_id.unwrap_or_default())
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_default(pool: &Pool<Sqlite>, store_id: i64) -> Result<Option<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM label_templates WHERE store_id = ? AND is_default = 1 LIMIT 1",
+        )
+        .bind(store_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 获取打印使用的模板：指定模板 > 门店默认模板 > 内置模板
+    pub async fn get_for_print(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        template_id: Option<i64>,
+    ) -> Result<Self> {
+        if let Some(id) = template_id {
+            return Self::get_by_id(pool, id)
+                .await?
+                .filter(|t| t.store_id == Some(store_id))
+                .ok_or(Error::not_found("标签模板不存在"));
+        }
+
+        Ok(Self::get_default(pool, store_id)
+            .await?
+            .unwrap_or_else(Self::builtin))
+    }
+}
+
+#[tauri::command]
+pub async fn get_label_template_pagination(
+    state: State<'_, AppState>,
+    page_params: PageParams,
+    mut template: LabelTemplate,
+) -> Result<PageResult<LabelTemplate>> {
+    let store_id = utils::get_user_id(&state).await?;
+    template.store_id = Some(store_id);
+    template.get_list(&state.pool, page_params).await
+}
+
+#[tauri::command]
+pub async fn get_label_template_by_id(
+    state: State<'_, AppState>,
+    id: i64,
+) -> Result<Option<LabelTemplate>> {
+    LabelTemplate::get_by_id(&state.pool, id).await
+}
+
+#[tauri::command]
+pub async fn get_default_label_template(state: State<'_, AppState>) -> Result<LabelTemplate> {
+    let store_id = utils::get_user_id(&state).await?;
+    LabelTemplate::get_for_print(&state.pool, store_id, None).await
+}
+
+#[tauri::command]
+pub async fn create_label_template(
+    state: State<'_, AppState>,
+    mut template: LabelTemplate,
+) -> Result<LabelTemplate> {
+    let store_id = utils::get_user_id(&state).await?;
+    template.store_id = Some(store_id);
+    template.validate()?;
+
+    let mut tx = state.pool.begin().await?;
+    let result = template.create(&mut tx).await?;
+    if result.is_default {
+        LabelTemplate::clear_default(&mut tx, store_id, result.template_id).await?;
+    }
+    tx.commit().await?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn update_label_template(
+    state: State<'_, AppState>,
+    mut template: LabelTemplate,
+) -> Result<bool> {
+    if template.template_id.is_none() {
+        return Err(Error::bad_request("template_id is required"));
+    }
+    let store_id = utils::get_user_id(&state).await?;
+    template.store_id = Some(store_id);
+    template.validate()?;
+
+    let mut tx = state.pool.begin().await?;
+    let result = template.update(&mut tx).await?;
+    if template.is_default {
+        LabelTemplate::clear_default(&mut tx, store_id, template.template_id).await?;
+    }
+    tx.commit().await?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn delete_label_templates(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
+    let mut tx = state.pool.begin().await?;
+    let result = LabelTemplate::delete_batch(&mut tx, &ids).await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_template_is_valid() {
+        assert!(LabelTemplate::builtin().validate().is_ok());
+    }
+
+    #[test]
+    fn unknown_variable_is_rejected() {
+        let mut template = LabelTemplate::builtin();
+        template.fields.push(LabelField::text("{unknown}", 1., 1.));
+        assert!(template.validate().is_err());
+    }
+
+    #[test]
+    fn too_small_barcode_is_rejected() {
+        let mut template = LabelTemplate::builtin();
+        template.barcode_type = BarcodeType::QrCode;
+        template.fields = vec![LabelField::barcode("{clothCode}", 1., 1., 5., 5.)];
+        assert!(template.validate().is_err());
+    }
+}
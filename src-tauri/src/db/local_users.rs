@@ -12,6 +12,8 @@ use crate::utils::device::DeviceInfo;
 use crate::utils::request::Token;
 use crate::{captcha, utils};
 
+use super::pricing_rules::PricingRule;
+use super::segments::SegmentRule;
 use super::sms_plan::SmsPlan;
 use super::sms_subscription::SmsSubscription;
 use super::subscription_plan::SubscriptionPlan;
@@ -97,6 +99,14 @@ impl LocalUser {
 }
 
 impl LocalUser {
+    pub async fn exists(tr: &mut Transaction<'_, Sqlite>, id: i64) -> Result<bool> {
+        let result = sqlx::query_scalar::<_, u64>("SELECT count(1) FROM local_users WHERE id = ?")
+            .bind(id)
+            .fetch_one(&mut **tr)
+            .await?;
+        Ok(result > 0)
+    }
+
     /// check account exist
     pub async fn check_username_unique(
         tr: &mut Transaction<'_, Sqlite>,
@@ -381,8 +391,18 @@ impl LoginReq {
         // token.user.role = Some("admin".to_string());
 
         let mut tx = state.pool.begin().await?;
+        // 门店首次在本机登录时创建默认数据
+        let is_new = match token.user.id {
+            Some(id) => !LocalUser::exists(&mut tx, id).await?,
+            None => false,
+        };
         // update local database
         token.user.upsert(&mut tx).await?;
+        if is_new {
+            let store_id = token.user.id.unwrap_or_default();
+            SegmentRule::seed_defaults(&mut tx, store_id).await?;
+            PricingRule::seed_defaults(&mut tx, store_id).await?;
+        }
 
         if let Some(id) = token.user.id {
             // get user's completed tours
@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqliteRow;
 use sqlx::types::chrono::{DateTime, FixedOffset};
-use sqlx::{FromRow, Pool, QueryBuilder, Row, Sqlite};
+use sqlx::{FromRow, Pool, QueryBuilder, Row, Sqlite, Transaction};
 use tauri::State;
 
+use crate::db::payments::Payment;
+use crate::db::user_coupons::UserCoupon;
+use crate::db::user_membership_level::UserMembershipLevel;
 use crate::db::{Curd, PageParams, PageResult, Validator};
 use crate::error::{Error, Result};
 use crate::state::AppState;
@@ -21,6 +24,21 @@ pub struct MembershipLevel {
     pub create_time: Option<DateTime<FixedOffset>>, // 创建时间
     pub update_time: Option<DateTime<FixedOffset>>, // 更新时间
     pub remark: Option<String>,                     // 备注信息
+    pub auto_assign: bool,                          // 是否参与自动评级
+    pub spend_threshold: Option<f64>,               // 升级条件：累计消费金额
+    pub points_threshold: Option<i64>,              // 升级条件：积分余额
+    pub balance_threshold: Option<f64>,             // 升级条件：储值余额
+    pub price_discount: Option<f64>,                // 等级权益：价格折扣（百分比）
+    pub points_multiplier: Option<f64>,             // 等级权益：积分倍数
+    pub free_storage_days: Option<i64>,             // 等级权益：免费存放天数
+}
+
+/// 会员评级用到的会员数据
+#[derive(Debug, Clone, Default)]
+pub struct LevelMetrics {
+    pub spend: f64,
+    pub points: i64,
+    pub balance: f64,
 }
 
 impl FromRow<'_, SqliteRow> for MembershipLevel {
@@ -34,6 +52,13 @@ impl FromRow<'_, SqliteRow> for MembershipLevel {
             create_time: row.try_get("create_time").unwrap_or_default(),
             update_time: row.try_get("update_time").unwrap_or_default(),
             remark: row.try_get("remark").unwrap_or_default(),
+            auto_assign: row.try_get("auto_assign").unwrap_or_default(),
+            spend_threshold: row.try_get("spend_threshold").unwrap_or_default(),
+            points_threshold: row.try_get("points_threshold").unwrap_or_default(),
+            balance_threshold: row.try_get("balance_threshold").unwrap_or_default(),
+            price_discount: row.try_get("price_discount").unwrap_or_default(),
+            points_multiplier: row.try_get("points_multiplier").unwrap_or_default(),
+            free_storage_days: row.try_get("free_storage_days").unwrap_or_default(),
         })
     }
 }
@@ -47,6 +72,24 @@ impl Validator for MembershipLevel {
         if self.level_code.is_none() {
             return Err(Error::bad_request("会员等级编码不能为空"));
         }
+
+        if self
+            .price_discount
+            .is_some_and(|discount| discount <= 0. || discount > 100.)
+        {
+            return Err(Error::bad_request("价格折扣需在 0 到 100 之间"));
+        }
+
+        if self
+            .points_multiplier
+            .is_some_and(|multiplier| multiplier < 0.)
+        {
+            return Err(Error::bad_request("积分倍数不能为负数"));
+        }
+
+        if self.free_storage_days.is_some_and(|days| days < 0) {
+            return Err(Error::bad_request("免费存放天数不能为负数"));
+        }
         Ok(())
     }
 }
@@ -80,13 +123,20 @@ impl Curd for MembershipLevel {
 impl MembershipLevel {
     // insert
     pub async fn insert(&mut self, tx: &mut sqlx::Transaction<'_, Sqlite>) -> Result<()> {
-        let id = sqlx::query("INSERT INTO membership_level (level_code, level_name, level_sort, status, create_time, remark) VALUES (?, ?, ?, ?, ?, ?)")
+        let id = sqlx::query("INSERT INTO membership_level (level_code, level_name, level_sort, status, create_time, remark, auto_assign, spend_threshold, points_threshold, balance_threshold, price_discount, points_multiplier, free_storage_days) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
             .bind(&self.level_code)
             .bind(&self.level_name)
             .bind(self.level_sort)
             .bind(&self.status)
             .bind(utils::get_now())
             .bind(&self.remark)
+            .bind(self.auto_assign)
+            .bind(self.spend_threshold)
+            .bind(self.points_threshold)
+            .bind(self.balance_threshold)
+            .bind(self.price_discount)
+            .bind(self.points_multiplier)
+            .bind(self.free_storage_days)
             .execute(&mut **tx)
             .await?
             .last_insert_rowid();
@@ -96,13 +146,20 @@ impl MembershipLevel {
 
     // update
     pub async fn update(&self, tx: &mut sqlx::Transaction<'_, Sqlite>) -> Result<bool> {
-        let result = sqlx::query("UPDATE membership_level SET level_code = ?, level_name = ?, level_sort = ?, status = ?, update_time = ?, remark = ? WHERE level_id = ?")
+        let result = sqlx::query("UPDATE membership_level SET level_code = ?, level_name = ?, level_sort = ?, status = ?, update_time = ?, remark = ?, auto_assign = ?, spend_threshold = ?, points_threshold = ?, balance_threshold = ?, price_discount = ?, points_multiplier = ?, free_storage_days = ? WHERE level_id = ?")
             .bind(&self.level_code)
             .bind(&self.level_name)
             .bind(self.level_sort)
             .bind(&self.status)
             .bind(utils::get_now())
             .bind(&self.remark)
+            .bind(self.auto_assign)
+            .bind(self.spend_threshold)
+            .bind(self.points_threshold)
+            .bind(self.balance_threshold)
+            .bind(self.price_discount)
+            .bind(self.points_multiplier)
+            .bind(self.free_storage_days)
             .bind(self.level_id.unwrap())
             .execute(&mut **tx)
             .await?;
@@ -132,6 +189,158 @@ impl MembershipLevel {
     }
 }
 
+impl MembershipLevel {
+    /// 会员当前的等级，有多个时取排序最高的
+    pub async fn get_by_user_id(pool: &Pool<Sqlite>, user_id: i64) -> Result<Option<Self>> {
+        let result = sqlx::query_as(
+            "SELECT ml.* FROM membership_level ml
+            INNER JOIN user_membership_level uml ON uml.level_id = ml.level_id
+            WHERE uml.user_id = ? AND ml.status = '0'
+            ORDER BY ml.level_sort DESC LIMIT 1",
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 会员等级的积分倍数，未设置时为 1
+    pub async fn points_multiplier(tr: &mut Transaction<'_, Sqlite>, user_id: i64) -> Result<f64> {
+        let multiplier: Option<f64> = sqlx::query_scalar(
+            "SELECT ml.points_multiplier FROM membership_level ml
+            INNER JOIN user_membership_level uml ON uml.level_id = ml.level_id
+            WHERE uml.user_id = ? AND ml.status = '0'
+            ORDER BY ml.level_sort DESC LIMIT 1",
+        )
+        .bind(user_id)
+        .fetch_optional(&mut **tr)
+        .await?
+        .flatten();
+        Ok(multiplier.unwrap_or(1.))
+    }
+
+    /// 满足任一升级条件即可，未设置条件的等级视为基础等级
+    fn qualifies(&self, metrics: &LevelMetrics) -> bool {
+        let rules = [
+            self.spend_threshold.map(|t| metrics.spend >= t),
+            self.points_threshold.map(|t| metrics.points >= t),
+            self.balance_threshold.map(|t| metrics.balance >= t),
+        ];
+        let mut has_rule = false;
+        for matched in rules.into_iter().flatten() {
+            if matched {
+                return true;
+            }
+            has_rule = true;
+        }
+        !has_rule
+    }
+
+    /// 在按 level_sort 升序排列的等级中选出满足条件的最高等级
+    fn pick(levels: &[Self], metrics: &LevelMetrics) -> Option<i64> {
+        levels
+            .iter()
+            .rev()
+            .find(|level| level.qualifies(metrics))
+            .and_then(|level| level.level_id)
+    }
+
+    async fn auto_levels(pool: &Pool<Sqlite>) -> Result<Vec<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM membership_level WHERE auto_assign = 1 AND status = '0'
+            ORDER BY level_sort ASC",
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    async fn evaluate_with(
+        pool: &Pool<Sqlite>,
+        levels: &[Self],
+        store_id: i64,
+        user_id: i64,
+    ) -> Result<Option<i64>> {
+        let current: Vec<i64> =
+            sqlx::query_scalar("SELECT level_id FROM user_membership_level WHERE user_id = ?")
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?;
+        // 持有员工等级等不参与自动评级的等级时不调整
+        if current
+            .iter()
+            .any(|id| !levels.iter().any(|level| level.level_id == Some(*id)))
+        {
+            return Ok(None);
+        }
+
+        let metrics = LevelMetrics {
+            spend: Payment::cal_total_amount(pool, user_id, store_id).await?,
+            points: sqlx::query_scalar(
+                "SELECT COALESCE(integral, 0) FROM users WHERE store_id = ? AND user_id = ?",
+            )
+            .bind(store_id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?
+            .unwrap_or_default(),
+            balance: UserCoupon::stored_value_balance(pool, store_id, user_id).await?,
+        };
+        let Some(level_id) = Self::pick(levels, &metrics) else {
+            return Ok(None);
+        };
+        if current == [level_id] {
+            return Ok(None);
+        }
+
+        let mut tr = pool.begin().await?;
+        sqlx::query("DELETE FROM user_membership_level WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tr)
+            .await?;
+        UserMembershipLevel::new(user_id, level_id)
+            .create(&mut tr)
+            .await?;
+        tr.commit().await?;
+        tracing::info!("user {} membership level changed to {}", user_id, level_id);
+        Ok(Some(level_id))
+    }
+
+    /// 重新评定会员等级，等级有变化时返回新的等级ID
+    pub async fn evaluate_user(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: i64,
+    ) -> Result<Option<i64>> {
+        let levels = Self::auto_levels(pool).await?;
+        Self::evaluate_with(pool, &levels, store_id, user_id).await
+    }
+
+    /// 重新评定门店所有会员的等级，返回等级有变化的会员数
+    pub async fn evaluate_store(pool: &Pool<Sqlite>, store_id: i64) -> Result<u64> {
+        let levels = Self::auto_levels(pool).await?;
+        if levels.is_empty() {
+            return Ok(0);
+        }
+        let user_ids: Vec<i64> =
+            sqlx::query_scalar("SELECT user_id FROM users WHERE store_id = ? AND del_flag = '0'")
+                .bind(store_id)
+                .fetch_all(pool)
+                .await?;
+
+        let mut changed = 0;
+        for user_id in user_ids {
+            if Self::evaluate_with(pool, &levels, store_id, user_id)
+                .await?
+                .is_some()
+            {
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+}
+
 #[tauri::command]
 pub async fn get_membership_level_pagination(
     state: State<'_, AppState>,
@@ -194,6 +403,22 @@ pub async fn update_membership_level(
     Ok(result)
 }
 
+/// 会员当前等级及权益
+#[tauri::command]
+pub async fn get_user_membership_level(
+    state: State<'_, AppState>,
+    user_id: i64,
+) -> Result<Option<MembershipLevel>> {
+    MembershipLevel::get_by_user_id(&state.pool, user_id).await
+}
+
+/// 立即重新评定门店所有会员的等级
+#[tauri::command]
+pub async fn evaluate_membership_levels(state: State<'_, AppState>) -> Result<u64> {
+    let store_id = utils::get_user_id(&state).await?;
+    MembershipLevel::evaluate_store(&state.pool, store_id).await
+}
+
 #[tauri::command]
 pub async fn delete_membership_level(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
     let mut tx = state.pool.begin().await?;
@@ -201,3 +426,37 @@ pub async fn delete_membership_level(state: State<'_, AppState>, ids: Vec<i64>)
     tx.commit().await?;
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(level_id: i64, spend: Option<f64>, points: Option<i64>) -> MembershipLevel {
+        MembershipLevel {
+            level_id: Some(level_id),
+            auto_assign: true,
+            spend_threshold: spend,
+            points_threshold: points,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pick() {
+        let levels = [
+            level(3, None, None),
+            level(4, Some(1000.), Some(500)),
+            level(5, Some(3000.), None),
+        ];
+        let metrics = |spend, points| LevelMetrics {
+            spend,
+            points,
+            balance: 0.,
+        };
+        assert_eq!(MembershipLevel::pick(&levels, &metrics(0., 0)), Some(3));
+        assert_eq!(MembershipLevel::pick(&levels, &metrics(0., 600)), Some(4));
+        assert_eq!(MembershipLevel::pick(&levels, &metrics(1500., 0)), Some(4));
+        assert_eq!(MembershipLevel::pick(&levels, &metrics(3000., 0)), Some(5));
+        assert_eq!(MembershipLevel::pick(&levels[1..], &metrics(10., 0)), None);
+    }
+}
@@ -30,6 +30,8 @@ pub enum MessageType {
     /// 配送状态更新
     DeliveryUpdate,
     NewUserRegister,
+    /// 发给会员的站内通知
+    Notice,
 }
 
 impl MessageType {
@@ -46,6 +48,7 @@ impl MessageType {
             Self::PaymentUpdate => "payment_update",
             Self::DeliveryUpdate => "delivery_update",
             Self::NewUserRegister => "new_user_register",
+            Self::Notice => "notice",
         }
     }
 }
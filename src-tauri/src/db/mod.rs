@@ -1,31 +1,45 @@
 pub(crate) mod adjust_price;
 pub(crate) mod alipay_config;
+pub(crate) mod checkout;
 pub(crate) mod cloth_price;
 pub(crate) mod cloth_sequence;
 pub(crate) mod clothing;
 pub(crate) mod clothing_category;
 pub(crate) mod clothing_style;
 pub(crate) mod configs;
+pub(crate) mod coupon_codes;
 pub(crate) mod coupon_orders;
 pub(crate) mod coupons;
 pub(crate) mod dict_data;
 pub(crate) mod dict_type;
 pub(crate) mod drying_rack;
 pub(crate) mod expenditure;
+pub(crate) mod family_groups;
+pub(crate) mod label_template;
 pub(crate) mod local_users;
 pub(crate) mod membership_level;
+pub(crate) mod notice_job;
 pub(crate) mod notice_temp;
 pub(crate) mod order_clothes;
 pub(crate) mod order_pictures;
 pub(crate) mod orders;
 pub(crate) mod payments;
+pub(crate) mod platform_vouchers;
+pub(crate) mod points;
+pub(crate) mod points_rules;
+pub(crate) mod pricing_rules;
+pub(crate) mod print_job;
+pub(crate) mod promote;
 pub(crate) mod printer;
 pub(crate) mod qrcode_payments;
+pub(crate) mod referrals;
+pub(crate) mod segments;
 // pub(crate) mod sms;
 pub(crate) mod delivery;
 pub(crate) mod message;
 pub(crate) mod sms_plan;
 pub(crate) mod sms_subscription;
+pub(crate) mod stored_value;
 pub(crate) mod subscription_plan;
 pub(crate) mod subscription_service;
 pub(crate) mod subscriptions;
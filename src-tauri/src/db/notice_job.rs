@@ -0,0 +1,256 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite};
+use tauri::State;
+
+use crate::constants::{NoticeJobStatus, NoticeJobType};
+use crate::db::notice_temp::NoticeTemp;
+use crate::db::{Curd, PageParams, PageResult, Validator};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+/// 一天的毫秒数
+pub const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// 定时通知任务
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct NoticeJob {
+    pub job_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub temp_id: Option<i64>,
+    pub job_type: Option<NoticeJobType>,
+    /// 一次性任务的会员ID，逗号分隔
+    pub user_ids: Option<String>,
+    /// 周期任务：上挂超过该天数仍未取件的订单才提醒
+    pub overdue_days: Option<i64>,
+    /// 周期任务：提醒间隔天数
+    pub interval_days: Option<i64>,
+    /// 下次执行时间（毫秒）
+    pub next_run_time: Option<i64>,
+    pub status: Option<NoticeJobStatus>,
+    pub last_run_time: Option<i64>,
+    /// 累计发送成功条数
+    pub sent_count: i64,
+    pub last_error: Option<String>,
+    pub create_time: Option<i64>,
+    pub update_time: Option<i64>,
+}
+
+impl Validator for NoticeJob {
+    fn validate(&self) -> Result<()> {
+        if self.temp_id.is_none() {
+            return Err(Error::bad_request("请选择通知模板"));
+        }
+        match self.job_type.unwrap_or_default() {
+            NoticeJobType::Once => {
+                if self.parse_user_ids().is_empty() {
+                    return Err(Error::bad_request("请选择通知的会员"));
+                }
+                if self.next_run_time.is_none() {
+                    return Err(Error::bad_request("请设置发送时间"));
+                }
+            }
+            NoticeJobType::Recurring => {
+                if self.overdue_days.unwrap_or_default() < 1 {
+                    return Err(Error::bad_request("未取件天数至少为 1 天"));
+                }
+                if self.interval_days.unwrap_or_default() < 1 {
+                    return Err(Error::bad_request("提醒间隔至少为 1 天"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Curd for NoticeJob {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM notice_jobs WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM notice_jobs WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM notice_jobs WHERE job_id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM notice_jobs WHERE job_id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY job_id DESC");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(temp_id) = &self.temp_id {
+            builder.push(" AND temp_id = ").push_bind(temp_id);
+        }
+
+        if let Some(job_type) = &self.job_type {
+            builder.push(" AND job_type = ").push_bind(job_type);
+        }
+
+        if let Some(status) = &self.status {
+            builder.push(" AND status = ").push_bind(status);
+        }
+    }
+}
+
+impl NoticeJob {
+    pub fn parse_user_ids(&self) -> Vec<i64> {
+        self.user_ids
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|id| id.trim().parse().ok())
+            .collect()
+    }
+
+    pub async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self> {
+        let now = utils::get_timestamp();
+        let result = sqlx::query_as(
+            "INSERT INTO notice_jobs (store_id, temp_id, job_type, user_ids, overdue_days,
+                interval_days, next_run_time, status, sent_count, create_time, update_time)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, 0, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(self.temp_id)
+        .bind(self.job_type.unwrap_or_default())
+        .bind(&self.user_ids)
+        .bind(self.overdue_days)
+        .bind(self.interval_days)
+        .bind(self.next_run_time.unwrap_or(now))
+        .bind(NoticeJobStatus::Pending)
+        .bind(now)
+        .bind(now)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 获取门店已到执行时间的任务
+    pub async fn list_due(pool: &Pool<Sqlite>, store_id: i64) -> Result<Vec<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM notice_jobs
+            WHERE store_id = ? AND status = ? AND next_run_time <= ?
+            ORDER BY next_run_time LIMIT 20",
+        )
+        .bind(store_id)
+        .bind(NoticeJobStatus::Pending)
+        .bind(utils::get_timestamp())
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 将任务标记为执行中，任务已被领取或已取消时返回 false
+    pub async fn claim(pool: &Pool<Sqlite>, job_id: i64) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE notice_jobs SET status = ?, update_time = ? WHERE job_id = ? AND status = ?",
+        )
+        .bind(NoticeJobStatus::Running)
+        .bind(utils::get_timestamp())
+        .bind(job_id)
+        .bind(NoticeJobStatus::Pending)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 记录执行结果。next_run_time 不为空时任务继续等待下次执行，
+    /// 否则按是否出错结束任务；执行期间被取消的任务保持取消状态
+    pub async fn finish(
+        pool: &Pool<Sqlite>,
+        job_id: i64,
+        sent: u64,
+        error: Option<&str>,
+        next_run_time: Option<i64>,
+    ) -> Result<()> {
+        let status = match (next_run_time, error) {
+            (Some(_), _) => NoticeJobStatus::Pending,
+            (None, Some(_)) => NoticeJobStatus::Failed,
+            (None, None) => NoticeJobStatus::Completed,
+        };
+        let now = utils::get_timestamp();
+        sqlx::query(
+            "UPDATE notice_jobs SET status = ?, sent_count = sent_count + ?, last_error = ?,
+                last_run_time = ?, next_run_time = COALESCE(?, next_run_time), update_time = ?
+            WHERE job_id = ? AND status = ?",
+        )
+        .bind(status)
+        .bind(sent as i64)
+        .bind(error)
+        .bind(now)
+        .bind(next_run_time)
+        .bind(now)
+        .bind(job_id)
+        .bind(NoticeJobStatus::Running)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn cancel(pool: &Pool<Sqlite>, store_id: i64, job_id: i64) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE notice_jobs SET status = ?, update_time = ?
+            WHERE job_id = ? AND store_id = ? AND status IN (?, ?)",
+        )
+        .bind(NoticeJobStatus::Cancelled)
+        .bind(utils::get_timestamp())
+        .bind(job_id)
+        .bind(store_id)
+        .bind(NoticeJobStatus::Pending)
+        .bind(NoticeJobStatus::Running)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 程序异常退出时可能遗留执行中的任务，启动时重新放回队列
+    pub async fn reset_interrupted(pool: &Pool<Sqlite>, store_id: i64) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE notice_jobs SET status = ?, update_time = ? WHERE store_id = ? AND status = ?",
+        )
+        .bind(NoticeJobStatus::Pending)
+        .bind(utils::get_timestamp())
+        .bind(store_id)
+        .bind(NoticeJobStatus::Running)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[tauri::command]
+pub async fn get_notice_jobs_pagination(
+    state: State<'_, AppState>,
+    page_params: PageParams,
+    mut job: NoticeJob,
+) -> Result<PageResult<NoticeJob>> {
+    let store_id = utils::get_user_id(&state).await?;
+    job.store_id = Some(store_id);
+    job.get_list(&state.pool, page_params).await
+}
+
+#[tauri::command]
+pub async fn create_notice_job(
+    state: State<'_, AppState>,
+    mut job: NoticeJob,
+) -> Result<NoticeJob> {
+    job.validate()?;
+    NoticeTemp::get_by_id(&state.pool, job.temp_id.unwrap_or_default())
+        .await?
+        .ok_or(Error::not_found("通知模板不存在"))?;
+    job.store_id = Some(utils::get_user_id(&state).await?);
+    job.create(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn cancel_notice_job(state: State<'_, AppState>, id: i64) -> Result<bool> {
+    let store_id = utils::get_user_id(&state).await?;
+    NoticeJob::cancel(&state.pool, store_id, id).await
+}
+
+#[tauri::command]
+pub async fn delete_notice_jobs(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
+    let mut tx = state.pool.begin().await?;
+    let result = NoticeJob::delete_batch(&mut tx, &ids).await?;
+    tx.commit().await?;
+    Ok(result)
+}
@@ -1,4 +1,3 @@
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqliteRow;
 use sqlx::types::chrono::{DateTime, FixedOffset};
@@ -7,8 +6,10 @@ use tauri::State;
 
 use crate::db::orders::Order;
 use crate::db::user::User;
-use crate::db::{Curd, PageParams, PageResult};
+use crate::db::{Curd, PageParams, PageResult, Validator};
 use crate::error::{Error, Result};
+use crate::notice::template::{self, Template, TemplateVariable};
+use crate::notice::{self, NoticeDispatcher, Outgoing, Recipient};
 use crate::state::AppState;
 use crate::utils;
 
@@ -30,6 +31,7 @@ pub struct NoticeTemp {
 #[serde(default)]
 pub struct NoticeRecord {
     pub notice_id: Option<i64>,                     // 自增主键，可以为 None
+    pub store_id: Option<i64>,                      // 商家 ID
     pub user_id: i64,                               // 用户 ID
     pub order_number: Option<String>,               // 订单号，可以为 None
     pub username: Option<String>,                   // 订单号，可以为 None
@@ -61,6 +63,7 @@ impl FromRow<'_, SqliteRow> for NoticeRecord {
     fn from_row(row: &'_ SqliteRow) -> std::result::Result<Self, sqlx::Error> {
         Ok(Self {
             notice_id: row.try_get("notice_id").unwrap_or_default(),
+            store_id: row.try_get("store_id").unwrap_or_default(),
             user_id: row.try_get("user_id").unwrap_or_default(),
             order_number: row.try_get("order_number").unwrap_or_default(),
             username: row.try_get("username").unwrap_or_default(),
@@ -103,6 +106,7 @@ impl Curd for NoticeTemp {
 
 const QUERY_RECORD_SQL: &str = "
         SELECT n.notice_id,
+               n.store_id,
                n.user_id,
                n.order_number,
                n.notice_method,
@@ -115,12 +119,14 @@ const QUERY_RECORD_SQL: &str = "
                u.nick_name,
                u.user_name,
                u.phonenumber FROM notice_record n
-        LEFT JOIN users u on n.user_id = u.user_id ";
+        LEFT JOIN users u on n.user_id = u.user_id WHERE 1=1 ";
 
 impl Curd for NoticeRecord {
-    const COUNT_SQL: &'static str = "SELECT COUNT(1) FROM notice_record WHERE 1=1";
+    const COUNT_SQL: &'static str =
+        "SELECT COUNT(1) FROM notice_record n LEFT JOIN users u on n.user_id = u.user_id WHERE 1=1";
     const QUERY_SQL: &'static str = QUERY_RECORD_SQL;
     const BY_ID_SQL: &'static str = "SELECT n.notice_id,
+               n.store_id,
                n.user_id,
                n.order_number,
                n.notice_method,
@@ -133,11 +139,15 @@ impl Curd for NoticeRecord {
                u.nick_name,
                u.user_name,
                u.phonenumber FROM notice_record n
-        LEFT JOIN users u on n.user_id = u.user_idWHERE notice_id = ?";
+        LEFT JOIN users u on n.user_id = u.user_id WHERE notice_id = ?";
     const DELETE_BATCH_SQL: &'static str = "DELETE FROM notice_record WHERE notice_id IN ( ";
-    const ORDER_SQL: Option<&'static str> = Some("ORDER BY notice_time DESC");
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY notice_time DESC");
 
     fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = self.store_id {
+            builder.push(" AND n.store_id = ").push_bind(store_id);
+        }
+
         if let Some(ref order_number) = self.order_number {
             builder.push(" AND order_number = ").push_bind(order_number);
         }
@@ -203,8 +213,9 @@ impl NoticeRecord {
     // insert
     pub async fn create(&mut self, tx: &mut Transaction<'_, Sqlite>) -> Result<()> {
         let result = sqlx::query(
-            "INSERT INTO notice_record (user_id, order_number, notice_method, notice_type, notice_time, title, content, result, remark)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            "INSERT INTO notice_record (store_id, user_id, order_number, notice_method, notice_type, notice_time, title, content, result, remark)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(self.store_id)
             .bind(self.user_id)
             .bind(&self.order_number)
             .bind(&self.notice_method)
@@ -221,6 +232,7 @@ impl NoticeRecord {
     }
 
     // 批量插入
+    #[allow(dead_code)]
     pub async fn insert_batch(
         tx: &mut Transaction<'_, Sqlite>,
         records: &[NoticeRecord],
@@ -231,13 +243,14 @@ impl NoticeRecord {
 
         // Initialize the QueryBuilder
         let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
-            "INSERT INTO notice_record (user_id, order_number, notice_method, notice_type, notice_time, title, content, result, remark) ",
+            "INSERT INTO notice_record (store_id, user_id, order_number, notice_method, notice_type, notice_time, title, content, result, remark) ",
         );
 
         let now = utils::get_now();
         // Begin values clause
         query_builder.push_values(records.into_iter(), |mut builder, record| {
             builder
+                .push_bind(&record.store_id) // Bind store_id
                 .push_bind(&record.user_id) // Bind user_id
                 .push_bind(&record.order_number) // Bind order_number
                 .push_bind(&record.notice_method) // Bind notice_method
@@ -278,6 +291,27 @@ impl NoticeRecord {
         Ok(result.rows_affected() > 0)
     }
 
+    /// 更新发送结果，notice_method 为实际发送的通知方式，remark 记录失败原因
+    pub async fn update_result(
+        pool: &Pool<Sqlite>,
+        notice_id: i64,
+        notice_method: Option<&str>,
+        result: &str,
+        remark: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE notice_record SET notice_method = COALESCE(?, notice_method), result = ?, remark = ?
+            WHERE notice_id = ?",
+        )
+            .bind(notice_method)
+            .bind(result)
+            .bind(remark)
+            .bind(notice_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
     // delete all
     pub async fn delete_all(pool: &Pool<Sqlite>) -> Result<u64> {
         let result = sqlx::query("DELETE FROM notice_record")
@@ -310,7 +344,7 @@ impl NoticeRecord {
             notice_time: Some(utils::get_now()),
             title: temp.temp_name,
             content: temp.content,
-            result: Some("2".to_string()),
+            result: Some(notice::RESULT_SENDING.to_string()),
             ..Default::default()
         }
     }
@@ -320,90 +354,117 @@ impl NoticeRecord {
 // const TEMP_TYPE_AD: &str = "1";
 // const TEMP_TYPE_OTHER: &str = "2";
 
+impl Validator for NoticeTemp {
+    fn validate(&self) -> Result<()> {
+        if self
+            .temp_name
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .is_empty()
+        {
+            return Err(Error::bad_request("模板名称不能为空"));
+        }
+        let content = self.content.as_deref().unwrap_or_default();
+        if content.trim().is_empty() {
+            return Err(Error::bad_request("通知内容不能为空"));
+        }
+        Template::parse(content)?;
+        Ok(())
+    }
+}
+
 impl NoticeTemp {
-    pub async fn send_notice(
-        pool: &Pool<Sqlite>,
-        store_id: i64,
+    /// 为会员待取件订单生成通知记录并逐条发送，返回发送成功的条数
+    pub async fn send_notice(state: &AppState, temp_id: i64, user_ids: &[i64]) -> Result<u64> {
+        let store_id = state.try_get_user_id().await?;
+
+        // pickup notice only remain
+        // select orders that wait to pickup by user ids
+        let orders =
+            Order::select_list_with_wait_to_pick_with_user_ids(&state.pool, store_id, user_ids)
+                .await?;
+        Self::send_for_orders(state, temp_id, orders).await
+    }
+
+    /// 按订单渲染模板，生成通知记录并逐条发送，返回发送成功的条数
+    pub async fn send_for_orders(
+        state: &AppState,
         temp_id: i64,
-        user_ids: &[i64],
+        orders: Vec<Order>,
     ) -> Result<u64> {
-        let mut tr = pool.begin().await?;
+        let pool = &state.pool;
+        let store = state.get_user_info().await.ok_or(Error::unauthorized())?;
+        let dispatcher = NoticeDispatcher::new(state).await?;
+        let store_id = dispatcher.store_id();
+
         // select temp info by temp_id
-        let mut temp = Self::get_by_id(pool, temp_id)
+        let temp = Self::get_by_id(pool, temp_id)
             .await?
             .ok_or(Error::not_found("temp not found"))?;
+        let template = Template::parse(temp.content.as_deref().unwrap_or_default())?;
 
-        // select users by user ids
-        let users = User::list_by_ids(pool, user_ids).await?;
-
-        let mut records = Vec::with_capacity(users.len());
+        if orders.is_empty() {
+            return Ok(0);
+        }
 
-        let content = temp.content.clone().unwrap_or_default();
+        // select users of orders
+        let mut user_ids: Vec<i64> = orders.iter().filter_map(|order| order.user_id).collect();
+        user_ids.sort_unstable();
+        user_ids.dedup();
+        let users = User::list_by_ids(pool, &user_ids).await?;
 
-        // pickup notice only remain
-        // select orders that wait to pickup by user ids
-        let orders =
-            Order::select_list_with_wait_to_pick_with_user_ids(pool, store_id, user_ids).await?;
-
-        // generate notice records
-        orders.into_iter().for_each(|order| {
+        // render content for each order before writing records
+        let mut items = Vec::with_capacity(orders.len());
+        for order in orders {
             // get user by user_id in order
-            let user = users
-                .iter()
-                .filter(|user| user.user_id == order.user_id)
-                .next();
-            if user.is_none() {
-                return;
-            }
-            let user = user.unwrap();
-
-            // replace variant in template
-            temp.content = Some(Self::create_content(&content, user, &order));
-
-            // generate notice record
-            let record = NoticeRecord::from_temp_with_user_id(
+            let Some(user) = users.iter().find(|user| user.user_id == order.user_id) else {
+                continue;
+            };
+
+            let values = template::load_values(pool, &template, &store, user, Some(&order)).await?;
+            let mut record = NoticeRecord::from_temp_with_user_id(
                 temp.clone(),
                 user.user_id.unwrap_or_default(),
                 order.order_number,
             );
-            records.push(record);
-        });
-
-        NoticeRecord::insert_batch(&mut tr, &records).await?;
+            record.store_id = Some(store_id);
+            record.content = Some(template.render(&values));
+
+            items.push(Outgoing {
+                record,
+                recipient: Recipient::from(user),
+                pickup_code: None,
+                picture: None,
+            });
+        }
 
-        // todo send notice
+        // insert one by one to get notice ids
+        let mut tr = pool.begin().await?;
+        for item in items.iter_mut() {
+            item.record.create(&mut tr).await?;
+        }
         tr.commit().await?;
-        Ok(0)
+
+        dispatcher.dispatch_all(&items).await
     }
 
-    fn create_content(template: &str, user: &User, order: &Order) -> String {
-        let mut result = template.to_string();
-        let regex = Regex::new(r"《(.*?)》").unwrap();
-
-        for caps in regex.captures_iter(template) {
-            match &caps[1] {
-                "会员姓名" => {
-                    let tel = user.phonenumber.clone().unwrap_or_default();
-                    let phone_tail = &tel[7..];
-                    result = result.replace(
-                        &format!("《{}》", &caps[1]),
-                        &format!(
-                            "{}{}",
-                            user.nick_name.clone().unwrap_or_default(),
-                            phone_tail
-                        ),
-                    );
-                }
-                "取件码" => {
-                    result = result.replace(
-                        &format!("《{}》", &caps[1]),
-                        &order.pickup_code.clone().unwrap_or_default(),
-                    );
-                }
-                _ => {}
-            }
-        }
-        result
+    /// 使用真实订单预览模板内容
+    pub async fn preview(state: &AppState, content: &str, order_id: i64) -> Result<String> {
+        let pool = &state.pool;
+        let store = state.get_user_info().await.ok_or(Error::unauthorized())?;
+        let store_id = store.id.ok_or(Error::unauthorized())?;
+        let template = Template::parse(content)?;
+
+        let order = Order::get_by_id(pool, store_id, order_id)
+            .await?
+            .ok_or(Error::not_found("订单不存在"))?;
+        let user = User::get_by_id(pool, order.user_id.unwrap_or_default())
+            .await?
+            .ok_or(Error::not_found("会员不存在"))?;
+
+        let values = template::load_values(pool, &template, &store, &user, Some(&order)).await?;
+        Ok(template.render(&values))
     }
 }
 
@@ -423,6 +484,7 @@ pub async fn get_temp_by_id(state: State<'_, AppState>, id: i64) -> Result<Optio
 
 #[tauri::command]
 pub async fn create_temp(state: State<'_, AppState>, temp: NoticeTemp) -> Result<NoticeTemp> {
+    temp.validate()?;
     let mut tx = state.pool.begin().await?;
     let res = temp.create(&mut tx).await?;
     tx.commit().await?;
@@ -431,9 +493,24 @@ pub async fn create_temp(state: State<'_, AppState>, temp: NoticeTemp) -> Result
 
 #[tauri::command]
 pub async fn update_temp(state: State<'_, AppState>, temp: NoticeTemp) -> Result<bool> {
+    temp.validate()?;
     temp.update(&state.pool).await
 }
 
+#[tauri::command]
+pub fn get_temp_variables() -> Vec<TemplateVariable> {
+    template::VARIABLES.to_vec()
+}
+
+#[tauri::command]
+pub async fn preview_temp(
+    state: State<'_, AppState>,
+    content: String,
+    order_id: i64,
+) -> Result<String> {
+    NoticeTemp::preview(&state, &content, order_id).await
+}
+
 #[tauri::command]
 pub async fn delete_temp(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
     if ids.contains(&1) {
@@ -449,8 +526,9 @@ pub async fn delete_temp(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bo
 pub async fn get_notice_record_pagination(
     state: State<'_, AppState>,
     page_params: PageParams,
-    record: NoticeRecord,
+    mut record: NoticeRecord,
 ) -> Result<PageResult<NoticeRecord>> {
+    record.store_id = Some(utils::get_user_id(&state).await?);
     record.get_list(&state.pool, page_params).await
 }
 
@@ -471,8 +549,6 @@ pub async fn send_notice(
     state: State<'_, AppState>,
     temp_id: i64,
     user_ids: Vec<i64>,
-) -> Result<bool> {
-    let store_id = utils::get_user_id(&state).await?;
-    let count = NoticeTemp::send_notice(&state.pool, store_id, temp_id, &user_ids).await?;
-    Ok(count == user_ids.len() as u64)
+) -> Result<u64> {
+    NoticeTemp::send_notice(&state, temp_id, &user_ids).await
 }
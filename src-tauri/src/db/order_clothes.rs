@@ -2,7 +2,6 @@ use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqliteRow;
 use sqlx::types::chrono::{DateTime, FixedOffset, Local};
 use sqlx::{FromRow, Pool, QueryBuilder, Row, Sqlite, Transaction};
-use std::collections::HashMap;
 use tauri::State;
 
 use crate::constants::{
@@ -11,12 +10,12 @@ use crate::constants::{
 use crate::db::cloth_sequence::ClothSequence;
 use crate::db::clothing::Clothing;
 use crate::db::drying_rack::DryingRack;
-use crate::db::notice_temp::NoticeRecord;
 use crate::db::order_pictures::OrderPicture;
 use crate::db::orders::Order;
 use crate::db::tags::Tag;
 use crate::db::{PageParams, PageResult, Validator};
 use crate::error::{Error, ErrorKind, Result};
+use crate::notice;
 use crate::state::AppState;
 use crate::utils;
 
@@ -735,25 +734,23 @@ impl OrderCloth {
             order.status = Some(OrderStatus::ReadyForPickup);
         }
 
-        // Self::update_order_and_notify(&mut tr, state, &mut order, is_all_hanged).await?;
-        if let Err(err) =
-            Self::update_order_and_notify(&mut tr, state, &mut order, is_all_hanged).await
-        {
-            match err.kind() {
-                // 这些错误类型需要返回给前端，但不应导致事务回滚
-                ErrorKind::SmsNotSubscribed | ErrorKind::SmsRemainShort => {
-                    tracing::warn!("SMS service error: {:?}", err);
-                    // 提交事务以确保其他操作成功
-                    tr.commit().await?;
-                    // 然后返回错误给前端
-                    return Err(err);
-                }
-                // 其他错误类型仍然返回错误
-                _ => return Err(err),
-            }
+        if !order.update(&mut tr).await? {
+            return Err(Error::with_details(
+                ErrorKind::InternalServer,
+                "update order information failed",
+            ));
+        }
+
+        if is_all_hanged {
+            Order::mark_ready(&mut tr, order.order_id.unwrap_or_default()).await?;
         }
 
         tr.commit().await?;
+
+        // 事务提交后再发送取件通知，避免通知记录的写入等待挂衣事务
+        if is_all_hanged && order.pickup_code.is_some() {
+            notice::send_pickup_notice(state, &order).await?;
+        }
         Ok(())
     }
 
@@ -769,132 +766,6 @@ impl OrderCloth {
         Ok(code.to_string())
     }
 
-    /// Updates the order status and sends a notification if necessary.
-    async fn update_order_and_notify(
-        tr: &mut Transaction<'_, Sqlite>,
-        state: &State<'_, AppState>,
-        order: &mut Order,
-        is_all_hanged: bool,
-    ) -> Result<()> {
-        if !order.update(tr).await? {
-            return Err(Error::with_details(
-                ErrorKind::InternalServer,
-                "update order information failed",
-            ));
-        }
-
-        if is_all_hanged {
-            if let Some(tel) = &order.phonenumber {
-                if let Some(code) = &order.pickup_code {
-                    Self::send_pickup_msg(
-                        tr,
-                        state,
-                        code,
-                        tel,
-                        &order.order_number.as_ref().unwrap_or(&String::new()),
-                        order.user_id.unwrap_or_default(),
-                    )
-                    .await?;
-                }
-            }
-        }
-
-        Ok(())
-    }
-
-    async fn send_pickup_msg(
-        tx: &mut Transaction<'_, Sqlite>,
-        state: &State<'_, AppState>,
-        code: &str,
-        tel: &str,
-        order_num: &str,
-        user_id: i64,
-    ) -> Result<()> {
-        let token = state
-            .token
-            .lock()
-            .await
-            .clone()
-            .ok_or(Error::account_or_pwd())?;
-        let token_str = token.token.clone();
-        let store_id = token.user.id.unwrap();
-
-        // release mutex
-        drop(token);
-
-        // 直接构建参数，调用专有接口
-        let param = HashMap::from([("code".to_string(), code.to_string())]);
-
-        let body = SendSmsRequest {
-            temp_id: 0, // 不再需要模板ID，服务端会处理
-            store_id,
-            phone: tel.to_string(),
-            args: Some(param),
-        };
-
-        tracing::info!("send hangup sms request: {:?}", body);
-
-        // 调用专有接口发送短信
-        let result = match state
-            .http_client
-            .post("/sms/hangup", body, Some(&token_str))
-            .await
-        {
-            Ok(res) => {
-                if res {
-                    String::from("0")
-                } else {
-                    String::from("1")
-                }
-            }
-            Err(err) => {
-                // 检查是否是短信服务相关错误
-                match err.kind() {
-                    ErrorKind::SmsNotSubscribed => {
-                        tracing::warn!("SMS service not subscribed: {:?}", err);
-                        // 返回特定错误，而不是简单忽略
-                        return Err(Error::with_details(
-                            ErrorKind::SmsNotSubscribed,
-                            "短信服务未订阅，请先订阅短信服务",
-                        ));
-                    }
-                    ErrorKind::SmsRemainShort => {
-                        tracing::warn!("SMS remaining count is low: {:?}", err);
-                        // 返回特定错误，而不是简单忽略
-                        return Err(Error::with_details(
-                            ErrorKind::SmsRemainShort,
-                            "短信余量不足，请及时充值",
-                        ));
-                    }
-                    _ => {
-                        tracing::error!("send sms failed: {:?}", err);
-                    }
-                }
-                String::from("1")
-            }
-        };
-
-        // 无论短信是否发送成功，都创建通知记录
-        let mut record = NoticeRecord {
-            notice_id: None,
-            user_id,
-            order_number: Some(order_num.to_string()),
-            notice_method: Some("0".to_string()),
-            notice_type: Some("0".to_string()),
-            title: Some("取衣通知".to_string()),
-            content: Some(format!("取件码：{}", code)),
-            result: Some(result),
-            ..Default::default()
-        };
-
-        // 尝试创建记录，但即使失败也不影响主流程
-        if let Err(e) = record.create(tx).await {
-            tracing::error!("Failed to create notice record: {:?}", e);
-        }
-
-        Ok(())
-    }
-
     /// clothes may be in different orders
     pub async fn pickup(pool: &Pool<Sqlite>, store_id: i64, ids: &[String]) -> Result<()> {
         let mut tr = pool.begin().await?;
@@ -957,14 +828,6 @@ impl OrderCloth {
     }
 }
 
-#[derive(Debug, Serialize)]
-pub struct SendSmsRequest {
-    pub temp_id: i64,
-    pub store_id: i64,
-    pub phone: String,
-    pub args: Option<HashMap<String, String>>,
-}
-
 #[tauri::command]
 pub async fn list_order_clothes_history(
     state: State<'_, AppState>,
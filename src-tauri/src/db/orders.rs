@@ -1,9 +1,4 @@
-use std::cmp::Reverse;
-use std::collections::BinaryHeap;
-
 use chrono::Local;
-use rust_decimal::prelude::*;
-use rust_decimal_macros::dec;
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqliteRow;
 use sqlx::{
@@ -13,18 +8,28 @@ use sqlx::{
 use tauri::{AppHandle, Manager, Runtime};
 
 use crate::constants::{
-    AlarmType, ClothStatus, CouponType, OrderStatus, PaymentMethod, PaymentStatus,
-    ServiceRequirmentType,
+    AlarmType, ClothStatus, CouponType, OrderStatus, PaymentMethod, PaymentStatus, PointsReason,
+    VoucherStatus,
 };
 use crate::db::adjust_price::OrderClothAdjust;
+use crate::db::checkout::CheckoutPlan;
 use crate::db::cloth_price::ClothPrice;
 use crate::db::configs::Config;
+use crate::db::family_groups::FamilyGroup;
+use crate::db::membership_level::MembershipLevel;
+use crate::db::notice_job::DAY_MILLIS;
 use crate::db::order_clothes::OrderCloth;
 use crate::db::payments::Payment;
+use crate::db::platform_vouchers::PlatformVoucher;
+use crate::db::points::PointsRecord;
+use crate::db::points_rules::{PointsRedeem, PointsRule};
+use crate::db::pricing_rules::{PriceBreakdown, PricingContext, PricingRule};
+use crate::db::referrals::ReferralReward;
 use crate::db::user::User;
 use crate::db::user_coupons::UserCoupon;
 use crate::db::{Curd, PageParams, PageResult};
 use crate::error::{Error, ErrorKind, Result};
+use crate::pay::voucher::{self, VoucherPlatform, VoucherSettings};
 use crate::payments::PaymentMethodDetail;
 use crate::qrcode_payments::QrcodePayment;
 use crate::state::AppState;
@@ -35,8 +40,6 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 
-use super::payments::CouponUsage;
-
 // const PAY_STATUS_NOT_PAID: &str = "01";
 // const PAY_STATUS_REFUND: &str = "05";
 // const ORDER_STATUS_REFUND: &str = "06";
@@ -142,6 +145,8 @@ pub struct PaymentReq {
     pub store_id: Option<i64>,                  // 商家ID
     pub subject: Option<String>,                // 订单标题
     pub payment_type: Option<PaymentReqMethod>, // 支付类型：alipay/wechat
+    pub points_redeem: Option<PointsRedeem>,    // 积分抵扣
+    pub voucher_codes: Option<Vec<String>>,     // 美团、抖音团购券码
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -801,9 +806,12 @@ impl Order {
         store_id: i64,
         user_ids: &[i64],
     ) -> Result<Vec<Self>> {
-        let mut builder = QueryBuilder::new(&format!(
-            "{SQL} WHERE o.store_id = {store_id} AND o.status = '02' AND o.user_id IN ("
-        ));
+        let mut builder = QueryBuilder::new(&format!("{SQL} WHERE o.store_id = "));
+        builder
+            .push_bind(store_id)
+            .push(" AND o.status = ")
+            .push_bind(OrderStatus::ReadyForPickup)
+            .push(" AND o.user_id IN (");
 
         // 添加占位符
         user_ids.iter().enumerate().for_each(|(i, id)| {
@@ -824,6 +832,39 @@ impl Order {
         Ok(result)
     }
 
+    /// 上挂时间早于 before（毫秒）仍未取件的订单
+    pub async fn select_overdue_for_pickup(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        before: i64,
+    ) -> Result<Vec<Self>> {
+        // 会员等级的免费存放天数内不算逾期
+        let result = sqlx::query_as(&format!(
+            "{SQL} WHERE o.store_id = ? AND o.status = ? AND o.ready_time <= ? - COALESCE((
+                SELECT MAX(ml.free_storage_days) FROM user_membership_level uml
+                INNER JOIN membership_level ml ON ml.level_id = uml.level_id
+                WHERE uml.user_id = o.user_id AND ml.status = '0'), 0) * ?
+            GROUP BY o.order_id"
+        ))
+        .bind(store_id)
+        .bind(OrderStatus::ReadyForPickup)
+        .bind(before)
+        .bind(DAY_MILLIS)
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 记录订单衣物全部上挂的时间
+    pub async fn mark_ready(tr: &mut Transaction<'_, Sqlite>, order_id: i64) -> Result<()> {
+        sqlx::query("UPDATE orders SET ready_time = ? WHERE order_id = ?")
+            .bind(utils::get_timestamp())
+            .bind(order_id)
+            .execute(&mut **tr)
+            .await?;
+        Ok(())
+    }
+
     pub async fn query_count_by_status(
         pool: &Pool<Sqlite>,
         store_id: i64,
@@ -1182,77 +1223,85 @@ impl Order {
         Ok(resp)
     }
 
-    async fn cal_total_price(
+    pub(crate) async fn cal_total_price(
         pool: &Pool<Sqlite>,
         order: &mut Order,
         clothes: &[OrderCloth],
     ) -> Result<f64> {
-        // calculate total price by clothes
-        let mut price = clothes
-            .iter()
-            .map(|cloth| {
-                if let Some(base_price) = cloth.price_value {
-                    let base_price = Decimal::from_f64(base_price).unwrap_or_default();
-                    let mut price = if Some(ServiceRequirmentType::Emergency)
-                        == cloth.service_requirement
-                    {
-                        base_price * dec!(2.0)
-                    } else if Some(ServiceRequirmentType::SingleWash) == cloth.service_requirement {
-                        base_price * dec!(1.5)
-                    } else {
-                        base_price
-                    };
-                    price += Decimal::from_f64(cloth.process_markup.unwrap_or_default())
-                        .unwrap_or_default();
-                    price
-                } else {
-                    Decimal::ZERO
-                }
-            })
-            .sum::<Decimal>();
+        Ok(Self::price_breakdown(pool, order, clothes).await?.total)
+    }
 
-        if let Some(price_ids) = &order.price_ids {
-            for price_id in price_ids {
-                // query price obj
-                let cloth_price =
-                    ClothPrice::get_by_id(pool, *price_id)
-                        .await?
-                        .ok_or(Error::with_details(
-                            ErrorKind::NotFound,
-                            "cloth price not found",
-                        ))?;
-
-                if let Some(price_value) = cloth_price.price_value {
-                    price = Decimal::from_f64(price_value).unwrap_or_default();
-                } else if let Some(price_discount) = cloth_price.price_discount {
-                    // Apply discount to price
-                    let discount =
-                        Decimal::from_f64(price_discount).unwrap_or_default() / dec!(100);
-                    let discount_value = price * discount;
-                    price -= discount_value.round_dp(2);
-                } else {
-                    return Err(Error::internal("Price tag configuration error"));
-                }
-            }
-        }
+    /// 按门店计价规则、会员等级、价格标签和调价计算订单的计价明细
+    pub(crate) async fn price_breakdown(
+        pool: &Pool<Sqlite>,
+        order: &Order,
+        clothes: &[OrderCloth],
+    ) -> Result<PriceBreakdown> {
+        let rules = PricingRule::list_enabled(pool, order.store_id.unwrap_or_default()).await?;
 
-        // Process adjustments
-        if let Some(adjust) = &order.adjust {
-            if let Some(adjust_total) = adjust.adjust_total {
-                price = Decimal::from_f64(adjust_total).unwrap_or_default();
-            } else {
-                price += Decimal::from_f64(adjust.adjust_value_add.unwrap_or_default())
-                    .unwrap_or_default();
-                price -= Decimal::from_f64(adjust.adjust_value_sub.unwrap_or_default())
-                    .unwrap_or_default();
-            }
+        let level = match order.user_id {
+            Some(user_id) => MembershipLevel::get_by_user_id(pool, user_id)
+                .await?
+                .and_then(|level| {
+                    level
+                        .price_discount
+                        .map(|discount| (level.level_name.unwrap_or_default(), discount))
+                }),
+            None => None,
+        };
+
+        let mut price_tags = Vec::new();
+        for price_id in order.price_ids.iter().flatten() {
+            let cloth_price =
+                ClothPrice::get_by_id(pool, *price_id)
+                    .await?
+                    .ok_or(Error::with_details(
+                        ErrorKind::NotFound,
+                        "cloth price not found",
+                    ))?;
+            price_tags.push(cloth_price);
         }
 
-        // 始终执行一次截断，确保精度一致
-        let rounded_price = price.round_dp_with_strategy(2, RoundingStrategy::ToZero);
+        PriceBreakdown::evaluate(
+            &PricingContext {
+                rules: &rules,
+                level,
+                price_tags: &price_tags,
+                adjust: order.adjust.as_ref(),
+                now: utils::get_timestamp(),
+            },
+            clothes,
+        )
+    }
+
+    /// 保存结账时的计价明细
+    async fn save_price_breakdown(
+        tr: &mut Transaction<'_, Sqlite>,
+        order_id: i64,
+        breakdown: &PriceBreakdown,
+    ) -> Result<()> {
+        let json = serde_json::to_string(breakdown)
+            .map_err(|e| Error::internal(format!("序列化计价明细失败: {e}")))?;
+        sqlx::query("UPDATE orders SET price_breakdown = ? WHERE order_id = ?")
+            .bind(json)
+            .bind(order_id)
+            .execute(&mut **tr)
+            .await?;
+        Ok(())
+    }
 
-        // Ensure the price is non-negative
-        Ok(rounded_price.to_f64().unwrap_or_default().max(0.0))
+    /// 获取结账时保存的计价明细
+    pub(crate) async fn get_price_breakdown(
+        pool: &Pool<Sqlite>,
+        order_id: i64,
+    ) -> Result<Option<PriceBreakdown>> {
+        let json: Option<String> =
+            sqlx::query_scalar("SELECT price_breakdown FROM orders WHERE order_id = ?")
+                .bind(order_id)
+                .fetch_optional(pool)
+                .await?
+                .flatten();
+        Ok(json.and_then(|json| serde_json::from_str(&json).ok()))
     }
 
     pub async fn query_list(
@@ -1324,7 +1373,6 @@ impl Order {
             .orders
             .take()
             .ok_or(Error::bad_request("订单不能为空"))?;
-        let mut is_time_based = false;
 
         let mut payment = payment_req
             .payment
@@ -1334,27 +1382,26 @@ impl Order {
         // set store_id
         payment.store_id = Some(store_id);
 
-        // 获取支付信息和卡券信息
-        let mut user_coupons: Vec<UserCoupon> = if let Some(ids) = &payment_req.uc_ids {
-            let coupons = UserCoupon::find_by_uc_ids(pool, store_id, &ids).await?;
-            if coupons.len() != ids.len() {
-                return Err(Error::bad_request("卡券信息不正确，存在未入库的卡券"));
-            }
-            coupons
-        } else if let Some(time_based) = &payment_req.time_based {
+        // 获取卡券信息，次卡可与其他卡券叠加使用
+        let mut ids = payment_req.uc_ids.clone().unwrap_or_default();
+        if let Some(time_based) = &payment_req.time_based {
             tracing::debug!("使用了次卡进行支付: {:?}", time_based);
-            // 如果使用了次卡
-            let ids: Vec<i64> = time_based.iter().map(|t| t.uc_id).collect();
+            ids.extend(time_based.iter().map(|t| t.uc_id));
+        }
+        let mut user_coupons: Vec<UserCoupon> = if ids.is_empty() {
+            vec![]
+        } else {
             let coupons = UserCoupon::find_by_uc_ids(pool, store_id, &ids).await?;
-
             if coupons.len() != ids.len() {
                 return Err(Error::bad_request("卡券信息不正确，存在未入库的卡券"));
             }
-            is_time_based = true;
             coupons
-        } else {
-            vec![]
         };
+        if !user_coupons.is_empty() {
+            // 支付明细和卡券使用记录按所有订单合并生成
+            payment.payment_method_details = Vec::new();
+            payment.coupon_usages = Vec::new();
+        }
 
         // 检查是否使用扫码支付
         let is_qr_code_payment = payment_req.auth_code.is_some();
@@ -1367,6 +1414,7 @@ impl Order {
         let mut order_numbers = Vec::new();
         let mut total_payment_amount = 0.0;
         let mut user_id = None;
+        let mut priced = Vec::new();
 
         for order in orders.iter() {
             if let Some(order_id) = order.order_id {
@@ -1382,31 +1430,10 @@ impl Order {
                 // 查询订单衣物信息
                 let clothes = OrderCloth::get_by_order_id(pool, order_id).await?;
 
-                // 计算订单总价
-                let order_total_amount =
-                    Self::cal_total_price(pool, &mut existing_order, &clothes).await?;
-                total_payment_amount += order_total_amount;
-
-                // 应用卡券或储值卡
-                if !user_coupons.is_empty() {
-                    if is_time_based {
-                        Self::validate_time_based_on_payment_new(
-                            &mut tr,
-                            &mut payment,
-                            &mut user_coupons,
-                            &clothes,
-                        )
-                        .await?;
-                    } else {
-                        Self::validate_and_apply_coupons_new(
-                            &mut tr,
-                            &mut payment,
-                            &mut user_coupons,
-                            order_total_amount,
-                        )
-                        .await?;
-                    }
-                }
+                // 计算订单总价，并保存计价明细
+                let breakdown = Self::price_breakdown(pool, &existing_order, &clothes).await?;
+                Self::save_price_breakdown(&mut tr, order_id, &breakdown).await?;
+                total_payment_amount += breakdown.total;
 
                 // 如果所有衣物状态为 "已取件"，更新订单状态为 "已完成"
                 if clothes
@@ -1424,6 +1451,28 @@ impl Order {
                 if user_id.is_none() {
                     user_id = existing_order.user_id;
                 }
+                priced.push((clothes, breakdown));
+            }
+        }
+
+        // 按叠加规则合并结算所有订单，每张卡券只结算一次
+        if !user_coupons.is_empty() {
+            let orders = priced
+                .iter()
+                .map(|(clothes, breakdown)| (clothes.as_slice(), breakdown))
+                .collect::<Vec<_>>();
+            CheckoutPlan::compute_orders(&mut user_coupons, &orders)?.apply(&mut payment)?;
+        }
+
+        // 卡券须属于下单会员，或为家庭共享给该会员使用的卡券
+        if let Some(user_id) = user_id {
+            FamilyGroup::check_coupon_access(pool, user_id, &user_coupons).await?;
+        }
+
+        // 保存卡券余额的变化
+        for user_coupon in &user_coupons {
+            if !user_coupon.update(&mut tr).await? {
+                return Err(Error::internal("update user coupon failed"));
             }
         }
 
@@ -1444,11 +1493,84 @@ impl Order {
         // 设置支付的总金额
         payment.total_amount = Some(total_payment_amount);
 
+        // 积分抵扣，剩余金额由其他方式支付
+        let mut payable_amount = total_payment_amount;
+        let mut points_detail = None;
+        if let Some(redeem) = payment_req.points_redeem.take() {
+            if !user_coupons.is_empty() {
+                return Err(Error::bad_request("积分抵扣不能与卡券同时使用"));
+            }
+            // 抵扣记录按订单号关联，退单时按订单退还
+            if order_ids.len() != 1 {
+                return Err(Error::bad_request("积分抵扣每次只能支付一个订单"));
+            }
+            let user_id = user_id.ok_or(Error::bad_request("订单未关联会员，不能使用积分抵扣"))?;
+            let quote = PointsRule::redeem_discount(
+                pool,
+                &mut tr,
+                store_id,
+                user_id,
+                &redeem,
+                total_payment_amount,
+                order_numbers.first().cloned(),
+            )
+            .await?;
+            payable_amount = ((total_payment_amount - quote.discount) * 100.0).round() / 100.0;
+            points_detail = Some(PaymentMethodDetail {
+                store_id: Some(store_id),
+                method: Some(PaymentMethod::Points),
+                amount: quote.discount,
+                payment_status: Some(PaymentStatus::Paid),
+                ..Default::default()
+            });
+        }
+
+        // 美团、抖音团购券先在平台核销，本地记账失败时撤销核销
+        let voucher_codes =
+            voucher::normalize_codes(payment_req.voucher_codes.take().unwrap_or_default())?;
+        let mut voucher_platform: Option<Box<dyn VoucherPlatform>> = None;
+        let mut consumed_vouchers = Vec::new();
+        let mut voucher_test_mode = false;
+        if !voucher_codes.is_empty() {
+            if is_qr_code_payment {
+                return Err(Error::bad_request("团购券不能与扫码支付同时使用"));
+            }
+            // 核销记录按支付记录关联，退单时按订单撤销
+            if order_ids.len() != 1 {
+                return Err(Error::bad_request("团购券每次只能支付一个订单"));
+            }
+            if !user_coupons.is_empty() {
+                return Err(Error::bad_request("团购券不能与卡券同时使用"));
+            }
+            let settings = VoucherSettings::load(pool).await?;
+            let method = payment.payment_method.clone().unwrap_or_default();
+            let platform = settings.platform(&method)?;
+            consumed_vouchers =
+                voucher::consume_for_payment(platform.as_ref(), &voucher_codes, payable_amount)
+                    .await?;
+            // 使用积分抵扣时由下方统一生成支付明细，剩余金额同样记在团购券名下
+            if points_detail.is_none() {
+                payment.payment_method_details = vec![PaymentMethodDetail {
+                    store_id: Some(store_id),
+                    method: Some(method),
+                    amount: payable_amount,
+                    payment_status: Some(PaymentStatus::Paid),
+                    ..Default::default()
+                }];
+            }
+            voucher_test_mode = settings.test_mode;
+            voucher_platform = Some(platform);
+        }
+
         // 如果是扫码支付，调用相应的支付接口
         if is_qr_code_payment {
             let subject_text =
                 subject.unwrap_or_else(|| format!("订单支付-{}", order_numbers.join(",")));
 
+            if payable_amount <= 0.0 {
+                return Err(Error::bad_request("积分已抵扣全部金额，无需扫码支付"));
+            }
+
             // 根据支付类型调用不同的支付接口
             if payment_type == PaymentReqMethod::Alipay {
                 if let Some(auth_code) = auth_code {
@@ -1456,7 +1578,7 @@ impl Order {
                     let req = crate::pay::AlipayAuthCodeRequest {
                         out_trade_no: format!("{}{}", "PAY", chrono::Utc::now().timestamp_millis()),
                         subject: subject_text.clone(),
-                        total_amount: total_payment_amount.to_string(),
+                        total_amount: payable_amount.to_string(),
                         auth_code: auth_code.clone(),
                         scene: "bar_code".to_string(),
                     };
@@ -1495,12 +1617,13 @@ impl Order {
                                     store_id: Some(store_id),
                                     payment_id: payment.pay_id.clone().unwrap_or_default(),
                                     method: Some(PaymentMethod::Alipay),
-                                    amount: total_payment_amount,
+                                    amount: payable_amount,
                                     payment_status: Some(PaymentStatus::Paid),
 
                                     creat_time: payment.create_time,
                                 };
                                 payment.payment_method_details = vec![alipay_payment];
+                                payment.payment_method_details.extend(points_detail.clone());
 
                                 let created_payment = payment.create_payment(&mut tr).await?;
 
@@ -1512,7 +1635,7 @@ impl Order {
                                     auth_code: Some(auth_code.clone()),
                                     out_trade_no: alipay_result.out_trade_no.clone(),
                                     trade_no: alipay_result.trade_no.clone(),
-                                    total_amount: Some(total_payment_amount),
+                                    total_amount: Some(payable_amount),
                                     subject: Some(subject_text.clone()),
                                     trade_status: alipay_result.trade_status.clone(),
                                     buyer_id: None,
@@ -1537,21 +1660,22 @@ impl Order {
 
                             // 更新用户积分
                             if let Some(user_id) = user_id {
-                                if !User::increase_points(
+                                Self::earn_points(
                                     &mut tr,
+                                    store_id,
                                     user_id,
-                                    total_payment_amount as i64,
+                                    payable_amount,
+                                    &order_numbers,
                                 )
-                                .await?
-                                {
-                                    return Err(Error::internal("更新用户积分失败"));
-                                }
+                                .await?;
                             }
 
                             // 同步支付信息到服务端
                             orders_with_payments.create_request(state).await?;
                             tr.commit().await?;
 
+                            Self::refresh_member_level(pool, store_id, user_id).await;
+                            Self::reward_referral(pool, store_id, user_id, &order_ids).await;
                             return Ok(());
                         } else {
                             // 支付失败
@@ -1572,690 +1696,277 @@ impl Order {
             }
         } else {
             // 非扫码支付，继续原有流程
-            for order_id in &order_ids {
-                let mut existing_order = Self::get_by_id(pool, store_id, *order_id)
-                    .await?
-                    .ok_or(Error::bad_request("order is not exist"))?;
-
-                // 更新订单支付状态为已支付
-                existing_order.payment_status = Some(PaymentStatus::Paid);
-                if !existing_order.update(&mut tr).await? {
-                    return Err(Error::internal("update order failed"));
+            if let Some(points_detail) = points_detail {
+                let mut details = vec![points_detail];
+                if payable_amount > 0.0 {
+                    details.push(PaymentMethodDetail {
+                        store_id: Some(store_id),
+                        method: payment.payment_method.clone(),
+                        amount: payable_amount,
+                        payment_status: Some(PaymentStatus::Paid),
+                        ..Default::default()
+                    });
                 }
+                payment.payment_method_details = details;
+            }
+
+            let result = async {
+                for order_id in &order_ids {
+                    let mut existing_order = Self::get_by_id(pool, store_id, *order_id)
+                        .await?
+                        .ok_or(Error::bad_request("order is not exist"))?;
 
-                // 创建支付记录
-                payment.pay_id = Some(uuid::Uuid::new_v4().to_string());
-                payment.payment_status = Some(PaymentStatus::Paid);
-                payment.pay_number = existing_order.order_number.clone();
-                payment.uc_order_id = Some(*order_id);
+                    // 更新订单支付状态为已支付
+                    existing_order.payment_status = Some(PaymentStatus::Paid);
+                    if !existing_order.update(&mut tr).await? {
+                        return Err(Error::internal("update order failed"));
+                    }
 
-                let created_payment = payment.create_payment(&mut tr).await?;
+                    // 创建支付记录
+                    payment.pay_id = Some(uuid::Uuid::new_v4().to_string());
+                    payment.payment_status = Some(PaymentStatus::Paid);
+                    payment.pay_number = existing_order.order_number.clone();
+                    payment.uc_order_id = Some(*order_id);
+
+                    let created_payment = payment.create_payment(&mut tr).await?;
+
+                    // 保存团购券核销记录
+                    for consumption in &consumed_vouchers {
+                        let mut record = PlatformVoucher::from_consumption(
+                            store_id,
+                            payment.pay_id.clone().unwrap_or_default(),
+                            *order_id,
+                            payment.payment_method.clone().unwrap_or_default(),
+                            consumption.clone(),
+                        );
+                        if voucher_test_mode {
+                            record.remark = Some("测试模式".to_string());
+                        }
+                        record.create(&mut tr).await?;
+                    }
 
-                // save order and payment
-                orders_with_payments.push(OrderWithPayment {
-                    order: existing_order,
-                    payment: created_payment,
-                });
-            }
+                    // save order and payment
+                    orders_with_payments.push(OrderWithPayment {
+                        order: existing_order,
+                        payment: created_payment,
+                    });
+                }
 
-            // 更新用户积分
-            if let Some(user_id) = user_id {
-                if !User::increase_points(&mut tr, user_id, total_payment_amount as i64).await? {
-                    return Err(Error::internal("更新用户积分失败"));
+                // 更新用户积分
+                if let Some(user_id) = user_id {
+                    Self::earn_points(&mut tr, store_id, user_id, payable_amount, &order_numbers)
+                        .await?;
                 }
+                // 同步支付信息到服务端
+                orders_with_payments.create_request(state).await?;
+                Ok::<(), Error>(())
+            }
+            .await;
+            if let Err(e) = result {
+                Self::cancel_consumed_vouchers(voucher_platform.as_deref(), &consumed_vouchers)
+                    .await;
+                return Err(e);
             }
-            // 同步支付信息到服务端
-            orders_with_payments.create_request(state).await?;
         }
 
-        tr.commit().await?;
+        if let Err(e) = tr.commit().await {
+            Self::cancel_consumed_vouchers(voucher_platform.as_deref(), &consumed_vouchers).await;
+            return Err(e.into());
+        }
+        Self::refresh_member_level(pool, store_id, user_id).await;
+        Self::reward_referral(pool, store_id, user_id, &order_ids).await;
         Ok(())
     }
 
-    async fn validate_and_apply_coupons_new(
-        tr: &mut Transaction<'_, Sqlite>,
-        payment: &mut Payment,
-        user_coupons: &mut Vec<UserCoupon>,
-        total_amount: f64,
-    ) -> Result<bool> {
-        if user_coupons.is_empty() {
-            return Ok(true);
-        }
-
-        let payment_method = payment
-            .payment_method
-            .as_ref()
-            .ok_or(Error::bad_request("支付方式不能为空"))?;
-        // 将输入值转换为Decimal，确保精度
-        let total_amount_decimal = Decimal::from_f64(total_amount).unwrap_or_default();
-
-        // 校验卡券是否过期
-        for coupon in user_coupons.iter() {
-            let coupon = coupon
-                .coupon
-                .as_ref()
-                .ok_or(Error::internal("get coupon failed"))?;
-            if Some(utils::get_now()) > coupon.valid_to {
-                return Err(Error::bad_request(format!(
-                    "优惠券 <{}> 已过期",
-                    coupon.coupon_title.clone().unwrap_or_default()
-                )));
-            }
+    /// 支付记账失败时撤销已核销的团购券
+    async fn cancel_consumed_vouchers(
+        platform: Option<&dyn VoucherPlatform>,
+        consumed: &[voucher::VoucherConsumption],
+    ) {
+        if let Some(platform) = platform {
+            voucher::cancel_all(platform, consumed).await;
         }
+    }
 
-        // 校验卡券类型一致性
-        let (storage_cards, discount_cards, other_coupons): (Vec<_>, Vec<_>, Vec<_>) = {
-            let mut storage = Vec::new();
-            let mut discount = Vec::new();
-            let mut others = Vec::new();
-
-            for coupon in user_coupons.iter_mut() {
-                if let Some(c) = coupon.coupon.as_ref() {
-                    match c.coupon_type {
-                        Some(CouponType::StoredValueCard) => storage.push(coupon),
-                        Some(CouponType::DiscountCard) => discount.push(coupon),
-                        _ => others.push(coupon),
-                    }
-                }
-            }
-            (storage, discount, others)
+    /// 退单后撤销团购券核销，撤销失败不影响退单，记录原因后由店员到平台处理
+    async fn cancel_vouchers(pool: &Pool<Sqlite>, store_id: i64, payment: &Payment) -> Result<()> {
+        let Some(pay_id) = payment.pay_id.as_deref() else {
+            return Ok(());
         };
-
-        // 检查卡券类型冲突
-        let card_types_count = [
-            !storage_cards.is_empty(),
-            !discount_cards.is_empty(),
-            !other_coupons.is_empty(),
-        ]
-        .iter()
-        .filter(|&&x| x)
-        .count();
-
-        if card_types_count > 1 {
-            return Err(Error::bad_request("不能同时使用不同类型的卡券"));
-        }
-
-        // 清空现有的支付方式明细和卡券使用记录
-        payment.payment_method_details = Vec::new();
-        payment.coupon_usages = Vec::new();
-
-        // 储值卡处理逻辑
-        if !storage_cards.is_empty() {
-            // 按余额升序排序储值卡
-            let mut sorted_storage_cards = storage_cards
+        let vouchers: Vec<PlatformVoucher> =
+            PlatformVoucher::list_by_pay_id(pool, store_id, pay_id)
+                .await?
                 .into_iter()
-                .filter(|card| card.available_value.unwrap_or(0.0) > 0.0)
-                .collect::<Vec<_>>();
-            sorted_storage_cards
-                .sort_by(|a, b| a.available_value.partial_cmp(&b.available_value).unwrap());
-
-            let mut remaining_amount = total_amount_decimal;
-
-            for storage_card in sorted_storage_cards {
-                let available_value = storage_card.available_value.unwrap_or(0.0);
-                let available_value_decimal =
-                    Decimal::from_f64(available_value).unwrap_or_default();
-
-                // 获取卡券ID
-                let coupon_id = storage_card.uc_id.unwrap_or_default();
-
-                if available_value_decimal >= remaining_amount {
-                    // 使用储值卡支付足够金额
-                    let applied_amount = remaining_amount.to_f64().unwrap_or_default();
-                    let new_balance = available_value_decimal - remaining_amount;
-                    storage_card.available_value = Some(new_balance.to_f64().unwrap_or_default());
-
-                    // 添加支付方式明细
-                    let payment_method_detail = PaymentMethodDetail {
-                        id: None,
-                        transaction_id: None,
-                        store_id: payment.store_id,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        method: Some(PaymentMethod::StoredValueCard),
-                        amount: applied_amount,
-                        payment_status: Some(PaymentStatus::Paid),
-
-                        creat_time: payment.create_time,
-                    };
-                    payment.payment_method_details.push(payment_method_detail);
-
-                    // 添加卡券使用记录
-                    let coupon_usage = CouponUsage {
-                        id: None,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        coupon_id: coupon_id,
-                        coupon_type: CouponType::StoredValueCard,
-                        applied_amount,
-                        is_refunded: false,
-                    };
-                    payment.coupon_usages.push(coupon_usage);
-
-                    // 更新用户卡券
-                    if !storage_card.update(tr).await? {
-                        return Err(Error::internal("update user coupon failed"));
-                    }
-
-                    remaining_amount = Decimal::ZERO;
-                    break;
-                } else {
-                    // 使用储值卡支付部分金额
-                    let applied_amount = available_value_decimal.to_f64().unwrap_or_default();
-                    remaining_amount -= available_value_decimal;
-                    storage_card.available_value = Some(0.0);
-
-                    // 添加支付方式明细
-                    let payment_method_detail = PaymentMethodDetail {
-                        id: None,
-                        transaction_id: None,
-                        store_id: payment.store_id,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        method: Some(PaymentMethod::StoredValueCard),
-                        amount: applied_amount,
-                        payment_status: Some(PaymentStatus::Paid),
-
-                        creat_time: payment.create_time,
-                    };
-                    payment.payment_method_details.push(payment_method_detail);
-
-                    // 添加卡券使用记录
-                    let coupon_usage = CouponUsage {
-                        id: None,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        coupon_id: coupon_id,
-                        coupon_type: CouponType::StoredValueCard,
-                        applied_amount,
-                        is_refunded: false,
-                    };
-                    payment.coupon_usages.push(coupon_usage);
-
-                    // 更新用户卡券
-                    if !storage_card.update(tr).await? {
-                        return Err(Error::internal("update user coupon failed"));
-                    }
-                }
-            }
-
-            // 如果还有剩余金额，添加现金支付方式
-            if remaining_amount > Decimal::ZERO {
-                let cash_amount = remaining_amount.to_f64().unwrap_or_default();
-                let cash_payment = PaymentMethodDetail {
-                    id: None,
-                    transaction_id: None,
-                    store_id: payment.store_id,
-                    payment_id: payment.pay_id.clone().unwrap_or_default(),
-                    method: payment_method
-                        .get_other_payment_method(&PaymentMethod::StoredValueCard),
-
-                    amount: cash_amount,
-                    payment_status: Some(PaymentStatus::Paid),
-
-                    creat_time: payment.create_time,
-                };
-                payment.payment_method_details.push(cash_payment);
-            }
-
-            return Ok(true);
+                .filter(|v| v.status == VoucherStatus::Consumed)
+                .collect();
+        if vouchers.is_empty() {
+            return Ok(());
         }
 
-        // 折扣卡处理逻辑
-        if !discount_cards.is_empty() {
-            // 验证所有折扣卡的折扣系数是否相同
-            let first_discount_rate = discount_cards[0]
-                .coupon
-                .as_ref()
-                .and_then(|c| c.usage_value)
-                .ok_or(Error::internal("获取折扣卡折扣系数失败"))?;
-
-            for discount_card in &discount_cards {
-                let discount_rate = discount_card
-                    .coupon
-                    .as_ref()
-                    .and_then(|c| c.usage_value)
-                    .ok_or(Error::internal("获取折扣卡折扣系数失败"))?;
-
-                if (discount_rate - first_discount_rate).abs() > 0.01 {
-                    return Err(Error::bad_request("只能同时使用相同折扣系数的折扣卡"));
+        let settings = VoucherSettings::load(pool).await?;
+        for v in vouchers {
+            let id = v.id.unwrap_or_default();
+            let result = match settings.platform(&v.platform) {
+                Ok(platform) => platform.cancel(&v.consumption()).await,
+                Err(e) => Err(e),
+            };
+            // 每张券的撤销结果单独提交，与已撤销的平台状态保持一致
+            let mut tr = pool.begin().await?;
+            match result {
+                Ok(()) => {
+                    PlatformVoucher::update_status(&mut tr, id, VoucherStatus::Cancelled, None)
+                        .await?;
                 }
-            }
-
-            // 计算折扣后的订单总金额
-            let discount_rate_decimal = Decimal::from_f64(first_discount_rate).unwrap_or_default();
-            let hundred = Decimal::from(100);
-            let discount_multiplier = discount_rate_decimal / hundred;
-            let discounted_total = (total_amount_decimal * discount_multiplier)
-                .round_dp_with_strategy(2, RoundingStrategy::MidpointTowardZero);
-
-            tracing::debug!(
-                "[支付] 折扣卡逻辑: 原订单金额: {}, 折扣系数: {}%, 折扣后金额: {}",
-                total_amount_decimal,
-                first_discount_rate,
-                discounted_total
-            );
-
-            // 按余额升序排序折扣卡
-            let mut sorted_discount_cards = discount_cards
-                .into_iter()
-                .filter(|card| card.available_value.unwrap_or(0.0) > 0.0)
-                .collect::<Vec<_>>();
-            sorted_discount_cards
-                .sort_by(|a, b| a.available_value.partial_cmp(&b.available_value).unwrap());
-
-            let mut remaining_amount = discounted_total;
-
-            for discount_card in sorted_discount_cards {
-                let available_value = discount_card.available_value.unwrap_or(0.0);
-                let available_value_decimal =
-                    Decimal::from_f64(available_value).unwrap_or_default();
-
-                // 获取卡券ID
-                let coupon_id = discount_card.uc_id.unwrap_or_default();
-
-                if available_value_decimal >= remaining_amount {
-                    // 使用折扣卡支付足够金额
-                    let applied_amount = remaining_amount.to_f64().unwrap_or_default();
-                    let new_balance = available_value_decimal - remaining_amount;
-                    discount_card.available_value = Some(new_balance.to_f64().unwrap_or_default());
-
-                    // 添加支付方式明细
-                    let payment_method_detail = PaymentMethodDetail {
-                        id: None,
-                        transaction_id: None,
-                        store_id: payment.store_id,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        method: Some(PaymentMethod::DiscountCard),
-                        amount: applied_amount,
-                        payment_status: Some(PaymentStatus::Paid),
-
-                        creat_time: payment.create_time,
-                    };
-                    payment.payment_method_details.push(payment_method_detail);
-
-                    // 添加卡券使用记录
-                    let coupon_usage = CouponUsage {
-                        id: None,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        coupon_id: coupon_id,
-                        coupon_type: CouponType::DiscountCard,
-                        applied_amount,
-                        is_refunded: false,
-                    };
-                    payment.coupon_usages.push(coupon_usage);
-
-                    // 更新用户卡券
-                    if !discount_card.update(tr).await? {
-                        return Err(Error::internal("更新用户折扣卡失败"));
-                    }
-
-                    break;
-                } else {
-                    // 使用折扣卡支付部分金额
-                    let applied_amount = available_value_decimal.to_f64().unwrap_or_default();
-                    remaining_amount -= available_value_decimal;
-                    discount_card.available_value = Some(0.0);
-
-                    // 添加支付方式明细
-                    let payment_method_detail = PaymentMethodDetail {
-                        id: None,
-                        transaction_id: None,
-                        store_id: payment.store_id,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        method: Some(PaymentMethod::DiscountCard),
-                        amount: applied_amount,
-                        payment_status: Some(PaymentStatus::Paid),
-
-                        creat_time: payment.create_time,
-                    };
-                    payment.payment_method_details.push(payment_method_detail);
-
-                    // 添加卡券使用记录
-                    let coupon_usage = CouponUsage {
-                        id: None,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        coupon_id: coupon_id,
-                        coupon_type: CouponType::DiscountCard,
-                        applied_amount,
-                        is_refunded: false,
-                    };
-                    payment.coupon_usages.push(coupon_usage);
-
-                    // 更新用户卡券
-                    if !discount_card.update(tr).await? {
-                        return Err(Error::internal("更新用户折扣卡失败"));
-                    }
+                Err(e) => {
+                    tracing::error!("撤销团购券核销失败 {}: {}", v.voucher_code, e);
+                    let remark = format!("撤销核销失败: {}", e);
+                    PlatformVoucher::update_status(
+                        &mut tr,
+                        id,
+                        VoucherStatus::Consumed,
+                        Some(&remark),
+                    )
+                    .await?;
                 }
             }
-
-            // 如果还有剩余金额，添加现金支付方式
-            if remaining_amount > Decimal::ZERO {
-                let cash_amount = remaining_amount.to_f64().unwrap_or_default();
-                let cash_payment = PaymentMethodDetail {
-                    id: None,
-                    transaction_id: None,
-                    store_id: payment.store_id,
-                    payment_id: payment.pay_id.clone().unwrap_or_default(),
-                    method: payment_method.get_other_payment_method(&PaymentMethod::DiscountCard),
-                    amount: cash_amount,
-                    payment_status: Some(PaymentStatus::Paid),
-
-                    creat_time: payment.create_time,
-                };
-                payment.payment_method_details.push(cash_payment);
-            }
-
-            return Ok(true);
+            tr.commit().await?;
         }
+        Ok(())
+    }
 
-        // 校验非储值卡优惠券数量
-        if other_coupons.len() > 1 {
-            return Err(Error::bad_request("每次支付只能使用一张优惠券"));
+    /// 支付完成后重新评定会员等级，失败不影响支付结果
+    async fn refresh_member_level(pool: &Pool<Sqlite>, store_id: i64, user_id: Option<i64>) {
+        if let Some(user_id) = user_id {
+            if let Err(e) = MembershipLevel::evaluate_user(pool, store_id, user_id).await {
+                tracing::error!("评定会员等级失败: {}", e);
+            }
         }
+    }
 
-        // 处理单张优惠券
-        if let Some(user_coupon) = other_coupons.into_iter().next() {
-            let coupon = user_coupon
-                .coupon
-                .as_ref()
-                .ok_or(Error::internal("Invalid coupon data"))?;
-
-            // 获取卡券ID
-            let coupon_id = user_coupon.uc_id.unwrap_or_default();
-            let coupon_type = coupon.coupon_type.clone().unwrap_or_default();
-
-            if coupon.coupon_type == Some(CouponType::DiscountCoupon)
-                || coupon.coupon_type == Some(CouponType::SpendAndSaveCard)
+    /// 被推荐会员首单支付后发放推荐奖励，失败不影响支付结果
+    async fn reward_referral(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: Option<i64>,
+        order_ids: &[i64],
+    ) {
+        if let Some(user_id) = user_id {
+            if let Err(e) =
+                ReferralReward::reward_first_order(pool, store_id, user_id, order_ids).await
             {
-                // sub count
-                let uc_count = user_coupon
-                    .uc_count
-                    .as_mut()
-                    .ok_or(Error::internal("获取用户优惠券数量失败"))?;
-                if *uc_count > 0 {
-                    *uc_count -= 1;
-                } else {
-                    return Err(Error::bad_request("优惠券数量不足"));
-                }
-
-                // 校验卡券最低消费
-                if let Some(min_spend) = coupon.min_spend {
-                    let min_spend_decimal = Decimal::from_f64(min_spend).unwrap_or_default();
-                    if total_amount_decimal < min_spend_decimal {
-                        return Err(Error::bad_request("最小消费金额未达到，请选择其他优惠券"));
-                    }
-                }
-
-                // 计算优惠金额
-                let (final_amount, discount_amount) =
-                    if coupon.coupon_type == Some(CouponType::DiscountCoupon) {
-                        if coupon.usage_value.is_none() || coupon.usage_limit.is_none() {
-                            return Err(Error::internal("get coupon usage value failed"));
-                        }
-                        // 折扣券逻辑
-                        let usage_value_decimal =
-                            Decimal::from_f64(coupon.usage_value.unwrap_or_default())
-                                .unwrap_or_default();
-                        let hundred = Decimal::from(100);
-                        let discount = Decimal::ONE - (usage_value_decimal / hundred);
-                        let discounted = (total_amount_decimal * discount)
-                            .round_dp_with_strategy(2, RoundingStrategy::MidpointTowardZero);
-                        let usage_limit = Decimal::from_f64(coupon.usage_limit.unwrap_or_default())
-                            .unwrap_or_default();
-                        let discount_amount = if discounted > usage_limit {
-                            usage_limit
-                        } else {
-                            discounted
-                        };
-                        tracing::debug!(
-                            "[支付] 折扣券逻辑: 总金额: {}, 折扣：{}, 优惠金额: {}",
-                            total_amount_decimal,
-                            discount,
-                            discount_amount
-                        );
-                        (total_amount_decimal - discount_amount, discount_amount)
-                    } else {
-                        // 满减券逻辑
-                        let usage_value = Decimal::from_f64(coupon.usage_value.unwrap_or_default())
-                            .unwrap_or_default();
-                        let final_amount = (total_amount_decimal - usage_value)
-                            .round_dp_with_strategy(2, RoundingStrategy::MidpointTowardZero);
-                        (final_amount, usage_value)
-                    };
-
-                // 添加卡券使用记录
-                let coupon_usage = CouponUsage {
-                    id: None,
-                    payment_id: payment.pay_id.clone().unwrap_or_default(),
-                    coupon_id: coupon_id,
-                    coupon_type: coupon_type,
-                    applied_amount: discount_amount.to_f64().unwrap_or_default(),
-                    is_refunded: false,
-                };
-                payment.coupon_usages.push(coupon_usage);
-
-                // 添加现金支付方式明细 - 剩余金额
-                if final_amount > Decimal::ZERO {
-                    let cash_payment = PaymentMethodDetail {
-                        id: None,
-                        transaction_id: None,
-                        store_id: payment.store_id,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        method: payment_method.get_coupon_another_method(),
-                        amount: final_amount.to_f64().unwrap_or_default(),
-                        payment_status: Some(PaymentStatus::Paid),
-
-                        creat_time: payment.create_time,
-                    };
-                    payment.payment_method_details.push(cash_payment);
-                }
-
-                // update user coupon
-                if !user_coupon.update(tr).await? {
-                    return Err(Error::internal("update user coupon failed"));
-                }
+                tracing::error!("发放推荐奖励失败: {}", e);
             }
-        } else {
-            // 如果没有使用优惠券，添加现金支付方式
-            let cash_payment = PaymentMethodDetail {
-                id: None,
-                transaction_id: None,
-                store_id: payment.store_id,
-                payment_id: payment.pay_id.clone().unwrap_or_default(),
-                method: payment.payment_method.clone(),
-                amount: total_amount_decimal.to_f64().unwrap_or_default(),
-                payment_status: Some(PaymentStatus::Paid),
-
-                creat_time: payment.create_time,
-            };
-            payment.payment_method_details.push(cash_payment);
         }
-
-        Ok(true)
     }
 
-    async fn validate_time_based_on_payment_new(
+    /// 支付赠送积分，每消费 1 元赠送 1 积分，按会员等级的积分倍数加成
+    async fn earn_points(
         tr: &mut Transaction<'_, Sqlite>,
-        payment: &mut Payment,
-        user_coupons: &mut Vec<UserCoupon>,
-        clothes: &[OrderCloth],
+        store_id: i64,
+        user_id: i64,
+        amount: f64,
+        order_numbers: &[String],
     ) -> Result<()> {
-        // Get cloth count
-        let mut cloth_count = clothes.len();
-        let mut used_coupon_ids = Vec::with_capacity(cloth_count);
-        let mut total_used_coupons = 0;
-
-        // 清空现有的支付方式明细和卡券使用记录
-        payment.payment_method_details = Vec::new();
-        payment.coupon_usages = Vec::new();
-
-        tracing::debug!("[次卡支付] 找到次卡数量: {}", user_coupons.len());
-
-        for coupon in user_coupons.iter_mut() {
-            if let Some(uc_id) = coupon.uc_id {
-                tracing::debug!("[次卡支付] 处理次卡ID: {}", uc_id);
-
-                let usable_count = coupon.available_value.unwrap_or_default() as usize;
-
-                tracing::debug!(
-                    "[次卡支付] 次卡ID: {}, 剩余次数: {}, 请求使用次数: {}",
-                    uc_id,
-                    usable_count,
-                    cloth_count
-                );
-
-                if usable_count == 0 {
-                    continue;
-                }
-
-                if usable_count >= cloth_count {
-                    tracing::debug!(
-                        "[次卡支付] 次卡ID: {}, 可完全覆盖剩余衣物({}件)",
-                        uc_id,
-                        cloth_count
-                    );
-
-                    let new_value = usable_count - cloth_count;
-                    tracing::debug!(
-                        "[次卡支付] 次卡ID: {}, 当前值: {}, 扣减: {}, 新值: {}",
-                        uc_id,
-                        usable_count,
-                        cloth_count,
-                        new_value
-                    );
-
-                    coupon.available_value = Some(new_value as f64);
-
-                    // 添加卡券使用记录
-                    let coupon_usage = CouponUsage {
-                        id: None,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        coupon_id: uc_id,
-                        coupon_type: CouponType::SessionCard,
-                        applied_amount: cloth_count as f64,
-                        is_refunded: false,
-                    };
-                    payment.coupon_usages.push(coupon_usage);
-
-                    // 更新数据库中的卡券
-                    if !coupon.update(tr).await? {
-                        tracing::error!("[次卡支付] 次卡ID: {}, 更新数据库失败", uc_id);
-                        return Err(Error::internal("update user coupon failed"));
-                    }
-
-                    used_coupon_ids.push(uc_id);
-                    total_used_coupons += cloth_count;
-                    cloth_count = 0;
+        let multiplier = MembershipLevel::points_multiplier(tr, user_id).await?;
+        let points = (amount * multiplier) as i64;
+        if points > 0 {
+            PointsRecord::earn(
+                tr,
+                store_id,
+                user_id,
+                points,
+                PointsReason::OrderPayment,
+                Some(order_numbers.join(",")),
+                None,
+            )
+            .await?;
+        }
+        Ok(())
+    }
 
-                    tracing::debug!(
-                        "[次卡支付] 次卡ID: {}, 使用完毕，总使用次数: {}",
-                        uc_id,
-                        total_used_coupons
-                    );
-                    break;
-                } else {
-                    tracing::debug!("[次卡支付] 次卡ID: {}, 不足以覆盖所有衣物，部分使用", uc_id);
-
-                    cloth_count -= usable_count;
-                    coupon.available_value = Some(0f64);
-
-                    // 添加卡券使用记录
-                    let coupon_usage = CouponUsage {
-                        id: None,
-                        payment_id: payment.pay_id.clone().unwrap_or_default(),
-                        coupon_id: uc_id,
-                        coupon_type: CouponType::SessionCard,
-                        applied_amount: usable_count as f64,
-                        is_refunded: false,
-                    };
-                    payment.coupon_usages.push(coupon_usage);
-
-                    tracing::debug!(
-                        "[次卡支付] 次卡ID: {}, 已用完，剩余衣物数: {}",
-                        uc_id,
-                        cloth_count
-                    );
-
-                    // 更新数据库中的卡券
-                    if !coupon.update(tr).await? {
-                        tracing::error!("[次卡支付] 次卡ID: {}, 更新数据库失败", uc_id);
-                        return Err(Error::internal("update user coupon failed"));
-                    }
+    /// 退单时退还抵扣的积分，并扣回支付赠送的积分，余额不足时扣到 0 为止
+    async fn refund_points(
+        tr: &mut Transaction<'_, Sqlite>,
+        store_id: i64,
+        order: &Order,
+        payment: &Payment,
+    ) -> Result<()> {
+        let Some(user_id) = order.user_id else {
+            return Ok(());
+        };
 
-                    used_coupon_ids.push(uc_id);
-                    total_used_coupons += usable_count;
-                    tracing::debug!("[次卡支付] 已累计使用次数: {}", total_used_coupons);
-                }
+        let mut earned = 0;
+        if let Some(order_number) = &order.order_number {
+            earned =
+                PointsRecord::sum_by_ref(tr, user_id, PointsReason::OrderPayment, order_number)
+                    .await?;
+            let redeemed =
+                -PointsRecord::sum_by_ref(tr, user_id, PointsReason::Redemption, order_number)
+                    .await?;
+            if redeemed > 0 {
+                PointsRecord::earn(
+                    tr,
+                    store_id,
+                    user_id,
+                    redeemed,
+                    PointsReason::Refund,
+                    Some(order_number.clone()),
+                    Some("退还抵扣积分".to_string()),
+                )
+                .await?;
             }
         }
 
-        if cloth_count > 0 {
-            // tracing::error!("[次卡支付] 次卡不足，剩余未覆盖衣物: {}", cloth_count);
-            // return Err(Error::bad_request("所选次卡数量不足"));
-            let price_diff = Self::calculate_top_x_total_price(clothes, cloth_count);
-            tracing::debug!("[次卡支付] 剩余衣物价格: {}", price_diff);
-            payment.payment_method_details.push(PaymentMethodDetail {
-                id: None,
-                transaction_id: None,
-                store_id: payment.store_id,
-                payment_id: payment.pay_id.clone().unwrap_or_default(),
-                method: payment
-                    .payment_method
-                    .as_ref()
-                    .map(|method| method.get_coupon_another_method().unwrap()),
-                amount: price_diff,
-                payment_status: Some(PaymentStatus::Paid),
-
-                creat_time: payment.create_time,
-            })
+        // 多个订单合并支付时赠送记录关联多个订单号，按实付金额估算；积分抵扣的部分不赠送积分
+        let points = if earned > 0 {
+            earned
+        } else {
+            let points_amount: f64 = payment
+                .payment_method_details
+                .iter()
+                .filter(|detail| detail.method == Some(PaymentMethod::Points))
+                .map(|detail| detail.amount)
+                .sum();
+            (payment.total_amount.unwrap_or_default() - points_amount) as i64
+        };
+        if points > 0 {
+            PointsRecord::deduct(
+                tr,
+                store_id,
+                user_id,
+                points,
+                PointsReason::Refund,
+                order.order_number.clone(),
+                None,
+            )
+            .await?;
         }
-
-        tracing::debug!("[次卡支付] 验证完成，次卡支付有效");
         Ok(())
     }
 
-    fn calculate_top_x_total_price(order_cloths: &[OrderCloth], x: usize) -> f64 {
-        // 如果 x 为 0 或数组为空，直接返回 0
-        if x == 0 || order_cloths.is_empty() {
-            return 0.0;
-        }
-
-        // 创建一个最小堆，用于保存前 x 个最大的价格
-        let mut min_heap = BinaryHeap::new();
-
-        for cloth in order_cloths {
-            // 提取价格，如果不存在则跳过
-            if let Some(price) = cloth
-                .cloth_info
-                .as_ref()
-                .and_then(|info| info.clothing_base_price)
-            {
-                let price = (price * 100.0) as i64;
-                // 如果堆的大小小于 x，直接添加
-                if min_heap.len() < x {
-                    min_heap.push(Reverse(price));
-                }
-                // 如果当前价格大于堆中最小的价格，替换它
-                else if let Some(Reverse(min_price)) = min_heap.peek() {
-                    if price > *min_price {
-                        min_heap.pop();
-                        min_heap.push(Reverse(price));
-                    }
-                }
+    pub async fn refund(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        order_id: i64,
+        refund_reason: String,
+    ) -> Result<()> {
+        let payment = Self::refund_local(pool, store_id, order_id, refund_reason).await?;
+        // 本地退单提交后再撤销美团、抖音团购券核销，撤销结果单独记录，失败不影响退单
+        if let Some(payment) = payment {
+            if let Err(e) = Self::cancel_vouchers(pool, store_id, &payment).await {
+                tracing::error!("撤销团购券核销失败: {}", e);
             }
         }
-
-        // 计算堆中所有价格的总和
-        min_heap
-            .into_iter()
-            .map(|Reverse(price)| price)
-            .sum::<i64>() as f64
-            / 100.0
+        Ok(())
     }
 
-    pub async fn refund(
+    /// 本地退单：退还卡券和积分并更新订单、支付状态，返回订单的支付记录
+    async fn refund_local(
         pool: &Pool<Sqlite>,
         store_id: i64,
         order_id: i64,
         refund_reason: String,
-    ) -> Result<()> {
+    ) -> Result<Option<Payment>> {
         let mut order = Order::get_by_id(pool, store_id, order_id)
             .await?
             .ok_or(Error::not_found("order not found"))?;
@@ -2285,7 +1996,7 @@ impl Order {
                 return Err(Error::internal("update order failed"));
             }
             tx.commit().await?;
-            return Ok(());
+            return Ok(None);
         } else {
             order.status = Some(OrderStatus::Refunded);
             if !order.update(&mut tx).await? {
@@ -2305,21 +2016,9 @@ impl Order {
                 return Err(Error::internal("更新支付状态失败"));
             }
             // refund user points
-            if let Some(total_amount) = payment.total_amount {
-                if total_amount > 0.0 {
-                    if !User::decrease_points(
-                        &mut tx,
-                        order.user_id.unwrap_or_default(),
-                        total_amount as i64,
-                    )
-                    .await?
-                    {
-                        return Err(Error::internal("退还积分失败"));
-                    }
-                }
-            }
+            Self::refund_points(&mut tx, store_id, &order, &payment).await?;
             tx.commit().await?;
-            return Ok(());
+            return Ok(Some(payment));
         }
 
         // 处理卡券退款
@@ -2336,6 +2035,17 @@ impl Order {
         // 查询所有涉及的用户卡券
         let mut user_coupons = UserCoupon::find_by_uc_ids(pool, store_id, &coupon_ids).await?;
 
+        // 已退卡的卡余额已结清，退回余额会让停用的卡重新可用
+        if let Some(uc) = user_coupons.iter().find(|uc| uc.is_refunded()) {
+            return Err(Error::bad_request(format!(
+                "订单使用的卡券 <{}> 已退卡，无法原路退回，请线下处理退款",
+                uc.coupon
+                    .as_ref()
+                    .and_then(|c| c.coupon_title.as_deref())
+                    .unwrap_or_default()
+            )));
+        }
+
         // 按照卡券使用记录退还
         for usage in &payment.coupon_usages {
             // 找到对应的用户卡券
@@ -2364,6 +2074,8 @@ impl Order {
                         let current_available = user_coupon.available_value.unwrap_or(0.0);
                         let new_available = current_available + amount_to_refund;
                         user_coupon.available_value = Some(new_available);
+                        // 储值卡同时退还扣减的赠送余额
+                        user_coupon.bonus_value += usage.bonus_amount;
 
                         tracing::debug!(
                             "[退款] 退还给{}卡 ID: {} 的金额: {}, 新余额: {}",
@@ -2403,22 +2115,10 @@ impl Order {
         }
 
         // 退还用户积分
-        if let Some(total_amount) = payment.total_amount {
-            if total_amount > 0.0 {
-                if !User::decrease_points(
-                    &mut tx,
-                    order.user_id.unwrap_or_default(),
-                    total_amount as i64,
-                )
-                .await?
-                {
-                    return Err(Error::internal("退还积分失败"));
-                }
-            }
-        }
+        Self::refund_points(&mut tx, store_id, &order, &payment).await?;
 
         tx.commit().await?;
-        Ok(())
+        Ok(Some(payment))
     }
 
     pub async fn delete_orders(pool: &Pool<Sqlite>, ids: &[i64]) -> Result<()> {
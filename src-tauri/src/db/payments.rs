@@ -1,4 +1,5 @@
 use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use rust_decimal::prelude::*;
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqliteRow;
 use sqlx::{
@@ -57,6 +58,20 @@ pub struct CouponUsage {
     pub coupon_type: CouponType,
     pub applied_amount: f64,
     pub is_refunded: bool,
+    /// 卡券金额在各衣物上的分摊
+    #[serde(default)]
+    pub allocations: Vec<CouponAllocation>,
+    /// 储值卡扣款中的赠送余额部分
+    #[serde(default)]
+    pub bonus_amount: f64,
+}
+
+// 卡券分摊明细，次卡为每件衣物使用的次数
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CouponAllocation {
+    pub cloth_id: String,
+    pub amount: f64,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
@@ -116,7 +131,9 @@ const SQL: &str = "SELECT
         'couponId', cu.coupon_id,
         'couponType', cu.coupon_type,
         'appliedAmount', cu.applied_amount,
-        'isRefunded', CASE WHEN cu.is_refunded = 1 THEN json('true') ELSE json('false') END  -- 转换为JSON布尔值
+        'isRefunded', CASE WHEN cu.is_refunded = 1 THEN json('true') ELSE json('false') END,  -- 转换为JSON布尔值
+        'allocations', json(COALESCE(cu.allocations, '[]')),
+        'bonusAmount', COALESCE(cu.bonus_amount, 0)
     ))
     FROM coupon_usages cu
     WHERE cu.payment_id = p.pay_id),
@@ -194,6 +211,15 @@ impl FromRow<'_, SqliteRow> for CouponUsage {
             coupon_type: row.try_get("coupon_type").unwrap_or_default(),
             applied_amount: row.try_get("applied_amount").unwrap_or_default(),
             is_refunded: row.try_get("is_refunded").unwrap_or_default(),
+            allocations: row
+                .try_get::<Option<String>, _>("allocations")
+                .unwrap_or_default()
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
+            bonus_amount: row
+                .try_get::<Option<f64>, _>("bonus_amount")
+                .unwrap_or_default()
+                .unwrap_or_default(),
         })
     }
 }
@@ -256,7 +282,7 @@ impl Payment {
         // 批量插入卡券使用记录
         if !self.coupon_usages.is_empty() {
             let mut query_builder = sqlx::QueryBuilder::new(
-                "INSERT INTO coupon_usages (payment_id, coupon_id, coupon_type, applied_amount, is_refunded) ",
+                "INSERT INTO coupon_usages (payment_id, coupon_id, coupon_type, applied_amount, is_refunded, allocations, bonus_amount) ",
             );
 
             query_builder.push_values(&self.coupon_usages, |mut b, usage| {
@@ -264,7 +290,9 @@ impl Payment {
                     .push_bind(usage.coupon_id)
                     .push_bind(&usage.coupon_type)
                     .push_bind(usage.applied_amount)
-                    .push_bind(usage.is_refunded);
+                    .push_bind(usage.is_refunded)
+                    .push_bind(serde_json::to_string(&usage.allocations).unwrap_or_default())
+                    .push_bind(usage.bonus_amount);
             });
 
             query_builder.build().execute(&mut **tr).await?;
@@ -274,6 +302,26 @@ impl Payment {
         Self::get_by_pay_id_with_details(tr, self.pay_id.as_deref().unwrap()).await
     }
 
+    /// 已支付金额：已支付的支付明细加上未退回的卡券抵扣
+    pub fn paid_amount(&self) -> f64 {
+        if self.payment_status == Some(PaymentStatus::Refunded) {
+            return 0.0;
+        }
+        let tendered: f64 = self
+            .payment_method_details
+            .iter()
+            .filter(|detail| detail.payment_status == Some(PaymentStatus::Paid))
+            .map(|detail| detail.amount)
+            .sum();
+        let coupons: f64 = self
+            .coupon_usages
+            .iter()
+            .filter(|usage| !usage.is_refunded)
+            .map(|usage| usage.applied_amount)
+            .sum();
+        tendered + coupons
+    }
+
     pub async fn get_by_order_id(
         pool: &Pool<Sqlite>,
         order_id: i64,
@@ -474,6 +522,32 @@ impl PaymentMethodDetail {
     }
 }
 
+impl CouponAllocation {
+    /// 按各衣物金额比例分摊，舍入误差计入最后一件
+    pub fn split(amount: Decimal, lines: &[(String, Decimal)]) -> Vec<Self> {
+        let total = lines.iter().map(|(_, value)| *value).sum::<Decimal>();
+        if lines.is_empty() || total <= Decimal::ZERO {
+            return Vec::new();
+        }
+
+        let mut remaining = amount;
+        let mut result = Vec::with_capacity(lines.len());
+        for (i, (cloth_id, value)) in lines.iter().enumerate() {
+            let share = if i + 1 == lines.len() {
+                remaining
+            } else {
+                (amount * *value / total).round_dp(2)
+            };
+            remaining -= share;
+            result.push(Self {
+                cloth_id: cloth_id.clone(),
+                amount: share.to_f64().unwrap_or_default(),
+            });
+        }
+        result
+    }
+}
+
 // CouponUsage 的相关实现
 impl CouponUsage {
     pub async fn mark_as_refunded(tx: &mut Transaction<'_, Sqlite>, pay_id: &str) -> Result<bool> {
@@ -607,7 +681,7 @@ pub async fn get_monthly_payment_summary(
         current_date = current_date + Duration::days(1);
     }
 
-    // 查询正常收入数据（排除储值卡支付，避免重复统计）
+    // 查询正常收入数据（排除储值卡、折扣卡和积分抵扣，避免重复统计）
     let normal_income_data: HashMap<String, f64> = sqlx::query(
         r#"
         SELECT
@@ -616,9 +690,10 @@ pub async fn get_monthly_payment_summary(
         FROM payment_method_details
         WHERE create_time BETWEEN ? AND ?
           AND payment_status = 'Paid'
-          AND amount > 0
+          AND amount != 0 -- 退卡退款为负数，冲减收入
           AND method != ? -- 排除储值卡支付
           AND method != ? -- 排除折扣卡支付
+          AND method != ? -- 排除积分抵扣
           AND store_id = ?
         GROUP BY date
         ORDER BY date
@@ -626,9 +701,10 @@ pub async fn get_monthly_payment_summary(
     )
     .bind(start_timestamp)
     .bind(end_timestamp)
-    .bind(store_id)
     .bind(PaymentMethod::StoredValueCard.to_string())
     .bind(PaymentMethod::DiscountCard.to_string())
+    .bind(PaymentMethod::Points.to_string())
+    .bind(store_id)
     .map(|row: SqliteRow| {
         let date: String = row.get("date");
         let income: f64 = row.get("income");
@@ -790,21 +866,22 @@ async fn query_amounts(
     let start = start.and_utc().timestamp_millis();
     let end = end.and_utc().timestamp_millis();
 
-    // 正常销售收入（排除储值卡支付的交易，避免重复统计）
+    // 正常销售收入（排除储值卡、折扣卡和积分抵扣的交易，避免重复统计），退卡退款为负数，冲减收入
     let income: f64 = sqlx::query_scalar(
         r#"
         SELECT COALESCE(SUM(amount), 0.0)
         FROM payment_method_details
         WHERE payment_status = 'Paid'
-          AND amount > 0
+          AND amount != 0
           AND store_id =?
-          AND method NOT IN (?, ?)
+          AND method NOT IN (?, ?, ?)
           AND create_time BETWEEN ? AND ?
         "#,
     )
     .bind(store_id)
     .bind(PaymentMethod::StoredValueCard)
     .bind(PaymentMethod::DiscountCard)
+    .bind(PaymentMethod::Points)
     .bind(start)
     .bind(end)
     .fetch_one(pool)
@@ -841,3 +918,47 @@ pub async fn get_total_amount(state: State<'_, AppState>, user_id: i64) -> Resul
     let store_id = utils::get_user_id(&state).await?;
     Payment::cal_total_amount(&state.pool, user_id, store_id).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_split_allocation() {
+        let lines = vec![
+            ("a".to_string(), dec!(10)),
+            ("b".to_string(), dec!(10)),
+            ("c".to_string(), dec!(10)),
+        ];
+        let result = CouponAllocation::split(dec!(10), &lines);
+        let amounts = result.iter().map(|a| a.amount).collect::<Vec<_>>();
+        assert_eq!(amounts, vec![3.33, 3.33, 3.34]);
+        assert!(CouponAllocation::split(dec!(10), &[]).is_empty());
+    }
+
+    #[test]
+    fn test_paid_amount() {
+        let detail = |amount, status| PaymentMethodDetail {
+            amount,
+            payment_status: Some(status),
+            ..Default::default()
+        };
+        let mut payment = Payment {
+            payment_status: Some(PaymentStatus::Unpaid),
+            payment_method_details: vec![
+                detail(20.0, PaymentStatus::Paid),
+                detail(30.0, PaymentStatus::Unpaid),
+            ],
+            coupon_usages: vec![CouponUsage {
+                applied_amount: 10.0,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(payment.paid_amount(), 30.0);
+
+        payment.payment_status = Some(PaymentStatus::Refunded);
+        assert_eq!(payment.paid_amount(), 0.0);
+    }
+}
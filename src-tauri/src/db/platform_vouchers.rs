@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite, Transaction};
+use tauri::State;
+
+use crate::constants::{PaymentMethod, VoucherStatus};
+use crate::error::Result;
+use crate::pay::voucher::VoucherConsumption;
+use crate::state::AppState;
+use crate::utils;
+
+/// 美团、抖音团购券核销记录
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PlatformVoucher {
+    pub id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub pay_id: String,
+    pub order_id: Option<i64>,
+    pub platform: PaymentMethod,
+    pub voucher_code: String,
+    pub platform_order_id: Option<String>,
+    pub certificate_id: Option<String>,
+    pub deal_title: Option<String>,
+    pub face_amount: f64,
+    pub settlement_amount: f64,
+    pub status: VoucherStatus,
+    pub raw_response: Option<String>,
+    pub create_time: Option<i64>,
+    pub update_time: Option<i64>,
+    pub remark: Option<String>,
+}
+
+impl PlatformVoucher {
+    pub fn from_consumption(
+        store_id: i64,
+        pay_id: String,
+        order_id: i64,
+        platform: PaymentMethod,
+        consumption: VoucherConsumption,
+    ) -> Self {
+        Self {
+            store_id: Some(store_id),
+            pay_id,
+            order_id: Some(order_id),
+            platform,
+            voucher_code: consumption.code,
+            platform_order_id: consumption.platform_order_id,
+            certificate_id: consumption.certificate_id,
+            deal_title: consumption.title,
+            face_amount: consumption.face_amount,
+            settlement_amount: consumption.settlement_amount,
+            status: VoucherStatus::Consumed,
+            raw_response: consumption.raw_response,
+            ..Default::default()
+        }
+    }
+
+    /// 撤销核销时传给平台的核销信息
+    pub fn consumption(&self) -> VoucherConsumption {
+        VoucherConsumption {
+            code: self.voucher_code.clone(),
+            platform_order_id: self.platform_order_id.clone(),
+            certificate_id: self.certificate_id.clone(),
+            title: self.deal_title.clone(),
+            face_amount: self.face_amount,
+            settlement_amount: self.settlement_amount,
+            raw_response: None,
+        }
+    }
+
+    pub async fn create(&self, tr: &mut Transaction<'_, Sqlite>) -> Result<Self> {
+        let result = sqlx::query_as(
+            "INSERT INTO platform_vouchers (store_id, pay_id, order_id, platform, voucher_code,
+                platform_order_id, certificate_id, deal_title, face_amount, settlement_amount,
+                status, raw_response, create_time, remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(&self.pay_id)
+        .bind(self.order_id)
+        .bind(&self.platform)
+        .bind(&self.voucher_code)
+        .bind(&self.platform_order_id)
+        .bind(&self.certificate_id)
+        .bind(&self.deal_title)
+        .bind(self.face_amount)
+        .bind(self.settlement_amount)
+        .bind(self.status)
+        .bind(&self.raw_response)
+        .bind(utils::get_timestamp())
+        .bind(&self.remark)
+        .fetch_one(&mut **tr)
+        .await?;
+        Ok(result)
+    }
+
+    pub async fn list_by_pay_id(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        pay_id: &str,
+    ) -> Result<Vec<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM platform_vouchers WHERE store_id = ? AND pay_id = ? ORDER BY id",
+        )
+        .bind(store_id)
+        .bind(pay_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 更新撤销结果，撤销失败时只记录原因
+    pub async fn update_status(
+        tr: &mut Transaction<'_, Sqlite>,
+        id: i64,
+        status: VoucherStatus,
+        remark: Option<&str>,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE platform_vouchers SET status = ?, remark = COALESCE(?, remark), update_time = ?
+            WHERE id = ?",
+        )
+        .bind(status)
+        .bind(remark)
+        .bind(utils::get_timestamp())
+        .bind(id)
+        .execute(&mut **tr)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[tauri::command]
+pub async fn get_platform_vouchers_by_pay_id(
+    state: State<'_, AppState>,
+    pay_id: String,
+) -> Result<Vec<PlatformVoucher>> {
+    let store_id = utils::get_user_id(&state).await?;
+    PlatformVoucher::list_by_pay_id(&state.pool, store_id, &pay_id).await
+}
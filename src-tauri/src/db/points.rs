@@ -0,0 +1,545 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite, Transaction};
+use tauri::State;
+
+use crate::constants::PointsReason;
+use crate::db::configs::Config;
+use crate::db::notice_job::DAY_MILLIS;
+use crate::db::{Curd, PageParams, PageResult, Validator};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+// 积分设置在 configs 表中的键名前缀
+const CONFIG_PREFIX: &str = "points.";
+
+/// 积分流水，会员积分的每次变动都记录一条
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PointsRecord {
+    pub id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub user_id: Option<i64>,
+    /// 变动积分，增加为正，扣减为负
+    pub change_points: i64,
+    /// 变动后余额
+    pub balance: i64,
+    pub reason: Option<PointsReason>,
+    /// 关联单据号
+    pub ref_id: Option<String>,
+    /// 尚未扣减或过期的积分
+    pub remaining: i64,
+    /// 过期时间（毫秒），为空时不过期
+    pub expire_time: Option<i64>,
+    pub remark: Option<String>,
+    pub create_time: Option<i64>,
+}
+
+impl Curd for PointsRecord {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM user_integral_record WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM user_integral_record WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM user_integral_record WHERE id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM user_integral_record WHERE id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY id DESC");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(user_id) = &self.user_id {
+            builder.push(" AND user_id = ").push_bind(user_id);
+        }
+
+        if let Some(reason) = &self.reason {
+            builder.push(" AND reason = ").push_bind(reason);
+        }
+
+        if let Some(ref_id) = &self.ref_id {
+            builder
+                .push(" AND ref_id LIKE ")
+                .push_bind(format!("%{}%", ref_id));
+        }
+    }
+}
+
+impl PointsRecord {
+    async fn insert(&self, tr: &mut Transaction<'_, Sqlite>) -> Result<Self> {
+        let result = sqlx::query_as(
+            "INSERT INTO user_integral_record (store_id, user_id, change_points, balance, reason,
+                ref_id, remaining, expire_time, remark, create_time)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(self.user_id)
+        .bind(self.change_points)
+        .bind(self.balance)
+        .bind(self.reason.unwrap_or_default())
+        .bind(&self.ref_id)
+        .bind(self.remaining)
+        .bind(self.expire_time)
+        .bind(&self.remark)
+        .bind(utils::get_timestamp())
+        .fetch_one(&mut **tr)
+        .await?;
+        Ok(result)
+    }
+
+    /// 会员当前积分余额
+    pub async fn balance(
+        tr: &mut Transaction<'_, Sqlite>,
+        store_id: i64,
+        user_id: i64,
+    ) -> Result<i64> {
+        let balance = sqlx::query_scalar(
+            "SELECT COALESCE(integral, 0) FROM users WHERE store_id = ? AND user_id = ?",
+        )
+        .bind(store_id)
+        .bind(user_id)
+        .fetch_optional(&mut **tr)
+        .await?
+        .ok_or(Error::not_found("用户未找到"))?;
+        Ok(balance)
+    }
+
+    /// 单据按变动原因累计的积分变动，扣减为负数
+    pub async fn sum_by_ref(
+        tr: &mut Transaction<'_, Sqlite>,
+        user_id: i64,
+        reason: PointsReason,
+        ref_id: &str,
+    ) -> Result<i64> {
+        let points = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(change_points), 0) FROM user_integral_record
+            WHERE user_id = ? AND reason = ? AND ref_id = ?",
+        )
+        .bind(user_id)
+        .bind(reason)
+        .bind(ref_id)
+        .fetch_one(&mut **tr)
+        .await?;
+        Ok(points)
+    }
+
+    /// 调整会员积分余额，返回调整后的余额
+    async fn change_balance(
+        tr: &mut Transaction<'_, Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        points: i64,
+    ) -> Result<i64> {
+        let balance = sqlx::query_scalar(
+            "UPDATE users SET integral = COALESCE(integral, 0) + ? WHERE store_id = ? AND user_id = ?
+            RETURNING integral",
+        )
+        .bind(points)
+        .bind(store_id)
+        .bind(user_id)
+        .fetch_optional(&mut **tr)
+        .await?
+        .ok_or(Error::not_found("用户未找到"))?;
+        Ok(balance)
+    }
+
+    /// 增加会员积分。按积分设置计算过期时间
+    pub async fn earn(
+        tr: &mut Transaction<'_, Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        points: i64,
+        reason: PointsReason,
+        ref_id: Option<String>,
+        remark: Option<String>,
+    ) -> Result<Self> {
+        if points <= 0 {
+            return Err(Error::bad_request("积分数量必须大于 0"));
+        }
+
+        let now = utils::get_timestamp();
+        let expire_days = PointsSettings::load_expire_days(tr).await?;
+        let record = PointsRecord {
+            store_id: Some(store_id),
+            user_id: Some(user_id),
+            change_points: points,
+            balance: Self::change_balance(tr, store_id, user_id, points).await?,
+            reason: Some(reason),
+            ref_id,
+            remaining: points,
+            expire_time: (expire_days > 0).then_some(now + expire_days * DAY_MILLIS),
+            remark,
+            ..Default::default()
+        };
+        record.insert(tr).await
+    }
+
+    /// 记录新建会员时带入的期初积分，余额已随会员写入
+    pub async fn opening(
+        tr: &mut Transaction<'_, Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        points: i64,
+    ) -> Result<()> {
+        if points > 0 {
+            PointsRecord {
+                store_id: Some(store_id),
+                user_id: Some(user_id),
+                change_points: points,
+                balance: points,
+                reason: Some(PointsReason::Opening),
+                remaining: points,
+                ..Default::default()
+            }
+            .insert(tr)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// 扣减会员积分，先扣减最早到期的积分，返回实际扣减的数量。
+    /// 退款扣回和过期时余额不足按余额扣减，其他情况余额不足时报错
+    pub async fn deduct(
+        tr: &mut Transaction<'_, Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        points: i64,
+        reason: PointsReason,
+        ref_id: Option<String>,
+        remark: Option<String>,
+    ) -> Result<i64> {
+        if points <= 0 {
+            return Err(Error::bad_request("积分数量必须大于 0"));
+        }
+
+        let current = Self::balance(tr, store_id, user_id).await?;
+
+        let points = match reason {
+            PointsReason::Refund | PointsReason::Expiry => points.min(current.max(0)),
+            _ if points > current => return Err(Error::bad_request("积分不足")),
+            _ => points,
+        };
+        if points == 0 {
+            return Ok(0);
+        }
+
+        // 按到期时间先后消耗剩余积分，不过期的积分最后消耗
+        let batches: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT id, remaining FROM user_integral_record
+            WHERE user_id = ? AND remaining > 0
+            ORDER BY expire_time IS NULL, expire_time, id",
+        )
+        .bind(user_id)
+        .fetch_all(&mut **tr)
+        .await?;
+        let mut left = points;
+        for (id, remaining) in batches {
+            if left == 0 {
+                break;
+            }
+            let used = remaining.min(left);
+            sqlx::query("UPDATE user_integral_record SET remaining = remaining - ? WHERE id = ?")
+                .bind(used)
+                .bind(id)
+                .execute(&mut **tr)
+                .await?;
+            left -= used;
+        }
+
+        PointsRecord {
+            store_id: Some(store_id),
+            user_id: Some(user_id),
+            change_points: -points,
+            balance: Self::change_balance(tr, store_id, user_id, -points).await?,
+            reason: Some(reason),
+            ref_id,
+            remark,
+            ..Default::default()
+        }
+        .insert(tr)
+        .await?;
+        Ok(points)
+    }
+
+    /// 手动调整积分，正数增加，负数扣减
+    pub async fn adjust(
+        tr: &mut Transaction<'_, Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        points: i64,
+        remark: Option<String>,
+    ) -> Result<()> {
+        match points {
+            0 => {}
+            p if p > 0 => {
+                Self::earn(tr, store_id, user_id, p, PointsReason::Manual, None, remark).await?;
+            }
+            p => {
+                Self::deduct(
+                    tr,
+                    store_id,
+                    user_id,
+                    -p,
+                    PointsReason::Manual,
+                    None,
+                    remark,
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 扣减门店已过期的积分，返回过期的积分总数
+    pub async fn expire_due(pool: &Pool<Sqlite>, store_id: i64) -> Result<i64> {
+        let rows: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT user_id, remaining FROM user_integral_record
+            WHERE store_id = ? AND remaining > 0 AND expire_time <= ?",
+        )
+        .bind(store_id)
+        .bind(utils::get_timestamp())
+        .fetch_all(pool)
+        .await?;
+
+        let mut expired: BTreeMap<i64, i64> = BTreeMap::new();
+        for (user_id, remaining) in rows {
+            *expired.entry(user_id).or_default() += remaining;
+        }
+
+        let mut total = 0;
+        let mut tr = pool.begin().await?;
+        for (user_id, points) in expired {
+            total += Self::deduct(
+                &mut tr,
+                store_id,
+                user_id,
+                points,
+                PointsReason::Expiry,
+                None,
+                None,
+            )
+            .await?;
+        }
+        tr.commit().await?;
+        Ok(total)
+    }
+}
+
+/// 积分设置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PointsSettings {
+    /// 积分获得后的有效天数，为 0 时不过期
+    pub expire_days: i64,
+}
+
+impl Validator for PointsSettings {
+    fn validate(&self) -> Result<()> {
+        if self.expire_days < 0 {
+            return Err(Error::bad_request("积分有效天数不能为负数"));
+        }
+        Ok(())
+    }
+}
+
+impl PointsSettings {
+    pub async fn load(pool: &Pool<Sqlite>) -> Result<Self> {
+        let mut tr = pool.begin().await?;
+        let expire_days = Self::load_expire_days(&mut tr).await?;
+        tr.commit().await?;
+        Ok(Self { expire_days })
+    }
+
+    // 在积分变动的事务中读取，避免占用额外连接
+    async fn load_expire_days(tr: &mut Transaction<'_, Sqlite>) -> Result<i64> {
+        let value: Option<String> =
+            sqlx::query_scalar("SELECT config_value FROM configs WHERE config_key = ?")
+                .bind(format!("{CONFIG_PREFIX}expireDays"))
+                .fetch_optional(&mut **tr)
+                .await?
+                .flatten();
+        Ok(value
+            .and_then(|days| days.trim().parse().ok())
+            .unwrap_or_default())
+    }
+
+    pub async fn save(&self, pool: &Pool<Sqlite>) -> Result<()> {
+        Config::set_value(
+            pool,
+            &format!("{CONFIG_PREFIX}expireDays"),
+            "积分-有效天数",
+            self.expire_days.to_string(),
+        )
+        .await
+    }
+}
+
+/// 积分变动明细，可按会员筛选
+#[tauri::command]
+pub async fn get_points_history(
+    state: State<'_, AppState>,
+    page_params: PageParams,
+    mut record: PointsRecord,
+) -> Result<PageResult<PointsRecord>> {
+    record.store_id = Some(utils::get_user_id(&state).await?);
+    record.get_list(&state.pool, page_params).await
+}
+
+#[tauri::command]
+pub async fn adjust_user_points(
+    state: State<'_, AppState>,
+    user_id: i64,
+    points: i64,
+    remark: Option<String>,
+) -> Result<()> {
+    if points == 0 {
+        return Err(Error::bad_request("调整积分不能为 0"));
+    }
+    let store_id = utils::get_user_id(&state).await?;
+    let mut tr = state.pool.begin().await?;
+    PointsRecord::adjust(&mut tr, store_id, user_id, points, remark).await?;
+    tr.commit().await?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_points_settings(state: State<'_, AppState>) -> Result<PointsSettings> {
+    PointsSettings::load(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn save_points_settings(
+    state: State<'_, AppState>,
+    settings: PointsSettings,
+) -> Result<()> {
+    settings.validate()?;
+    settings.save(&state.pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{Executor, SqlitePool};
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        pool.execute(
+            r#"
+            CREATE TABLE users (user_id INTEGER PRIMARY KEY, store_id INTEGER, integral INTEGER);
+            CREATE TABLE configs (config_key TEXT, config_value TEXT);
+            CREATE TABLE user_integral_record (
+                id INTEGER PRIMARY KEY AUTOINCREMENT, store_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL, change_points INTEGER NOT NULL, balance INTEGER NOT NULL,
+                reason TEXT NOT NULL, ref_id TEXT, remaining INTEGER NOT NULL DEFAULT 0,
+                expire_time INTEGER, remark TEXT, create_time INTEGER NOT NULL
+            );
+            INSERT INTO users VALUES (1, 1, 0);
+            INSERT INTO configs VALUES ('points.expireDays', '30');
+            "#,
+        )
+        .await
+        .unwrap();
+        pool
+    }
+
+    async fn remaining(pool: &SqlitePool) -> Vec<i64> {
+        sqlx::query_scalar(
+            "SELECT remaining FROM user_integral_record WHERE change_points > 0 ORDER BY id",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ledger_deducts_earliest_expiry_first() {
+        let pool = setup_test_db().await;
+        let mut tr = pool.begin().await.unwrap();
+        let first = PointsRecord::earn(&mut tr, 1, 1, 100, PointsReason::OrderPayment, None, None)
+            .await
+            .unwrap();
+        assert_eq!(first.balance, 100);
+        assert!(first.expire_time.is_some());
+        PointsRecord::earn(&mut tr, 1, 1, 50, PointsReason::OrderPayment, None, None)
+            .await
+            .unwrap();
+
+        let used = PointsRecord::deduct(&mut tr, 1, 1, 120, PointsReason::Redemption, None, None)
+            .await
+            .unwrap();
+        assert_eq!(used, 120);
+        assert!(
+            PointsRecord::deduct(&mut tr, 1, 1, 31, PointsReason::Redemption, None, None)
+                .await
+                .is_err()
+        );
+        // 退款扣回不超过余额
+        let used = PointsRecord::deduct(&mut tr, 1, 1, 100, PointsReason::Refund, None, None)
+            .await
+            .unwrap();
+        assert_eq!(used, 30);
+        assert_eq!(PointsRecord::balance(&mut tr, 1, 1).await.unwrap(), 0);
+        tr.commit().await.unwrap();
+
+        assert_eq!(remaining(&pool).await, vec![0, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_expire_due() {
+        let pool = setup_test_db().await;
+        let mut tr = pool.begin().await.unwrap();
+        let expired = PointsRecord::earn(&mut tr, 1, 1, 80, PointsReason::OrderPayment, None, None)
+            .await
+            .unwrap();
+        PointsRecord::earn(&mut tr, 1, 1, 20, PointsReason::OrderPayment, None, None)
+            .await
+            .unwrap();
+        tr.commit().await.unwrap();
+        sqlx::query("UPDATE user_integral_record SET expire_time = ? WHERE id = ?")
+            .bind(utils::get_timestamp() - DAY_MILLIS)
+            .bind(expired.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(PointsRecord::expire_due(&pool, 1).await.unwrap(), 80);
+        assert_eq!(PointsRecord::expire_due(&pool, 1).await.unwrap(), 0);
+        assert_eq!(remaining(&pool).await, vec![0, 20]);
+        let integral: i64 = sqlx::query_scalar("SELECT integral FROM users WHERE user_id = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(integral, 20);
+    }
+
+    #[tokio::test]
+    async fn test_manual_adjust() {
+        let pool = setup_test_db().await;
+        let mut tr = pool.begin().await.unwrap();
+        PointsRecord::adjust(&mut tr, 1, 1, 30, Some("补录".to_string()))
+            .await
+            .unwrap();
+        PointsRecord::adjust(&mut tr, 1, 1, -10, None)
+            .await
+            .unwrap();
+        assert!(
+            PointsRecord::adjust(&mut tr, 1, 1, -50, None)
+                .await
+                .is_err()
+        );
+        assert!(PointsRecord::adjust(&mut tr, 1, 2, 10, None).await.is_err());
+        assert_eq!(PointsRecord::balance(&mut tr, 1, 1).await.unwrap(), 20);
+
+        let reasons: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT change_points, balance FROM user_integral_record
+            WHERE reason = 'Manual' ORDER BY id",
+        )
+        .fetch_all(&mut *tr)
+        .await
+        .unwrap();
+        assert_eq!(reasons, vec![(30, 30), (-10, 20)]);
+    }
+}
@@ -0,0 +1,362 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite, Transaction};
+use tauri::State;
+
+use crate::constants::{PointsReason, PointsRuleType};
+use crate::db::coupons::{Coupon, CouponIdCount};
+use crate::db::points::PointsRecord;
+use crate::db::{Curd, Validator};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+/// 积分兑换规则
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PointsRule {
+    pub rule_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub rule_name: Option<String>,
+    pub rule_type: Option<PointsRuleType>,
+    /// 每次兑换所需积分
+    pub points: i64,
+    /// 抵扣规则：每次兑换抵扣的金额
+    pub amount: Option<f64>,
+    /// 兑换卡券规则：兑换的卡券
+    pub coupon_id: Option<i64>,
+    /// 抵扣规则：单笔订单最多抵扣的比例（百分比）
+    pub max_ratio: Option<f64>,
+    pub enabled: bool,
+    pub create_time: Option<i64>,
+    pub update_time: Option<i64>,
+    pub remark: Option<String>,
+}
+
+/// 结账时使用积分抵扣
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PointsRedeem {
+    pub rule_id: i64,
+    /// 本次最多使用的积分
+    pub points: i64,
+}
+
+/// 积分抵扣试算结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedeemQuote {
+    /// 实际使用的积分，按规则取整
+    pub points: i64,
+    /// 抵扣金额
+    pub discount: f64,
+}
+
+impl Validator for PointsRule {
+    fn validate(&self) -> Result<()> {
+        if self
+            .rule_name
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .is_empty()
+        {
+            return Err(Error::bad_request("规则名称不能为空"));
+        }
+        if self.points < 1 {
+            return Err(Error::bad_request("兑换所需积分至少为 1"));
+        }
+        match self.rule_type {
+            None => return Err(Error::bad_request("请选择规则类型")),
+            Some(PointsRuleType::Discount) => {
+                if self.amount.is_none_or(|amount| amount <= 0.) {
+                    return Err(Error::bad_request("请设置抵扣金额"));
+                }
+                if self
+                    .max_ratio
+                    .is_some_and(|ratio| ratio <= 0. || ratio > 100.)
+                {
+                    return Err(Error::bad_request("最高抵扣比例需在 0 到 100 之间"));
+                }
+            }
+            Some(PointsRuleType::Coupon) => {
+                if self.coupon_id.is_none() {
+                    return Err(Error::bad_request("请选择兑换的卡券"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Curd for PointsRule {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM points_rules WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM points_rules WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM points_rules WHERE rule_id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM points_rules WHERE rule_id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY rule_id");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(rule_type) = &self.rule_type {
+            builder.push(" AND rule_type = ").push_bind(rule_type);
+        }
+    }
+}
+
+impl PointsRule {
+    pub async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self> {
+        let now = utils::get_timestamp();
+        let result = sqlx::query_as(
+            "INSERT INTO points_rules (store_id, rule_name, rule_type, points, amount, coupon_id,
+                max_ratio, enabled, create_time, update_time, remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(self.rule_name.as_deref().map(str::trim))
+        .bind(self.rule_type)
+        .bind(self.points)
+        .bind(self.amount)
+        .bind(self.coupon_id)
+        .bind(self.max_ratio)
+        .bind(self.enabled)
+        .bind(now)
+        .bind(now)
+        .bind(&self.remark)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    pub async fn update(&self, pool: &Pool<Sqlite>) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE points_rules SET rule_name = ?, rule_type = ?, points = ?, amount = ?,
+                coupon_id = ?, max_ratio = ?, enabled = ?, update_time = ?, remark = ?
+            WHERE rule_id = ? AND store_id = ?",
+        )
+        .bind(self.rule_name.as_deref().map(str::trim))
+        .bind(self.rule_type)
+        .bind(self.points)
+        .bind(self.amount)
+        .bind(self.coupon_id)
+        .bind(self.max_ratio)
+        .bind(self.enabled)
+        .bind(utils::get_timestamp())
+        .bind(&self.remark)
+        .bind(self.rule_id)
+        .bind(self.store_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 获取门店可用的兑换规则
+    async fn get_enabled(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        rule_id: i64,
+        rule_type: PointsRuleType,
+    ) -> Result<Self> {
+        Self::get_by_id(pool, rule_id)
+            .await?
+            .filter(|rule| {
+                rule.store_id == Some(store_id) && rule.enabled && rule.rule_type == Some(rule_type)
+            })
+            .ok_or(Error::not_found("积分兑换规则不存在或已停用"))
+    }
+
+    /// 按规则计算抵扣：积分按整份兑换，抵扣金额不超过订单金额和最高抵扣比例
+    pub fn quote(&self, balance: i64, points: i64, order_amount: f64) -> Result<RedeemQuote> {
+        if points > balance {
+            return Err(Error::bad_request("积分不足"));
+        }
+        let amount_cents = (self.amount.unwrap_or_default() * 100.).round() as i64;
+        let ratio = self.max_ratio.unwrap_or(100.).min(100.);
+        let cap_cents = (order_amount * ratio).floor() as i64;
+        if self.points < 1 || amount_cents < 1 {
+            return Err(Error::bad_request("积分兑换规则配置错误"));
+        }
+
+        let units = (points / self.points).min(cap_cents / amount_cents);
+        if units < 1 {
+            return Err(Error::bad_request(format!(
+                "至少需要 {} 积分才能抵扣",
+                self.points
+            )));
+        }
+        Ok(RedeemQuote {
+            points: units * self.points,
+            discount: (units * amount_cents) as f64 / 100.,
+        })
+    }
+
+    /// 结账时扣减积分抵扣订单金额，ref_id 为订单号
+    pub async fn redeem_discount(
+        pool: &Pool<Sqlite>,
+        tr: &mut Transaction<'_, Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        redeem: &PointsRedeem,
+        order_amount: f64,
+        ref_id: Option<String>,
+    ) -> Result<RedeemQuote> {
+        let rule =
+            Self::get_enabled(pool, store_id, redeem.rule_id, PointsRuleType::Discount).await?;
+        let balance = PointsRecord::balance(tr, store_id, user_id).await?;
+        let quote = rule.quote(balance, redeem.points, order_amount)?;
+        PointsRecord::deduct(
+            tr,
+            store_id,
+            user_id,
+            quote.points,
+            PointsReason::Redemption,
+            ref_id,
+            rule.rule_name.clone(),
+        )
+        .await?;
+        Ok(quote)
+    }
+
+    /// 积分兑换卡券，返回会员卡券ID
+    pub async fn redeem_coupon(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        rule_id: i64,
+        count: i32,
+    ) -> Result<i64> {
+        if count < 1 {
+            return Err(Error::bad_request("兑换数量至少为 1"));
+        }
+        let rule = Self::get_enabled(pool, store_id, rule_id, PointsRuleType::Coupon).await?;
+        let info = CouponIdCount {
+            coupon_id: rule.coupon_id.unwrap_or_default(),
+            count,
+        };
+
+        let mut tr = pool.begin().await?;
+        let uc_id = Coupon::issue(pool, &mut tr, user_id, &info).await?;
+        PointsRecord::deduct(
+            &mut tr,
+            store_id,
+            user_id,
+            rule.points * count as i64,
+            PointsReason::Redemption,
+            Some(uc_id.to_string()),
+            rule.rule_name.clone(),
+        )
+        .await?;
+        tr.commit().await?;
+        Ok(uc_id)
+    }
+}
+
+#[tauri::command]
+pub async fn get_points_rules(state: State<'_, AppState>) -> Result<Vec<PointsRule>> {
+    let store_id = utils::get_user_id(&state).await?;
+    PointsRule {
+        store_id: Some(store_id),
+        ..Default::default()
+    }
+    .get_all(&state.pool)
+    .await
+}
+
+#[tauri::command]
+pub async fn create_points_rule(
+    state: State<'_, AppState>,
+    mut rule: PointsRule,
+) -> Result<PointsRule> {
+    rule.validate()?;
+    rule.store_id = Some(utils::get_user_id(&state).await?);
+    rule.create(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn update_points_rule(state: State<'_, AppState>, mut rule: PointsRule) -> Result<bool> {
+    rule.validate()?;
+    rule.store_id = Some(utils::get_user_id(&state).await?);
+    rule.update(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn delete_points_rules(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
+    let mut tx = state.pool.begin().await?;
+    let result = PointsRule::delete_batch(&mut tx, &ids).await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+/// 结账前试算积分抵扣金额
+#[tauri::command]
+pub async fn get_points_redeem_quote(
+    state: State<'_, AppState>,
+    user_id: i64,
+    redeem: PointsRedeem,
+    order_amount: f64,
+) -> Result<RedeemQuote> {
+    let store_id = utils::get_user_id(&state).await?;
+    let rule = PointsRule::get_enabled(
+        &state.pool,
+        store_id,
+        redeem.rule_id,
+        PointsRuleType::Discount,
+    )
+    .await?;
+    let mut tr = state.pool.begin().await?;
+    let balance = PointsRecord::balance(&mut tr, store_id, user_id).await?;
+    tr.commit().await?;
+    rule.quote(balance, redeem.points, order_amount)
+}
+
+#[tauri::command]
+pub async fn redeem_points_for_coupon(
+    state: State<'_, AppState>,
+    user_id: i64,
+    rule_id: i64,
+    count: i32,
+) -> Result<i64> {
+    let store_id = utils::get_user_id(&state).await?;
+    PointsRule::redeem_coupon(&state.pool, store_id, user_id, rule_id, count).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(max_ratio: Option<f64>) -> PointsRule {
+        PointsRule {
+            rule_type: Some(PointsRuleType::Discount),
+            points: 100,
+            amount: Some(1.),
+            max_ratio,
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_quote() {
+        let quote = rule(None).quote(1000, 350, 50.).unwrap();
+        assert_eq!(quote.points, 300);
+        assert_eq!(quote.discount, 3.);
+
+        // 不超过订单金额
+        let quote = rule(None).quote(1000, 1000, 4.5).unwrap();
+        assert_eq!(quote.points, 400);
+        assert_eq!(quote.discount, 4.);
+
+        // 不超过最高抵扣比例
+        let quote = rule(Some(10.)).quote(1000, 1000, 50.).unwrap();
+        assert_eq!(quote.points, 500);
+        assert_eq!(quote.discount, 5.);
+
+        assert!(rule(None).quote(100, 200, 50.).is_err());
+        assert!(rule(None).quote(1000, 50, 50.).is_err());
+    }
+}
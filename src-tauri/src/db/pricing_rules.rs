@@ -0,0 +1,695 @@
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite, Transaction};
+use tauri::State;
+
+use crate::constants::{PricingRuleType, PricingStacking, ServiceRequirmentType};
+use crate::db::adjust_price::OrderClothAdjust;
+use crate::db::cloth_price::ClothPrice;
+use crate::db::order_clothes::OrderCloth;
+use crate::db::orders::Order;
+use crate::db::{Curd, Validator};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+/// 计价规则
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PricingRule {
+    pub rule_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub rule_name: Option<String>,
+    pub rule_type: Option<PricingRuleType>,
+    /// 优先级，数值小的先计算
+    pub priority: i64,
+    pub stacking: Option<PricingStacking>,
+    /// 适用的服务要求，为空时不限制
+    pub service_requirement: Option<ServiceRequirmentType>,
+    /// 适用的品类，为空时不限制
+    pub category_id: Option<i64>,
+    /// 适用的款式，为空时不限制
+    pub style_id: Option<i64>,
+    /// 规则参数，含义见规则类型
+    pub value: Option<f64>,
+    /// 数量优惠的件数
+    pub quantity: Option<i64>,
+    /// 生效时间（毫秒）
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub enabled: bool,
+    pub create_time: Option<i64>,
+    pub update_time: Option<i64>,
+    pub remark: Option<String>,
+}
+
+/// 新门店的默认规则，与原有计价一致：(规则名称, 规则类型, 优先级, 服务要求, 参数)
+const DEFAULT_RULES: &[(
+    &str,
+    PricingRuleType,
+    i64,
+    Option<ServiceRequirmentType>,
+    Option<f64>,
+)] = &[
+    (
+        "加急",
+        PricingRuleType::ServiceMultiplier,
+        10,
+        Some(ServiceRequirmentType::Emergency),
+        Some(2.),
+    ),
+    (
+        "单洗",
+        PricingRuleType::ServiceMultiplier,
+        10,
+        Some(ServiceRequirmentType::SingleWash),
+        Some(1.5),
+    ),
+    ("会员折扣", PricingRuleType::LevelDiscount, 50, None, None),
+];
+
+/// 计价明细行，正数为加价，负数为优惠
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PriceLine {
+    /// 来源规则，价格标签和调价为空
+    pub rule_id: Option<i64>,
+    pub label: String,
+    pub amount: f64,
+}
+
+/// 单件衣物的计价明细
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ItemPrice {
+    pub cloth_id: Option<String>,
+    pub name: Option<String>,
+    pub base_price: f64,
+    pub lines: Vec<PriceLine>,
+    pub price: f64,
+}
+
+/// 订单计价明细，结账时随订单保存并打印在小票上
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PriceBreakdown {
+    pub items: Vec<ItemPrice>,
+    /// 订单级的优惠、价格标签和调价
+    pub lines: Vec<PriceLine>,
+    pub total: f64,
+}
+
+/// 计价所需的订单信息
+#[derive(Debug, Default)]
+pub struct PricingContext<'a> {
+    /// 已按优先级排序的规则
+    pub rules: &'a [PricingRule],
+    /// 会员等级名称及价格折扣（百分比）
+    pub level: Option<(String, f64)>,
+    pub price_tags: &'a [ClothPrice],
+    pub adjust: Option<&'a OrderClothAdjust>,
+    pub now: i64,
+}
+
+struct ItemState {
+    base: Decimal,
+    price: Decimal,
+    lines: Vec<PriceLine>,
+    /// 命中独占规则或已参与数量优惠后不再应用其他规则
+    locked: bool,
+}
+
+fn dec(value: f64) -> Decimal {
+    Decimal::from_f64(value).unwrap_or_default()
+}
+
+fn line(rule_id: Option<i64>, label: &str, amount: Decimal) -> PriceLine {
+    PriceLine {
+        rule_id,
+        label: label.to_string(),
+        amount: amount.to_f64().unwrap_or_default(),
+    }
+}
+
+impl PriceBreakdown {
+    /// 按优先级依次应用规则，再应用价格标签、会员折扣和调价
+    pub fn evaluate(ctx: &PricingContext<'_>, clothes: &[OrderCloth]) -> Result<Self> {
+        let mut items = clothes
+            .iter()
+            .map(|cloth| {
+                let base = dec(cloth.price_value.unwrap_or_default());
+                let mut item = ItemState {
+                    base,
+                    price: base,
+                    lines: Vec::new(),
+                    locked: cloth.price_value.is_none(),
+                };
+                let markup = dec(cloth.process_markup.unwrap_or_default());
+                if !item.locked && !markup.is_zero() {
+                    item.price += markup;
+                    item.lines.push(line(None, "工艺加价", markup));
+                }
+                item
+            })
+            .collect::<Vec<_>>();
+        let mut lines = Vec::new();
+        // 会员折扣在价格标签之后按订单金额计算
+        let mut level_rules = Vec::new();
+
+        for rule in ctx.rules.iter().filter(|rule| rule.in_effect(ctx.now)) {
+            if rule.rule_type == Some(PricingRuleType::LevelDiscount) {
+                level_rules.push(rule);
+                continue;
+            }
+            let label = rule.rule_name.as_deref().unwrap_or_default();
+            let value = dec(rule.value.unwrap_or_default());
+            let exclusive = rule.stacking == Some(PricingStacking::Exclusive);
+            let matched = clothes
+                .iter()
+                .enumerate()
+                .filter(|(i, cloth)| !items[*i].locked && rule.matches(cloth))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+
+            if rule.rule_type == Some(PricingRuleType::QuantityBreak) {
+                // 优先将单价高的衣物凑成组合，仅在比原价便宜时生效
+                let quantity = rule.quantity.unwrap_or_default().max(0) as usize;
+                if quantity == 0 {
+                    continue;
+                }
+                let mut matched = matched;
+                matched.sort_by(|a, b| items[*b].price.cmp(&items[*a].price));
+                let bundles = matched.len() / quantity;
+                let bundled = &matched[..bundles * quantity];
+                let original = bundled.iter().map(|i| items[*i].price).sum::<Decimal>();
+                let saving = original - value * Decimal::from(bundles);
+                if saving > Decimal::ZERO {
+                    lines.push(line(rule.rule_id, label, -saving));
+                    for i in bundled {
+                        items[*i].locked = true;
+                    }
+                }
+                continue;
+            }
+
+            for i in matched {
+                let item = &mut items[i];
+                let amount = match rule.rule_type {
+                    Some(PricingRuleType::ServiceMultiplier) => item.base * (value - Decimal::ONE),
+                    Some(PricingRuleType::Surcharge) => value,
+                    Some(PricingRuleType::TimePromotion) => {
+                        -(item.price * (Decimal::ONE_HUNDRED - value) / Decimal::ONE_HUNDRED)
+                    }
+                    _ => continue,
+                }
+                .round_dp(2);
+                if amount.is_zero() {
+                    continue;
+                }
+                item.price += amount;
+                item.lines.push(line(rule.rule_id, label, amount));
+                item.locked |= exclusive;
+            }
+        }
+
+        let mut total = items.iter().map(|item| item.price).sum::<Decimal>()
+            + lines
+                .iter()
+                .map(|line: &PriceLine| dec(line.amount))
+                .sum::<Decimal>();
+        let rules_total = total;
+
+        // 价格标签：指定价格时直接改价，否则按折扣比例优惠
+        for tag in ctx.price_tags {
+            let label = tag.price_name.as_deref().unwrap_or("价格标签");
+            let amount = if let Some(price_value) = tag.price_value {
+                dec(price_value) - total
+            } else if let Some(price_discount) = tag.price_discount {
+                -(total * dec(price_discount) / Decimal::ONE_HUNDRED).round_dp(2)
+            } else {
+                return Err(Error::internal("Price tag configuration error"));
+            };
+            total += amount;
+            lines.push(line(None, label, amount));
+        }
+
+        // 会员折扣：按适用衣物在规则计价后金额中的占比，对价格标签后的金额打折。
+        // 命中独占规则或已参与数量优惠的衣物不打折
+        if let Some((level_name, discount)) = &ctx.level {
+            let mut discounted = vec![false; items.len()];
+            for rule in level_rules {
+                if rules_total <= Decimal::ZERO {
+                    break;
+                }
+                let mut eligible = Decimal::ZERO;
+                for (i, cloth) in clothes.iter().enumerate() {
+                    if !items[i].locked && !discounted[i] && rule.matches(cloth) {
+                        discounted[i] = true;
+                        eligible += items[i].price;
+                    }
+                }
+                let amount = -(total * eligible / rules_total
+                    * (Decimal::ONE_HUNDRED - dec(*discount))
+                    / Decimal::ONE_HUNDRED)
+                    .round_dp(2);
+                if amount.is_zero() {
+                    continue;
+                }
+                let label = rule.rule_name.as_deref().unwrap_or_default();
+                total += amount;
+                lines.push(line(
+                    rule.rule_id,
+                    &format!("{label}（{level_name}）"),
+                    amount,
+                ));
+            }
+        }
+
+        if let Some(adjust) = ctx.adjust {
+            if let Some(adjust_total) = adjust.adjust_total {
+                let amount = dec(adjust_total) - total;
+                total += amount;
+                lines.push(line(None, "调价", amount));
+            } else {
+                let add = dec(adjust.adjust_value_add.unwrap_or_default());
+                let sub = dec(adjust.adjust_value_sub.unwrap_or_default());
+                if !add.is_zero() {
+                    total += add;
+                    lines.push(line(None, "加价", add));
+                }
+                if !sub.is_zero() {
+                    total -= sub;
+                    lines.push(line(None, "减价", -sub));
+                }
+            }
+        }
+
+        // 始终执行一次截断，确保精度一致
+        let total = total.round_dp_with_strategy(2, RoundingStrategy::ToZero);
+
+        Ok(Self {
+            items: clothes
+                .iter()
+                .zip(items)
+                .map(|(cloth, item)| ItemPrice {
+                    cloth_id: cloth.cloth_id.clone(),
+                    name: cloth
+                        .cloth_info
+                        .as_ref()
+                        .and_then(|info| info.title.clone()),
+                    base_price: item.base.to_f64().unwrap_or_default(),
+                    lines: item.lines,
+                    price: item.price.to_f64().unwrap_or_default(),
+                })
+                .collect(),
+            lines,
+            total: total.to_f64().unwrap_or_default().max(0.0),
+        })
+    }
+}
+
+impl Validator for PricingRule {
+    fn validate(&self) -> Result<()> {
+        if self
+            .rule_name
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .is_empty()
+        {
+            return Err(Error::bad_request("规则名称不能为空"));
+        }
+        if let (Some(start), Some(end)) = (self.start_time, self.end_time) {
+            if start > end {
+                return Err(Error::bad_request("开始时间不能晚于结束时间"));
+            }
+        }
+        let value = self.value.unwrap_or_default();
+        match self.rule_type {
+            None => return Err(Error::bad_request("请选择规则类型")),
+            Some(PricingRuleType::ServiceMultiplier) => {
+                if self.service_requirement.is_none() {
+                    return Err(Error::bad_request("请选择服务要求"));
+                }
+                if value <= 0. {
+                    return Err(Error::bad_request("倍率必须大于 0"));
+                }
+            }
+            Some(PricingRuleType::Surcharge) => {
+                if self.value.is_none() {
+                    return Err(Error::bad_request("请设置加价金额"));
+                }
+            }
+            Some(PricingRuleType::LevelDiscount) => {}
+            Some(PricingRuleType::QuantityBreak) => {
+                if self.quantity.unwrap_or_default() < 2 {
+                    return Err(Error::bad_request("优惠件数至少为 2 件"));
+                }
+                if value <= 0. {
+                    return Err(Error::bad_request("请设置优惠价格"));
+                }
+            }
+            Some(PricingRuleType::TimePromotion) => {
+                if value <= 0. || value > 100. {
+                    return Err(Error::bad_request("折扣需在 0 到 100 之间"));
+                }
+                if self.start_time.is_none() || self.end_time.is_none() {
+                    return Err(Error::bad_request("请设置活动时间"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Curd for PricingRule {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM pricing_rules WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM pricing_rules WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM pricing_rules WHERE rule_id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM pricing_rules WHERE rule_id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY priority, rule_id");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(rule_type) = &self.rule_type {
+            builder.push(" AND rule_type = ").push_bind(rule_type);
+        }
+    }
+}
+
+impl PricingRule {
+    pub async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self> {
+        let now = utils::get_timestamp();
+        let result = sqlx::query_as(
+            "INSERT INTO pricing_rules (store_id, rule_name, rule_type, priority, stacking,
+                service_requirement, category_id, style_id, value, quantity, start_time, end_time,
+                enabled, create_time, update_time, remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(self.rule_name.as_deref().map(str::trim))
+        .bind(self.rule_type)
+        .bind(self.priority)
+        .bind(self.stacking.unwrap_or_default())
+        .bind(&self.service_requirement)
+        .bind(self.category_id)
+        .bind(self.style_id)
+        .bind(self.value)
+        .bind(self.quantity)
+        .bind(self.start_time)
+        .bind(self.end_time)
+        .bind(self.enabled)
+        .bind(now)
+        .bind(now)
+        .bind(&self.remark)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 为新门店创建默认规则，已有门店的默认规则由迁移脚本创建
+    pub async fn seed_defaults(tr: &mut Transaction<'_, Sqlite>, store_id: i64) -> Result<()> {
+        let now = utils::get_timestamp();
+        for (rule_name, rule_type, priority, service_requirement, value) in DEFAULT_RULES {
+            sqlx::query(
+                "INSERT INTO pricing_rules (store_id, rule_name, rule_type, priority,
+                    service_requirement, value, create_time, update_time)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(store_id)
+            .bind(rule_name)
+            .bind(rule_type)
+            .bind(priority)
+            .bind(service_requirement)
+            .bind(value)
+            .bind(now)
+            .bind(now)
+            .execute(&mut **tr)
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn update(&self, pool: &Pool<Sqlite>) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE pricing_rules SET rule_name = ?, rule_type = ?, priority = ?, stacking = ?,
+                service_requirement = ?, category_id = ?, style_id = ?, value = ?, quantity = ?,
+                start_time = ?, end_time = ?, enabled = ?, update_time = ?, remark = ?
+            WHERE rule_id = ? AND store_id = ?",
+        )
+        .bind(self.rule_name.as_deref().map(str::trim))
+        .bind(self.rule_type)
+        .bind(self.priority)
+        .bind(self.stacking.unwrap_or_default())
+        .bind(&self.service_requirement)
+        .bind(self.category_id)
+        .bind(self.style_id)
+        .bind(self.value)
+        .bind(self.quantity)
+        .bind(self.start_time)
+        .bind(self.end_time)
+        .bind(self.enabled)
+        .bind(utils::get_timestamp())
+        .bind(&self.remark)
+        .bind(self.rule_id)
+        .bind(self.store_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 门店已启用的规则，按优先级排序
+    pub async fn list_enabled(pool: &Pool<Sqlite>, store_id: i64) -> Result<Vec<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM pricing_rules WHERE store_id = ? AND enabled = 1
+            ORDER BY priority, rule_id",
+        )
+        .bind(store_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 规则是否适用于该衣物
+    pub fn matches(&self, cloth: &OrderCloth) -> bool {
+        (self.service_requirement.is_none()
+            || self.service_requirement == cloth.service_requirement)
+            && (self.category_id.is_none() || self.category_id == cloth.category_id)
+            && (self.style_id.is_none() || self.style_id == cloth.style_id)
+    }
+
+    /// 规则在该时间是否生效
+    pub fn in_effect(&self, now: i64) -> bool {
+        self.enabled
+            && self.start_time.is_none_or(|start| start <= now)
+            && self.end_time.is_none_or(|end| now <= end)
+    }
+}
+
+#[tauri::command]
+pub async fn get_pricing_rules(state: State<'_, AppState>) -> Result<Vec<PricingRule>> {
+    let store_id = utils::get_user_id(&state).await?;
+    PricingRule {
+        store_id: Some(store_id),
+        ..Default::default()
+    }
+    .get_all(&state.pool)
+    .await
+}
+
+#[tauri::command]
+pub async fn create_pricing_rule(
+    state: State<'_, AppState>,
+    mut rule: PricingRule,
+) -> Result<PricingRule> {
+    rule.validate()?;
+    rule.store_id = Some(utils::get_user_id(&state).await?);
+    rule.create(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn update_pricing_rule(
+    state: State<'_, AppState>,
+    mut rule: PricingRule,
+) -> Result<bool> {
+    rule.validate()?;
+    rule.store_id = Some(utils::get_user_id(&state).await?);
+    rule.update(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn delete_pricing_rules(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
+    let mut tx = state.pool.begin().await?;
+    let result = PricingRule::delete_batch(&mut tx, &ids).await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+/// 订单计价明细：已结账的订单返回结账时保存的明细，否则按当前规则试算
+#[tauri::command]
+pub async fn get_order_price_breakdown(
+    state: State<'_, AppState>,
+    order_id: i64,
+) -> Result<PriceBreakdown> {
+    let store_id = utils::get_user_id(&state).await?;
+    let order = Order::get_by_id(&state.pool, store_id, order_id)
+        .await?
+        .ok_or(Error::not_found("订单不存在"))?;
+    if let Some(breakdown) = Order::get_price_breakdown(&state.pool, order_id).await? {
+        return Ok(breakdown);
+    }
+    let clothes = OrderCloth::get_by_order_id(&state.pool, order_id).await?;
+    Order::price_breakdown(&state.pool, &order, &clothes).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cloth(cloth_id: &str, price: f64, service: ServiceRequirmentType) -> OrderCloth {
+        OrderCloth {
+            cloth_id: Some(cloth_id.to_string()),
+            category_id: Some(1),
+            price_value: Some(price),
+            service_requirement: Some(service),
+            ..Default::default()
+        }
+    }
+
+    fn rule(rule_id: i64, rule_type: PricingRuleType, value: f64) -> PricingRule {
+        PricingRule {
+            rule_id: Some(rule_id),
+            rule_name: Some(rule_type.to_string()),
+            rule_type: Some(rule_type),
+            value: Some(value),
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let clothes = vec![
+            cloth("a", 20., ServiceRequirmentType::Emergency),
+            cloth("b", 15., ServiceRequirmentType::Normal),
+            cloth("c", 15., ServiceRequirmentType::Normal),
+            cloth("d", 10., ServiceRequirmentType::Normal),
+        ];
+        let rules = vec![
+            PricingRule {
+                service_requirement: Some(ServiceRequirmentType::Emergency),
+                stacking: Some(PricingStacking::Exclusive),
+                ..rule(1, PricingRuleType::ServiceMultiplier, 2.)
+            },
+            PricingRule {
+                quantity: Some(2),
+                ..rule(2, PricingRuleType::QuantityBreak, 25.)
+            },
+            rule(3, PricingRuleType::LevelDiscount, 0.),
+        ];
+        let ctx = PricingContext {
+            rules: &rules,
+            level: Some(("黄金会员".to_string(), 90.)),
+            ..Default::default()
+        };
+        let breakdown = PriceBreakdown::evaluate(&ctx, &clothes).unwrap();
+
+        // 加急 ×2 后独占，不再打会员折扣
+        assert_eq!(breakdown.items[0].price, 40.);
+        // 两件 15 元组合为 25 元，剩余一件打 9 折
+        assert_eq!(breakdown.lines[0].amount, -5.);
+        assert_eq!(breakdown.items[3].price, 10.);
+        assert_eq!(breakdown.lines[1].amount, -1.);
+        assert_eq!(breakdown.total, 74.);
+
+        // 价格标签在会员折扣之前计算，调价最后计算：
+        // 75 打 9 折为 67.5，剩余一件占 10/75，会员折扣 0.9，再减 4 元
+        let adjust = OrderClothAdjust {
+            adjust_value_sub: Some(4.),
+            ..Default::default()
+        };
+        let tags = vec![ClothPrice {
+            price_discount: Some(10.),
+            ..Default::default()
+        }];
+        let ctx = PricingContext {
+            price_tags: &tags,
+            adjust: Some(&adjust),
+            ..ctx
+        };
+        let breakdown = PriceBreakdown::evaluate(&ctx, &clothes).unwrap();
+        let amounts = breakdown
+            .lines
+            .iter()
+            .map(|line| line.amount)
+            .collect::<Vec<_>>();
+        assert_eq!(amounts, vec![-5., -7.5, -0.9, -4.]);
+        assert_eq!(breakdown.total, 62.6);
+    }
+
+    #[test]
+    fn test_default_rules() {
+        // 默认规则与原有计价一致：加急 ×2、单洗 ×1.5，价格标签后再打会员折扣
+        let rules = DEFAULT_RULES
+            .iter()
+            .enumerate()
+            .map(
+                |(i, (rule_name, rule_type, priority, service, value))| PricingRule {
+                    rule_id: Some(i as i64 + 1),
+                    rule_name: Some(rule_name.to_string()),
+                    rule_type: Some(*rule_type),
+                    priority: *priority,
+                    service_requirement: service.clone(),
+                    value: *value,
+                    enabled: true,
+                    ..Default::default()
+                },
+            )
+            .collect::<Vec<_>>();
+        assert!(rules.iter().all(|rule| rule.validate().is_ok()));
+
+        let clothes = vec![
+            cloth("a", 20., ServiceRequirmentType::Emergency),
+            cloth("b", 10., ServiceRequirmentType::SingleWash),
+            cloth("c", 10., ServiceRequirmentType::Normal),
+        ];
+        let tags = vec![ClothPrice {
+            price_value: Some(50.),
+            ..Default::default()
+        }];
+        let ctx = PricingContext {
+            rules: &rules,
+            level: Some(("黄金会员".to_string(), 80.)),
+            price_tags: &tags,
+            ..Default::default()
+        };
+        // 40 + 15 + 10 = 65，价格标签改为 50，会员 8 折为 40
+        assert_eq!(PriceBreakdown::evaluate(&ctx, &clothes).unwrap().total, 40.);
+    }
+
+    #[test]
+    fn test_time_promotion_window() {
+        let clothes = vec![cloth("a", 30., ServiceRequirmentType::Normal)];
+        let rules = vec![PricingRule {
+            start_time: Some(100),
+            end_time: Some(200),
+            ..rule(1, PricingRuleType::TimePromotion, 80.)
+        }];
+        let mut ctx = PricingContext {
+            rules: &rules,
+            now: 150,
+            ..Default::default()
+        };
+        assert_eq!(PriceBreakdown::evaluate(&ctx, &clothes).unwrap().total, 24.);
+        ctx.now = 300;
+        assert_eq!(PriceBreakdown::evaluate(&ctx, &clothes).unwrap().total, 30.);
+    }
+}
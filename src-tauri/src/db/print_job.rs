@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite};
+use tauri::State;
+
+use crate::constants::{PrintJobStatus, PrintJobType};
+use crate::db::printer::PrinterConfiguration;
+use crate::db::{Curd, PageParams, PageResult};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+/// 默认最大打印次数（含首次）
+pub const DEFAULT_MAX_ATTEMPTS: i64 = 5;
+
+/// 打印任务，payload 为打印内容的 JSON，可用于失败重试和补打
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PrintJob {
+    pub job_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub job_type: Option<PrintJobType>,
+    /// 关联单号：小票为订单号，标签为衣物编码
+    pub ref_no: Option<String>,
+    /// 入队时使用的打印机名称
+    pub printer_name: Option<String>,
+    /// 入队时的打印机配置（JSON）
+    pub printer_config: Option<String>,
+    pub payload: String,
+    pub status: Option<PrintJobStatus>,
+    pub attempts: i64,
+    /// 标签任务已打印的张数
+    pub printed: i64,
+    pub max_attempts: i64,
+    pub last_error: Option<String>,
+    /// 下次重试时间，为空时立即执行
+    pub next_retry_time: Option<i64>,
+    pub create_time: Option<i64>,
+    pub update_time: Option<i64>,
+}
+
+impl Curd for PrintJob {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM print_jobs WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM print_jobs WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM print_jobs WHERE job_id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM print_jobs WHERE job_id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY job_id DESC");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(job_type) = &self.job_type {
+            builder.push(" AND job_type = ").push_bind(job_type);
+        }
+
+        if let Some(status) = &self.status {
+            builder.push(" AND status = ").push_bind(status);
+        }
+
+        if let Some(ref_no) = &self.ref_no {
+            builder
+                .push(" AND ref_no LIKE ")
+                .push_bind(format!("%{}%", ref_no));
+        }
+    }
+}
+
+impl PrintJob {
+    pub fn new(
+        store_id: i64,
+        job_type: PrintJobType,
+        ref_no: String,
+        printer: &PrinterConfiguration,
+        payload: String,
+    ) -> Self {
+        Self {
+            store_id: Some(store_id),
+            job_type: Some(job_type),
+            ref_no: Some(ref_no),
+            printer_name: Some(printer.name.clone()),
+            printer_config: serde_json::to_string(printer).ok(),
+            payload,
+            status: Some(PrintJobStatus::Pending),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            ..Default::default()
+        }
+    }
+
+    pub async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self> {
+        let now = utils::get_timestamp();
+        let result = sqlx::query_as(
+            "INSERT INTO print_jobs (store_id, job_type, ref_no, printer_name, printer_config,
+                payload, status, attempts, max_attempts, create_time, update_time)
+            VALUES (?, ?, ?, ?, ?, ?, ?, 0, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(self.job_type)
+        .bind(&self.ref_no)
+        .bind(&self.printer_name)
+        .bind(&self.printer_config)
+        .bind(&self.payload)
+        .bind(PrintJobStatus::Pending)
+        .bind(self.max_attempts.max(1))
+        .bind(now)
+        .bind(now)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 获取门店已到重试时间的待打印任务
+    pub async fn list_due(pool: &Pool<Sqlite>, store_id: i64) -> Result<Vec<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM print_jobs
+            WHERE store_id = ? AND status = ? AND (next_retry_time IS NULL OR next_retry_time <= ?)
+            ORDER BY job_id LIMIT 20",
+        )
+        .bind(store_id)
+        .bind(PrintJobStatus::Pending)
+        .bind(utils::get_timestamp())
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 将任务标记为打印中，任务已被其他流程领取时返回 false
+    pub async fn claim(pool: &Pool<Sqlite>, job_id: i64) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE print_jobs SET status = ?, attempts = attempts + 1, update_time = ?
+            WHERE job_id = ? AND status = ?",
+        )
+        .bind(PrintJobStatus::Printing)
+        .bind(utils::get_timestamp())
+        .bind(job_id)
+        .bind(PrintJobStatus::Pending)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn complete(pool: &Pool<Sqlite>, job_id: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE print_jobs SET status = ?, last_error = NULL, next_retry_time = NULL, update_time = ?
+            WHERE job_id = ?",
+        )
+        .bind(PrintJobStatus::Completed)
+        .bind(utils::get_timestamp())
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 记录标签任务已打印的张数
+    pub async fn advance(pool: &Pool<Sqlite>, job_id: i64, printed: i64) -> Result<()> {
+        sqlx::query("UPDATE print_jobs SET printed = ?, update_time = ? WHERE job_id = ?")
+            .bind(printed)
+            .bind(utils::get_timestamp())
+            .bind(job_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 记录失败原因，next_retry_time 为空时任务不再自动重试
+    pub async fn fail(
+        pool: &Pool<Sqlite>,
+        job_id: i64,
+        error: &str,
+        next_retry_time: Option<i64>,
+    ) -> Result<()> {
+        let status = if next_retry_time.is_some() {
+            PrintJobStatus::Pending
+        } else {
+            PrintJobStatus::Failed
+        };
+        sqlx::query(
+            "UPDATE print_jobs SET status = ?, last_error = ?, next_retry_time = ?, update_time = ?
+            WHERE job_id = ?",
+        )
+        .bind(status)
+        .bind(error)
+        .bind(next_retry_time)
+        .bind(utils::get_timestamp())
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 失败或已取消的任务重新入队，重试次数清零
+    pub async fn requeue(pool: &Pool<Sqlite>, store_id: i64, job_id: i64) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE print_jobs SET status = ?, attempts = 0, next_retry_time = NULL, update_time = ?
+            WHERE job_id = ? AND store_id = ? AND status IN (?, ?)",
+        )
+        .bind(PrintJobStatus::Pending)
+        .bind(utils::get_timestamp())
+        .bind(job_id)
+        .bind(store_id)
+        .bind(PrintJobStatus::Failed)
+        .bind(PrintJobStatus::Cancelled)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn cancel(pool: &Pool<Sqlite>, store_id: i64, job_id: i64) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE print_jobs SET status = ?, next_retry_time = NULL, update_time = ?
+            WHERE job_id = ? AND store_id = ? AND status IN (?, ?)",
+        )
+        .bind(PrintJobStatus::Cancelled)
+        .bind(utils::get_timestamp())
+        .bind(job_id)
+        .bind(store_id)
+        .bind(PrintJobStatus::Pending)
+        .bind(PrintJobStatus::Failed)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 程序异常退出时可能遗留打印中的任务，启动时重新放回队列
+    pub async fn reset_interrupted(pool: &Pool<Sqlite>, store_id: i64) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE print_jobs SET status = ?, update_time = ? WHERE store_id = ? AND status = ?",
+        )
+        .bind(PrintJobStatus::Pending)
+        .bind(utils::get_timestamp())
+        .bind(store_id)
+        .bind(PrintJobStatus::Printing)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// 获取当前门店的任务
+    pub async fn get_for_store(pool: &Pool<Sqlite>, store_id: i64, job_id: i64) -> Result<Self> {
+        Self::get_by_id(pool, job_id)
+            .await?
+            .filter(|job| job.store_id == Some(store_id))
+            .ok_or(Error::not_found("打印任务不存在"))
+    }
+
+    /// 删除当前门店的任务，其他门店的任务忽略
+    pub async fn delete_for_store(pool: &Pool<Sqlite>, store_id: i64, ids: &[i64]) -> Result<bool> {
+        if ids.is_empty() {
+            return Ok(true);
+        }
+
+        let mut builder = QueryBuilder::<Sqlite>::new("DELETE FROM print_jobs WHERE store_id = ");
+        builder.push_bind(store_id);
+
+        builder.push(" AND job_id IN (");
+        ids.iter().enumerate().for_each(|(i, id)| {
+            if i > 0 {
+                builder.push(", ");
+            }
+            builder.push_bind(id);
+        });
+
+        builder.push(")");
+
+        let result = builder.build().execute(pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[tauri::command]
+pub async fn get_print_jobs_pagination(
+    state: State<'_, AppState>,
+    page_params: PageParams,
+    mut job: PrintJob,
+) -> Result<PageResult<PrintJob>> {
+    let store_id = utils::get_user_id(&state).await?;
+    job.store_id = Some(store_id);
+    job.get_list(&state.pool, page_params).await
+}
+
+#[tauri::command]
+pub async fn get_print_job_by_id(state: State<'_, AppState>, id: i64) -> Result<PrintJob> {
+    let store_id = utils::get_user_id(&state).await?;
+    PrintJob::get_for_store(&state.pool, store_id, id).await
+}
+
+#[tauri::command]
+pub async fn cancel_print_job(state: State<'_, AppState>, id: i64) -> Result<bool> {
+    let store_id = utils::get_user_id(&state).await?;
+    PrintJob::cancel(&state.pool, store_id, id).await
+}
+
+#[tauri::command]
+pub async fn delete_print_jobs(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
+    let store_id = utils::get_user_id(&state).await?;
+    PrintJob::delete_for_store(&state.pool, store_id, &ids).await
+}
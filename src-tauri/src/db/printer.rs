@@ -1,15 +1,33 @@
 use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
 use tauri::State;
 
-use crate::error::Result;
+use crate::constants::PrinterLanguage;
+use crate::db::print_job::PrintJob;
+use crate::error::{Error, ErrorKind, Result};
 use crate::state::AppState;
 
+fn default_cut_paper() -> bool {
+    true
+}
+
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct PrinterConfiguration {
     pub name: String,
     pub system_name: String,
     pub driver_name: String,
     pub printer_type: String, // 'business' 或 'receipt'
+    #[serde(default)]
+    pub language: PrinterLanguage,
+    // 原始指令输出目标，为空时发送到系统打印机，支持 tcp://ip:port 和 file://path
+    #[serde(default)]
+    pub target: Option<String>,
+    // 打印小票后切纸
+    #[serde(default = "default_cut_paper")]
+    pub cut_paper: bool,
+    // 打印小票后弹出钱箱
+    #[serde(default)]
+    pub open_drawer: bool,
 }
 
 impl PrinterConfiguration {
@@ -24,6 +42,42 @@ impl PrinterConfiguration {
             system_name,
             driver_name,
             printer_type,
+            language: PrinterLanguage::default(),
+            target: None,
+            cut_paper: default_cut_paper(),
+            open_drawer: false,
+        }
+    }
+
+    pub async fn get_by_type(pool: &Pool<Sqlite>, printer_type: &str) -> Result<Option<Self>> {
+        let device = sqlx::query_as::<_, PrinterConfiguration>(
+            "SELECT * FROM printers WHERE printer_type = ? LIMIT 1",
+        )
+        .bind(printer_type)
+        .fetch_optional(pool)
+        .await?;
+        Ok(device)
+    }
+
+    /// 打印任务使用的打印机配置，按入队时保存的配置发送；早期任务未保存配置时，
+    /// 只有记录的打印机仍是当前配置才使用当前配置，否则任务失败
+    pub async fn get_for_job(
+        pool: &Pool<Sqlite>,
+        printer_type: &str,
+        job: &PrintJob,
+    ) -> Result<Self> {
+        if let Some(config) = job.printer_config.as_deref() {
+            return Ok(serde_json::from_str(config)?);
+        }
+        let current = Self::get_by_type(pool, printer_type)
+            .await?
+            .ok_or(Error::with_kind(ErrorKind::PrinterNotSet))?;
+        match job.printer_name.as_deref() {
+            Some(name) if name != current.name => Err(Error::with_details(
+                ErrorKind::PrinterNotFound,
+                format!("打印任务使用的打印机 {name} 已不是当前配置的打印机，请重新打印"),
+            )),
+            _ => Ok(current),
         }
     }
 }
@@ -49,25 +103,23 @@ pub async fn get_settled_printer(
     state: State<'_, AppState>,
     printer_type: String,
 ) -> Result<Option<PrinterConfiguration>> {
-    let device = sqlx::query_as::<_, PrinterConfiguration>(
-        "SELECT * FROM printers WHERE printer_type = ? LIMIT 1",
-    )
-    .bind(&printer_type)
-    .fetch_optional(&state.pool)
-    .await?;
-    Ok(device)
+    PrinterConfiguration::get_by_type(&state.pool, &printer_type).await
 }
 
 #[tauri::command]
 pub async fn set_printer(state: State<'_, AppState>, printer: PrinterConfiguration) -> Result<()> {
     sqlx::query(
-        "INSERT OR REPLACE INTO printers (id, name, system_name, driver_name, printer_type) VALUES ((SELECT id FROM printers WHERE printer_type = ?), ?, ?, ?, ?)"
+        "INSERT OR REPLACE INTO printers (id, name, system_name, driver_name, printer_type, language, target, cut_paper, open_drawer) VALUES ((SELECT id FROM printers WHERE printer_type = ?), ?, ?, ?, ?, ?, ?, ?, ?)"
     )
     .bind(&printer.printer_type)
     .bind(&printer.name)
     .bind(&printer.system_name)
     .bind(&printer.driver_name)
     .bind(&printer.printer_type)
+    .bind(printer.language)
+    .bind(&printer.target)
+    .bind(printer.cut_paper)
+    .bind(printer.open_drawer)
     .execute(&state.pool)
     .await?;
     Ok(())
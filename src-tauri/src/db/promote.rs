@@ -0,0 +1,696 @@
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, FixedOffset};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite};
+use tauri::State;
+
+use crate::constants::{NoticeChannel, PaymentOrderType, PaymentStatus};
+use crate::db::notice_temp::NoticeRecord;
+use crate::db::user::User;
+use crate::db::{Curd, PageParams, PageResult, Validator};
+use crate::error::{Error, Result};
+use crate::notice::template::{self, Template};
+use crate::notice::{NoticeDispatcher, Outgoing, RESULT_SENDING, Recipient};
+use crate::state::AppState;
+use crate::utils;
+
+/// 模板类型字典值：推广
+const TEMP_TYPE_PROMOTE: &str = "1";
+
+/// 推广结果字典值
+const PROMOTE_SUCCESS: &str = "0";
+const PROMOTE_FAILED: &str = "1";
+const PROMOTE_PARTIAL: &str = "2";
+
+/// 推广方式字典值对应的通知渠道：01 短信，00 小程序及 02 其他发站内消息，由小程序同步给会员
+fn channel_of(promote_method: &str) -> NoticeChannel {
+    match promote_method {
+        "01" => NoticeChannel::Sms,
+        _ => NoticeChannel::InApp,
+    }
+}
+
+/// 目标人群筛选条件，各条件之间为“且”的关系，未设置的条件不限制
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct Audience {
+    /// 会员画像，命中任一即可
+    pub tags: Vec<String>,
+    /// 会员等级，命中任一即可
+    pub level_ids: Vec<i64>,
+    /// 距最近一次下单至少多少天，从未下单的会员也包含在内
+    pub min_idle_days: Option<i64>,
+    /// 距最近一次下单至多多少天
+    pub max_idle_days: Option<i64>,
+    /// 累计实付金额区间
+    pub min_spend: Option<f64>,
+    pub max_spend: Option<f64>,
+}
+
+/// 目标人群预估
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudiencePreview {
+    pub total: i64,
+    /// 已退订通知的会员数，发送时会被跳过
+    pub opted_out: i64,
+}
+
+impl Audience {
+    fn push_query<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>, store_id: i64) {
+        let now = utils::get_now();
+        builder.push(
+            " FROM users u
+            LEFT JOIN user_tags t ON t.user_id = u.user_id
+            LEFT JOIN (SELECT user_id, MAX(create_time) AS last_visit FROM orders
+                WHERE store_id = ",
+        );
+        builder.push_bind(store_id).push(
+            " GROUP BY user_id) v ON v.user_id = u.user_id
+            LEFT JOIN (SELECT o.user_id, SUM(p.total_amount) AS spend FROM payments p
+                JOIN orders o ON o.order_id = p.uc_order_id
+                WHERE p.store_id = ",
+        );
+        builder
+            .push_bind(store_id)
+            .push(" AND p.order_type = ")
+            .push_bind(PaymentOrderType::Laundry)
+            .push(" AND p.payment_status = ")
+            .push_bind(PaymentStatus::Paid)
+            .push(" GROUP BY o.user_id) s ON s.user_id = u.user_id");
+
+        builder
+            .push(" WHERE u.del_flag = '0' AND u.store_id = ")
+            .push_bind(store_id);
+
+        if !self.tags.is_empty() {
+            builder.push(" AND (");
+            for (i, tag) in self.tags.iter().enumerate() {
+                if i > 0 {
+                    builder.push(" OR ");
+                }
+                builder
+                    .push("(',' || t.tags || ',') LIKE ")
+                    .push_bind(format!("%,{tag},%"));
+            }
+            builder.push(")");
+        }
+
+        // 会员可能有多个等级，用 EXISTS 避免同一会员出现多次
+        if !self.level_ids.is_empty() {
+            builder.push(
+                " AND EXISTS (SELECT 1 FROM user_membership_level up
+                    WHERE up.user_id = u.user_id AND up.level_id IN (",
+            );
+            let mut separated = builder.separated(", ");
+            for level_id in &self.level_ids {
+                separated.push_bind(level_id);
+            }
+            builder.push("))");
+        }
+
+        if let Some(days) = self.min_idle_days {
+            builder
+                .push(" AND (v.last_visit IS NULL OR v.last_visit <= ")
+                .push_bind(now - Duration::days(days))
+                .push(")");
+        }
+
+        if let Some(days) = self.max_idle_days {
+            builder
+                .push(" AND v.last_visit >= ")
+                .push_bind(now - Duration::days(days));
+        }
+
+        if let Some(min_spend) = self.min_spend {
+            builder
+                .push(" AND COALESCE(s.spend, 0) >= ")
+                .push_bind(min_spend);
+        }
+
+        if let Some(max_spend) = self.max_spend {
+            builder
+                .push(" AND COALESCE(s.spend, 0) <= ")
+                .push_bind(max_spend);
+        }
+    }
+
+    pub async fn preview(&self, pool: &Pool<Sqlite>, store_id: i64) -> Result<AudiencePreview> {
+        let mut builder = QueryBuilder::new(
+            "SELECT COUNT(*) AS total, COALESCE(SUM(u.notice_opt_out), 0) AS opted_out",
+        );
+        self.push_query(&mut builder, store_id);
+        let (total, opted_out): (i64, i64) = builder.build_query_as().fetch_one(pool).await?;
+        Ok(AudiencePreview { total, opted_out })
+    }
+
+    pub async fn list_users(&self, pool: &Pool<Sqlite>, store_id: i64) -> Result<Vec<User>> {
+        let mut builder = QueryBuilder::new("SELECT u.*");
+        self.push_query(&mut builder, store_id);
+        Ok(builder.build_query_as().fetch_all(pool).await?)
+    }
+}
+
+/// 推广模板（营销活动）
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PromoteTemplate {
+    pub id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub title: Option<String>,
+    /// 推广内容，可使用通知模板变量
+    pub content: Option<String>,
+    /// 推广类型，字典 sys_promote_type
+    pub promote_type: Option<String>,
+    /// 推广方式，字典 sys_promote_method
+    pub promote_method: Option<String>,
+    /// 已发送次数
+    pub promote_count: Option<i64>,
+    pub promote_objects: Option<String>,
+    pub create_time: Option<DateTime<FixedOffset>>,
+    pub promote_picture: Option<String>,
+    pub is_pin: Option<String>,
+    pub remark: Option<String>,
+    /// 目标人群筛选条件（JSON）
+    pub audience: Option<String>,
+    pub unit_cost: Option<f64>,
+    pub attribution_days: Option<i64>,
+}
+
+impl Validator for PromoteTemplate {
+    fn validate(&self) -> Result<()> {
+        if self
+            .title
+            .as_deref()
+            .is_none_or(|title| title.trim().is_empty())
+        {
+            return Err(Error::bad_request("活动标题不能为空"));
+        }
+        let content = self.content.as_deref().unwrap_or_default();
+        if content.trim().is_empty() {
+            return Err(Error::bad_request("推广内容不能为空"));
+        }
+        Template::parse(content)?;
+        if self.promote_type.is_none() {
+            return Err(Error::bad_request("请选择推广类型"));
+        }
+        if self.promote_method.is_none() {
+            return Err(Error::bad_request("请选择推广方式"));
+        }
+        self.parse_audience()?;
+        if self.unit_cost.unwrap_or_default() < 0. {
+            return Err(Error::bad_request("推送成本不能为负数"));
+        }
+        if self.attribution_days.unwrap_or(7) < 1 {
+            return Err(Error::bad_request("效果统计天数至少为 1 天"));
+        }
+        Ok(())
+    }
+}
+
+impl Curd for PromoteTemplate {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM promote_template WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM promote_template WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM promote_template WHERE id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM promote_template WHERE id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY is_pin DESC, id DESC");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(title) = &self.title {
+            builder
+                .push(" AND title LIKE ")
+                .push_bind(format!("%{}%", title));
+        }
+
+        if let Some(promote_type) = &self.promote_type {
+            builder.push(" AND promote_type = ").push_bind(promote_type);
+        }
+
+        if let Some(promote_method) = &self.promote_method {
+            builder
+                .push(" AND promote_method = ")
+                .push_bind(promote_method);
+        }
+    }
+}
+
+impl PromoteTemplate {
+    pub fn parse_audience(&self) -> Result<Audience> {
+        match self.audience.as_deref() {
+            Some(audience) if !audience.trim().is_empty() => Ok(serde_json::from_str(audience)
+                .map_err(|e| Error::bad_request(format!("目标人群条件格式错误: {e}")))?),
+            _ => Ok(Audience::default()),
+        }
+    }
+
+    pub async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self> {
+        let result = sqlx::query_as(
+            "INSERT INTO promote_template (store_id, title, content, promote_type, promote_method,
+                promote_count, create_time, promote_picture, is_pin, remark, audience, unit_cost,
+                attribution_days)
+            VALUES (?, ?, ?, ?, ?, 0, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(&self.title)
+        .bind(&self.content)
+        .bind(&self.promote_type)
+        .bind(&self.promote_method)
+        .bind(utils::get_now())
+        .bind(&self.promote_picture)
+        .bind(self.is_pin.as_deref().unwrap_or("0"))
+        .bind(&self.remark)
+        .bind(&self.audience)
+        .bind(self.unit_cost.unwrap_or_default())
+        .bind(self.attribution_days.unwrap_or(7))
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    pub async fn update(&self, pool: &Pool<Sqlite>) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE promote_template SET title = ?, content = ?, promote_type = ?,
+                promote_method = ?, promote_picture = ?, is_pin = ?, remark = ?, audience = ?,
+                unit_cost = ?, attribution_days = ?
+            WHERE id = ? AND store_id = ?",
+        )
+        .bind(&self.title)
+        .bind(&self.content)
+        .bind(&self.promote_type)
+        .bind(&self.promote_method)
+        .bind(&self.promote_picture)
+        .bind(self.is_pin.as_deref().unwrap_or("0"))
+        .bind(&self.remark)
+        .bind(&self.audience)
+        .bind(self.unit_cost.unwrap_or_default())
+        .bind(self.attribution_days.unwrap_or(7))
+        .bind(self.id)
+        .bind(self.store_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 按目标人群渲染并发送推广内容，生成推广记录
+    pub async fn send(&self, state: &AppState) -> Result<PromoteRecord> {
+        let pool = &state.pool;
+        let store = state.get_user_info().await.ok_or(Error::unauthorized())?;
+        let dispatcher = NoticeDispatcher::new(state).await?;
+        let store_id = dispatcher.store_id();
+        if self.store_id != Some(store_id) {
+            return Err(Error::not_found("推广活动不存在"));
+        }
+
+        let template = Template::parse(self.content.as_deref().unwrap_or_default())?;
+        let users = self.parse_audience()?.list_users(pool, store_id).await?;
+        if users.is_empty() {
+            return Err(Error::bad_request("目标人群为空"));
+        }
+
+        let channel = channel_of(self.promote_method.as_deref().unwrap_or_default());
+        let now = utils::get_now();
+        let mut items = Vec::with_capacity(users.len());
+        for user in &users {
+            let values = template::load_values(pool, &template, &store, user, None).await?;
+            items.push(Outgoing {
+                record: NoticeRecord {
+                    store_id: Some(store_id),
+                    user_id: user.user_id.unwrap_or_default(),
+                    notice_method: channel.method().map(str::to_string),
+                    notice_type: Some(TEMP_TYPE_PROMOTE.to_string()),
+                    notice_time: Some(now),
+                    title: self.title.clone(),
+                    content: Some(template.render(&values)),
+                    result: Some(RESULT_SENDING.to_string()),
+                    ..Default::default()
+                },
+                recipient: Recipient::from(user),
+                pickup_code: None,
+                picture: self.promote_picture.clone(),
+            });
+        }
+
+        let mut tr = pool.begin().await?;
+        for item in items.iter_mut() {
+            item.record.create(&mut tr).await?;
+        }
+        tr.commit().await?;
+
+        let success = dispatcher.dispatch_all(&items).await? as i64;
+        let total = items.len() as i64;
+        let status = match success {
+            0 => PROMOTE_FAILED,
+            s if s == total => PROMOTE_SUCCESS,
+            _ => PROMOTE_PARTIAL,
+        };
+
+        let mut tr = pool.begin().await?;
+        let record: PromoteRecord = sqlx::query_as(
+            "INSERT INTO promote_record (store_id, temp_id, promote_objects, promote_time, status,
+                total_count, success_count, cost)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(store_id)
+        .bind(self.id)
+        .bind(
+            users
+                .iter()
+                .filter_map(|user| user.user_id)
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+        )
+        .bind(now)
+        .bind(status)
+        .bind(total)
+        .bind(success)
+        .bind(success as f64 * self.unit_cost.unwrap_or_default())
+        .fetch_one(&mut *tr)
+        .await?;
+        sqlx::query("UPDATE promote_template SET promote_count = COALESCE(promote_count, 0) + 1 WHERE id = ?")
+            .bind(self.id)
+            .execute(&mut *tr)
+            .await?;
+        tr.commit().await?;
+        Ok(record)
+    }
+}
+
+/// 推广记录，每次发送一条
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct PromoteRecord {
+    pub id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub temp_id: Option<i64>,
+    /// 本次推送的会员ID，逗号分隔
+    pub promote_objects: Option<String>,
+    pub promote_time: Option<DateTime<FixedOffset>>,
+    /// 推广结果，字典 sys_promote_result
+    pub status: Option<String>,
+    pub total_count: Option<i64>,
+    pub success_count: Option<i64>,
+    pub cost: Option<f64>,
+}
+
+impl Curd for PromoteRecord {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM promote_record WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM promote_record WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM promote_record WHERE id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM promote_record WHERE id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY id DESC");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(temp_id) = &self.temp_id {
+            builder.push(" AND temp_id = ").push_bind(temp_id);
+        }
+
+        if let Some(status) = &self.status {
+            builder.push(" AND status = ").push_bind(status);
+        }
+    }
+}
+
+/// 推广效果：统计期内推送对象产生的洗衣订单和卡券购买
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromoteStats {
+    pub total_count: i64,
+    pub success_count: i64,
+    /// 下单会员数
+    pub converted_users: i64,
+    pub order_count: i64,
+    pub order_amount: f64,
+    pub coupon_count: i64,
+    pub coupon_amount: f64,
+    pub cost: f64,
+    /// 投入产出比 (收入 - 成本) / 成本，成本为 0 时为空
+    pub roi: Option<f64>,
+}
+
+fn push_user_ids<'a>(builder: &mut QueryBuilder<'a, Sqlite>, user_ids: &'a [i64]) {
+    builder.push(" IN (");
+    let mut separated = builder.separated(", ");
+    for user_id in user_ids {
+        separated.push_bind(user_id);
+    }
+    builder.push(")");
+}
+
+impl PromoteRecord {
+    pub fn parse_user_ids(&self) -> Vec<i64> {
+        self.promote_objects
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|id| id.trim().parse().ok())
+            .collect()
+    }
+
+    pub async fn stats(&self, pool: &Pool<Sqlite>) -> Result<PromoteStats> {
+        let store_id = self.store_id.unwrap_or_default();
+        let cost = self.cost.unwrap_or_default();
+        let mut stats = PromoteStats {
+            total_count: self.total_count.unwrap_or_default(),
+            success_count: self.success_count.unwrap_or_default(),
+            cost,
+            ..Default::default()
+        };
+
+        let user_ids = self.parse_user_ids();
+        let Some(start) = self.promote_time else {
+            return Ok(stats);
+        };
+        if user_ids.is_empty() {
+            return Ok(stats);
+        }
+
+        let days = match self.temp_id {
+            Some(temp_id) => PromoteTemplate::get_by_id(pool, temp_id)
+                .await?
+                .and_then(|temp| temp.attribution_days),
+            None => None,
+        }
+        .unwrap_or(7);
+        let end = start + Duration::days(days);
+
+        // 统计期内推送对象新建的洗衣订单及其实付金额
+        let mut builder = QueryBuilder::new(
+            "SELECT COUNT(DISTINCT o.user_id), COUNT(DISTINCT o.order_id),
+                COALESCE(SUM(p.total_amount), 0)
+            FROM orders o
+            LEFT JOIN payments p ON p.uc_order_id = o.order_id AND p.order_type = ",
+        );
+        builder
+            .push_bind(PaymentOrderType::Laundry)
+            .push(" AND p.payment_status = ")
+            .push_bind(PaymentStatus::Paid)
+            .push(" WHERE o.store_id = ")
+            .push_bind(store_id)
+            .push(" AND o.create_time BETWEEN ")
+            .push_bind(start)
+            .push(" AND ")
+            .push_bind(end)
+            .push(" AND o.user_id");
+        push_user_ids(&mut builder, &user_ids);
+        let (converted_users, order_count, order_amount): (i64, i64, f64) =
+            builder.build_query_as().fetch_one(pool).await?;
+        stats.converted_users = converted_users;
+        stats.order_count = order_count;
+        stats.order_amount = order_amount;
+
+        // 统计期内推送对象购买卡券的支付
+        let mut builder = QueryBuilder::new(
+            "SELECT COUNT(*), COALESCE(SUM(p.total_amount), 0) FROM payments p
+            WHERE p.store_id = ",
+        );
+        builder
+            .push_bind(store_id)
+            .push(" AND p.order_type = ")
+            .push_bind(PaymentOrderType::Coupon)
+            .push(" AND p.payment_status = ")
+            .push_bind(PaymentStatus::Paid)
+            .push(" AND p.create_time BETWEEN ")
+            .push_bind(start.timestamp_millis())
+            .push(" AND ")
+            .push_bind(end.timestamp_millis())
+            .push(" AND p.pay_id IN (SELECT pay_id FROM user_coupons WHERE user_id");
+        push_user_ids(&mut builder, &user_ids);
+        builder.push(")");
+        let (coupon_count, coupon_amount): (i64, f64) =
+            builder.build_query_as().fetch_one(pool).await?;
+        stats.coupon_count = coupon_count;
+        stats.coupon_amount = coupon_amount;
+
+        if cost > 0. {
+            stats.roi = Some((order_amount + coupon_amount - cost) / cost);
+        }
+        Ok(stats)
+    }
+}
+
+#[tauri::command]
+pub async fn get_promote_templates_pagination(
+    state: State<'_, AppState>,
+    page_params: PageParams,
+    mut temp: PromoteTemplate,
+) -> Result<PageResult<PromoteTemplate>> {
+    temp.store_id = Some(utils::get_user_id(&state).await?);
+    temp.get_list(&state.pool, page_params).await
+}
+
+#[tauri::command]
+pub async fn get_promote_template_by_id(
+    state: State<'_, AppState>,
+    id: i64,
+) -> Result<Option<PromoteTemplate>> {
+    let store_id = utils::get_user_id(&state).await?;
+    Ok(PromoteTemplate::get_by_id(&state.pool, id)
+        .await?
+        .filter(|temp| temp.store_id == Some(store_id)))
+}
+
+#[tauri::command]
+pub async fn create_promote_template(
+    state: State<'_, AppState>,
+    mut temp: PromoteTemplate,
+) -> Result<PromoteTemplate> {
+    temp.validate()?;
+    temp.store_id = Some(utils::get_user_id(&state).await?);
+    temp.create(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn update_promote_template(
+    state: State<'_, AppState>,
+    mut temp: PromoteTemplate,
+) -> Result<bool> {
+    temp.validate()?;
+    temp.store_id = Some(utils::get_user_id(&state).await?);
+    temp.update(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn delete_promote_templates(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
+    let mut tx = state.pool.begin().await?;
+    let result = PromoteTemplate::delete_batch(&mut tx, &ids).await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn preview_promote_audience(
+    state: State<'_, AppState>,
+    audience: Audience,
+) -> Result<AudiencePreview> {
+    let store_id = utils::get_user_id(&state).await?;
+    audience.preview(&state.pool, store_id).await
+}
+
+#[tauri::command]
+pub async fn send_promote(state: State<'_, AppState>, id: i64) -> Result<PromoteRecord> {
+    let temp = PromoteTemplate::get_by_id(&state.pool, id)
+        .await?
+        .ok_or(Error::not_found("推广活动不存在"))?;
+    temp.send(&state).await
+}
+
+#[tauri::command]
+pub async fn get_promote_records_pagination(
+    state: State<'_, AppState>,
+    page_params: PageParams,
+    mut record: PromoteRecord,
+) -> Result<PageResult<PromoteRecord>> {
+    record.store_id = Some(utils::get_user_id(&state).await?);
+    record.get_list(&state.pool, page_params).await
+}
+
+#[tauri::command]
+pub async fn get_promote_stats(state: State<'_, AppState>, id: i64) -> Result<PromoteStats> {
+    let store_id = utils::get_user_id(&state).await?;
+    let record = PromoteRecord::get_by_id(&state.pool, id)
+        .await?
+        .filter(|record| record.store_id == Some(store_id))
+        .ok_or(Error::not_found("推广记录不存在"))?;
+    record.stats(&state.pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{Executor, SqlitePool};
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        pool.execute(
+            r#"
+            CREATE TABLE users (
+                user_id INTEGER PRIMARY KEY,
+                store_id INTEGER,
+                del_flag TEXT DEFAULT '0',
+                notice_opt_out INTEGER DEFAULT 0
+            );
+            CREATE TABLE user_membership_level (
+                user_id INTEGER NOT NULL,
+                level_id INTEGER NOT NULL,
+                PRIMARY KEY (user_id, level_id)
+            );
+            CREATE TABLE user_tags (user_id INTEGER PRIMARY KEY, tags TEXT NOT NULL, remark TEXT);
+            CREATE TABLE orders (order_id INTEGER PRIMARY KEY, user_id INTEGER, store_id INTEGER,
+                create_time TEXT);
+            CREATE TABLE payments (pay_id TEXT PRIMARY KEY, uc_order_id INTEGER, store_id INTEGER,
+                order_type TEXT, total_amount REAL, payment_status TEXT);
+
+            INSERT INTO users (user_id, store_id, notice_opt_out) VALUES (1, 1, 0), (2, 1, 1), (3, 2, 0);
+            INSERT INTO user_membership_level VALUES (1, 10), (1, 11), (2, 11), (3, 10);
+            INSERT INTO user_tags (user_id, tags) VALUES (1, '常客,高端'), (2, '新客');
+            "#,
+        )
+        .await
+        .unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_audience_preview() {
+        let pool = setup_test_db().await;
+
+        let all = Audience::default().preview(&pool, 1).await.unwrap();
+        assert_eq!((all.total, all.opted_out), (2, 1));
+
+        // 会员 1 同时有两个等级，只计一次；其他门店的会员不计入
+        let levels = Audience {
+            level_ids: vec![10, 11],
+            ..Default::default()
+        };
+        assert_eq!(levels.preview(&pool, 1).await.unwrap().total, 2);
+        let users = levels.list_users(&pool, 1).await.unwrap();
+        assert_eq!(users.len(), 2);
+
+        let tags = Audience {
+            tags: vec!["高端".to_string()],
+            level_ids: vec![10],
+            ..Default::default()
+        };
+        assert_eq!(tags.preview(&pool, 1).await.unwrap().total, 1);
+
+        let spend = Audience {
+            min_spend: Some(1.),
+            ..Default::default()
+        };
+        assert_eq!(spend.preview(&pool, 1).await.unwrap().total, 0);
+    }
+}
@@ -0,0 +1,550 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite, Transaction};
+use tauri::State;
+
+use crate::constants::{PaymentOrderType, PaymentStatus, PointsReason, ReferralRewardStatus};
+use crate::db::configs::Config;
+use crate::db::coupons::{Coupon, CouponBuyReq, CouponIdCount};
+use crate::db::points::PointsRecord;
+use crate::db::user::User;
+use crate::db::{Curd, Validator};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+// 推荐奖励设置在 configs 表中的键名前缀
+const CONFIG_PREFIX: &str = "referral.";
+
+/// 推荐奖励设置，推荐人和被推荐人可分别奖励积分和卡券
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ReferralSettings {
+    pub enabled: bool,
+    pub referrer_points: i64,
+    pub referrer_coupon_id: Option<i64>,
+    pub referee_points: i64,
+    pub referee_coupon_id: Option<i64>,
+}
+
+/// 推荐奖励记录
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ReferralReward {
+    pub reward_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub referrer_id: i64,
+    pub referee_id: i64,
+    pub referee_phone: Option<String>,
+    pub referrer_points: i64,
+    pub referrer_coupon_id: Option<i64>,
+    pub referee_points: i64,
+    pub referee_coupon_id: Option<i64>,
+    pub status: ReferralRewardStatus,
+    pub create_time: Option<i64>,
+    /// 未发放的原因
+    pub remark: Option<String>,
+    #[sqlx(default)]
+    pub referrer_name: Option<String>,
+    #[sqlx(default)]
+    pub referee_name: Option<String>,
+}
+
+impl Validator for ReferralSettings {
+    fn validate(&self) -> Result<()> {
+        if self.referrer_points < 0 || self.referee_points < 0 {
+            return Err(Error::bad_request("奖励积分不能为负数"));
+        }
+        if self.enabled
+            && self.referrer_points == 0
+            && self.referee_points == 0
+            && self.referrer_coupon_id.is_none()
+            && self.referee_coupon_id.is_none()
+        {
+            return Err(Error::bad_request("请至少设置一项推荐奖励"));
+        }
+        Ok(())
+    }
+}
+
+impl ReferralSettings {
+    // (键名, 参数名称, 参数值)
+    fn entries(&self) -> Vec<(&'static str, &'static str, String)> {
+        let id = |id: Option<i64>| id.map(|id| id.to_string()).unwrap_or_default();
+        vec![
+            ("enabled", "推荐-启用奖励", self.enabled.to_string()),
+            (
+                "referrerPoints",
+                "推荐-推荐人奖励积分",
+                self.referrer_points.to_string(),
+            ),
+            (
+                "referrerCouponId",
+                "推荐-推荐人奖励卡券",
+                id(self.referrer_coupon_id),
+            ),
+            (
+                "refereePoints",
+                "推荐-被推荐人奖励积分",
+                self.referee_points.to_string(),
+            ),
+            (
+                "refereeCouponId",
+                "推荐-被推荐人奖励卡券",
+                id(self.referee_coupon_id),
+            ),
+        ]
+    }
+
+    fn apply(&mut self, key: &str, value: &str) {
+        let value = value.trim();
+        match key {
+            "enabled" => self.enabled = utils::to_bool(value),
+            "referrerPoints" => self.referrer_points = value.parse().unwrap_or_default(),
+            "referrerCouponId" => self.referrer_coupon_id = value.parse().ok(),
+            "refereePoints" => self.referee_points = value.parse().unwrap_or_default(),
+            "refereeCouponId" => self.referee_coupon_id = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    pub async fn load(pool: &Pool<Sqlite>) -> Result<Self> {
+        let configs = Config {
+            config_key: Some(CONFIG_PREFIX.to_string()),
+            ..Default::default()
+        }
+        .get_all(pool)
+        .await?;
+
+        let mut settings = Self::default();
+        for config in configs {
+            if let Some(key) = config
+                .config_key
+                .as_deref()
+                .and_then(|key| key.strip_prefix(CONFIG_PREFIX))
+            {
+                settings.apply(key, &config.config_value.unwrap_or_default());
+            }
+        }
+        Ok(settings)
+    }
+
+    pub async fn save(&self, pool: &Pool<Sqlite>) -> Result<()> {
+        for (key, name, value) in self.entries() {
+            Config::set_value(pool, &format!("{CONFIG_PREFIX}{key}"), name, value).await?;
+        }
+        Ok(())
+    }
+}
+
+impl ReferralReward {
+    async fn insert(&self, tr: &mut Transaction<'_, Sqlite>) -> Result<Self> {
+        let result = sqlx::query_as(
+            "INSERT INTO referral_rewards (store_id, referrer_id, referee_id, referee_phone,
+                referrer_points, referrer_coupon_id, referee_points, referee_coupon_id, status,
+                create_time, remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(self.referrer_id)
+        .bind(self.referee_id)
+        .bind(&self.referee_phone)
+        .bind(self.referrer_points)
+        .bind(self.referrer_coupon_id)
+        .bind(self.referee_points)
+        .bind(self.referee_coupon_id)
+        .bind(self.status)
+        .bind(utils::get_timestamp())
+        .bind(&self.remark)
+        .fetch_one(&mut **tr)
+        .await?;
+        Ok(result)
+    }
+
+    async fn exists_by_referee_id(pool: &Pool<Sqlite>, referee_id: i64) -> Result<bool> {
+        let count: i64 =
+            sqlx::query_scalar("SELECT COUNT(1) FROM referral_rewards WHERE referee_id = ?")
+                .bind(referee_id)
+                .fetch_one(pool)
+                .await?;
+        Ok(count > 0)
+    }
+
+    /// 本次支付之前是否已有支付完成的洗衣订单
+    async fn paid_before(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        order_ids: &[i64],
+    ) -> Result<bool> {
+        let mut builder = QueryBuilder::new(
+            "SELECT COUNT(1) FROM payments p
+            INNER JOIN orders o ON p.uc_order_id = o.order_id
+            WHERE o.user_id = ",
+        );
+        builder
+            .push_bind(user_id)
+            .push(" AND p.store_id = ")
+            .push_bind(store_id)
+            .push(" AND p.payment_status = ")
+            .push_bind(PaymentStatus::Paid)
+            .push(" AND p.order_type = ")
+            .push_bind(PaymentOrderType::Laundry);
+        if !order_ids.is_empty() {
+            builder.push(" AND p.uc_order_id NOT IN (");
+            let mut separated = builder.separated(", ");
+            for id in order_ids {
+                separated.push_bind(id);
+            }
+            separated.push_unseparated(")");
+        }
+        let count: i64 = builder.build_query_scalar().fetch_one(pool).await?;
+        Ok(count > 0)
+    }
+
+    /// 防刷校验，返回不发放奖励的原因
+    async fn check_abuse(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        referee: &User,
+        referrer_id: i64,
+    ) -> Result<Option<&'static str>> {
+        // get_by_id 只查未删除的会员，这里再限定为本门店正常状态的会员
+        let referrer = User::get_by_id(pool, referrer_id).await?;
+        let Some(referrer) = referrer
+            .filter(|user| user.store_id == Some(store_id) && user.status.as_deref() == Some("0"))
+        else {
+            return Ok(Some("推荐人不存在或已停用"));
+        };
+        let Some(phone) = referee.phonenumber.as_deref().filter(|p| !p.is_empty()) else {
+            return Ok(Some("被推荐人未登记手机号"));
+        };
+        if referrer.phonenumber.as_deref() == Some(phone) {
+            return Ok(Some("推荐人与被推荐人手机号相同"));
+        }
+        if User::phone_ever_registered(pool, store_id, phone, referee.user_id).await? {
+            return Ok(Some("该手机号曾注册过会员"));
+        }
+
+        let rewarded: i64 = sqlx::query_scalar(
+            "SELECT COUNT(1) FROM referral_rewards
+            WHERE store_id = ? AND referee_phone = ? AND status = ?",
+        )
+        .bind(store_id)
+        .bind(phone)
+        .bind(ReferralRewardStatus::Rewarded)
+        .fetch_one(pool)
+        .await?;
+        if rewarded > 0 {
+            return Ok(Some("该手机号已领取过推荐奖励"));
+        }
+        Ok(None)
+    }
+
+    /// 被推荐人首单支付后向推荐人和被推荐人发放奖励，每个被推荐人只处理一次
+    pub async fn reward_first_order(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        order_ids: &[i64],
+    ) -> Result<Option<Self>> {
+        let settings = ReferralSettings::load(pool).await?;
+        if !settings.enabled {
+            return Ok(None);
+        }
+        let Some(referee) = User::get_by_id(pool, user_id).await? else {
+            return Ok(None);
+        };
+        let Some(referrer_id) = referee.referrer_id else {
+            return Ok(None);
+        };
+        if Self::exists_by_referee_id(pool, user_id).await?
+            || Self::paid_before(pool, store_id, user_id, order_ids).await?
+        {
+            return Ok(None);
+        }
+
+        let mut reward = Self {
+            store_id: Some(store_id),
+            referrer_id,
+            referee_id: user_id,
+            referee_phone: referee.phonenumber.clone(),
+            ..Default::default()
+        };
+        if let Some(reason) = Self::check_abuse(pool, store_id, &referee, referrer_id).await? {
+            reward.status = ReferralRewardStatus::Rejected;
+            reward.remark = Some(reason.to_string());
+            let mut tr = pool.begin().await?;
+            let reward = reward.insert(&mut tr).await?;
+            tr.commit().await?;
+            return Ok(Some(reward));
+        }
+
+        reward.status = ReferralRewardStatus::Rewarded;
+        reward.referrer_points = settings.referrer_points;
+        reward.referrer_coupon_id = settings.referrer_coupon_id;
+        reward.referee_points = settings.referee_points;
+        reward.referee_coupon_id = settings.referee_coupon_id;
+
+        let mut tr = pool.begin().await?;
+        let reward = reward.insert(&mut tr).await?;
+        for (uid, points) in [
+            (referrer_id, reward.referrer_points),
+            (user_id, reward.referee_points),
+        ] {
+            if points > 0 {
+                PointsRecord::earn(
+                    &mut tr,
+                    store_id,
+                    uid,
+                    points,
+                    PointsReason::Referral,
+                    Some(user_id.to_string()),
+                    Some("推荐奖励".to_string()),
+                )
+                .await?;
+            }
+        }
+        tr.commit().await?;
+
+        // 卡券通过赠送发放并通知会员，发放失败不影响已发放的积分
+        for (uid, coupon_id) in [
+            (referrer_id, reward.referrer_coupon_id),
+            (user_id, reward.referee_coupon_id),
+        ] {
+            let Some(coupon_id) = coupon_id else {
+                continue;
+            };
+            let req = CouponBuyReq {
+                coupons: vec![CouponIdCount {
+                    coupon_id,
+                    count: 1,
+                }],
+                user_id: uid,
+                ..Default::default()
+            };
+            if let Err(e) = Coupon::gift(pool, req).await {
+                tracing::error!("发放推荐奖励卡券失败: {}", e);
+            }
+        }
+        Ok(Some(reward))
+    }
+
+    /// 门店的推荐奖励记录，可按推荐人筛选
+    pub async fn list(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        referrer_id: Option<i64>,
+    ) -> Result<Vec<Self>> {
+        let mut builder = QueryBuilder::new(
+            "SELECT r.*, a.nick_name AS referrer_name, b.nick_name AS referee_name
+            FROM referral_rewards r
+            LEFT JOIN users a ON a.user_id = r.referrer_id
+            LEFT JOIN users b ON b.user_id = r.referee_id
+            WHERE r.store_id = ",
+        );
+        builder.push_bind(store_id);
+        if let Some(referrer_id) = referrer_id {
+            builder.push(" AND r.referrer_id = ").push_bind(referrer_id);
+        }
+        builder.push(" ORDER BY r.reward_id DESC");
+        let result = builder.build_query_as().fetch_all(pool).await?;
+        Ok(result)
+    }
+}
+
+#[tauri::command]
+pub async fn get_referral_settings(state: State<'_, AppState>) -> Result<ReferralSettings> {
+    ReferralSettings::load(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn save_referral_settings(
+    state: State<'_, AppState>,
+    settings: ReferralSettings,
+) -> Result<()> {
+    settings.validate()?;
+    let store_id = utils::get_user_id(&state).await?;
+    for coupon_id in [settings.referrer_coupon_id, settings.referee_coupon_id]
+        .into_iter()
+        .flatten()
+    {
+        let coupon = Coupon::get_by_id(&state.pool, coupon_id)
+            .await?
+            .filter(|c| c.store_id == Some(store_id) && c.del_flag.as_deref() != Some("2"));
+        if coupon.is_none() {
+            return Err(Error::not_found("奖励的卡券不存在"));
+        }
+    }
+    settings.save(&state.pool).await
+}
+
+/// 查询会员的推荐码，没有时生成
+#[tauri::command]
+pub async fn get_referral_code(state: State<'_, AppState>, user_id: i64) -> Result<String> {
+    let store_id = utils::get_user_id(&state).await?;
+    let mut tr = state.pool.begin().await?;
+    let code = User::assign_referral_code(&mut tr, store_id, user_id).await?;
+    tr.commit().await?;
+    Ok(code)
+}
+
+#[tauri::command]
+pub async fn get_referral_rewards(
+    state: State<'_, AppState>,
+    referrer_id: Option<i64>,
+) -> Result<Vec<ReferralReward>> {
+    let store_id = utils::get_user_id(&state).await?;
+    ReferralReward::list(&state.pool, store_id, referrer_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_entries() {
+        let settings = ReferralSettings {
+            enabled: true,
+            referrer_points: 100,
+            referrer_coupon_id: Some(3),
+            referee_points: 50,
+            referee_coupon_id: None,
+        };
+        let mut loaded = ReferralSettings::default();
+        for (key, _, value) in settings.entries() {
+            loaded.apply(key, &value);
+        }
+        assert_eq!(loaded, settings);
+
+        assert!(
+            ReferralSettings {
+                enabled: true,
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            ReferralSettings {
+                referee_points: -1,
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+
+    async fn setup_test_db() -> Pool<Sqlite> {
+        use sqlx::{Executor, SqlitePool};
+
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        pool.execute(
+            r#"
+            CREATE TABLE users (user_id INTEGER PRIMARY KEY, store_id INTEGER, status TEXT,
+                del_flag TEXT, phonenumber TEXT, referrer_id INTEGER, integral INTEGER);
+            CREATE TABLE user_membership_level (user_id INTEGER, level_id INTEGER);
+            CREATE TABLE membership_level (level_id INTEGER PRIMARY KEY, level_name TEXT);
+            CREATE TABLE user_tags (user_id INTEGER PRIMARY KEY, tags TEXT, remark TEXT,
+                auto_tags TEXT);
+            CREATE TABLE configs (config_key TEXT, config_value TEXT);
+            CREATE TABLE orders (order_id INTEGER PRIMARY KEY, user_id INTEGER);
+            CREATE TABLE payments (pay_id TEXT PRIMARY KEY, uc_order_id INTEGER, store_id INTEGER,
+                order_type TEXT, payment_status TEXT);
+            CREATE TABLE user_integral_record (
+                id INTEGER PRIMARY KEY AUTOINCREMENT, store_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL, change_points INTEGER NOT NULL, balance INTEGER NOT NULL,
+                reason TEXT NOT NULL, ref_id TEXT, remaining INTEGER NOT NULL DEFAULT 0,
+                expire_time INTEGER, remark TEXT, create_time INTEGER NOT NULL
+            );
+            CREATE TABLE referral_rewards (reward_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                store_id INTEGER NOT NULL, referrer_id INTEGER NOT NULL,
+                referee_id INTEGER NOT NULL UNIQUE, referee_phone TEXT,
+                referrer_points INTEGER NOT NULL DEFAULT 0, referrer_coupon_id INTEGER,
+                referee_points INTEGER NOT NULL DEFAULT 0, referee_coupon_id INTEGER,
+                status TEXT NOT NULL, create_time INTEGER, remark TEXT);
+
+            INSERT INTO configs VALUES ('referral.enabled', 'true'),
+                ('referral.referrerPoints', '100'), ('referral.refereePoints', '50');
+            -- 推荐人：1 正常，5 已删除，9 属于门店 2
+            INSERT INTO users VALUES
+                (1, 1, '0', '0', '13800000001', NULL, 0),
+                (5, 1, '0', '2', '13800000005', NULL, 0),
+                (9, 2, '0', '0', '13800000009', NULL, 0),
+                (2, 1, '0', '0', '13800000002', 1, 0),
+                (3, 1, '0', '0', '13800000003', 9, 0),
+                (4, 1, '0', '0', '13800000004', 5, 0),
+                (6, 1, '0', '0', '13800000001', 1, 0),
+                (7, 1, '0', '0', '13800000007', 1, 0);
+            -- 会员 7 之前已支付过洗衣订单
+            INSERT INTO orders VALUES (1, 7), (2, 7);
+            INSERT INTO payments VALUES ('p1', 1, 1, 'Laundry', 'Paid');
+            "#,
+        )
+        .await
+        .unwrap();
+        pool
+    }
+
+    async fn integral(pool: &Pool<Sqlite>, user_id: i64) -> i64 {
+        sqlx::query_scalar("SELECT integral FROM users WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reward_first_order() {
+        let pool = setup_test_db().await;
+
+        let reward = ReferralReward::reward_first_order(&pool, 1, 2, &[10])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reward.status, ReferralRewardStatus::Rewarded);
+        assert_eq!((reward.referrer_points, reward.referee_points), (100, 50));
+        assert_eq!(integral(&pool, 1).await, 100);
+        assert_eq!(integral(&pool, 2).await, 50);
+
+        // 每个被推荐人只处理一次
+        assert!(
+            ReferralReward::reward_first_order(&pool, 1, 2, &[11])
+                .await
+                .unwrap()
+                .is_none()
+        );
+        // 本次之前已支付过订单的不算首单
+        assert!(
+            ReferralReward::reward_first_order(&pool, 1, 7, &[2])
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reward_abuse_rules() {
+        let pool = setup_test_db().await;
+        let rejected = |user_id| {
+            let pool = pool.clone();
+            async move {
+                let reward = ReferralReward::reward_first_order(&pool, 1, user_id, &[])
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(reward.status, ReferralRewardStatus::Rejected);
+                reward.remark.unwrap()
+            }
+        };
+
+        // 推荐人属于其他门店或已删除
+        assert_eq!(rejected(3).await, "推荐人不存在或已停用");
+        assert_eq!(rejected(4).await, "推荐人不存在或已停用");
+        // 被推荐人与推荐人手机号相同
+        assert_eq!(rejected(6).await, "推荐人与被推荐人手机号相同");
+        assert_eq!(integral(&pool, 1).await, 0);
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite, Transaction};
+use tauri::{AppHandle, Manager, Runtime, State};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::constants::{CouponType, PaymentOrderType, PaymentStatus, SegmentRuleType};
+use crate::db::membership_level::MembershipLevel;
+use crate::db::points::PointsRecord;
+use crate::db::user_tags::UserTags;
+use crate::db::{Curd, Validator};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+// 会员定时任务间隔（秒）
+const REFRESH_INTERVAL: u64 = 60 * 60;
+
+/// 黑灰名单字典值：黑名单
+const IDENTIFY_BLACKLIST: &str = "01";
+
+/// 新门店的默认规则：(标签名称, 规则类型, 金额阈值, 统计天数)
+const DEFAULT_RULES: &[(&str, SegmentRuleType, Option<f64>, Option<i64>)] = &[
+    ("高消费", SegmentRuleType::SpendOver, Some(500.), Some(90)),
+    ("有储值余额", SegmentRuleType::HasBalance, Some(0.), None),
+    ("沉睡会员", SegmentRuleType::NoVisit, None, Some(60)),
+    ("黑名单", SegmentRuleType::Blacklisted, None, None),
+];
+
+/// 会员分群规则，命中的会员自动打上 tag_name 标签
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct SegmentRule {
+    pub rule_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub tag_name: Option<String>,
+    pub rule_type: Option<SegmentRuleType>,
+    /// 金额阈值
+    pub threshold: Option<f64>,
+    /// 统计天数
+    pub days: Option<i64>,
+    pub enabled: bool,
+    /// 上次计算命中的会员数
+    pub matched_count: i64,
+    pub last_run_time: Option<i64>,
+    pub create_time: Option<i64>,
+    pub update_time: Option<i64>,
+    pub remark: Option<String>,
+}
+
+impl Validator for SegmentRule {
+    fn validate(&self) -> Result<()> {
+        let tag_name = self.tag_name.as_deref().unwrap_or_default().trim();
+        if tag_name.is_empty() {
+            return Err(Error::bad_request("标签名称不能为空"));
+        }
+        if tag_name.contains(',') {
+            return Err(Error::bad_request("标签名称不能包含逗号"));
+        }
+        if self.days.is_some_and(|days| days < 1) {
+            return Err(Error::bad_request("统计天数至少为 1 天"));
+        }
+        match self.rule_type {
+            None => return Err(Error::bad_request("请选择规则类型")),
+            Some(SegmentRuleType::SpendOver) | Some(SegmentRuleType::HasBalance) => {
+                if self.threshold.is_none_or(|threshold| threshold < 0.) {
+                    return Err(Error::bad_request("请设置金额阈值"));
+                }
+            }
+            Some(SegmentRuleType::NoVisit) => {
+                if self.days.is_none() {
+                    return Err(Error::bad_request("请设置未到店天数"));
+                }
+            }
+            Some(SegmentRuleType::Blacklisted) => {}
+        }
+        Ok(())
+    }
+}
+
+impl Curd for SegmentRule {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM segment_rules WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM segment_rules WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM segment_rules WHERE rule_id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM segment_rules WHERE rule_id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY rule_id");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+
+        if let Some(rule_type) = &self.rule_type {
+            builder.push(" AND rule_type = ").push_bind(rule_type);
+        }
+    }
+}
+
+impl SegmentRule {
+    pub async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self> {
+        let now = utils::get_timestamp();
+        let result = sqlx::query_as(
+            "INSERT INTO segment_rules (store_id, tag_name, rule_type, threshold, days, enabled,
+                create_time, update_time, remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(self.tag_name.as_deref().map(str::trim))
+        .bind(self.rule_type)
+        .bind(self.threshold)
+        .bind(self.days)
+        .bind(self.enabled)
+        .bind(now)
+        .bind(now)
+        .bind(&self.remark)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 为新门店创建默认规则，已有门店的默认规则由迁移脚本创建
+    pub async fn seed_defaults(tr: &mut Transaction<'_, Sqlite>, store_id: i64) -> Result<()> {
+        let now = utils::get_timestamp();
+        for (tag_name, rule_type, threshold, days) in DEFAULT_RULES {
+            sqlx::query(
+                "INSERT INTO segment_rules (store_id, tag_name, rule_type, threshold, days,
+                    create_time, update_time)
+                VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(store_id)
+            .bind(tag_name)
+            .bind(rule_type)
+            .bind(threshold)
+            .bind(days)
+            .bind(now)
+            .bind(now)
+            .execute(&mut **tr)
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn update(&self, pool: &Pool<Sqlite>) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE segment_rules SET tag_name = ?, rule_type = ?, threshold = ?, days = ?,
+                enabled = ?, update_time = ?, remark = ?
+            WHERE rule_id = ? AND store_id = ?",
+        )
+        .bind(self.tag_name.as_deref().map(str::trim))
+        .bind(self.rule_type)
+        .bind(self.threshold)
+        .bind(self.days)
+        .bind(self.enabled)
+        .bind(utils::get_timestamp())
+        .bind(&self.remark)
+        .bind(self.rule_id)
+        .bind(self.store_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 计算命中规则的会员ID
+    pub async fn evaluate(&self, pool: &Pool<Sqlite>) -> Result<Vec<i64>> {
+        let store_id = self.store_id.unwrap_or_default();
+        let now = utils::get_now();
+        let since = self.days.map(|days| now - Duration::days(days));
+        let threshold = self.threshold.unwrap_or_default();
+
+        let mut builder = QueryBuilder::<Sqlite>::new("");
+        match self.rule_type.unwrap_or_default() {
+            SegmentRuleType::SpendOver => {
+                builder
+                    .push(
+                        "SELECT o.user_id FROM payments p
+                        JOIN orders o ON o.order_id = p.uc_order_id
+                        WHERE p.store_id = ",
+                    )
+                    .push_bind(store_id)
+                    .push(" AND p.order_type = ")
+                    .push_bind(PaymentOrderType::Laundry)
+                    .push(" AND p.payment_status = ")
+                    .push_bind(PaymentStatus::Paid);
+                if let Some(since) = since {
+                    builder
+                        .push(" AND p.create_time >= ")
+                        .push_bind(since.timestamp_millis());
+                }
+                builder
+                    .push(" GROUP BY o.user_id HAVING SUM(p.total_amount) > ")
+                    .push_bind(threshold);
+            }
+            SegmentRuleType::HasBalance => {
+                builder
+                    .push(
+                        "SELECT uc.user_id FROM user_coupons uc
+                        JOIN coupons c ON c.coupon_id = uc.coupon_id
+                        WHERE uc.store_id = ",
+                    )
+                    .push_bind(store_id)
+                    .push(" AND c.coupon_type = ")
+                    .push_bind(CouponType::StoredValueCard)
+                    .push(" AND COALESCE(uc.expire_time, c.valid_to) >= ")
+                    .push_bind(now)
+                    .push(" AND uc.available_value > 0")
+                    .push(" GROUP BY uc.user_id HAVING SUM(uc.available_value) > ")
+                    .push_bind(threshold);
+            }
+            SegmentRuleType::NoVisit => {
+                // 注册时间不足统计天数的新会员不算沉睡
+                let since = since.unwrap_or(now);
+                builder
+                    .push(
+                        "SELECT u.user_id FROM users u
+                        WHERE u.del_flag = '0' AND u.store_id = ",
+                    )
+                    .push_bind(store_id)
+                    .push(" AND COALESCE(u.create_time, 0) < ")
+                    .push_bind(since.timestamp_millis())
+                    .push(
+                        " AND NOT EXISTS (SELECT 1 FROM orders o
+                        WHERE o.user_id = u.user_id AND o.create_time >= ",
+                    )
+                    .push_bind(since)
+                    .push(")");
+            }
+            SegmentRuleType::Blacklisted => {
+                builder
+                    .push("SELECT user_id FROM users WHERE del_flag = '0' AND store_id = ")
+                    .push_bind(store_id)
+                    .push(" AND identify = ")
+                    .push_bind(IDENTIFY_BLACKLIST);
+            }
+        }
+
+        Ok(builder.build_query_scalar().fetch_all(pool).await?)
+    }
+
+    async fn update_run_result(pool: &Pool<Sqlite>, rule_id: i64, matched: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE segment_rules SET matched_count = ?, last_run_time = ? WHERE rule_id = ?",
+        )
+        .bind(matched)
+        .bind(utils::get_timestamp())
+        .bind(rule_id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 按门店已启用的规则重新计算会员标签，返回带标签的会员数
+    pub async fn refresh(pool: &Pool<Sqlite>, store_id: i64) -> Result<u64> {
+        let rules = SegmentRule {
+            store_id: Some(store_id),
+            ..Default::default()
+        }
+        .get_all(pool)
+        .await?;
+
+        let mut user_tags: BTreeMap<i64, Vec<&str>> = BTreeMap::new();
+        for rule in rules.iter().filter(|rule| rule.enabled) {
+            let user_ids = rule.evaluate(pool).await?;
+            Self::update_run_result(
+                pool,
+                rule.rule_id.unwrap_or_default(),
+                user_ids.len() as i64,
+            )
+            .await?;
+
+            let tag = rule.tag_name.as_deref().unwrap_or_default();
+            for user_id in user_ids {
+                let tags = user_tags.entry(user_id).or_default();
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+
+        let mut tr = pool.begin().await?;
+        UserTags::clear_auto_tags(&mut tr, store_id).await?;
+        for (user_id, tags) in &user_tags {
+            UserTags::set_auto_tags(&mut tr, *user_id, &tags.join(",")).await?;
+        }
+        tr.commit().await?;
+        Ok(user_tags.len() as u64)
+    }
+}
+
+/// 会员定时任务：每小时扣减过期积分、评定会员等级并刷新会员分群
+#[derive(Debug, Clone)]
+pub struct SegmentManager {
+    task_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl SegmentManager {
+    pub fn new() -> Self {
+        Self {
+            task_handle: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// 启动会员定时任务，启动后立即执行一次
+    pub async fn start<R: Runtime>(&self, app_handle: AppHandle<R>) -> Result<()> {
+        let state = app_handle.state::<AppState>();
+        let pool = state.pool.clone();
+        let store_id = utils::get_user_id(&state).await?;
+        let task_handle = self.task_handle.clone();
+
+        // 先停止已存在的任务
+        self.stop().await;
+
+        let handle = tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(tokio::time::Duration::from_secs(REFRESH_INTERVAL));
+            loop {
+                interval.tick().await;
+                // 先扣减过期积分，再按最新数据评定等级、计算分群
+                match PointsRecord::expire_due(&pool, store_id).await {
+                    Ok(points) if points > 0 => tracing::info!("{} points expired", points),
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("扣减过期积分失败: {}", e),
+                }
+                match MembershipLevel::evaluate_store(&pool, store_id).await {
+                    Ok(count) if count > 0 => {
+                        tracing::info!("{} users changed membership level", count)
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("评定会员等级失败: {}", e),
+                }
+                match SegmentRule::refresh(&pool, store_id).await {
+                    Ok(count) => tracing::debug!("{} users tagged by segment rules", count),
+                    Err(e) => tracing::error!("刷新会员分群失败: {}", e),
+                }
+            }
+        });
+
+        // 保存任务句柄
+        let mut handle_guard = task_handle.lock().await;
+        *handle_guard = Some(handle);
+
+        Ok(())
+    }
+
+    /// 停止会员定时任务
+    pub async fn stop(&self) {
+        let mut handle_guard = self.task_handle.lock().await;
+        if let Some(handle) = handle_guard.take() {
+            handle.abort();
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_segment_rules(state: State<'_, AppState>) -> Result<Vec<SegmentRule>> {
+    let store_id = utils::get_user_id(&state).await?;
+    SegmentRule {
+        store_id: Some(store_id),
+        ..Default::default()
+    }
+    .get_all(&state.pool)
+    .await
+}
+
+#[tauri::command]
+pub async fn create_segment_rule(
+    state: State<'_, AppState>,
+    mut rule: SegmentRule,
+) -> Result<SegmentRule> {
+    rule.validate()?;
+    rule.store_id = Some(utils::get_user_id(&state).await?);
+    rule.create(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn update_segment_rule(
+    state: State<'_, AppState>,
+    mut rule: SegmentRule,
+) -> Result<bool> {
+    rule.validate()?;
+    rule.store_id = Some(utils::get_user_id(&state).await?);
+    rule.update(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn delete_segment_rules(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
+    let mut tx = state.pool.begin().await?;
+    let result = SegmentRule::delete_batch(&mut tx, &ids).await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+/// 立即重新计算会员分群
+#[tauri::command]
+pub async fn refresh_segments(state: State<'_, AppState>) -> Result<u64> {
+    let store_id = utils::get_user_id(&state).await?;
+    SegmentRule::refresh(&state.pool, store_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{Executor, SqlitePool};
+
+    async fn setup_test_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        pool.execute(
+            r#"
+            CREATE TABLE segment_rules (
+                rule_id INTEGER PRIMARY KEY AUTOINCREMENT, store_id INTEGER NOT NULL,
+                tag_name TEXT NOT NULL, rule_type TEXT NOT NULL, threshold REAL, days INTEGER,
+                enabled BOOLEAN NOT NULL DEFAULT 1, matched_count INTEGER NOT NULL DEFAULT 0,
+                last_run_time INTEGER, create_time INTEGER, update_time INTEGER, remark TEXT
+            );
+            CREATE TABLE users (user_id INTEGER PRIMARY KEY, store_id INTEGER,
+                del_flag TEXT DEFAULT '0', identify TEXT, create_time INTEGER);
+            CREATE TABLE orders (order_id INTEGER PRIMARY KEY, user_id INTEGER, store_id INTEGER,
+                create_time TEXT);
+            CREATE TABLE payments (pay_id TEXT PRIMARY KEY, uc_order_id INTEGER, store_id INTEGER,
+                order_type TEXT, total_amount REAL, payment_status TEXT, create_time INTEGER);
+            CREATE TABLE coupons (coupon_id INTEGER PRIMARY KEY, coupon_type TEXT, valid_to TEXT);
+            CREATE TABLE user_coupons (uc_id INTEGER PRIMARY KEY, store_id INTEGER,
+                user_id INTEGER, coupon_id INTEGER, available_value REAL, expire_time TEXT);
+
+            -- 会员 1 常来高消费，2 很久没来且在黑名单，3 是其他门店的会员
+            INSERT INTO users (user_id, store_id, identify, create_time)
+            VALUES (1, 1, '00', 0), (2, 1, '01', 0), (3, 2, '01', 0);
+            "#,
+        )
+        .await
+        .unwrap();
+
+        let now = utils::get_now();
+        sqlx::query(
+            "INSERT INTO orders (order_id, user_id, store_id, create_time)
+            VALUES (1, 1, 1, ?), (2, 2, 1, ?), (3, 3, 2, ?)",
+        )
+        .bind(now - Duration::days(3))
+        .bind(now - Duration::days(100))
+        .bind(now - Duration::days(3))
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO payments VALUES
+                ('p1', 1, 1, 'Laundry', 600, 'Paid', ?),
+                ('p2', 2, 1, 'Laundry', 800, 'Paid', ?),
+                ('p3', 3, 2, 'Laundry', 900, 'Paid', ?)",
+        )
+        .bind((now - Duration::days(3)).timestamp_millis())
+        .bind((now - Duration::days(100)).timestamp_millis())
+        .bind((now - Duration::days(3)).timestamp_millis())
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO coupons VALUES (1, 'StoredValueCard', ?)")
+            .bind(now + Duration::days(30))
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO user_coupons VALUES (1, 1, 1, 1, 50, NULL), (2, 1, 2, 1, 0, NULL)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool
+    }
+
+    fn rule(rule_type: SegmentRuleType, threshold: Option<f64>, days: Option<i64>) -> SegmentRule {
+        SegmentRule {
+            store_id: Some(1),
+            rule_type: Some(rule_type),
+            threshold,
+            days,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_evaluate() {
+        let pool = setup_test_db().await;
+
+        // 90 天前的消费不计入
+        let spend = rule(SegmentRuleType::SpendOver, Some(500.), Some(90));
+        assert_eq!(spend.evaluate(&pool).await.unwrap(), vec![1]);
+        let spend_all = rule(SegmentRuleType::SpendOver, Some(500.), None);
+        let mut users = spend_all.evaluate(&pool).await.unwrap();
+        users.sort_unstable();
+        assert_eq!(users, vec![1, 2]);
+
+        let balance = rule(SegmentRuleType::HasBalance, Some(0.), None);
+        assert_eq!(balance.evaluate(&pool).await.unwrap(), vec![1]);
+
+        let idle = rule(SegmentRuleType::NoVisit, None, Some(60));
+        assert_eq!(idle.evaluate(&pool).await.unwrap(), vec![2]);
+
+        let blacklisted = rule(SegmentRuleType::Blacklisted, None, None);
+        assert_eq!(blacklisted.evaluate(&pool).await.unwrap(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_seed_defaults() {
+        let pool = setup_test_db().await;
+        let mut tr = pool.begin().await.unwrap();
+        SegmentRule::seed_defaults(&mut tr, 2).await.unwrap();
+        tr.commit().await.unwrap();
+
+        let rules = SegmentRule {
+            store_id: Some(2),
+            ..Default::default()
+        }
+        .get_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(rules.len(), DEFAULT_RULES.len());
+        assert!(
+            rules
+                .iter()
+                .all(|rule| rule.enabled && rule.validate().is_ok())
+        );
+    }
+}
@@ -0,0 +1,1091 @@
+use chrono::Utc;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite};
+use tauri::State;
+
+use crate::constants::{CouponType, PaymentMethod, PaymentOrderType, PaymentStatus};
+use crate::db::configs::Config;
+use crate::db::family_groups::FamilyGroup;
+use crate::db::payments::{Payment, PaymentMethodDetail};
+use crate::db::user::User;
+use crate::db::user_coupons::{UC_STATUS_REFUNDED, UserCoupon};
+use crate::db::{Curd, Validator};
+use crate::error::{Error, Result};
+use crate::notice::{self, TEMP_TYPE_STORED_VALUE};
+use crate::state::AppState;
+use crate::utils;
+
+/// 充值赠送档位
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct TopupTier {
+    pub tier_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub tier_name: Option<String>,
+    /// 单次充值满该金额可享受此档位
+    pub min_amount: f64,
+    pub bonus_amount: f64,
+    pub enabled: bool,
+    pub create_time: Option<i64>,
+    pub update_time: Option<i64>,
+    pub remark: Option<String>,
+}
+
+/// 储值卡充值记录
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct StoredValueTopup {
+    pub topup_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub uc_id: Option<i64>,
+    pub user_id: Option<i64>,
+    /// 充值本金
+    pub amount: f64,
+    pub bonus_amount: f64,
+    pub tier_id: Option<i64>,
+    pub pay_id: Option<String>,
+    pub create_time: Option<i64>,
+    pub remark: Option<String>,
+}
+
+/// 储值卡充值请求
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct TopupReq {
+    pub uc_id: i64,
+    pub amount: f64,
+    pub payment_method: PaymentMethod,
+    pub remark: Option<String>,
+}
+
+/// 储值卡流水类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum StatementKind {
+    /// 开卡余额
+    #[default]
+    Opening,
+    TopUp,
+    Consume,
+    /// 消费退款退回余额
+    Refund,
+    /// 家庭成员间转入
+    TransferIn,
+    TransferOut,
+    /// 退卡清零
+    CashOut,
+}
+
+/// 储值卡流水，金额为余额变动，正数为增加
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementEntry {
+    pub kind: StatementKind,
+    pub time: Option<i64>,
+    pub amount: f64,
+    /// 其中赠送余额的变动
+    pub bonus_amount: f64,
+    /// 变动后的余额
+    pub balance: f64,
+    pub bonus_balance: f64,
+    /// 充值或消费的支付记录
+    pub pay_id: Option<String>,
+    pub order_id: Option<i64>,
+}
+
+/// 储值卡对账单
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CardStatement {
+    pub uc_id: i64,
+    pub balance: f64,
+    pub principal: f64,
+    pub bonus: f64,
+    pub entries: Vec<StatementEntry>,
+}
+
+/// 储值余额转移记录
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct StoredValueTransfer {
+    pub transfer_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub from_uc_id: i64,
+    pub to_uc_id: i64,
+    pub from_user_id: i64,
+    pub to_user_id: i64,
+    pub amount: f64,
+    /// 随转移金额按比例转出的赠送余额
+    pub bonus_amount: f64,
+    pub create_time: Option<i64>,
+    pub remark: Option<String>,
+}
+
+/// 储值余额转移请求
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct TransferReq {
+    pub from_uc_id: i64,
+    pub to_uc_id: i64,
+    pub amount: f64,
+    pub remark: Option<String>,
+}
+
+/// 储值卡、次卡退卡记录，未保存时作为退卡试算结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct CardRefund {
+    pub refund_id: Option<i64>,
+    pub store_id: Option<i64>,
+    pub uc_id: i64,
+    pub user_id: i64,
+    pub coupon_type: CouponType,
+    /// 退卡前的余额，次卡为剩余次数
+    pub balance: f64,
+    /// 作废的赠送部分，次卡为赠送次数
+    pub bonus_amount: f64,
+    /// 可退本金
+    pub principal_amount: f64,
+    pub fee_amount: f64,
+    /// 实退金额
+    pub refund_amount: f64,
+    pub payment_method: Option<PaymentMethod>,
+    pub pay_id: Option<String>,
+    pub create_time: Option<i64>,
+    pub remark: Option<String>,
+}
+
+/// 退卡请求
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct CardRefundReq {
+    pub uc_id: i64,
+    pub payment_method: PaymentMethod,
+    pub remark: Option<String>,
+}
+
+/// 退卡手续费，按可退本金的比例计算，不低于最低手续费且不超过可退本金
+#[derive(Debug, Clone, Copy, Default)]
+struct RefundFee {
+    /// 百分比
+    rate: Decimal,
+    min: Decimal,
+}
+
+const REFUND_FEE_RATE_KEY: &str = "card_refund_fee_rate";
+const REFUND_FEE_MIN_KEY: &str = "card_refund_fee_min";
+
+/// 储值卡消费记录
+#[derive(Debug, FromRow)]
+struct UsageRow {
+    payment_id: String,
+    applied_amount: f64,
+    bonus_amount: Option<f64>,
+    is_refunded: bool,
+    uc_order_id: Option<i64>,
+    create_time: Option<i64>,
+    update_time: Option<i64>,
+}
+
+fn to_decimal(value: f64) -> Decimal {
+    Decimal::from_f64(value).unwrap_or_default().round_dp(2)
+}
+
+/// 校验为未过期的储值卡
+fn check_card(card: &UserCoupon) -> Result<()> {
+    let coupon = card
+        .coupon
+        .as_ref()
+        .ok_or(Error::not_found("储值卡不存在"))?;
+    if coupon.coupon_type != Some(CouponType::StoredValueCard) {
+        return Err(Error::bad_request(format!(
+            "<{}> 不是储值卡",
+            coupon.coupon_title.as_deref().unwrap_or_default()
+        )));
+    }
+    if coupon
+        .valid_to
+        .is_some_and(|valid_to| utils::get_now() > valid_to)
+    {
+        return Err(Error::bad_request(format!(
+            "储值卡 <{}> 已过期",
+            coupon.coupon_title.as_deref().unwrap_or_default()
+        )));
+    }
+    if card.status.as_deref() == Some(UC_STATUS_REFUNDED) {
+        return Err(Error::bad_request(format!(
+            "储值卡 <{}> 已退卡",
+            coupon.coupon_title.as_deref().unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
+impl Validator for TopupTier {
+    fn validate(&self) -> Result<()> {
+        if self
+            .tier_name
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .is_empty()
+        {
+            return Err(Error::bad_request("档位名称不能为空"));
+        }
+        if self.min_amount <= 0. {
+            return Err(Error::bad_request("充值金额必须大于 0"));
+        }
+        if self.bonus_amount < 0. {
+            return Err(Error::bad_request("赠送金额不能为负数"));
+        }
+        Ok(())
+    }
+}
+
+impl Curd for TopupTier {
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM topup_tiers WHERE 1 = 1";
+    const QUERY_SQL: &'static str = "SELECT * FROM topup_tiers WHERE 1 = 1";
+    const BY_ID_SQL: &'static str = "SELECT * FROM topup_tiers WHERE tier_id = ?";
+    const DELETE_BATCH_SQL: &'static str = "DELETE FROM topup_tiers WHERE tier_id IN (";
+    const ORDER_SQL: Option<&'static str> = Some(" ORDER BY min_amount");
+
+    fn apply_filters<'a>(&'a self, builder: &mut QueryBuilder<'a, Sqlite>) {
+        if let Some(store_id) = &self.store_id {
+            builder.push(" AND store_id = ").push_bind(store_id);
+        }
+    }
+}
+
+impl TopupTier {
+    pub async fn create(&self, pool: &Pool<Sqlite>) -> Result<Self> {
+        let now = utils::get_timestamp();
+        let result = sqlx::query_as(
+            "INSERT INTO topup_tiers (store_id, tier_name, min_amount, bonus_amount, enabled,
+                create_time, update_time, remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(self.store_id)
+        .bind(self.tier_name.as_deref().map(str::trim))
+        .bind(self.min_amount)
+        .bind(self.bonus_amount)
+        .bind(self.enabled)
+        .bind(now)
+        .bind(now)
+        .bind(&self.remark)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    pub async fn update(&self, pool: &Pool<Sqlite>) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE topup_tiers SET tier_name = ?, min_amount = ?, bonus_amount = ?, enabled = ?,
+                update_time = ?, remark = ?
+            WHERE tier_id = ? AND store_id = ?",
+        )
+        .bind(self.tier_name.as_deref().map(str::trim))
+        .bind(self.min_amount)
+        .bind(self.bonus_amount)
+        .bind(self.enabled)
+        .bind(utils::get_timestamp())
+        .bind(&self.remark)
+        .bind(self.tier_id)
+        .bind(self.store_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 取充值金额满足条件的最高档位
+    pub fn pick(tiers: &[Self], amount: f64) -> Option<&Self> {
+        tiers
+            .iter()
+            .filter(|tier| tier.enabled && tier.min_amount <= amount)
+            .max_by(|a, b| a.min_amount.total_cmp(&b.min_amount))
+    }
+
+    /// 删除当前门店的档位，其他门店的档位忽略
+    pub async fn delete_for_store(pool: &Pool<Sqlite>, store_id: i64, ids: &[i64]) -> Result<bool> {
+        if ids.is_empty() {
+            return Ok(true);
+        }
+
+        let mut builder = QueryBuilder::<Sqlite>::new("DELETE FROM topup_tiers WHERE store_id = ");
+        builder.push_bind(store_id);
+
+        builder.push(" AND tier_id IN (");
+        ids.iter().enumerate().for_each(|(i, id)| {
+            if i > 0 {
+                builder.push(", ");
+            }
+            builder.push_bind(id);
+        });
+
+        builder.push(")");
+
+        let result = builder.build().execute(pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+impl StoredValueTopup {
+    /// 为会员已有的储值卡充值，按档位赠送余额
+    pub async fn topup(pool: &Pool<Sqlite>, store_id: i64, req: TopupReq) -> Result<Self> {
+        if !matches!(
+            req.payment_method,
+            PaymentMethod::Cash | PaymentMethod::Alipay | PaymentMethod::WechatPay
+        ) {
+            return Err(Error::bad_request("充值仅支持现金、支付宝或微信支付"));
+        }
+        let amount = to_decimal(req.amount);
+        if amount <= Decimal::ZERO {
+            return Err(Error::bad_request("充值金额必须大于 0"));
+        }
+
+        let card = UserCoupon::find_by_uc_ids(pool, store_id, &[req.uc_id])
+            .await?
+            .pop()
+            .ok_or(Error::not_found("储值卡不存在"))?;
+        check_card(&card)?;
+
+        let tiers = TopupTier {
+            store_id: Some(store_id),
+            ..Default::default()
+        }
+        .get_all(pool)
+        .await?;
+        let amount = amount.to_f64().unwrap_or_default();
+        let tier = TopupTier::pick(&tiers, amount);
+        let bonus = tier.map(|tier| tier.bonus_amount).unwrap_or_default();
+
+        let mut tr = pool.begin().await?;
+
+        let pay_id = uuid::Uuid::new_v4().to_string();
+        let mut payment = Payment {
+            pay_id: Some(pay_id.clone()),
+            pay_number: Some(format!("CZ-{}", Utc::now().timestamp_millis())),
+            order_type: Some(PaymentOrderType::Coupon),
+            total_amount: Some(amount),
+            payment_status: Some(PaymentStatus::Paid),
+            payment_method: Some(req.payment_method.clone()),
+            store_id: Some(store_id),
+            ..Default::default()
+        };
+        payment.payment_method_details.push(PaymentMethodDetail {
+            method: Some(req.payment_method),
+            amount,
+            payment_id: pay_id.clone(),
+            store_id: Some(store_id),
+            payment_status: Some(PaymentStatus::Paid),
+            ..Default::default()
+        });
+        payment.create_payment(&mut tr).await?;
+
+        sqlx::query(
+            "UPDATE user_coupons SET available_value = COALESCE(available_value, 0) + ?,
+                bonus_value = COALESCE(bonus_value, 0) + ?
+            WHERE uc_id = ? AND store_id = ?",
+        )
+        .bind(amount + bonus)
+        .bind(bonus)
+        .bind(req.uc_id)
+        .bind(store_id)
+        .execute(&mut *tr)
+        .await?;
+
+        let topup = sqlx::query_as(
+            "INSERT INTO stored_value_topups (store_id, uc_id, user_id, amount, bonus_amount,
+                tier_id, pay_id, create_time, remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(store_id)
+        .bind(req.uc_id)
+        .bind(card.user_id)
+        .bind(amount)
+        .bind(bonus)
+        .bind(tier.and_then(|tier| tier.tier_id))
+        .bind(&pay_id)
+        .bind(utils::get_timestamp())
+        .bind(&req.remark)
+        .fetch_one(&mut *tr)
+        .await?;
+
+        tr.commit().await?;
+        Ok(topup)
+    }
+
+    pub async fn find_by_uc_id(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        uc_id: i64,
+    ) -> Result<Vec<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM stored_value_topups WHERE store_id = ? AND uc_id = ? ORDER BY topup_id",
+        )
+        .bind(store_id)
+        .bind(uc_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+}
+
+impl StoredValueTransfer {
+    /// 在同一家庭组成员的储值卡之间转移余额，赠送余额按比例随之转移
+    pub async fn transfer(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        req: &TransferReq,
+    ) -> Result<(Self, UserCoupon, UserCoupon)> {
+        let amount = to_decimal(req.amount);
+        if amount <= Decimal::ZERO {
+            return Err(Error::bad_request("转移金额必须大于 0"));
+        }
+        if req.from_uc_id == req.to_uc_id {
+            return Err(Error::bad_request("转出和转入的储值卡不能相同"));
+        }
+
+        let cards =
+            UserCoupon::find_by_uc_ids(pool, store_id, &[req.from_uc_id, req.to_uc_id]).await?;
+        let find = |uc_id| {
+            cards
+                .iter()
+                .find(|uc| uc.uc_id == Some(uc_id))
+                .cloned()
+                .ok_or(Error::not_found("储值卡不存在"))
+        };
+        let mut from = find(req.from_uc_id)?;
+        let mut to = find(req.to_uc_id)?;
+        check_card(&from)?;
+        check_card(&to)?;
+
+        let from_user_id = from.user_id.unwrap_or_default();
+        let to_user_id = to.user_id.unwrap_or_default();
+        if from_user_id != to_user_id
+            && !FamilyGroup::same_family(pool, store_id, from_user_id, to_user_id).await?
+        {
+            return Err(Error::bad_request("只能在同一家庭组的成员之间转移余额"));
+        }
+        if to_decimal(from.available_value.unwrap_or_default()) < amount {
+            return Err(Error::bad_request("转出储值卡余额不足"));
+        }
+
+        let bonus = from.deduct(amount);
+        to.available_value = (to_decimal(to.available_value.unwrap_or_default()) + amount).to_f64();
+        to.bonus_value = (to_decimal(to.bonus_value) + bonus)
+            .to_f64()
+            .unwrap_or_default();
+
+        let mut tr = pool.begin().await?;
+        if !from.update(&mut tr).await? || !to.update(&mut tr).await? {
+            return Err(Error::internal("更新储值卡余额失败"));
+        }
+        let transfer = sqlx::query_as(
+            "INSERT INTO stored_value_transfers (store_id, from_uc_id, to_uc_id, from_user_id,
+                to_user_id, amount, bonus_amount, create_time, remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(store_id)
+        .bind(req.from_uc_id)
+        .bind(req.to_uc_id)
+        .bind(from_user_id)
+        .bind(to_user_id)
+        .bind(amount.to_f64().unwrap_or_default())
+        .bind(bonus.to_f64().unwrap_or_default())
+        .bind(utils::get_timestamp())
+        .bind(&req.remark)
+        .fetch_one(&mut *tr)
+        .await?;
+        tr.commit().await?;
+        Ok((transfer, from, to))
+    }
+
+    pub async fn find_by_uc_id(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        uc_id: i64,
+    ) -> Result<Vec<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM stored_value_transfers
+            WHERE store_id = ? AND (from_uc_id = ? OR to_uc_id = ?)
+            ORDER BY transfer_id",
+        )
+        .bind(store_id)
+        .bind(uc_id)
+        .bind(uc_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 通知转出和转入双方，发送失败只记录日志
+    async fn notify(&self, state: &AppState, from: &UserCoupon, to: &UserCoupon) -> Result<()> {
+        let users = User::list_by_ids(&state.pool, &[self.from_user_id, self.to_user_id]).await?;
+        let find = |user_id| users.iter().find(|user| user.user_id == Some(user_id));
+        let name = |user: Option<&User>| {
+            user.and_then(|user| user.nick_name.clone())
+                .unwrap_or_default()
+        };
+        let (from_user, to_user) = (find(self.from_user_id), find(self.to_user_id));
+
+        let notices = [
+            (
+                from_user,
+                format!(
+                    "尊敬的{}，您的储值卡已向{}转出{:.2}元，当前余额{:.2}元。",
+                    name(from_user),
+                    name(to_user),
+                    self.amount,
+                    from.available_value.unwrap_or_default()
+                ),
+            ),
+            (
+                to_user,
+                format!(
+                    "尊敬的{}，{}向您的储值卡转入{:.2}元，当前余额{:.2}元。",
+                    name(to_user),
+                    name(from_user),
+                    self.amount,
+                    to.available_value.unwrap_or_default()
+                ),
+            ),
+        ];
+        for (user, content) in notices {
+            let Some(user) = user else {
+                continue;
+            };
+            if let Err(e) = notice::send_user_notice(
+                state,
+                user,
+                TEMP_TYPE_STORED_VALUE,
+                "储值余额变动",
+                content,
+            )
+            .await
+            {
+                tracing::warn!("stored value transfer notice failed: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RefundFee {
+    async fn load(pool: &Pool<Sqlite>) -> Result<Self> {
+        let value = |config: Option<Config>| {
+            config
+                .and_then(|config| config.config_value)
+                .and_then(|value| Decimal::from_str(value.trim()).ok())
+                .unwrap_or_default()
+                .max(Decimal::ZERO)
+        };
+        Ok(Self {
+            rate: value(Config::get_config_by_key(pool, REFUND_FEE_RATE_KEY).await?),
+            min: value(Config::get_config_by_key(pool, REFUND_FEE_MIN_KEY).await?),
+        })
+    }
+
+    fn calc(&self, principal: Decimal) -> Decimal {
+        if principal <= Decimal::ZERO {
+            return Decimal::ZERO;
+        }
+        (principal * self.rate / Decimal::ONE_HUNDRED)
+            .round_dp(2)
+            .max(self.min)
+            .min(principal)
+    }
+}
+
+impl CardRefund {
+    /// 试算退卡金额：储值卡退还本金余额，次卡按单次价格退还付费的剩余次数
+    fn calc(card: &UserCoupon, fee: &RefundFee) -> Result<Self> {
+        let coupon = card
+            .coupon
+            .as_ref()
+            .ok_or(Error::not_found("会员卡券不存在"))?;
+        let title = coupon.coupon_title.as_deref().unwrap_or_default();
+        let coupon_type = match &coupon.coupon_type {
+            Some(tp @ (CouponType::StoredValueCard | CouponType::SessionCard)) => tp.clone(),
+            _ => return Err(Error::bad_request(format!("<{title}> 不是储值卡或次卡"))),
+        };
+        if card.status.as_deref() == Some(UC_STATUS_REFUNDED) {
+            return Err(Error::bad_request(format!("<{title}> 已退卡")));
+        }
+
+        let balance = to_decimal(card.available_value.unwrap_or_default()).max(Decimal::ZERO);
+        let principal = to_decimal(card.principal_value()).min(balance);
+        let principal_amount = if coupon_type == CouponType::SessionCard {
+            // 单次价格 = 售价 / 每张次数
+            let usage_value = to_decimal(coupon.usage_value.unwrap_or_default());
+            if usage_value > Decimal::ZERO {
+                (principal * to_decimal(coupon.coupon_value.unwrap_or_default()) / usage_value)
+                    .round_dp(2)
+            } else {
+                Decimal::ZERO
+            }
+        } else {
+            principal
+        };
+        let fee_amount = fee.calc(principal_amount);
+
+        Ok(Self {
+            store_id: card.store_id,
+            uc_id: card.uc_id.unwrap_or_default(),
+            user_id: card.user_id.unwrap_or_default(),
+            coupon_type,
+            balance: balance.to_f64().unwrap_or_default(),
+            bonus_amount: (balance - principal).to_f64().unwrap_or_default(),
+            principal_amount: principal_amount.to_f64().unwrap_or_default(),
+            fee_amount: fee_amount.to_f64().unwrap_or_default(),
+            refund_amount: (principal_amount - fee_amount).to_f64().unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+
+    pub async fn quote(pool: &Pool<Sqlite>, store_id: i64, uc_id: i64) -> Result<Self> {
+        let card = UserCoupon::find_by_uc_ids(pool, store_id, &[uc_id])
+            .await?
+            .pop()
+            .ok_or(Error::not_found("会员卡券不存在"))?;
+        Self::calc(&card, &RefundFee::load(pool).await?)
+    }
+
+    /// 退卡：以负数金额记录卡券类支付，清零余额并停用会员卡券
+    pub async fn refund(pool: &Pool<Sqlite>, store_id: i64, req: &CardRefundReq) -> Result<Self> {
+        if !matches!(
+            req.payment_method,
+            PaymentMethod::Cash | PaymentMethod::Alipay | PaymentMethod::WechatPay
+        ) {
+            return Err(Error::bad_request("退卡仅支持现金、支付宝或微信退款"));
+        }
+        let mut refund = Self::quote(pool, store_id, req.uc_id).await?;
+        refund.payment_method = Some(req.payment_method.clone());
+        refund.remark = req.remark.clone();
+
+        let mut tr = pool.begin().await?;
+
+        if refund.refund_amount > 0. {
+            let pay_id = uuid::Uuid::new_v4().to_string();
+            let amount = -refund.refund_amount;
+            let mut payment = Payment {
+                pay_id: Some(pay_id.clone()),
+                pay_number: Some(format!("TK-{}", Utc::now().timestamp_millis())),
+                order_type: Some(PaymentOrderType::Coupon),
+                total_amount: Some(amount),
+                payment_status: Some(PaymentStatus::Paid),
+                payment_method: Some(req.payment_method.clone()),
+                store_id: Some(store_id),
+                refund_reason: req.remark.clone(),
+                ..Default::default()
+            };
+            payment.payment_method_details.push(PaymentMethodDetail {
+                method: Some(req.payment_method.clone()),
+                amount,
+                payment_id: pay_id.clone(),
+                store_id: Some(store_id),
+                payment_status: Some(PaymentStatus::Paid),
+                ..Default::default()
+            });
+            payment.create_payment(&mut tr).await?;
+            refund.pay_id = Some(pay_id);
+        }
+
+        let result = sqlx::query(
+            "UPDATE user_coupons SET available_value = 0, bonus_value = 0, status = ?
+            WHERE uc_id = ? AND store_id = ? AND COALESCE(status, '') != ?",
+        )
+        .bind(UC_STATUS_REFUNDED)
+        .bind(req.uc_id)
+        .bind(store_id)
+        .bind(UC_STATUS_REFUNDED)
+        .execute(&mut *tr)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::bad_request("该卡已退卡"));
+        }
+
+        let refund = sqlx::query_as(
+            "INSERT INTO card_refunds (store_id, uc_id, user_id, coupon_type, balance, bonus_amount,
+                principal_amount, fee_amount, refund_amount, payment_method, pay_id, create_time,
+                remark)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *",
+        )
+        .bind(store_id)
+        .bind(refund.uc_id)
+        .bind(refund.user_id)
+        .bind(&refund.coupon_type)
+        .bind(refund.balance)
+        .bind(refund.bonus_amount)
+        .bind(refund.principal_amount)
+        .bind(refund.fee_amount)
+        .bind(refund.refund_amount)
+        .bind(&refund.payment_method)
+        .bind(&refund.pay_id)
+        .bind(utils::get_timestamp())
+        .bind(&refund.remark)
+        .fetch_one(&mut *tr)
+        .await?;
+
+        tr.commit().await?;
+        Ok(refund)
+    }
+
+    pub async fn find_by_uc_id(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        uc_id: i64,
+    ) -> Result<Option<Self>> {
+        let result = sqlx::query_as("SELECT * FROM card_refunds WHERE store_id = ? AND uc_id = ?")
+            .bind(store_id)
+            .bind(uc_id)
+            .fetch_optional(pool)
+            .await?;
+        Ok(result)
+    }
+}
+
+impl CardStatement {
+    /// 查询储值卡的充值和消费流水
+    pub async fn load(pool: &Pool<Sqlite>, store_id: i64, uc_id: i64) -> Result<Self> {
+        let card = UserCoupon::find_by_uc_ids(pool, store_id, &[uc_id])
+            .await?
+            .pop()
+            .ok_or(Error::not_found("储值卡不存在"))?;
+        if card.coupon.as_ref().and_then(|c| c.coupon_type) != Some(CouponType::StoredValueCard) {
+            return Err(Error::bad_request("只有储值卡有余额流水"));
+        }
+
+        let mut entries = StoredValueTopup::find_by_uc_id(pool, store_id, uc_id)
+            .await?
+            .into_iter()
+            .map(|topup| StatementEntry {
+                kind: StatementKind::TopUp,
+                time: topup.create_time,
+                amount: topup.amount + topup.bonus_amount,
+                bonus_amount: topup.bonus_amount,
+                pay_id: topup.pay_id,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let usages: Vec<UsageRow> = sqlx::query_as(
+            "SELECT cu.payment_id, cu.applied_amount, cu.bonus_amount, cu.is_refunded,
+                p.uc_order_id, p.create_time, p.update_time
+            FROM coupon_usages cu
+            INNER JOIN payments p ON cu.payment_id = p.pay_id
+            WHERE cu.coupon_id = ? AND cu.coupon_type = ? AND p.store_id = ?",
+        )
+        .bind(uc_id)
+        .bind(CouponType::StoredValueCard)
+        .bind(store_id)
+        .fetch_all(pool)
+        .await?;
+        for usage in usages {
+            let bonus = usage.bonus_amount.unwrap_or_default();
+            let entry = StatementEntry {
+                kind: StatementKind::Consume,
+                time: usage.create_time,
+                amount: -usage.applied_amount,
+                bonus_amount: -bonus,
+                pay_id: Some(usage.payment_id),
+                order_id: usage.uc_order_id,
+                ..Default::default()
+            };
+            if usage.is_refunded {
+                entries.push(StatementEntry {
+                    kind: StatementKind::Refund,
+                    time: usage.update_time.or(usage.create_time),
+                    amount: usage.applied_amount,
+                    bonus_amount: bonus,
+                    ..entry.clone()
+                });
+            }
+            entries.push(entry);
+        }
+
+        for transfer in StoredValueTransfer::find_by_uc_id(pool, store_id, uc_id).await? {
+            let outgoing = transfer.from_uc_id == uc_id;
+            let sign = if outgoing { -1. } else { 1. };
+            entries.push(StatementEntry {
+                kind: if outgoing {
+                    StatementKind::TransferOut
+                } else {
+                    StatementKind::TransferIn
+                },
+                time: transfer.create_time,
+                amount: sign * transfer.amount,
+                bonus_amount: sign * transfer.bonus_amount,
+                ..Default::default()
+            });
+        }
+
+        if let Some(refund) = CardRefund::find_by_uc_id(pool, store_id, uc_id).await? {
+            entries.push(StatementEntry {
+                kind: StatementKind::CashOut,
+                time: refund.create_time,
+                amount: -refund.balance,
+                bonus_amount: -refund.bonus_amount,
+                pay_id: refund.pay_id,
+                ..Default::default()
+            });
+        }
+
+        Ok(Self::build(&card, entries))
+    }
+
+    /// 按时间排序流水并计算每笔变动后的余额，开卡余额由当前余额倒推
+    fn build(card: &UserCoupon, mut entries: Vec<StatementEntry>) -> Self {
+        entries.sort_by_key(|entry| entry.time);
+
+        let balance = to_decimal(card.available_value.unwrap_or_default());
+        let bonus = to_decimal(card.bonus_value);
+        let changed = entries
+            .iter()
+            .map(|e| to_decimal(e.amount))
+            .sum::<Decimal>();
+        let bonus_changed = entries
+            .iter()
+            .map(|e| to_decimal(e.bonus_amount))
+            .sum::<Decimal>();
+
+        let mut running = balance - changed;
+        let mut running_bonus = bonus - bonus_changed;
+        let opening = StatementEntry {
+            kind: StatementKind::Opening,
+            time: card.create_time.map(|time| time.timestamp_millis()),
+            amount: running.to_f64().unwrap_or_default(),
+            bonus_amount: running_bonus.to_f64().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let mut result = Vec::with_capacity(entries.len() + 1);
+        for mut entry in std::iter::once(opening).chain(entries) {
+            if entry.kind != StatementKind::Opening {
+                running += to_decimal(entry.amount);
+                running_bonus += to_decimal(entry.bonus_amount);
+            }
+            entry.balance = running.to_f64().unwrap_or_default();
+            entry.bonus_balance = running_bonus.to_f64().unwrap_or_default();
+            result.push(entry);
+        }
+
+        Self {
+            uc_id: card.uc_id.unwrap_or_default(),
+            balance: balance.to_f64().unwrap_or_default(),
+            principal: card.principal_value(),
+            bonus: bonus.to_f64().unwrap_or_default(),
+            entries: result,
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_topup_tiers(state: State<'_, AppState>) -> Result<Vec<TopupTier>> {
+    let store_id = utils::get_user_id(&state).await?;
+    TopupTier {
+        store_id: Some(store_id),
+        ..Default::default()
+    }
+    .get_all(&state.pool)
+    .await
+}
+
+#[tauri::command]
+pub async fn create_topup_tier(
+    state: State<'_, AppState>,
+    mut tier: TopupTier,
+) -> Result<TopupTier> {
+    tier.validate()?;
+    tier.store_id = Some(utils::get_user_id(&state).await?);
+    tier.create(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn update_topup_tier(state: State<'_, AppState>, mut tier: TopupTier) -> Result<bool> {
+    tier.validate()?;
+    tier.store_id = Some(utils::get_user_id(&state).await?);
+    tier.update(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn delete_topup_tiers(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
+    let store_id = utils::get_user_id(&state).await?;
+    TopupTier::delete_for_store(&state.pool, store_id, &ids).await
+}
+
+#[tauri::command]
+pub async fn topup_stored_value_card(
+    state: State<'_, AppState>,
+    req: TopupReq,
+) -> Result<StoredValueTopup> {
+    let store_id = utils::get_user_id(&state).await?;
+    StoredValueTopup::topup(&state.pool, store_id, req).await
+}
+
+/// 家庭成员间转移储值余额，并通知双方
+#[tauri::command]
+pub async fn transfer_stored_value(
+    state: State<'_, AppState>,
+    req: TransferReq,
+) -> Result<StoredValueTransfer> {
+    let store_id = utils::get_user_id(&state).await?;
+    let (transfer, from, to) = StoredValueTransfer::transfer(&state.pool, store_id, &req).await?;
+    if let Err(e) = transfer.notify(&state, &from, &to).await {
+        tracing::warn!("stored value transfer notice failed: {:?}", e);
+    }
+    Ok(transfer)
+}
+
+/// 退卡试算
+#[tauri::command]
+pub async fn get_card_refund_quote(state: State<'_, AppState>, uc_id: i64) -> Result<CardRefund> {
+    let store_id = utils::get_user_id(&state).await?;
+    CardRefund::quote(&state.pool, store_id, uc_id).await
+}
+
+#[tauri::command]
+pub async fn refund_card(state: State<'_, AppState>, req: CardRefundReq) -> Result<CardRefund> {
+    let store_id = utils::get_user_id(&state).await?;
+    CardRefund::refund(&state.pool, store_id, &req).await
+}
+
+#[tauri::command]
+pub async fn get_stored_value_statement(
+    state: State<'_, AppState>,
+    uc_id: i64,
+) -> Result<CardStatement> {
+    let store_id = utils::get_user_id(&state).await?;
+    CardStatement::load(&state.pool, store_id, uc_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::coupons::Coupon;
+
+    fn tier(min_amount: f64, bonus_amount: f64, enabled: bool) -> TopupTier {
+        TopupTier {
+            min_amount,
+            bonus_amount,
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pick_tier() {
+        let tiers = vec![
+            tier(200., 20., true),
+            tier(500., 80., true),
+            tier(1000., 200., false),
+        ];
+        assert!(TopupTier::pick(&tiers, 100.).is_none());
+        assert_eq!(TopupTier::pick(&tiers, 499.).unwrap().bonus_amount, 20.);
+        assert_eq!(TopupTier::pick(&tiers, 1000.).unwrap().bonus_amount, 80.);
+    }
+
+    #[test]
+    fn test_statement_balance() {
+        let mut card = UserCoupon {
+            uc_id: Some(1),
+            available_value: Some(500.),
+            bonus_value: 50.,
+            ..Default::default()
+        };
+        // 余额 500（赠送 50）中扣款 100，赠送余额按比例扣减 10
+        assert_eq!(card.deduct(Decimal::from(100)), Decimal::from(10));
+        assert_eq!(card.available_value, Some(400.));
+        assert_eq!(card.principal_value(), 360.);
+
+        let entries = vec![
+            StatementEntry {
+                kind: StatementKind::Consume,
+                time: Some(2),
+                amount: -100.,
+                bonus_amount: -10.,
+                ..Default::default()
+            },
+            StatementEntry {
+                kind: StatementKind::TopUp,
+                time: Some(1),
+                amount: 300.,
+                bonus_amount: 40.,
+                ..Default::default()
+            },
+        ];
+        let statement = CardStatement::build(&card, entries);
+        let balances = statement
+            .entries
+            .iter()
+            .map(|entry| (entry.kind, entry.balance, entry.bonus_balance))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            balances,
+            vec![
+                (StatementKind::Opening, 200., 10.),
+                (StatementKind::TopUp, 500., 50.),
+                (StatementKind::Consume, 400., 40.),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_card_refund_calc() {
+        let fee = RefundFee {
+            rate: Decimal::from(5),
+            min: Decimal::from(10),
+        };
+        let card =
+            |coupon_type, coupon_value, usage_value, available_value, bonus_value| UserCoupon {
+                uc_id: Some(1),
+                available_value: Some(available_value),
+                bonus_value,
+                coupon: Some(Coupon {
+                    coupon_type: Some(coupon_type),
+                    coupon_value: Some(coupon_value),
+                    usage_value: Some(usage_value),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+
+        // 储值卡余额 400（赠送 40），退本金 360，手续费 5% 为 18
+        let refund = CardRefund::calc(
+            &card(CouponType::StoredValueCard, 1000., 1100., 400., 40.),
+            &fee,
+        )
+        .unwrap();
+        assert_eq!(refund.principal_amount, 360.);
+        assert_eq!(refund.bonus_amount, 40.);
+        assert_eq!(refund.fee_amount, 18.);
+        assert_eq!(refund.refund_amount, 342.);
+
+        // 次卡 300 元 10 次，剩余 4 次退 120，手续费取最低 10
+        let refund =
+            CardRefund::calc(&card(CouponType::SessionCard, 300., 10., 4., 0.), &fee).unwrap();
+        assert_eq!(refund.principal_amount, 120.);
+        assert_eq!(refund.fee_amount, 10.);
+        assert_eq!(refund.refund_amount, 110.);
+
+        // 赠送的次卡无可退本金，也不收手续费
+        let refund =
+            CardRefund::calc(&card(CouponType::SessionCard, 300., 10., 4., 10.), &fee).unwrap();
+        assert_eq!(refund.principal_amount, 0.);
+        assert_eq!(refund.refund_amount, 0.);
+
+        assert!(
+            CardRefund::calc(&card(CouponType::DiscountCoupon, 10., 10., 10., 0.), &fee).is_err()
+        );
+    }
+}
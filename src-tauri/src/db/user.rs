@@ -1,11 +1,14 @@
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqliteRow;
 use sqlx::{FromRow, Pool, QueryBuilder, Row, Sqlite, Transaction};
 use tauri::State;
 
-use crate::constants::CouponType;
+use crate::constants::NoticeChannel;
+use crate::db::coupon_codes::{gen_code, normalize_code};
 use crate::db::order_clothes::OrderCloth;
 use crate::db::orders::Order;
+use crate::db::points::PointsRecord;
 use crate::db::user_coupons::UserCoupon;
 use crate::db::user_membership_level::UserMembershipLevel;
 use crate::db::user_tags::UserTags;
@@ -69,10 +72,22 @@ pub struct User {
     pub remark: Option<String>,
     /// 家庭住址
     pub address: Option<String>,
+    /// 生日（YYYY-MM-DD）
+    pub birthday: Option<String>,
+    /// 入会日期（YYYY-MM-DD），用于入会周年祝福
+    pub join_date: Option<String>,
 
     /// tags
     pub user_tags: Option<String>,
     pub tags_remark: Option<String>,
+    /// 分群规则自动计算的标签
+    pub auto_tags: Option<String>,
+    /// 查询条件：手动标签
+    #[serde(skip_serializing)]
+    pub tag: Option<String>,
+    /// 查询条件：分群标签
+    #[serde(skip_serializing)]
+    pub segment: Option<String>,
 
     /// 余额
     pub balance: f64,
@@ -80,6 +95,19 @@ pub struct User {
     /// 等级名称
     pub level_id: Option<i64>,
     pub level_name: Option<String>,
+
+    /// 首选通知渠道（短信或站内消息），为空时按模板的通知方式发送
+    pub notice_channel: Option<NoticeChannel>,
+    /// 是否退订通知
+    pub notice_opt_out: bool,
+
+    /// 推荐人
+    pub referrer_id: Option<i64>,
+    /// 会员自己的推荐码
+    pub referral_code: Option<String>,
+    /// 新建会员时填写的推荐人推荐码
+    #[serde(skip_serializing)]
+    pub referrer_code: Option<String>,
 }
 
 impl FromRow<'_, SqliteRow> for User {
@@ -109,10 +137,20 @@ impl FromRow<'_, SqliteRow> for User {
             update_time: row.try_get("update_time").unwrap_or_default(),
             remark: row.try_get("remark").unwrap_or_default(),
             address: row.try_get("address").unwrap_or_default(),
+            birthday: row.try_get("birthday").unwrap_or_default(),
+            join_date: row.try_get("join_date").unwrap_or_default(),
             user_tags: row.try_get("user_tags").unwrap_or_default(),
             tags_remark: row.try_get("tags_remark").unwrap_or_default(),
+            auto_tags: row.try_get("auto_tags").unwrap_or_default(),
+            tag: None,
+            segment: None,
             level_name: row.try_get("level_name").unwrap_or_default(),
             level_id: row.try_get("level_id").unwrap_or_default(),
+            notice_channel: row.try_get("notice_channel").unwrap_or_default(),
+            notice_opt_out: row.try_get("notice_opt_out").unwrap_or_default(),
+            referrer_id: row.try_get("referrer_id").unwrap_or_default(),
+            referral_code: row.try_get("referral_code").unwrap_or_default(),
+            referrer_code: None,
             balance: 0.,
         })
     }
@@ -140,26 +178,53 @@ impl User {
         if self.del_flag.is_none() {
             self.del_flag = Some("0".to_string());
         }
+
+        if self.join_date.is_none() {
+            self.join_date = Some(utils::get_now().format(DATE_FORMAT).to_string());
+        }
+    }
+
+    /// 生日和入会日期为空或 YYYY-MM-DD 格式
+    fn check_dates(&mut self) -> Result<()> {
+        for (date, name) in [
+            (&mut self.birthday, "生日"),
+            (&mut self.join_date, "入会日期"),
+        ] {
+            *date = date.take().filter(|d| !d.trim().is_empty());
+            if let Some(value) = date {
+                if NaiveDate::parse_from_str(value.trim(), DATE_FORMAT).is_err() {
+                    return Err(Error::bad_request(format!("{name}格式应为 YYYY-MM-DD")));
+                }
+                *value = value.trim().to_string();
+            }
+        }
+        Ok(())
     }
 }
 const USER_MEMBERSHIP_COSTUMER: i64 = 3;
+const REFERRAL_CODE_LEN: usize = 8;
+const REFERRAL_CODE_RETRIES: usize = 5;
+const DATE_FORMAT: &str = "%Y-%m-%d";
 
 const QUERY_SQL: &str = "select u.*, p.level_name, p.level_id, t.tags AS user_tags,
-        t.remark as tags_remark from users u
+        t.remark as tags_remark, t.auto_tags from users u
         left join user_membership_level up on u.user_id = up.user_id
         left join membership_level p on up.level_id = p.level_id
         left join user_tags t  on t.user_id = u.user_id
         where u.del_flag = '0'";
 
 const BY_ID_SQL: &str = "select u.*, p.level_name, p.level_id, t.tags AS user_tags,
-        t.remark as tags_remark from users u
+        t.remark as tags_remark, t.auto_tags from users u
         left join user_membership_level up on u.user_id = up.user_id
         left join membership_level p on up.level_id = p.level_id
         left join user_tags t  on t.user_id = u.user_id
         where u.del_flag = '0' AND u.user_id = ? ";
 
 impl Curd for User {
-    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM users u LEFT JOIN user_membership_level up ON u.user_id = up.user_id WHERE u.del_flag = '0' ";
+    const COUNT_SQL: &'static str = "SELECT COUNT(*) FROM users u
+        LEFT JOIN user_membership_level up ON u.user_id = up.user_id
+        LEFT JOIN user_tags t ON t.user_id = u.user_id
+        WHERE u.del_flag = '0' ";
     const QUERY_SQL: &'static str = QUERY_SQL;
     const BY_ID_SQL: &'static str = BY_ID_SQL;
     const DELETE_BATCH_SQL: &'static str = "UPDATE users SET del_flag = '2' WHERE user_id IN ( ";
@@ -196,6 +261,18 @@ impl Curd for User {
         self.status.as_ref().filter(|s| !s.is_empty()).map(|s| {
             builder.push(" AND u.status = ").push_bind(s);
         });
+
+        self.tag.as_ref().filter(|t| !t.is_empty()).map(|t| {
+            builder
+                .push(" AND (',' || t.tags || ',') LIKE ")
+                .push_bind(format!("%,{},%", t));
+        });
+
+        self.segment.as_ref().filter(|s| !s.is_empty()).map(|s| {
+            builder
+                .push(" AND (',' || t.auto_tags || ',') LIKE ")
+                .push_bind(format!("%,{},%", s));
+        });
     }
 }
 
@@ -205,8 +282,8 @@ impl User {
             "INSERT INTO users (
             user_id, store_id, open_id, dept_id, user_name, nick_name, user_type, email, phonenumber,
             sex, avatar, password, status, integral, identify, login_ip,
-            login_date, create_by, create_time, remark, address, del_flag
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, '0')
+            login_date, create_by, create_time, remark, address, birthday, join_date, del_flag
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, '0')
         RETURNING *",
         )
         .bind(&self.user_id)
@@ -230,6 +307,8 @@ impl User {
         .bind(utils::get_timestamp())
         .bind(&self.remark)
         .bind(&self.address)
+        .bind(&self.birthday)
+        .bind(&self.join_date)
         .fetch_one(&mut **tr)
         .await?;
         Ok(row)
@@ -249,13 +328,14 @@ impl User {
             avatar = ?, 
             password = ?, 
             status = ?, 
-            integral = ?, 
             identify = ?, 
             login_ip = ?, 
             login_date = ?, 
             update_by = ?, 
             remark = ?, 
             address = ?, 
+            birthday = ?, 
+            join_date = ?, 
             update_time = ? 
             WHERE store_id = ? AND user_id = ?",
         )
@@ -270,13 +350,14 @@ impl User {
         .bind(&self.avatar)
         .bind(&self.password)
         .bind(&self.status)
-        .bind(&self.integral)
         .bind(&self.identify)
         .bind(&self.login_ip)
         .bind(&self.login_date)
         .bind(&self.update_by)
         .bind(&self.remark)
         .bind(&self.address)
+        .bind(&self.birthday)
+        .bind(&self.join_date)
         .bind(utils::get_timestamp())
         .bind(self.store_id)
         .bind(self.user_id)
@@ -332,32 +413,111 @@ impl User {
         Ok(result > 0)
     }
 
-    /// increase user points
-    pub async fn increase_points(
-        tx: &mut Transaction<'_, Sqlite>,
-        id: i64,
-        points: i64,
+    /// 手机号是否注册过会员，包括已删除的会员
+    pub async fn phone_ever_registered(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        tel: &str,
+        exclude_user_id: Option<i64>,
     ) -> Result<bool> {
-        let result = sqlx::query("UPDATE users SET integral = integral + ? WHERE user_id = ?")
-            .bind(points)
-            .bind(id)
-            .execute(&mut **tx)
+        let result = sqlx::query_scalar::<_, u64>(
+            "SELECT count(1) FROM users WHERE store_id = ? AND phonenumber = ? AND user_id != ?",
+        )
+        .bind(store_id)
+        .bind(tel)
+        .bind(exclude_user_id.unwrap_or_default())
+        .fetch_one(pool)
+        .await?;
+
+        Ok(result > 0)
+    }
+
+    /// 按推荐码查找正常状态的会员
+    pub async fn find_by_referral_code(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        code: &str,
+    ) -> Result<Option<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM users
+            WHERE del_flag = '0' AND status = '0' AND store_id = ? AND referral_code = ?",
+        )
+        .bind(store_id)
+        .bind(normalize_code(code))
+        .fetch_optional(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 为会员生成推荐码，已有推荐码时直接返回
+    pub async fn assign_referral_code(
+        tr: &mut Transaction<'_, Sqlite>,
+        store_id: i64,
+        user_id: i64,
+    ) -> Result<String> {
+        let current: Option<String> = sqlx::query_scalar(
+            "SELECT referral_code FROM users WHERE store_id = ? AND user_id = ?",
+        )
+        .bind(store_id)
+        .bind(user_id)
+        .fetch_optional(&mut **tr)
+        .await?
+        .ok_or(Error::not_found("用户未找到"))?;
+        if let Some(code) = current.filter(|code| !code.is_empty()) {
+            return Ok(code);
+        }
+
+        for _ in 0..REFERRAL_CODE_RETRIES {
+            let code = gen_code(REFERRAL_CODE_LEN);
+            let result = sqlx::query(
+                "UPDATE users SET referral_code = ? WHERE store_id = ? AND user_id = ?
+                AND NOT EXISTS (SELECT 1 FROM users WHERE store_id = ? AND referral_code = ?)",
+            )
+            .bind(&code)
+            .bind(store_id)
+            .bind(user_id)
+            .bind(store_id)
+            .bind(&code)
+            .execute(&mut **tr)
             .await?;
-        Ok(result.rows_affected() > 0)
+            if result.rows_affected() > 0 {
+                return Ok(code);
+            }
+        }
+        Err(Error::internal("生成推荐码失败"))
     }
 
-    /// decrease user points
-    pub async fn decrease_points(
-        tx: &mut Transaction<'_, Sqlite>,
-        id: i64,
-        points: i64,
+    /// 门店中登记了生日或入会日期的正常会员
+    pub async fn list_with_dates(pool: &Pool<Sqlite>, store_id: i64) -> Result<Vec<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM users WHERE del_flag = '0' AND status = '0' AND store_id = ?
+            AND (birthday IS NOT NULL OR join_date IS NOT NULL)",
+        )
+        .bind(store_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 更新通知偏好
+    pub async fn update_notice_preference(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: i64,
+        channel: Option<NoticeChannel>,
+        opt_out: bool,
     ) -> Result<bool> {
-        let result =
-            sqlx::query("UPDATE users SET integral = MAX(integral - ?, 0) WHERE user_id = ?")
-                .bind(points)
-                .bind(id)
-                .execute(&mut **tx)
-                .await?;
+        let result = sqlx::query(
+            "UPDATE users SET notice_channel = ?, notice_opt_out = ?, update_time = ?
+            WHERE store_id = ? AND user_id = ?",
+        )
+        .bind(channel)
+        .bind(opt_out)
+        .bind(utils::get_timestamp())
+        .bind(store_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
         Ok(result.rows_affected() > 0)
     }
 
@@ -421,6 +581,32 @@ impl User {
             return Err(Error::bad_request("手机号已经存在"));
         }
 
+        // 推荐码只对首次注册的手机号有效，防止删除后重新注册重复领取奖励
+        let referrer_code = self
+            .referrer_code
+            .take()
+            .filter(|code| !code.trim().is_empty());
+        let referrer_id = match referrer_code {
+            Some(code) => {
+                let store_id = self.store_id.unwrap();
+                if User::phone_ever_registered(
+                    pool,
+                    store_id,
+                    self.phonenumber.as_ref().unwrap(),
+                    None,
+                )
+                .await?
+                {
+                    return Err(Error::bad_request("该手机号曾注册过会员，不能使用推荐码"));
+                }
+                let referrer = User::find_by_referral_code(pool, store_id, &code)
+                    .await?
+                    .ok_or(Error::bad_request("推荐码无效"))?;
+                referrer.user_id
+            }
+            None => None,
+        };
+
         if self.user_name.is_none() {
             self.user_name = self.phonenumber.clone();
         }
@@ -435,14 +621,31 @@ impl User {
             return Err(Error::bad_request("账号已存在"));
         }
 
+        self.check_dates()?;
         self.init();
 
         let user_tags = self.user_tags.clone();
         let tags_remark = self.tags_remark.clone();
         // create user to server
-        let user = self.create_request(state).await?;
+        let mut user = self.create_request(state).await?;
+        // 服务端返回的会员信息不一定包含生日和入会日期，以提交的为准
+        user.birthday = self.birthday.clone();
+        user.join_date = self.join_date.clone();
+
+        let mut user = user.create(&mut tr).await?;
+        let store_id = user.store_id.unwrap_or_default();
+        let user_id = user.user_id.unwrap();
+        if referrer_id.is_some() {
+            sqlx::query("UPDATE users SET referrer_id = ? WHERE store_id = ? AND user_id = ?")
+                .bind(referrer_id)
+                .bind(store_id)
+                .bind(user_id)
+                .execute(&mut *tr)
+                .await?;
+            user.referrer_id = referrer_id;
+        }
+        user.referral_code = Some(Self::assign_referral_code(&mut tr, store_id, user_id).await?);
 
-        let user = user.create(&mut tr).await?;
         // create user tags
         if let Some(tags) = user_tags {
             UserTags::new(user.user_id.unwrap(), tags, tags_remark)
@@ -450,6 +653,14 @@ impl User {
                 .await?;
         }
 
+        PointsRecord::opening(
+            &mut tr,
+            user.store_id.unwrap_or_default(),
+            user.user_id.unwrap(),
+            user.integral.unwrap_or_default() as i64,
+        )
+        .await?;
+
         // combine member level
         UserMembershipLevel::new(user.user_id.unwrap(), USER_MEMBERSHIP_COSTUMER)
             .create(&mut tr)
@@ -458,10 +669,11 @@ impl User {
         Ok(user)
     }
 
-    pub async fn update_user(&self, state: &State<'_, AppState>) -> Result<bool> {
+    pub async fn update_user(&mut self, state: &State<'_, AppState>) -> Result<bool> {
         let pool = &state.pool;
         let mut tr = pool.begin().await?;
         // validate
+        self.check_dates()?;
         if self.store_id.is_none() {
             return Err(Error::unauthorized());
         }
@@ -486,6 +698,22 @@ impl User {
             return Err(Error::bad_request("update user to server failed"));
         }
 
+        // 积分只能通过流水变动，编辑时的差额记为手动调整
+        if let Some(integral) = self.integral {
+            let current = Self::get_by_id(pool, self.user_id.unwrap())
+                .await?
+                .and_then(|user| user.integral)
+                .unwrap_or_default();
+            PointsRecord::adjust(
+                &mut tr,
+                self.store_id.unwrap(),
+                self.user_id.unwrap(),
+                (integral - current) as i64,
+                Some("编辑会员信息".to_string()),
+            )
+            .await?;
+        }
+
         // update user to database
         let result = self.update(&mut tr).await?;
         tr.commit().await?;
@@ -501,22 +729,8 @@ impl User {
         for user in result.rows.iter_mut() {
             // query user coupons for storage card and calculate balance
             if let Some(id) = user.user_id {
-                let coupons = UserCoupon::find_by_user_id(pool, self.store_id.unwrap(), id).await?;
-                user.balance = coupons
-                    .iter()
-                    .filter(|c| {
-                        if let Some(coupon) = c.coupon.as_ref() {
-                            if let Some(valid_to) = coupon.valid_to {
-                                return coupon.coupon_type == Some(CouponType::StoredValueCard)
-                                    && utils::get_now() <= valid_to
-                                    && c.available_value.is_some()
-                                    && c.available_value.unwrap() > 0.;
-                            }
-                        }
-                        false
-                    })
-                    .map(|c| c.available_value.unwrap_or_default())
-                    .sum();
+                user.balance =
+                    UserCoupon::stored_value_balance(pool, self.store_id.unwrap(), id).await?;
             }
         }
         Ok(result)
@@ -572,22 +786,7 @@ pub async fn get_user_by_id(state: State<'_, AppState>, id: i64) -> Result<Optio
 
     let store_id = utils::get_user_id(&state).await?;
     // cal balance
-    let coupons = UserCoupon::find_by_user_id(pool, store_id, id).await?;
-    user.balance = coupons
-        .iter()
-        .filter(|c| {
-            if let Some(coupon) = c.coupon.as_ref() {
-                if let Some(valid_to) = coupon.valid_to {
-                    return coupon.coupon_type == Some(CouponType::StoredValueCard)
-                        && utils::get_now() <= valid_to
-                        && c.available_value.is_some()
-                        && c.available_value.unwrap() > 0.;
-                }
-            }
-            false
-        })
-        .map(|c| c.available_value.unwrap_or_default())
-        .sum();
+    user.balance = UserCoupon::stored_value_balance(pool, store_id, id).await?;
 
     Ok(Some(user))
 }
@@ -656,6 +855,28 @@ pub async fn change_user_identify(
     Ok(result)
 }
 
+#[tauri::command]
+pub async fn update_user_notice_preference(
+    state: State<'_, AppState>,
+    user_id: i64,
+    channel: Option<String>,
+    opt_out: bool,
+) -> Result<bool> {
+    // 首选渠道只能是短信或站内消息，微信等没有服务端接口的渠道不接受
+    let channel = match channel.as_deref().filter(|value| !value.trim().is_empty()) {
+        Some(value) => Some(
+            NoticeChannel::parse(value)
+                .filter(|channel| matches!(channel, NoticeChannel::Sms | NoticeChannel::InApp))
+                .ok_or(Error::bad_request(
+                    "不支持的通知渠道，首选渠道只能是短信或站内消息",
+                ))?,
+        ),
+        None => None,
+    };
+    let store_id = utils::get_user_id(&state).await?;
+    User::update_notice_preference(&state.pool, store_id, user_id, channel, opt_out).await
+}
+
 #[tauri::command]
 pub async fn delete_users(state: State<'_, AppState>, ids: Vec<i64>) -> Result<bool> {
     User::delete_users(&state.pool, ids).await
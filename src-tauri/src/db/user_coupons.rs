@@ -1,3 +1,4 @@
+use rust_decimal::prelude::*;
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqliteRow;
 use sqlx::{
@@ -6,12 +7,16 @@ use sqlx::{
 };
 use tauri::State;
 
+use crate::constants::CouponType;
 use crate::db::Validator;
-use crate::db::coupons::Coupon;
+use crate::db::coupons::{AUTO_DELAY_YES, Coupon};
 use crate::error::{Error, ErrorKind, Result};
 use crate::state::AppState;
 use crate::utils;
 
+/// 会员卡券状态：已退卡停用
+pub const UC_STATUS_REFUNDED: &str = "1";
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(default)]
@@ -29,11 +34,21 @@ pub struct UserCoupon {
     pub remark: Option<String>,
     pub coupon: Option<Coupon>,
     pub store_id: Option<i64>,
+    /// 自动延期后的有效期，为空时以卡券有效期为准
+    pub expire_time: Option<DateTime<FixedOffset>>,
+    /// 储值卡余额中的赠送部分，次卡为赠送次数，不可退；available_value - bonus_value 为本金
+    pub bonus_value: f64,
 }
 
 impl FromRow<'_, SqliteRow> for UserCoupon {
     fn from_row(row: &'_ SqliteRow) -> std::result::Result<Self, sqlx::Error> {
-        let coupon = Coupon::from_row(row)?;
+        let mut coupon = Coupon::from_row(row)?;
+        let expire_time: Option<DateTime<FixedOffset>> =
+            row.try_get("expire_time").unwrap_or_default();
+        // 延期后的会员卡券以自身有效期为准
+        if expire_time.is_some() {
+            coupon.valid_to = expire_time;
+        }
         Ok(Self {
             uc_id: row.try_get("uc_id").unwrap_or_default(),
             user_id: row.try_get("user_id").unwrap_or_default(),
@@ -47,6 +62,8 @@ impl FromRow<'_, SqliteRow> for UserCoupon {
             status: row.try_get("status").unwrap_or_default(),
             remark: row.try_get("remark").unwrap_or_default(),
             store_id: row.try_get("store_id").unwrap_or_default(),
+            expire_time,
+            bonus_value: row.try_get("bonus_value").unwrap_or_default(),
             coupon: Some(coupon),
         })
     }
@@ -86,8 +103,8 @@ impl UserCoupon {
             r#"
             INSERT INTO user_coupons (
                 store_id, user_id, coupon_id, create_time, obtain_at, available_value,
-                uc_count, pay_id, uc_type, status, remark
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                uc_count, pay_id, uc_type, status, remark, bonus_value
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING *
             "#,
         )
@@ -102,6 +119,7 @@ impl UserCoupon {
         .bind(&self.uc_type)
         .bind(&self.status)
         .bind(&self.remark)
+        .bind(self.bonus_value)
         .fetch_one(&mut **tr)
         .await?;
 
@@ -129,7 +147,7 @@ impl UserCoupon {
     ) -> Result<Vec<Self>> {
         let result = sqlx::query_as(&format!(
             "{SQL} WHERE uc.store_id = ?  AND uc.user_id = ? 
-                  AND ? BETWEEN c.valid_from AND c.valid_to
+                  AND ? BETWEEN c.valid_from AND COALESCE(uc.expire_time, c.valid_to)
                   AND uc.available_value > 0
                   AND uc.uc_count > 0;"
         ))
@@ -172,7 +190,8 @@ impl UserCoupon {
                 pay_id = ?,
                 uc_type = ?,
                 status = ?,
-                remark = ?
+                remark = ?,
+                bonus_value = ?
             WHERE store_id = ? AND uc_id = ?
             "#,
         )
@@ -186,6 +205,7 @@ impl UserCoupon {
         .bind(&self.uc_type)
         .bind(&self.status)
         .bind(&self.remark)
+        .bind(self.bonus_value)
         .bind(self.store_id)
         .bind(self.uc_id)
         .execute(&mut **tr)
@@ -266,6 +286,160 @@ impl UserCoupon {
 
         Ok(result)
     }
+
+    /// 查询有效期在 [from, to] 之间且仍有剩余的会员卡券
+    pub async fn list_expiring(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        from: DateTime<FixedOffset>,
+        to: DateTime<FixedOffset>,
+    ) -> Result<Vec<Self>> {
+        let result = sqlx::query_as(&format!(
+            "{SQL} WHERE uc.store_id = ? AND c.del_flag = '0'
+                  AND COALESCE(uc.expire_time, c.valid_to) BETWEEN ? AND ?
+                  AND uc.available_value > 0
+                  AND uc.uc_count > 0"
+        ))
+        .bind(store_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 查询已过期、仍有剩余且卡券设置了自动延期的会员卡券
+    pub async fn list_expired_auto_delay(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        now: DateTime<FixedOffset>,
+    ) -> Result<Vec<Self>> {
+        let result = sqlx::query_as(&format!(
+            "{SQL} WHERE uc.store_id = ? AND c.del_flag = '0' AND c.auto_delay = ?
+                  AND COALESCE(uc.expire_time, c.valid_to) < ?
+                  AND uc.available_value > 0
+                  AND uc.uc_count > 0"
+        ))
+        .bind(store_id)
+        .bind(AUTO_DELAY_YES)
+        .bind(now)
+        .fetch_all(pool)
+        .await?;
+        Ok(result)
+    }
+
+    pub async fn update_expire_time(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        uc_id: i64,
+        expire_time: DateTime<FixedOffset>,
+    ) -> Result<bool> {
+        let result =
+            sqlx::query("UPDATE user_coupons SET expire_time = ? WHERE store_id = ? AND uc_id = ?")
+                .bind(expire_time)
+                .bind(store_id)
+                .bind(uc_id)
+                .execute(pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 该到期时间的提醒阈值是否已提醒过
+    pub async fn expiry_notified(
+        pool: &Pool<Sqlite>,
+        uc_id: i64,
+        threshold_days: i64,
+        expire_time: DateTime<FixedOffset>,
+    ) -> Result<bool> {
+        let result = sqlx::query_scalar::<_, i64>(
+            "SELECT 1 FROM coupon_expiry_notices
+            WHERE uc_id = ? AND threshold_days = ? AND expire_time = ?",
+        )
+        .bind(uc_id)
+        .bind(threshold_days)
+        .bind(expire_time)
+        .fetch_optional(pool)
+        .await?;
+        Ok(result.is_some())
+    }
+
+    pub async fn mark_expiry_notified(
+        tr: &mut Transaction<'_, Sqlite>,
+        uc_id: i64,
+        threshold_days: i64,
+        expire_time: DateTime<FixedOffset>,
+        notice_id: Option<i64>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO coupon_expiry_notices
+                (uc_id, threshold_days, expire_time, notice_id, create_time)
+            VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(uc_id)
+        .bind(threshold_days)
+        .bind(expire_time)
+        .bind(notice_id)
+        .bind(utils::get_timestamp())
+        .execute(&mut **tr)
+        .await?;
+        Ok(())
+    }
+
+    /// 是否已退卡停用，退卡后余额已结清，不能再使用或退回余额
+    pub fn is_refunded(&self) -> bool {
+        self.status.as_deref() == Some(UC_STATUS_REFUNDED)
+    }
+
+    /// 储值卡本金余额，次卡为付费的剩余次数
+    pub fn principal_value(&self) -> f64 {
+        (self.available_value.unwrap_or_default() - self.bonus_value).max(0.)
+    }
+
+    /// 储值卡扣款，赠送余额按其在余额中的占比同步扣减，返回扣减的赠送金额
+    pub fn deduct(&mut self, amount: Decimal) -> Decimal {
+        let balance =
+            Decimal::from_f64(self.available_value.unwrap_or_default()).unwrap_or_default();
+        let bonus = Decimal::from_f64(self.bonus_value)
+            .unwrap_or_default()
+            .clamp(Decimal::ZERO, balance.max(Decimal::ZERO));
+        let applied = amount.min(balance);
+        if applied <= Decimal::ZERO {
+            return Decimal::ZERO;
+        }
+
+        let bonus_part = if applied == balance {
+            bonus
+        } else {
+            (bonus * applied / balance).round_dp(2)
+        };
+        self.available_value = (balance - applied).to_f64();
+        self.bonus_value = (bonus - bonus_part).to_f64().unwrap_or_default();
+        bonus_part
+    }
+
+    /// 计算用户储值卡余额，仅统计未过期且有剩余金额的储值卡
+    pub async fn stored_value_balance(
+        pool: &Pool<Sqlite>,
+        store_id: i64,
+        user_id: i64,
+    ) -> Result<f64> {
+        let coupons = Self::find_by_user_id(pool, store_id, user_id).await?;
+        Ok(coupons
+            .iter()
+            .filter(|c| {
+                if let Some(coupon) = c.coupon.as_ref() {
+                    if let Some(valid_to) = coupon.valid_to {
+                        return coupon.coupon_type == Some(CouponType::StoredValueCard)
+                            && utils::get_now() <= valid_to
+                            && c.available_value.is_some()
+                            && c.available_value.unwrap() > 0.;
+                    }
+                }
+                false
+            })
+            .map(|c| c.available_value.unwrap_or_default())
+            .sum())
+    }
 }
 
 #[tauri::command]
@@ -77,4 +77,35 @@ impl UserTags {
         query.build().execute(&mut **tr).await?;
         Ok(())
     }
+
+    /// 清空门店会员的规则标签
+    pub async fn clear_auto_tags(tr: &mut Transaction<'_, Sqlite>, store_id: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE user_tags SET auto_tags = NULL
+            WHERE user_id IN (SELECT user_id FROM users WHERE store_id = ?)",
+        )
+        .bind(store_id)
+        .execute(&mut **tr)
+        .await?;
+        Ok(())
+    }
+
+    /// 写入规则标签，不影响手动维护的标签
+    pub async fn set_auto_tags(
+        tr: &mut Transaction<'_, Sqlite>,
+        user_id: i64,
+        auto_tags: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO user_tags (user_id, tags, auto_tags)
+                        VALUES (?, '', ?)
+                        ON CONFLICT(user_id) DO UPDATE SET
+                        auto_tags = excluded.auto_tags",
+        )
+        .bind(user_id)
+        .bind(auto_tags)
+        .execute(&mut **tr)
+        .await?;
+        Ok(())
+    }
 }
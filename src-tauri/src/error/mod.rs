@@ -61,6 +61,10 @@ impl Error {
         self.kind.clone()
     }
 
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
     #[inline]
     pub fn with_kind(kind: ErrorKind) -> Self {
         let err = Self {
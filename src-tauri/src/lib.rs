@@ -4,6 +4,7 @@ pub mod db;
 pub mod error;
 pub mod files;
 pub mod home;
+pub mod notice;
 pub mod pay;
 pub mod printer;
 pub mod state;
@@ -17,10 +18,12 @@ use tauri::{AppHandle, Manager, PhysicalSize, Runtime, Size};
 use tauri_plugin_fs::FsExt;
 
 use crate::db::{
-    alipay_config, cloth_price, clothing, clothing_category, clothing_style, configs, coupons,
-    delivery, dict_data, dict_type, drying_rack, expenditure, local_users, membership_level,
-    message, notice_temp, order_clothes, orders, payments, qrcode_payments, subscription_service,
-    subscriptions, tags, user, user_coupons, user_tours, wechat_config,
+    alipay_config, checkout, cloth_price, clothing, clothing_category, clothing_style, configs,
+    coupon_codes, coupons, delivery, dict_data, dict_type, drying_rack, expenditure, family_groups,
+    label_template, local_users, membership_level, message, notice_job, notice_temp, order_clothes,
+    orders, payments, platform_vouchers, points, points_rules, pricing_rules, print_job, promote,
+    qrcode_payments, referrals, segments, stored_value, subscription_service, subscriptions, tags,
+    user, user_coupons, user_tours, wechat_config,
 };
 
 fn set_window_size<R: tauri::Runtime>(app_handle: &AppHandle<R>) {
@@ -107,6 +110,11 @@ fn handle_command<R: Runtime>(invoke: Invoke<R>) -> bool {
         qrcode_payments::get_qrcode_payment_by_pay_id,
         qrcode_payments::get_qrcode_payment_by_trade_no,
         qrcode_payments::get_qrcode_payment_by_out_trade_no,
+        // platform vouchers
+        pay::voucher::get_voucher_settings,
+        pay::voucher::save_voucher_settings,
+        pay::voucher::verify_voucher,
+        platform_vouchers::get_platform_vouchers_by_pay_id,
         // wechat pay configuration
         wechat_config::save_wechat_config,
         wechat_config::get_wechat_config,
@@ -143,12 +151,30 @@ fn handle_command<R: Runtime>(invoke: Invoke<R>) -> bool {
         user::update_user,
         user::change_user_identify,
         user::change_user_status,
+        user::update_user_notice_preference,
         user::delete_users,
         printer::print,
         printer::print_receipt,
+        printer::preview_label,
+        printer::receipt::get_receipt_settings,
+        printer::receipt::save_receipt_settings,
         db::printer::get_printers,
         db::printer::set_printer,
         db::printer::get_settled_printer,
+        // print jobs
+        print_job::get_print_jobs_pagination,
+        print_job::get_print_job_by_id,
+        print_job::cancel_print_job,
+        print_job::delete_print_jobs,
+        printer::queue::retry_print_job,
+        printer::queue::reprint_print_job,
+        // label templates
+        label_template::get_label_template_pagination,
+        label_template::get_label_template_by_id,
+        label_template::get_default_label_template,
+        label_template::create_label_template,
+        label_template::update_label_template,
+        label_template::delete_label_templates,
         // tags
         tags::list_pagination,
         tags::list_all,
@@ -211,10 +237,44 @@ fn handle_command<R: Runtime>(invoke: Invoke<R>) -> bool {
         coupons::buy_coupons,
         coupons::gift_coupons,
         coupons::delete_coupons,
+        // coupon codes
+        coupon_codes::get_coupon_code_batches,
+        coupon_codes::generate_coupon_codes,
+        coupon_codes::void_coupon_codes,
+        coupon_codes::get_coupon_codes,
+        coupon_codes::export_coupon_codes_csv,
+        coupon_codes::get_coupon_code_qrcodes,
+        coupon_codes::redeem_coupon_code,
+        coupon_codes::get_coupon_code_stats,
         // user coupons
         user_coupons::get_user_coupons,
         user_coupons::get_user_coupons4sale,
         user_coupons::get_user_coupon_by_user_id,
+        // stored value cards
+        stored_value::get_topup_tiers,
+        stored_value::create_topup_tier,
+        stored_value::update_topup_tier,
+        stored_value::delete_topup_tiers,
+        stored_value::topup_stored_value_card,
+        stored_value::transfer_stored_value,
+        stored_value::get_card_refund_quote,
+        stored_value::refund_card,
+        stored_value::get_stored_value_statement,
+        // family groups
+        family_groups::get_family_groups,
+        family_groups::get_family_group,
+        family_groups::get_family_group_by_user,
+        family_groups::create_family_group,
+        family_groups::update_family_group,
+        family_groups::dissolve_family_group,
+        family_groups::save_family_member,
+        family_groups::remove_family_member,
+        family_groups::share_family_coupon,
+        // referrals
+        referrals::get_referral_settings,
+        referrals::save_referral_settings,
+        referrals::get_referral_code,
+        referrals::get_referral_rewards,
         // orders
         orders::create_order,
         orders::get_orders_pagination,
@@ -224,6 +284,7 @@ fn handle_command<R: Runtime>(invoke: Invoke<R>) -> bool {
         orders::delete_orders,
         orders::update_adjust,
         orders::pay_order,
+        checkout::get_checkout_recommendation,
         orders::get_refund_info,
         orders::refund_order,
         orders::get_orders4history,
@@ -276,10 +337,53 @@ fn handle_command<R: Runtime>(invoke: Invoke<R>) -> bool {
         notice_temp::create_temp,
         notice_temp::update_temp,
         notice_temp::delete_temp,
+        notice_temp::get_temp_variables,
+        notice_temp::preview_temp,
         notice_temp::get_notice_record_pagination,
         notice_temp::delete_all_record,
         notice_temp::delete_old_record,
         notice_temp::send_notice,
+        notice::get_notice_settings,
+        promote::get_promote_templates_pagination,
+        promote::get_promote_template_by_id,
+        promote::create_promote_template,
+        promote::update_promote_template,
+        promote::delete_promote_templates,
+        promote::preview_promote_audience,
+        promote::send_promote,
+        promote::get_promote_records_pagination,
+        promote::get_promote_stats,
+        segments::get_segment_rules,
+        segments::create_segment_rule,
+        segments::update_segment_rule,
+        segments::delete_segment_rules,
+        segments::refresh_segments,
+        points::get_points_history,
+        points::adjust_user_points,
+        points::get_points_settings,
+        points::save_points_settings,
+        points_rules::get_points_rules,
+        points_rules::create_points_rule,
+        points_rules::update_points_rule,
+        points_rules::delete_points_rules,
+        points_rules::get_points_redeem_quote,
+        points_rules::redeem_points_for_coupon,
+        pricing_rules::get_pricing_rules,
+        pricing_rules::create_pricing_rule,
+        pricing_rules::update_pricing_rule,
+        pricing_rules::delete_pricing_rules,
+        pricing_rules::get_order_price_breakdown,
+        notice::expiry::get_coupon_expiry_settings,
+        notice::expiry::save_coupon_expiry_settings,
+        notice::expiry::check_expiring_coupons_now,
+        notice::greeting::get_greeting_settings,
+        notice::greeting::save_greeting_settings,
+        notice::greeting::send_greetings_now,
+        notice_job::get_notice_jobs_pagination,
+        notice_job::create_notice_job,
+        notice_job::cancel_notice_job,
+        notice_job::delete_notice_jobs,
+        notice::save_notice_settings,
         // membership_level
         membership_level::get_membership_level_pagination,
         membership_level::get_membership_level_by_id,
@@ -287,6 +391,8 @@ fn handle_command<R: Runtime>(invoke: Invoke<R>) -> bool {
         membership_level::create_membership_level,
         membership_level::update_membership_level,
         membership_level::delete_membership_level,
+        membership_level::get_user_membership_level,
+        membership_level::evaluate_membership_levels,
         // subscription
         subscriptions::create_subscription,
         subscriptions::update_subscription,
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use tokio::io::AsyncWriteExt;
+
+use crate::db::message::{MessageType, WsMessage};
+use crate::error::{Error, Result};
+use crate::utils;
+use crate::utils::request::HttpClient;
+
+// 服务端目前只提供取件通知短信接口
+const URL_SMS_HANGUP: &str = "/sms/hangup";
+
+/// 一条待发送的通知
+#[derive(Debug, Serialize)]
+pub struct Message<'a> {
+    pub store_id: i64,
+    pub user_id: i64,
+    pub phone: Option<&'a str>,
+    pub order_number: Option<&'a str>,
+    /// 取件码，短信走服务端的取件专用模板
+    pub pickup_code: Option<&'a str>,
+    /// 图片地址，站内消息中另起一行附上
+    pub picture: Option<&'a str>,
+    pub title: &'a str,
+    pub content: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct SmsHangupRequest<'a> {
+    temp_id: i64,
+    store_id: i64,
+    phone: &'a str,
+    args: HashMap<&'static str, &'a str>,
+}
+
+pub async fn send_sms(client: &HttpClient, token: &str, msg: &Message<'_>) -> Result<()> {
+    let phone = msg
+        .phone
+        .filter(|phone| !phone.is_empty())
+        .ok_or(Error::bad_request("会员未填写手机号"))?;
+
+    let code = msg
+        .pickup_code
+        .ok_or(Error::bad_request("服务端暂未提供通用短信接口"))?;
+    let body = SmsHangupRequest {
+        temp_id: 0, // 服务端按取件模板发送，不需要模板ID
+        store_id: msg.store_id,
+        phone,
+        args: HashMap::from([("code", code)]),
+    };
+
+    if client.post(URL_SMS_HANGUP, body, Some(token)).await? {
+        Ok(())
+    } else {
+        Err(Error::internal("短信发送失败"))
+    }
+}
+
+/// 写入站内消息，由小程序同步给会员
+pub async fn send_in_app(pool: &Pool<Sqlite>, msg: &Message<'_>) -> Result<()> {
+    let now = utils::get_timestamp();
+    WsMessage {
+        sender_id: msg.store_id,
+        receiver_id: msg.user_id,
+        message_type: Some(MessageType::Notice.to_string()),
+        content: match msg.picture.filter(|picture| !picture.is_empty()) {
+            Some(picture) => format!("{}\n{}\n{}", msg.title, msg.content, picture),
+            None => format!("{}\n{}", msg.title, msg.content),
+        },
+        read: Some(false),
+        sent: Some(false),
+        created_at: now,
+        updated_at: now,
+        ..Default::default()
+    }
+    .create(pool)
+    .await?;
+    Ok(())
+}
+
+/// 追加到 log_file（每行一条 JSON），未配置文件时输出到应用日志
+pub async fn send_log(log_file: &str, msg: &Message<'_>) -> Result<()> {
+    if log_file.is_empty() {
+        tracing::info!("notice: {:?}", msg);
+        return Ok(());
+    }
+
+    let mut line = serde_json::to_value(msg)?;
+    line["time"] = utils::get_now().to_rfc3339().into();
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .await?;
+    file.write_all(format!("{line}\n").as_bytes()).await?;
+    Ok(())
+}
@@ -0,0 +1,297 @@
+use chrono::{DateTime, Duration, FixedOffset};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use tauri::State;
+
+use super::{NoticeDispatcher, Outgoing, RESULT_SENDING, Recipient};
+use crate::constants::CouponType;
+use crate::db::configs::Config;
+use crate::db::coupons::AUTO_DELAY_YES;
+use crate::db::notice_temp::NoticeRecord;
+use crate::db::user::User;
+use crate::db::user_coupons::UserCoupon;
+use crate::db::{Curd, Validator};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+// 卡券到期设置在 configs 表中的键名前缀
+const CONFIG_PREFIX: &str = "coupon.expiry.";
+
+/// 模板类型字典值：卡券到期提醒
+pub const TEMP_TYPE_COUPON_EXPIRY: &str = "3";
+
+/// 卡券到期提醒及自动延期设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ExpirySettings {
+    pub enabled: bool,
+    /// 到期前多少天提醒，每个阈值只提醒一次
+    pub thresholds: Vec<i64>,
+    /// 设置了自动延期的卡券到期后延长的天数，为 0 时不延期
+    pub delay_days: i64,
+}
+
+impl Default for ExpirySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            thresholds: vec![14, 3],
+            delay_days: 30,
+        }
+    }
+}
+
+impl Validator for ExpirySettings {
+    fn validate(&self) -> Result<()> {
+        if self.thresholds.iter().any(|days| *days < 1) {
+            return Err(Error::bad_request("提醒天数至少为 1 天"));
+        }
+        if self.delay_days < 0 {
+            return Err(Error::bad_request("延期天数不能为负数"));
+        }
+        Ok(())
+    }
+}
+
+impl ExpirySettings {
+    pub async fn load(pool: &Pool<Sqlite>) -> Result<Self> {
+        let configs = Config {
+            config_key: Some(CONFIG_PREFIX.to_string()),
+            ..Default::default()
+        }
+        .get_all(pool)
+        .await?;
+
+        let mut settings = Self::default();
+        for config in configs {
+            let value = config.config_value.unwrap_or_default();
+            match config
+                .config_key
+                .as_deref()
+                .and_then(|key| key.strip_prefix(CONFIG_PREFIX))
+            {
+                Some("enabled") => settings.enabled = utils::to_bool(value),
+                Some("thresholds") => {
+                    settings.thresholds = value
+                        .split(',')
+                        .filter_map(|days| days.trim().parse().ok())
+                        .collect()
+                }
+                Some("delayDays") => settings.delay_days = value.parse().unwrap_or_default(),
+                _ => {}
+            }
+        }
+        Ok(settings)
+    }
+
+    pub async fn save(&self, pool: &Pool<Sqlite>) -> Result<()> {
+        let key = |name: &str| format!("{CONFIG_PREFIX}{name}");
+        let thresholds = self
+            .thresholds
+            .iter()
+            .map(|days| days.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        Config::set_value(
+            pool,
+            &key("enabled"),
+            "卡券到期-启用提醒",
+            self.enabled.to_string(),
+        )
+        .await?;
+        Config::set_value(pool, &key("thresholds"), "卡券到期-提醒天数", thresholds).await?;
+        Config::set_value(
+            pool,
+            &key("delayDays"),
+            "卡券到期-自动延期天数",
+            self.delay_days.to_string(),
+        )
+        .await
+    }
+}
+
+/// 剩余时间对应的提醒阈值：不小于剩余天数的最小阈值。
+/// 临近到期才领取的卡券只按最近的阈值提醒一次
+fn threshold_for(remaining: Duration, thresholds: &[i64]) -> Option<i64> {
+    // 不足一天按一天计算
+    let days = (remaining.num_seconds() + 86399) / 86400;
+    thresholds.iter().copied().filter(|t| days <= *t).min()
+}
+
+fn remaining_desc(uc: &UserCoupon) -> String {
+    let value = uc.available_value.unwrap_or_default();
+    match uc
+        .coupon
+        .as_ref()
+        .and_then(|coupon| coupon.coupon_type.clone())
+    {
+        Some(CouponType::StoredValueCard) | Some(CouponType::DiscountCard) => {
+            format!("余额{value:.2}元")
+        }
+        Some(CouponType::SessionCard) => format!("剩余{value}次"),
+        _ => format!("剩余{}张", uc.uc_count.unwrap_or_default()),
+    }
+}
+
+/// 自动延期：已过期但仍有剩余的卡券按延期天数顺延，直到晚于当前时间
+async fn delay_expired(
+    pool: &Pool<Sqlite>,
+    store_id: i64,
+    now: DateTime<FixedOffset>,
+    delay_days: i64,
+) -> Result<()> {
+    let delay = Duration::days(delay_days);
+    for uc in UserCoupon::list_expired_auto_delay(pool, store_id, now).await? {
+        let (Some(uc_id), Some(mut expire_time)) =
+            (uc.uc_id, uc.coupon.and_then(|coupon| coupon.valid_to))
+        else {
+            continue;
+        };
+        while expire_time <= now {
+            expire_time += delay;
+        }
+        UserCoupon::update_expire_time(pool, store_id, uc_id, expire_time).await?;
+        tracing::info!("user coupon {} delayed to {}", uc_id, expire_time);
+    }
+    Ok(())
+}
+
+/// 检查即将到期的会员卡券：先处理自动延期，再按阈值提醒持有人，返回发送成功的条数
+pub async fn check_expiring_coupons(state: &AppState) -> Result<u64> {
+    let pool = &state.pool;
+    let store_id = state.try_get_user_id().await?;
+    let settings = ExpirySettings::load(pool).await?;
+    if !settings.enabled {
+        return Ok(0);
+    }
+
+    let now = utils::get_now();
+    if settings.delay_days > 0 {
+        delay_expired(pool, store_id, now, settings.delay_days).await?;
+    }
+
+    let Some(max_days) = settings.thresholds.iter().copied().max() else {
+        return Ok(0);
+    };
+    let expiring =
+        UserCoupon::list_expiring(pool, store_id, now, now + Duration::days(max_days)).await?;
+
+    // 筛选出本次需要提醒的卡券及其阈值
+    let mut pending = Vec::new();
+    for uc in expiring {
+        let (Some(uc_id), Some(coupon)) = (uc.uc_id, uc.coupon.as_ref()) else {
+            continue;
+        };
+        let Some(expire_time) = coupon.valid_to else {
+            continue;
+        };
+        let Some(threshold) = threshold_for(expire_time - now, &settings.thresholds) else {
+            continue;
+        };
+        if !UserCoupon::expiry_notified(pool, uc_id, threshold, expire_time).await? {
+            pending.push((uc, threshold, expire_time));
+        }
+    }
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let dispatcher = NoticeDispatcher::new(state).await?;
+    let mut user_ids: Vec<i64> = pending.iter().filter_map(|(uc, ..)| uc.user_id).collect();
+    user_ids.sort_unstable();
+    user_ids.dedup();
+    let users = User::list_by_ids(pool, &user_ids).await?;
+
+    // 提醒记录与阈值标记一起入库，发送失败也不重复提醒
+    let mut items = Vec::with_capacity(pending.len());
+    let mut tr = pool.begin().await?;
+    for (uc, threshold, expire_time) in pending {
+        let Some(user) = users.iter().find(|user| user.user_id == uc.user_id) else {
+            continue;
+        };
+        let coupon = uc.coupon.as_ref().unwrap();
+        let mut content = format!(
+            "尊敬的{}，您的{}（{}）将于{}到期，请及时使用",
+            user.nick_name.as_deref().unwrap_or_default(),
+            coupon.coupon_title.as_deref().unwrap_or_default(),
+            remaining_desc(&uc),
+            expire_time.format("%Y-%m-%d")
+        );
+        if coupon.auto_delay.as_deref() == Some(AUTO_DELAY_YES) && settings.delay_days > 0 {
+            content.push_str(&format!("，到期后将自动延期{}天", settings.delay_days));
+        }
+        content.push('。');
+
+        let recipient = Recipient::from(user);
+        let mut record = NoticeRecord {
+            store_id: Some(store_id),
+            user_id: user.user_id.unwrap_or_default(),
+            notice_method: Some(dispatcher.notice_method(&recipient, false)),
+            notice_type: Some(TEMP_TYPE_COUPON_EXPIRY.to_string()),
+            notice_time: Some(now),
+            title: Some("卡券到期提醒".to_string()),
+            content: Some(content),
+            result: Some(RESULT_SENDING.to_string()),
+            ..Default::default()
+        };
+        record.create(&mut tr).await?;
+        UserCoupon::mark_expiry_notified(
+            &mut tr,
+            uc.uc_id.unwrap_or_default(),
+            threshold,
+            expire_time,
+            record.notice_id,
+        )
+        .await?;
+
+        items.push(Outgoing {
+            record,
+            recipient,
+            pickup_code: None,
+            picture: None,
+        });
+    }
+    tr.commit().await?;
+
+    dispatcher.dispatch_all(&items).await
+}
+
+#[tauri::command]
+pub async fn get_coupon_expiry_settings(state: State<'_, AppState>) -> Result<ExpirySettings> {
+    ExpirySettings::load(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn save_coupon_expiry_settings(
+    state: State<'_, AppState>,
+    settings: ExpirySettings,
+) -> Result<()> {
+    settings.validate()?;
+    settings.save(&state.pool).await
+}
+
+/// 立即执行一次卡券到期检查
+#[tauri::command]
+pub async fn check_expiring_coupons_now(state: State<'_, AppState>) -> Result<u64> {
+    check_expiring_coupons(&state).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_for() {
+        let thresholds = [14, 3];
+        assert_eq!(threshold_for(Duration::days(20), &thresholds), None);
+        assert_eq!(threshold_for(Duration::days(14), &thresholds), Some(14));
+        assert_eq!(
+            threshold_for(Duration::hours(13 * 24 + 1), &thresholds),
+            Some(14)
+        );
+        assert_eq!(threshold_for(Duration::days(3), &thresholds), Some(3));
+        assert_eq!(threshold_for(Duration::hours(5), &thresholds), Some(3));
+    }
+}
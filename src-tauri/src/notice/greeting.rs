@@ -0,0 +1,349 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite, Transaction};
+use tauri::State;
+
+use super::{NoticeDispatcher, Outgoing, RESULT_SENDING, Recipient};
+use crate::constants::GreetingKind;
+use crate::db::configs::Config;
+use crate::db::coupons::{Coupon, CouponBuyReq, CouponIdCount};
+use crate::db::notice_temp::NoticeRecord;
+use crate::db::user::User;
+use crate::db::{Curd, Validator};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+// 会员祝福设置在 configs 表中的键名前缀
+const CONFIG_PREFIX: &str = "greeting.";
+
+/// 模板类型字典值：会员祝福
+pub const TEMP_TYPE_GREETING: &str = "5";
+
+/// 生日及入会周年祝福设置，未设置赠送卡券时只发送祝福
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct GreetingSettings {
+    pub enabled: bool,
+    pub birthday_coupon_id: Option<i64>,
+    pub anniversary_coupon_id: Option<i64>,
+}
+
+impl Validator for GreetingSettings {
+    fn validate(&self) -> Result<()> {
+        if [self.birthday_coupon_id, self.anniversary_coupon_id]
+            .iter()
+            .flatten()
+            .any(|id| *id <= 0)
+        {
+            return Err(Error::bad_request("赠送的卡券不正确"));
+        }
+        Ok(())
+    }
+}
+
+impl GreetingSettings {
+    pub async fn load(pool: &Pool<Sqlite>) -> Result<Self> {
+        let configs = Config {
+            config_key: Some(CONFIG_PREFIX.to_string()),
+            ..Default::default()
+        }
+        .get_all(pool)
+        .await?;
+
+        let mut settings = Self::default();
+        for config in configs {
+            let value = config.config_value.unwrap_or_default();
+            match config
+                .config_key
+                .as_deref()
+                .and_then(|key| key.strip_prefix(CONFIG_PREFIX))
+            {
+                Some("enabled") => settings.enabled = utils::to_bool(value),
+                Some("birthdayCouponId") => settings.birthday_coupon_id = value.trim().parse().ok(),
+                Some("anniversaryCouponId") => {
+                    settings.anniversary_coupon_id = value.trim().parse().ok()
+                }
+                _ => {}
+            }
+        }
+        Ok(settings)
+    }
+
+    pub async fn save(&self, pool: &Pool<Sqlite>) -> Result<()> {
+        let key = |name: &str| format!("{CONFIG_PREFIX}{name}");
+        let id = |id: Option<i64>| id.map(|id| id.to_string()).unwrap_or_default();
+        Config::set_value(
+            pool,
+            &key("enabled"),
+            "会员祝福-启用",
+            self.enabled.to_string(),
+        )
+        .await?;
+        Config::set_value(
+            pool,
+            &key("birthdayCouponId"),
+            "会员祝福-生日赠送卡券",
+            id(self.birthday_coupon_id),
+        )
+        .await?;
+        Config::set_value(
+            pool,
+            &key("anniversaryCouponId"),
+            "会员祝福-入会周年赠送卡券",
+            id(self.anniversary_coupon_id),
+        )
+        .await
+    }
+
+    fn coupon_id(&self, kind: GreetingKind) -> Option<i64> {
+        match kind {
+            GreetingKind::Birthday => self.birthday_coupon_id,
+            GreetingKind::Anniversary => self.anniversary_coupon_id,
+        }
+    }
+}
+
+/// 今天是否为该日期的周年日，返回相隔的年数。2 月 29 日在平年按 2 月 28 日计算
+fn years_on(date: NaiveDate, today: NaiveDate) -> Option<i32> {
+    let years = today.year() - date.year();
+    if years < 0 {
+        return None;
+    }
+    let day = NaiveDate::from_ymd_opt(today.year(), date.month(), date.day())
+        .or_else(|| NaiveDate::from_ymd_opt(today.year(), date.month(), date.day() - 1))?;
+    (day == today).then_some(years)
+}
+
+/// 会员今天应收到的祝福及周年数，入会当天不算周年
+fn due_greetings(user: &User, today: NaiveDate) -> Vec<(GreetingKind, i32)> {
+    let parse = |date: Option<&String>| {
+        date.and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+    };
+    let mut result = Vec::new();
+    if let Some(years) = parse(user.birthday.as_ref()).and_then(|date| years_on(date, today)) {
+        result.push((GreetingKind::Birthday, years));
+    }
+    if let Some(years) = parse(user.join_date.as_ref())
+        .and_then(|date| years_on(date, today))
+        .filter(|years| *years > 0)
+    {
+        result.push((GreetingKind::Anniversary, years));
+    }
+    result
+}
+
+fn greeting_content(user: &User, kind: GreetingKind, years: i32, gift: &str) -> String {
+    let name = user.nick_name.as_deref().unwrap_or_default();
+    match kind {
+        GreetingKind::Birthday => format!("尊敬的{name}，祝您生日快乐！{gift}"),
+        GreetingKind::Anniversary => {
+            format!("尊敬的{name}，今天是您成为本店会员{years}周年的日子，感谢一路相伴！{gift}")
+        }
+    }
+}
+
+/// 占用会员当年的祝福记录，已发放过时返回 false
+async fn claim(
+    tr: &mut Transaction<'_, Sqlite>,
+    store_id: i64,
+    user_id: i64,
+    kind: GreetingKind,
+    year: i32,
+    coupon_id: Option<i64>,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "INSERT OR IGNORE INTO greeting_gifts (store_id, user_id, kind, year, coupon_id, create_time)
+        VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(store_id)
+    .bind(user_id)
+    .bind(kind)
+    .bind(year)
+    .bind(coupon_id)
+    .bind(utils::get_timestamp())
+    .execute(&mut **tr)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// 发放当天的生日及入会周年祝福：先占用当年记录，再赠送卡券并发送祝福，返回发送成功的条数
+pub async fn send_greetings(state: &AppState) -> Result<u64> {
+    let pool = &state.pool;
+    let store_id = state.try_get_user_id().await?;
+    let settings = GreetingSettings::load(pool).await?;
+    if !settings.enabled {
+        return Ok(0);
+    }
+
+    let now = utils::get_now();
+    let today = now.date_naive();
+    let mut pending = Vec::new();
+    for user in User::list_with_dates(pool, store_id).await? {
+        for (kind, years) in due_greetings(&user, today) {
+            pending.push((user.clone(), kind, years));
+        }
+    }
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let dispatcher = NoticeDispatcher::new(state).await?;
+    let mut items = Vec::with_capacity(pending.len());
+    for (user, kind, years) in pending {
+        let user_id = user.user_id.unwrap_or_default();
+        let coupon = match settings.coupon_id(kind) {
+            Some(coupon_id) => Coupon::get_by_id(pool, coupon_id).await?,
+            None => None,
+        };
+
+        // 先占用当年记录，赠券失败也不重复赠送
+        let mut tr = pool.begin().await?;
+        if !claim(
+            &mut tr,
+            store_id,
+            user_id,
+            kind,
+            today.year(),
+            coupon.as_ref().and_then(|c| c.coupon_id),
+        )
+        .await?
+        {
+            continue;
+        }
+        tr.commit().await?;
+
+        let mut gift = String::new();
+        let mut remark = None;
+        if let Some(coupon) = &coupon {
+            let req = CouponBuyReq {
+                coupons: vec![CouponIdCount {
+                    coupon_id: coupon.coupon_id.unwrap_or_default(),
+                    count: 1,
+                }],
+                user_id,
+                ..Default::default()
+            };
+            match Coupon::gift(pool, req).await {
+                Ok(()) => {
+                    gift = format!(
+                        "特为您送上{}一张，已放入您的会员账户。",
+                        coupon.coupon_title.as_deref().unwrap_or_default()
+                    )
+                }
+                Err(e) => {
+                    tracing::error!("会员祝福赠券失败: {}", e);
+                    remark = Some(format!("赠券失败: {}", e));
+                }
+            }
+        }
+
+        let recipient = Recipient::from(&user);
+        let mut record = NoticeRecord {
+            store_id: Some(store_id),
+            user_id,
+            notice_method: Some(dispatcher.notice_method(&recipient, false)),
+            notice_type: Some(TEMP_TYPE_GREETING.to_string()),
+            notice_time: Some(now),
+            title: Some(match kind {
+                GreetingKind::Birthday => "生日祝福".to_string(),
+                GreetingKind::Anniversary => "入会周年祝福".to_string(),
+            }),
+            content: Some(greeting_content(&user, kind, years, &gift)),
+            result: Some(RESULT_SENDING.to_string()),
+            ..Default::default()
+        };
+        let mut tr = pool.begin().await?;
+        record.create(&mut tr).await?;
+        sqlx::query(
+            "UPDATE greeting_gifts SET notice_id = ?, remark = ?,
+                coupon_id = CASE WHEN ? IS NULL THEN coupon_id ELSE NULL END
+            WHERE user_id = ? AND kind = ? AND year = ?",
+        )
+        .bind(record.notice_id)
+        .bind(&remark)
+        .bind(&remark)
+        .bind(user_id)
+        .bind(kind)
+        .bind(today.year())
+        .execute(&mut *tr)
+        .await?;
+        tr.commit().await?;
+
+        items.push(Outgoing {
+            record,
+            recipient,
+            pickup_code: None,
+            picture: None,
+        });
+    }
+
+    dispatcher.dispatch_all(&items).await
+}
+
+#[tauri::command]
+pub async fn get_greeting_settings(state: State<'_, AppState>) -> Result<GreetingSettings> {
+    GreetingSettings::load(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn save_greeting_settings(
+    state: State<'_, AppState>,
+    settings: GreetingSettings,
+) -> Result<()> {
+    settings.validate()?;
+    for coupon_id in [settings.birthday_coupon_id, settings.anniversary_coupon_id]
+        .into_iter()
+        .flatten()
+    {
+        if Coupon::get_by_id(&state.pool, coupon_id).await?.is_none() {
+            return Err(Error::not_found("赠送的卡券不存在"));
+        }
+    }
+    settings.save(&state.pool).await
+}
+
+/// 立即发放一次当天的会员祝福
+#[tauri::command]
+pub async fn send_greetings_now(state: State<'_, AppState>) -> Result<u64> {
+    send_greetings(&state).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_years_on() {
+        assert_eq!(years_on(date(1990, 5, 20), date(2025, 5, 20)), Some(35));
+        assert_eq!(years_on(date(1990, 5, 20), date(2025, 5, 21)), None);
+        assert_eq!(years_on(date(2026, 5, 20), date(2025, 5, 20)), None);
+        // 闰日生日在平年按 2 月 28 日
+        assert_eq!(years_on(date(2000, 2, 29), date(2025, 2, 28)), Some(25));
+        assert_eq!(years_on(date(2000, 2, 29), date(2024, 2, 28)), None);
+        assert_eq!(years_on(date(2000, 2, 29), date(2024, 2, 29)), Some(24));
+    }
+
+    #[test]
+    fn test_due_greetings() {
+        let user = User {
+            birthday: Some("1990-06-01".to_string()),
+            join_date: Some("2025-06-01".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            due_greetings(&user, date(2025, 6, 1)),
+            vec![(GreetingKind::Birthday, 35)]
+        );
+        assert_eq!(
+            due_greetings(&user, date(2026, 6, 1)),
+            vec![(GreetingKind::Birthday, 36), (GreetingKind::Anniversary, 1)]
+        );
+        assert!(due_greetings(&user, date(2026, 6, 2)).is_empty());
+    }
+}
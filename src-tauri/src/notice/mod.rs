@@ -0,0 +1,521 @@
+use std::sync::Mutex;
+
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use tauri::State;
+
+use crate::constants::NoticeChannel;
+use crate::db::configs::Config;
+use crate::db::notice_temp::NoticeRecord;
+use crate::db::orders::Order;
+use crate::db::user::User;
+use crate::db::{Curd, Validator};
+use crate::error::{Error, ErrorKind, Result};
+use crate::state::AppState;
+use crate::utils;
+use crate::utils::request::HttpClient;
+use channel::Message;
+
+pub(crate) mod channel;
+pub(crate) mod expiry;
+pub(crate) mod greeting;
+pub(crate) mod scheduler;
+pub(crate) mod template;
+
+// 通知设置在 configs 表中的键名前缀
+const CONFIG_PREFIX: &str = "notice.";
+
+/// 通知结果字典值
+pub const RESULT_SUCCESS: &str = "0";
+pub const RESULT_FAILED: &str = "1";
+pub const RESULT_SENDING: &str = "2";
+
+/// 模板类型字典值：取衣通知
+pub const TEMP_TYPE_PICKUP: &str = "0";
+/// 模板类型字典值：储值变动通知
+pub const TEMP_TYPE_STORED_VALUE: &str = "4";
+
+/// 通知发送设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct NoticeSettings {
+    /// 测试模式：所有通知只写入本地日志，不实际发送
+    pub log_only: bool,
+    /// 日志文件路径，为空时写入应用日志
+    pub log_file: String,
+    /// 首选渠道发送失败后依次尝试的渠道
+    pub fallback: Vec<NoticeChannel>,
+    /// 免打扰时段（HH:MM），开始晚于结束时跨午夜，为空时不启用。定时通知在该时段内顺延
+    pub quiet_start: String,
+    pub quiet_end: String,
+}
+
+impl Default for NoticeSettings {
+    fn default() -> Self {
+        Self {
+            log_only: false,
+            log_file: String::new(),
+            fallback: vec![NoticeChannel::Sms, NoticeChannel::InApp],
+            quiet_start: String::new(),
+            quiet_end: String::new(),
+        }
+    }
+}
+
+impl Validator for NoticeSettings {
+    fn validate(&self) -> Result<()> {
+        if !self.log_file.is_empty() && std::path::Path::new(&self.log_file).is_dir() {
+            return Err(Error::bad_request("日志文件路径不能是目录"));
+        }
+        if self.fallback.contains(&NoticeChannel::Log) {
+            return Err(Error::bad_request("备用渠道不能包含日志渠道"));
+        }
+        if self.quiet_start.is_empty() != self.quiet_end.is_empty() {
+            return Err(Error::bad_request("免打扰时段需同时设置开始和结束时间"));
+        }
+        if !self.quiet_start.is_empty() && self.quiet_hours().is_none() {
+            return Err(Error::bad_request("免打扰时间格式应为 HH:MM"));
+        }
+        Ok(())
+    }
+}
+
+impl NoticeSettings {
+    pub async fn load(pool: &Pool<Sqlite>) -> Result<Self> {
+        let configs = Config {
+            config_key: Some(CONFIG_PREFIX.to_string()),
+            ..Default::default()
+        }
+        .get_all(pool)
+        .await?;
+
+        let mut settings = Self::default();
+        for config in configs {
+            let value = config.config_value.unwrap_or_default();
+            match config
+                .config_key
+                .as_deref()
+                .and_then(|key| key.strip_prefix(CONFIG_PREFIX))
+            {
+                Some("logOnly") => settings.log_only = utils::to_bool(value),
+                Some("logFile") => settings.log_file = value,
+                Some("quietStart") => settings.quiet_start = value,
+                Some("quietEnd") => settings.quiet_end = value,
+                Some("fallback") => {
+                    settings.fallback = value.split(',').filter_map(NoticeChannel::parse).collect()
+                }
+                _ => {}
+            }
+        }
+        Ok(settings)
+    }
+
+    pub async fn save(&self, pool: &Pool<Sqlite>) -> Result<()> {
+        let key = |name: &str| format!("{CONFIG_PREFIX}{name}");
+        let fallback = self
+            .fallback
+            .iter()
+            .map(|channel| channel.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        Config::set_value(
+            pool,
+            &key("logOnly"),
+            "通知-仅写入日志",
+            self.log_only.to_string(),
+        )
+        .await?;
+        Config::set_value(
+            pool,
+            &key("logFile"),
+            "通知-日志文件",
+            self.log_file.clone(),
+        )
+        .await?;
+        Config::set_value(
+            pool,
+            &key("quietStart"),
+            "通知-免打扰开始",
+            self.quiet_start.clone(),
+        )
+        .await?;
+        Config::set_value(
+            pool,
+            &key("quietEnd"),
+            "通知-免打扰结束",
+            self.quiet_end.clone(),
+        )
+        .await?;
+        Config::set_value(pool, &key("fallback"), "通知-备用渠道", fallback).await
+    }
+
+    fn quiet_hours(&self) -> Option<(NaiveTime, NaiveTime)> {
+        let start = NaiveTime::parse_from_str(&self.quiet_start, "%H:%M").ok()?;
+        let end = NaiveTime::parse_from_str(&self.quiet_end, "%H:%M").ok()?;
+        Some((start, end))
+    }
+
+    /// 指定时间是否处于免打扰时段
+    pub fn in_quiet_hours(&self, time: NaiveTime) -> bool {
+        match self.quiet_hours() {
+            Some((start, end)) if start <= end => start <= time && time < end,
+            Some((start, end)) => time >= start || time < end,
+            None => false,
+        }
+    }
+
+    /// 实际发送的渠道顺序：会员首选渠道（未设置时按模板通知方式），然后是备用渠道
+    fn channels(&self, recipient: &Recipient, notice_method: Option<&str>) -> Vec<NoticeChannel> {
+        let first = recipient
+            .preferred
+            .unwrap_or_else(|| NoticeChannel::from_method(notice_method));
+        let mut channels = vec![first];
+        for channel in &self.fallback {
+            if !channels.contains(channel) {
+                channels.push(*channel);
+            }
+        }
+        channels
+    }
+
+    /// 新建通知记录时的通知方式（notice_record 中不能为空）：首个可用渠道，
+    /// 都不可用时记为首选渠道，发送后回写实际渠道。测试模式下同样按实际发送的渠道记录
+    pub fn notice_method(&self, recipient: &Recipient, pickup: bool) -> String {
+        let channels = self.channels(recipient, None);
+        channels
+            .iter()
+            .filter(|channel| channel.is_available(pickup))
+            .chain(&channels)
+            .find_map(|channel| channel.method())
+            .or(NoticeChannel::InApp.method())
+            .map(str::to_string)
+            .unwrap_or_default()
+    }
+}
+
+/// 通知接收人的联系方式及通知偏好
+#[derive(Debug, Clone, Default)]
+pub struct Recipient {
+    pub phone: Option<String>,
+    pub preferred: Option<NoticeChannel>,
+    pub opt_out: bool,
+}
+
+impl From<&User> for Recipient {
+    fn from(user: &User) -> Self {
+        Self {
+            phone: user.phonenumber.clone(),
+            preferred: user.notice_channel,
+            opt_out: user.notice_opt_out,
+        }
+    }
+}
+
+/// 待发送的通知：已入库的通知记录及接收人
+#[derive(Debug, Clone, Default)]
+pub struct Outgoing {
+    pub record: NoticeRecord,
+    pub recipient: Recipient,
+    /// 取件通知的取件码，短信渠道使用服务端取件模板
+    pub pickup_code: Option<String>,
+    /// 推广图片地址，仅站内消息携带
+    pub picture: Option<String>,
+}
+
+fn reason(e: &Error) -> String {
+    e.details()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:?}", e.kind()))
+}
+
+/// 通知路由：按会员首选渠道及备用渠道依次发送已入库的通知记录，并回写实际渠道和结果
+pub struct NoticeDispatcher<'a> {
+    pool: &'a Pool<Sqlite>,
+    http_client: &'a HttpClient,
+    token: String,
+    store_id: i64,
+    settings: NoticeSettings,
+    // 短信未订阅或余量不足后，同一批次不再尝试短信
+    sms_issue: Mutex<Option<ErrorKind>>,
+}
+
+impl<'a> NoticeDispatcher<'a> {
+    pub async fn new(state: &'a AppState) -> Result<Self> {
+        let token = state.try_token().await?;
+        let store_id = token.user.id.ok_or(Error::unauthorized())?;
+        Ok(Self {
+            pool: &state.pool,
+            http_client: &state.http_client,
+            token: token.token,
+            store_id,
+            settings: NoticeSettings::load(&state.pool).await?,
+            sms_issue: Mutex::new(None),
+        })
+    }
+
+    pub fn store_id(&self) -> i64 {
+        self.store_id
+    }
+
+    /// 本批次发送中遇到的短信服务问题
+    pub fn sms_issue(&self) -> Option<ErrorKind> {
+        self.sms_issue.lock().unwrap().clone()
+    }
+
+    /// 发送顺序：测试模式只写日志，否则按会员偏好及备用渠道
+    fn routes(&self, recipient: &Recipient, notice_method: Option<&str>) -> Vec<NoticeChannel> {
+        if self.settings.log_only {
+            return vec![NoticeChannel::Log];
+        }
+        self.settings.channels(recipient, notice_method)
+    }
+
+    /// 新建通知记录时的通知方式
+    pub fn notice_method(&self, recipient: &Recipient, pickup: bool) -> String {
+        self.settings.notice_method(recipient, pickup)
+    }
+
+    async fn send(&self, channel: NoticeChannel, item: &Outgoing) -> Result<()> {
+        let record = &item.record;
+        let msg = Message {
+            store_id: self.store_id,
+            user_id: record.user_id,
+            phone: item.recipient.phone.as_deref(),
+            order_number: record.order_number.as_deref(),
+            pickup_code: item.pickup_code.as_deref(),
+            picture: item.picture.as_deref(),
+            title: record.title.as_deref().unwrap_or_default(),
+            content: record.content.as_deref().unwrap_or_default(),
+        };
+        match channel {
+            NoticeChannel::Sms => channel::send_sms(self.http_client, &self.token, &msg).await,
+            NoticeChannel::InApp => channel::send_in_app(self.pool, &msg).await,
+            NoticeChannel::Log => channel::send_log(&self.settings.log_file, &msg).await,
+        }
+    }
+
+    /// 发送单条通知并更新记录，所有渠道均失败时返回 false，失败原因写入备注
+    pub async fn dispatch(&self, item: &Outgoing) -> Result<bool> {
+        let notice_id = item
+            .record
+            .notice_id
+            .ok_or(Error::bad_request("notice_id is required"))?;
+
+        if item.recipient.opt_out {
+            NoticeRecord::update_result(
+                self.pool,
+                notice_id,
+                None,
+                RESULT_FAILED,
+                Some("会员已退订通知"),
+            )
+            .await?;
+            return Ok(false);
+        }
+
+        let mut errors = Vec::new();
+        for channel in self.routes(&item.recipient, item.record.notice_method.as_deref()) {
+            if !channel.is_available(item.pickup_code.is_some()) {
+                errors.push(format!("{channel}: 服务端暂不支持该类通知"));
+                continue;
+            }
+            if channel == NoticeChannel::Sms && self.sms_issue().is_some() {
+                errors.push(format!("{channel}: 短信服务不可用"));
+                continue;
+            }
+
+            match self.send(channel, item).await {
+                Ok(()) => {
+                    let remark = (!errors.is_empty()).then(|| errors.join("; "));
+                    NoticeRecord::update_result(
+                        self.pool,
+                        notice_id,
+                        channel.method(),
+                        RESULT_SUCCESS,
+                        remark.as_deref(),
+                    )
+                    .await?;
+                    return Ok(true);
+                }
+                Err(e) => {
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::SmsNotSubscribed | ErrorKind::SmsRemainShort
+                    ) {
+                        *self.sms_issue.lock().unwrap() = Some(e.kind());
+                    }
+                    errors.push(format!("{channel}: {}", reason(&e)));
+                }
+            }
+        }
+
+        let remark = errors.join("; ");
+        tracing::warn!("notice {} send failed: {}", notice_id, remark);
+        NoticeRecord::update_result(self.pool, notice_id, None, RESULT_FAILED, Some(&remark))
+            .await?;
+        Ok(false)
+    }
+
+    /// 依次发送，返回成功条数
+    pub async fn dispatch_all(&self, items: &[Outgoing]) -> Result<u64> {
+        let mut sent = 0;
+        for item in items {
+            if self.dispatch(item).await? {
+                sent += 1;
+            }
+        }
+        Ok(sent)
+    }
+}
+
+/// 订单衣物全部上挂后发送取件通知。
+/// 短信服务不可用时即使已通过其他渠道送达，也返回对应错误提醒门店处理
+pub async fn send_pickup_notice(state: &AppState, order: &Order) -> Result<()> {
+    let pool = &state.pool;
+    let dispatcher = NoticeDispatcher::new(state).await?;
+    let user_id = order.user_id.unwrap_or_default();
+    let code = order.pickup_code.clone().unwrap_or_default();
+
+    let recipient = match User::get_by_id(pool, user_id).await? {
+        Some(user) => Recipient::from(&user),
+        None => Recipient {
+            phone: order.phonenumber.clone(),
+            ..Default::default()
+        },
+    };
+
+    let mut record = NoticeRecord {
+        store_id: Some(dispatcher.store_id()),
+        user_id,
+        order_number: order.order_number.clone(),
+        notice_method: Some(dispatcher.notice_method(&recipient, true)),
+        notice_type: Some(TEMP_TYPE_PICKUP.to_string()),
+        notice_time: Some(utils::get_now()),
+        title: Some("取衣通知".to_string()),
+        content: Some(format!("取件码：{}", code)),
+        result: Some(RESULT_SENDING.to_string()),
+        ..Default::default()
+    };
+    let mut tx = pool.begin().await?;
+    record.create(&mut tx).await?;
+    tx.commit().await?;
+
+    dispatcher
+        .dispatch(&Outgoing {
+            record,
+            recipient,
+            pickup_code: Some(code),
+            picture: None,
+        })
+        .await?;
+
+    match dispatcher.sms_issue() {
+        Some(ErrorKind::SmsNotSubscribed) => Err(Error::with_details(
+            ErrorKind::SmsNotSubscribed,
+            "短信服务未订阅，请先订阅短信服务",
+        )),
+        Some(ErrorKind::SmsRemainShort) => Err(Error::with_details(
+            ErrorKind::SmsRemainShort,
+            "短信余量不足，请及时充值",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// 向会员发送一条通知：记录入库后按会员的通知偏好发送，返回是否送达
+pub async fn send_user_notice(
+    state: &AppState,
+    user: &User,
+    notice_type: &str,
+    title: &str,
+    content: String,
+) -> Result<bool> {
+    let dispatcher = NoticeDispatcher::new(state).await?;
+    let recipient = Recipient::from(user);
+    let mut record = NoticeRecord {
+        store_id: Some(dispatcher.store_id()),
+        user_id: user.user_id.unwrap_or_default(),
+        notice_method: Some(dispatcher.notice_method(&recipient, false)),
+        notice_type: Some(notice_type.to_string()),
+        notice_time: Some(utils::get_now()),
+        title: Some(title.to_string()),
+        content: Some(content),
+        result: Some(RESULT_SENDING.to_string()),
+        ..Default::default()
+    };
+    let mut tx = state.pool.begin().await?;
+    record.create(&mut tx).await?;
+    tx.commit().await?;
+
+    dispatcher
+        .dispatch(&Outgoing {
+            record,
+            recipient,
+            pickup_code: None,
+            picture: None,
+        })
+        .await
+}
+
+#[tauri::command]
+pub async fn get_notice_settings(state: State<'_, AppState>) -> Result<NoticeSettings> {
+    NoticeSettings::load(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn save_notice_settings(
+    state: State<'_, AppState>,
+    settings: NoticeSettings,
+) -> Result<()> {
+    settings.validate()?;
+    settings.save(&state.pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quiet_hours_across_midnight() {
+        let settings = NoticeSettings {
+            quiet_start: "22:00".to_string(),
+            quiet_end: "08:00".to_string(),
+            ..Default::default()
+        };
+        let at = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        assert!(settings.in_quiet_hours(at(23, 30)));
+        assert!(settings.in_quiet_hours(at(7, 59)));
+        assert!(!settings.in_quiet_hours(at(8, 0)));
+        assert!(!settings.in_quiet_hours(at(12, 0)));
+        assert!(!NoticeSettings::default().in_quiet_hours(at(23, 30)));
+    }
+
+    #[test]
+    fn test_channel_available() {
+        // 服务端短信只有取件模板，其他通知回退到站内消息
+        assert!(NoticeChannel::Sms.is_available(true));
+        assert!(!NoticeChannel::Sms.is_available(false));
+        assert!(NoticeChannel::InApp.is_available(false));
+    }
+
+    #[test]
+    fn test_notice_method_never_empty() {
+        let recipient = Recipient::default();
+        // 测试模式按实际发送的渠道记录，而不是日志渠道
+        let settings = NoticeSettings {
+            log_only: true,
+            ..Default::default()
+        };
+        assert_eq!(settings.notice_method(&recipient, true), "0");
+        assert_eq!(settings.notice_method(&recipient, false), "2");
+
+        // 没有可用渠道时记为首选渠道
+        let settings = NoticeSettings {
+            fallback: vec![],
+            ..Default::default()
+        };
+        assert_eq!(settings.notice_method(&recipient, false), "0");
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use tauri::{AppHandle, Manager, Runtime};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use super::{NoticeSettings, expiry, greeting};
+use crate::constants::NoticeJobType;
+use crate::db::notice_job::{DAY_MILLIS, NoticeJob};
+use crate::db::notice_temp::NoticeTemp;
+use crate::db::orders::Order;
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+// 定时通知轮询间隔（秒）
+const POLL_INTERVAL: u64 = 60;
+// 卡券到期检查间隔
+const EXPIRY_CHECK_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(60 * 60);
+// 会员祝福检查间隔，每人每年只发放一次
+const GREETING_CHECK_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(60 * 60);
+
+async fn send(state: &AppState, job: &NoticeJob) -> Result<u64> {
+    let temp_id = job
+        .temp_id
+        .ok_or(Error::bad_request("temp_id is required"))?;
+    match job.job_type.unwrap_or_default() {
+        NoticeJobType::Once => NoticeTemp::send_notice(state, temp_id, &job.parse_user_ids()).await,
+        NoticeJobType::Recurring => {
+            let store_id = job.store_id.ok_or(Error::unauthorized())?;
+            let before = utils::get_timestamp() - job.overdue_days.unwrap_or_default() * DAY_MILLIS;
+            let orders = Order::select_overdue_for_pickup(&state.pool, store_id, before).await?;
+            NoticeTemp::send_for_orders(state, temp_id, orders).await
+        }
+    }
+}
+
+// 周期任务的下次执行时间，跳过停机期间错过的周期
+fn next_run_time(job: &NoticeJob) -> Option<i64> {
+    if job.job_type != Some(NoticeJobType::Recurring) {
+        return None;
+    }
+    let interval = job.interval_days.unwrap_or(1).max(1) * DAY_MILLIS;
+    let now = utils::get_timestamp();
+    let mut next = job.next_run_time.unwrap_or(now) + interval;
+    while next <= now {
+        next += interval;
+    }
+    Some(next)
+}
+
+/// 执行一次定时通知任务并记录结果
+pub async fn run_job(state: &AppState, job: NoticeJob) -> Result<()> {
+    let job_id = job.job_id.ok_or(Error::bad_request("job_id is required"))?;
+
+    // 任务已被领取或已取消
+    if !NoticeJob::claim(&state.pool, job_id).await? {
+        return Ok(());
+    }
+
+    let next_run_time = next_run_time(&job);
+    match send(state, &job).await {
+        Ok(sent) => {
+            tracing::info!("notice job {} sent {} notices", job_id, sent);
+            NoticeJob::finish(&state.pool, job_id, sent, None, next_run_time).await
+        }
+        Err(e) => {
+            let error = format!("{:?}: {}", e.kind(), e.details().unwrap_or_default());
+            tracing::warn!("notice job {} failed: {}", job_id, error);
+            NoticeJob::finish(&state.pool, job_id, 0, Some(&error), next_run_time).await
+        }
+    }
+}
+
+/// 定时通知后台任务，同时每小时检查一次卡券到期及会员祝福。
+/// 免打扰时段内到期的任务顺延到时段结束后执行
+#[derive(Debug, Clone)]
+pub struct NoticeSchedulerManager {
+    task_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl NoticeSchedulerManager {
+    pub fn new() -> Self {
+        Self {
+            task_handle: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// 启动定时通知任务
+    pub async fn start<R: Runtime>(&self, app_handle: AppHandle<R>) -> Result<()> {
+        let state = app_handle.state::<AppState>();
+        let store_id = state.try_get_user_id().await?;
+        let task_handle = self.task_handle.clone();
+
+        // 先停止已存在的任务
+        self.stop().await;
+
+        let count = NoticeJob::reset_interrupted(&state.pool, store_id).await?;
+        if count > 0 {
+            tracing::info!("{} interrupted notice jobs requeued", count);
+        }
+
+        let handle = tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(tokio::time::Duration::from_secs(POLL_INTERVAL));
+            let mut last_expiry_check: Option<tokio::time::Instant> = None;
+            let mut last_greeting_check: Option<tokio::time::Instant> = None;
+            loop {
+                interval.tick().await;
+                let state = app_handle.state::<AppState>();
+
+                match NoticeSettings::load(&state.pool).await {
+                    Ok(settings) if settings.in_quiet_hours(utils::get_now().time()) => continue,
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::error!("获取通知设置失败: {}", e);
+                        continue;
+                    }
+                }
+
+                let jobs = match NoticeJob::list_due(&state.pool, store_id).await {
+                    Ok(jobs) => jobs,
+                    Err(e) => {
+                        tracing::error!("获取定时通知任务失败: {}", e);
+                        continue;
+                    }
+                };
+                for job in jobs {
+                    if let Err(e) = run_job(&state, job).await {
+                        tracing::error!("执行定时通知任务失败: {}", e);
+                    }
+                }
+
+                if last_expiry_check.is_none_or(|at| at.elapsed() >= EXPIRY_CHECK_INTERVAL) {
+                    last_expiry_check = Some(tokio::time::Instant::now());
+                    match expiry::check_expiring_coupons(&state).await {
+                        Ok(sent) if sent > 0 => {
+                            tracing::info!("{} coupon expiry notices sent", sent)
+                        }
+                        Ok(_) => {}
+                        Err(e) => tracing::error!("卡券到期检查失败: {}", e),
+                    }
+                }
+
+                if last_greeting_check.is_none_or(|at| at.elapsed() >= GREETING_CHECK_INTERVAL) {
+                    last_greeting_check = Some(tokio::time::Instant::now());
+                    match greeting::send_greetings(&state).await {
+                        Ok(sent) if sent > 0 => tracing::info!("{} greeting notices sent", sent),
+                        Ok(_) => {}
+                        Err(e) => tracing::error!("会员祝福发送失败: {}", e),
+                    }
+                }
+            }
+        });
+
+        // 保存任务句柄
+        let mut handle_guard = task_handle.lock().await;
+        *handle_guard = Some(handle);
+
+        Ok(())
+    }
+
+    /// 停止定时通知任务
+    pub async fn stop(&self) {
+        let mut handle_guard = self.task_handle.lock().await;
+        if let Some(handle) = handle_guard.take() {
+            handle.abort();
+        }
+    }
+}
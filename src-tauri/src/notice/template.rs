@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+
+use crate::constants::{CouponType, PaymentStatus};
+use crate::db::Curd;
+use crate::db::drying_rack::DryingRack;
+use crate::db::order_clothes::OrderCloth;
+use crate::db::orders::Order;
+use crate::db::payments::Payment;
+use crate::db::user::User;
+use crate::db::user_coupons::UserCoupon;
+use crate::error::{Error, Result};
+use crate::local_users::LocalUser;
+
+/// 模板变量说明
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateVariable {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub example: &'static str,
+}
+
+const fn var(
+    name: &'static str,
+    description: &'static str,
+    example: &'static str,
+) -> TemplateVariable {
+    TemplateVariable {
+        name,
+        description,
+        example,
+    }
+}
+
+/// 模板变量目录。
+/// 模板中用《变量》插入变量值；《#变量》...《/变量》仅在变量有值（非空且不为 0）时输出，
+/// 《^变量》...《/变量》则相反
+pub const VARIABLES: &[TemplateVariable] = &[
+    var("会员姓名", "会员昵称加手机尾号", "张三5678"),
+    var("手机尾号", "会员手机号后四位", "5678"),
+    var("门店名称", "当前门店名称", "干净洗衣"),
+    var("门店地址", "当前门店地址", "北京市朝阳区建国路 1 号"),
+    var("订单号", "订单编号", "250101000123"),
+    var("取件码", "订单取件码", "123456"),
+    var("衣物件数", "订单中的衣物件数", "3"),
+    var("应付金额", "订单总价减去已支付部分，已付清时为 0", "58.00"),
+    var("取件日期", "订单预计完成日期", "2025-01-05"),
+    var("挂衣位置", "已上挂衣物的衣挂位置", "A架-12、A架-13"),
+    var("会员余额", "会员储值卡余额", "200.00"),
+    var(
+        "优惠券到期",
+        "会员最早到期的有效优惠券的到期日期",
+        "2025-02-01",
+    ),
+];
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Var(String),
+    Section {
+        name: String,
+        inverted: bool,
+        children: Vec<Node>,
+    },
+}
+
+/// 解析后的通知模板
+#[derive(Debug)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+fn check_variable(name: &str) -> Result<()> {
+    if VARIABLES.iter().any(|v| v.name == name) {
+        Ok(())
+    } else {
+        Err(Error::bad_request(format!("未知的模板变量《{name}》")))
+    }
+}
+
+// 非空且不为 0 的值视为有值
+fn is_truthy(value: &str) -> bool {
+    !value.is_empty() && value.parse::<f64>().map_or(true, |v| v != 0.0)
+}
+
+/// 手机号后四位，不足四位时返回原号码
+pub fn phone_tail(phone: &str) -> &str {
+    let count = phone.chars().count();
+    match phone.char_indices().nth(count.saturating_sub(4)) {
+        Some((idx, _)) => &phone[idx..],
+        None => phone,
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        // 栈底为模板本身，其余为尚未结束的条件段落
+        let mut stack: Vec<(String, bool, Vec<Node>)> = vec![(String::new(), false, Vec::new())];
+        let push_text = |stack: &mut Vec<(String, bool, Vec<Node>)>, text: &str| {
+            if !text.is_empty() {
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .2
+                    .push(Node::Text(text.to_string()));
+            }
+        };
+
+        let mut rest = source;
+        while let Some(start) = rest.find('《') {
+            push_text(&mut stack, &rest[..start]);
+            let after = &rest[start + '《'.len_utf8()..];
+            let end = after
+                .find('》')
+                .ok_or(Error::bad_request("模板中的《缺少对应的》"))?;
+            let tag = after[..end].trim();
+            rest = &after[end + '》'.len_utf8()..];
+
+            if let Some(name) = tag.strip_prefix('#') {
+                check_variable(name)?;
+                stack.push((name.to_string(), false, Vec::new()));
+            } else if let Some(name) = tag.strip_prefix('^') {
+                check_variable(name)?;
+                stack.push((name.to_string(), true, Vec::new()));
+            } else if let Some(name) = tag.strip_prefix('/') {
+                if stack.len() == 1 {
+                    return Err(Error::bad_request(format!("多余的结束标记《/{name}》")));
+                }
+                let (open, inverted, children) = stack.pop().unwrap();
+                if open != name {
+                    return Err(Error::bad_request(format!(
+                        "《#{open}》与结束标记《/{name}》不匹配"
+                    )));
+                }
+                stack.last_mut().unwrap().2.push(Node::Section {
+                    name: open,
+                    inverted,
+                    children,
+                });
+            } else {
+                check_variable(tag)?;
+                stack.last_mut().unwrap().2.push(Node::Var(tag.to_string()));
+            }
+        }
+        push_text(&mut stack, rest);
+
+        if stack.len() > 1 {
+            let (open, ..) = stack.pop().unwrap();
+            return Err(Error::bad_request(format!(
+                "《#{open}》缺少结束标记《/{open}》"
+            )));
+        }
+        Ok(Self {
+            nodes: stack.pop().unwrap().2,
+        })
+    }
+
+    /// 模板中用到的变量
+    pub fn variables(&self) -> Vec<&str> {
+        fn collect<'a>(nodes: &'a [Node], names: &mut Vec<&'a str>) {
+            for node in nodes {
+                match node {
+                    Node::Text(_) => {}
+                    Node::Var(name) => names.push(name),
+                    Node::Section { name, children, .. } => {
+                        names.push(name);
+                        collect(children, names);
+                    }
+                }
+            }
+        }
+        let mut names = Vec::new();
+        collect(&self.nodes, &mut names);
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    pub fn render(&self, values: &HashMap<&str, String>) -> String {
+        fn render_nodes(nodes: &[Node], values: &HashMap<&str, String>, out: &mut String) {
+            for node in nodes {
+                match node {
+                    Node::Text(text) => out.push_str(text),
+                    Node::Var(name) => {
+                        out.push_str(values.get(name.as_str()).map_or("", String::as_str))
+                    }
+                    Node::Section {
+                        name,
+                        inverted,
+                        children,
+                    } => {
+                        let truthy = values
+                            .get(name.as_str())
+                            .is_some_and(|value| is_truthy(value));
+                        if truthy != *inverted {
+                            render_nodes(children, values, out);
+                        }
+                    }
+                }
+            }
+        }
+        let mut out = String::new();
+        render_nodes(&self.nodes, values, &mut out);
+        out
+    }
+}
+
+/// 按模板用到的变量查询数据，未用到的变量不查询
+pub async fn load_values(
+    pool: &Pool<Sqlite>,
+    template: &Template,
+    store: &LocalUser,
+    user: &User,
+    order: Option<&Order>,
+) -> Result<HashMap<&'static str, String>> {
+    let store_id = store.id.unwrap_or_default();
+    let user_id = user.user_id.unwrap_or_default();
+    let phone = user.phonenumber.as_deref().unwrap_or_default();
+    let used = template.variables();
+
+    // 订单衣物只在用到件数、金额或挂衣位置时查询一次
+    let mut clothes = None;
+    if let Some(order_id) = order.and_then(|order| order.order_id) {
+        if used
+            .iter()
+            .any(|name| matches!(*name, "衣物件数" | "应付金额" | "挂衣位置"))
+        {
+            clothes = Some(OrderCloth::get_by_order_id(pool, order_id).await?);
+        }
+    }
+
+    let mut values = HashMap::new();
+    for variable in VARIABLES {
+        if !used.contains(&variable.name) {
+            continue;
+        }
+        let value = match variable.name {
+            "会员姓名" => format!(
+                "{}{}",
+                user.nick_name.as_deref().unwrap_or_default(),
+                phone_tail(phone)
+            ),
+            "手机尾号" => phone_tail(phone).to_string(),
+            "门店名称" => store.store_name.clone().unwrap_or_default(),
+            "门店地址" => store_address(store),
+            "订单号" => order
+                .and_then(|o| o.order_number.clone())
+                .unwrap_or_default(),
+            "取件码" => order
+                .and_then(|o| o.pickup_code.clone())
+                .unwrap_or_default(),
+            "衣物件数" => clothes
+                .as_ref()
+                .map(|c| c.len().to_string())
+                .unwrap_or_default(),
+            "应付金额" => match (order, &clothes) {
+                (Some(order), _) if order.payment_status == Some(PaymentStatus::Paid) => {
+                    "0.00".to_string()
+                }
+                (Some(order), Some(clothes)) => {
+                    let mut order = order.clone();
+                    let total = Order::cal_total_price(pool, &mut order, clothes).await?;
+                    // 扣除已支付部分（支付明细及卡券抵扣）
+                    let paid = match order.order_id {
+                        Some(order_id) => Payment::get_by_order_id(pool, order_id, store_id)
+                            .await?
+                            .map(|payment| payment.paid_amount())
+                            .unwrap_or_default(),
+                        None => 0.0,
+                    };
+                    format!("{:.2}", (total - paid).max(0.0))
+                }
+                _ => String::new(),
+            },
+            "取件日期" => order
+                .and_then(|o| o.desire_complete_time)
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            "挂衣位置" => match &clothes {
+                Some(clothes) => hang_positions(pool, clothes).await?,
+                None => String::new(),
+            },
+            "会员余额" => {
+                let balance = UserCoupon::stored_value_balance(pool, store_id, user_id).await?;
+                format!("{balance:.2}")
+            }
+            "优惠券到期" => UserCoupon::find_valid_time_by_user_id(pool, store_id, user_id)
+                .await?
+                .into_iter()
+                .filter_map(|uc| uc.coupon)
+                .filter(|c| c.coupon_type != Some(CouponType::StoredValueCard))
+                .filter_map(|c| c.valid_to)
+                .min()
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+        values.insert(variable.name, value);
+    }
+    Ok(values)
+}
+
+fn store_address(store: &LocalUser) -> String {
+    let parts = [
+        &store.province,
+        &store.city,
+        &store.district,
+        &store.address_detail,
+    ];
+    let address: String = parts.iter().filter_map(|p| p.as_deref()).collect();
+    if address.is_empty() {
+        store.store_location.clone().unwrap_or_default()
+    } else {
+        address
+    }
+}
+
+// 衣架名称-衣挂编号，多个位置用顿号分隔
+async fn hang_positions(pool: &Pool<Sqlite>, clothes: &[OrderCloth]) -> Result<String> {
+    let mut positions: Vec<String> = Vec::new();
+    for cloth in clothes {
+        let Some(location) = cloth.hang_location_code else {
+            continue;
+        };
+        let rack = DryingRack::get_by_id(pool, location)
+            .await?
+            .and_then(|rack| rack.name)
+            .unwrap_or_default();
+        let position = match cloth.hanger_number {
+            Some(number) => format!("{rack}-{number}"),
+            None => rack,
+        };
+        if !position.is_empty() && !positions.contains(&position) {
+            positions.push(position);
+        }
+    }
+    Ok(positions.join("、"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_sections() {
+        let template =
+            Template::parse("《会员姓名》您好《#应付金额》，待付《应付金额》元《/应付金额》《^应付金额》，已付清《/应付金额》")
+                .unwrap();
+        let mut values = HashMap::from([
+            ("会员姓名", "张三".to_string()),
+            ("应付金额", "12.50".to_string()),
+        ]);
+        assert_eq!(template.render(&values), "张三您好，待付12.50元");
+        values.insert("应付金额", "0.00".to_string());
+        assert_eq!(template.render(&values), "张三您好，已付清");
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(Template::parse("《未知变量》").is_err());
+        assert!(Template::parse("《#取件码》未结束").is_err());
+        assert!(Template::parse("《#取件码》《/订单号》").is_err());
+        assert!(Template::parse("缺少右括号《取件码").is_err());
+    }
+
+    #[test]
+    fn test_phone_tail_short_number() {
+        assert_eq!(phone_tail("13812345678"), "5678");
+        assert_eq!(phone_tail("123"), "123");
+        assert_eq!(phone_tail(""), "");
+    }
+}
@@ -10,6 +10,9 @@ use crate::error::{Error, ErrorKind, Result};
 pub mod wechat;
 pub use wechat::*;
 
+// 美团、抖音团购券核销
+pub mod voucher;
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AlipayQrCodeResponse {
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use serde_json::{Value, json};
+use tokio::sync::Mutex;
+
+use super::{VoucherConsumption, VoucherInfo, VoucherPlatform};
+use crate::error::{Error, ErrorKind, Result};
+use crate::utils;
+
+const API_URL: &str = "https://open.douyin.com";
+const URL_CLIENT_TOKEN: &str = "/oauth/client_token/";
+const URL_PREPARE: &str = "/goodlife/v1/fulfilment/certificate/prepare/";
+const URL_VERIFY: &str = "/goodlife/v1/fulfilment/certificate/verify/";
+const URL_CANCEL: &str = "/goodlife/v1/fulfilment/certificate/cancel/";
+const TIMEOUT: u64 = 15;
+// 令牌提前过期的时间（毫秒），避免请求途中过期
+const TOKEN_EXPIRE_MARGIN: i64 = 5 * 60 * 1000;
+
+// 按接口地址和应用 Key 缓存的接口令牌及过期时间
+static TOKENS: Lazy<Mutex<HashMap<String, (String, i64)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 抖音来客团购券核销，金额单位为分
+pub struct DouyinVoucher {
+    client: reqwest::Client,
+    base_url: String,
+    client_key: String,
+    client_secret: String,
+    poi_id: String,
+}
+
+/// 抖音接口返回 data.error_code 不为 0 时表示失败
+fn check(resp: &Value) -> Result<&Value> {
+    let data = &resp["data"];
+    if data["error_code"].as_i64().unwrap_or_default() != 0 {
+        let msg = data["description"].as_str().unwrap_or("未知错误");
+        return Err(Error::with_details(
+            ErrorKind::BadRequest,
+            format!("抖音团购券接口调用失败: {msg}"),
+        ));
+    }
+    Ok(data)
+}
+
+fn yuan(fen: &Value) -> f64 {
+    fen.as_i64().unwrap_or_default() as f64 / 100.0
+}
+
+fn id_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+impl DouyinVoucher {
+    pub fn new(client_key: &str, client_secret: &str, poi_id: &str) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(TIMEOUT))
+                .build()
+                .unwrap_or_default(),
+            base_url: API_URL.to_string(),
+            client_key: client_key.to_string(),
+            client_secret: client_secret.to_string(),
+            poi_id: poi_id.to_string(),
+        }
+    }
+
+    /// 改用其他接口地址，测试模式下指向本地模拟平台
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    // 令牌按接口地址和应用 Key 缓存，测试模式的令牌不会用于正式接口
+    fn token_key(&self) -> String {
+        format!("{}#{}", self.base_url, self.client_key)
+    }
+
+    async fn access_token(&self) -> Result<String> {
+        let mut tokens = TOKENS.lock().await;
+        let now = utils::get_timestamp();
+        if let Some((token, expire_at)) = tokens.get(&self.token_key()) {
+            if *expire_at > now {
+                return Ok(token.clone());
+            }
+        }
+
+        let resp: Value = self
+            .client
+            .post(format!("{}{URL_CLIENT_TOKEN}", self.base_url))
+            .json(&json!({
+                "client_key": self.client_key,
+                "client_secret": self.client_secret,
+                "grant_type": "client_credential",
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        let data = check(&resp)?;
+        let token = data["access_token"]
+            .as_str()
+            .ok_or(Error::internal("抖音接口令牌获取失败"))?
+            .to_string();
+        let expire_at =
+            now + data["expires_in"].as_i64().unwrap_or_default() * 1000 - TOKEN_EXPIRE_MARGIN;
+        tokens.insert(self.token_key(), (token.clone(), expire_at));
+        Ok(token)
+    }
+
+    async fn get(&self, path: &str, query: &[(&str, &str)]) -> Result<Value> {
+        let resp: Value = self
+            .client
+            .get(format!("{}{path}", self.base_url))
+            .header("access-token", self.access_token().await?)
+            .query(query)
+            .send()
+            .await?
+            .json()
+            .await?;
+        tracing::debug!("抖音团购券接口 {} 返回: {}", path, resp);
+        check(&resp)?;
+        Ok(resp)
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<Value> {
+        let resp: Value = self
+            .client
+            .post(format!("{}{path}", self.base_url))
+            .header("access-token", self.access_token().await?)
+            .json(&body)
+            .send()
+            .await?
+            .json()
+            .await?;
+        tracing::debug!("抖音团购券接口 {} 返回: {}", path, resp);
+        check(&resp)?;
+        Ok(resp)
+    }
+
+    /// 验券准备，返回核销所需的 verify_token 及券信息
+    async fn prepare(&self, code: &str) -> Result<(String, Value)> {
+        let resp = self
+            .get(URL_PREPARE, &[("code", code), ("poi_id", &self.poi_id)])
+            .await?;
+        let data = &resp["data"];
+        let certificate = data["certificates"]
+            .as_array()
+            .and_then(|list| list.first())
+            .cloned()
+            .ok_or(Error::bad_request("券码不可用"))?;
+        let verify_token = data["verify_token"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        Ok((verify_token, certificate))
+    }
+}
+
+#[async_trait]
+impl VoucherPlatform for DouyinVoucher {
+    async fn verify(&self, code: &str) -> Result<VoucherInfo> {
+        let (_, certificate) = self.prepare(code).await?;
+        let amount = &certificate["amount"];
+        Ok(VoucherInfo {
+            code: code.to_string(),
+            title: certificate["sku"]["title"].as_str().map(str::to_string),
+            face_amount: yuan(&amount["original_amount"]),
+            settlement_amount: yuan(&amount["pay_amount"]),
+        })
+    }
+
+    async fn consume(&self, code: &str) -> Result<VoucherConsumption> {
+        let (verify_token, certificate) = self.prepare(code).await?;
+        let resp = self
+            .post(
+                URL_VERIFY,
+                json!({
+                    "verify_token": verify_token,
+                    "poi_id": self.poi_id,
+                    "encrypted_codes": [certificate["encrypted_code"]],
+                }),
+            )
+            .await?;
+        let result = resp["data"]["verify_results"]
+            .as_array()
+            .and_then(|list| list.first())
+            .ok_or(Error::internal("抖音团购券核销结果为空"))?;
+        if result["result"].as_i64().unwrap_or_default() != 0 {
+            let msg = result["msg"].as_str().unwrap_or("未知错误");
+            return Err(Error::bad_request(format!("抖音团购券核销失败: {msg}")));
+        }
+
+        let amount = &certificate["amount"];
+        Ok(VoucherConsumption {
+            code: code.to_string(),
+            platform_order_id: id_string(&result["verify_id"]),
+            certificate_id: id_string(&result["certificate_id"]),
+            title: certificate["sku"]["title"].as_str().map(str::to_string),
+            face_amount: yuan(&amount["original_amount"]),
+            settlement_amount: yuan(&amount["pay_amount"]),
+            raw_response: Some(resp.to_string()),
+        })
+    }
+
+    async fn cancel(&self, consumption: &VoucherConsumption) -> Result<()> {
+        self.post(
+            URL_CANCEL,
+            json!({
+                "verify_id": consumption.platform_order_id,
+                "certificate_id": consumption.certificate_id,
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pay::voucher::mock;
+
+    const TOKEN: &str = r#"{"data":{"error_code":0,"access_token":"clt.test","expires_in":7200}}"#;
+    const PREPARE: &str = r#"{"data":{"error_code":0,"verify_token":"vt-1","certificates":[{"encrypted_code":"enc-1","amount":{"original_amount":5900,"pay_amount":5310},"sku":{"title":"洗衣券"}}]}}"#;
+
+    #[tokio::test]
+    async fn test_consume_and_cancel() {
+        let (base_url, requests) = mock::serve(vec![
+            (URL_CLIENT_TOKEN, TOKEN.to_string()),
+            (URL_PREPARE, PREPARE.to_string()),
+            (
+                URL_VERIFY,
+                r#"{"data":{"error_code":0,"verify_results":[{"result":0,"verify_id":"v-1","certificate_id":7001}]}}"#.to_string(),
+            ),
+            (URL_CANCEL, r#"{"data":{"error_code":0}}"#.to_string()),
+        ])
+        .await;
+        let platform = DouyinVoucher::new("key", "secret", "poi").with_base_url(&base_url);
+
+        let info = platform.verify("123456").await.unwrap();
+        assert_eq!(info.title.as_deref(), Some("洗衣券"));
+        assert_eq!(info.face_amount, 59.);
+        assert_eq!(info.settlement_amount, 53.1);
+
+        let consumption = platform.consume("123456").await.unwrap();
+        assert_eq!(consumption.platform_order_id.as_deref(), Some("v-1"));
+        assert_eq!(consumption.certificate_id.as_deref(), Some("7001"));
+        platform.cancel(&consumption).await.unwrap();
+
+        // 令牌只获取一次，核销使用验券准备返回的 verify_token 和加密券码
+        let requests = requests.lock().unwrap();
+        let paths = requests
+            .iter()
+            .map(|r| r.split(' ').nth(1).unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                URL_CLIENT_TOKEN,
+                URL_PREPARE,
+                URL_PREPARE,
+                URL_VERIFY,
+                URL_CANCEL
+            ]
+        );
+        assert!(requests[3].contains(r#""verify_token":"vt-1""#));
+        assert!(requests[3].contains(r#""encrypted_codes":["enc-1"]"#));
+        assert!(requests[4].contains(r#""certificate_id":"7001""#));
+    }
+
+    #[tokio::test]
+    async fn test_verify_failed() {
+        let (base_url, _) = mock::serve(vec![
+            (URL_CLIENT_TOKEN, TOKEN.to_string()),
+            (URL_PREPARE, PREPARE.to_string()),
+            (
+                URL_VERIFY,
+                r#"{"data":{"error_code":0,"verify_results":[{"result":1,"msg":"券码已核销"}]}}"#
+                    .to_string(),
+            ),
+        ])
+        .await;
+        let err = DouyinVoucher::new("key", "secret", "poi")
+            .with_base_url(&base_url)
+            .consume("123456")
+            .await
+            .unwrap_err();
+        assert!(err.details().unwrap_or_default().contains("券码已核销"));
+    }
+}
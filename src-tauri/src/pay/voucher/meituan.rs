@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use sha1::{Digest, Sha1};
+
+use super::{VoucherConsumption, VoucherInfo, VoucherPlatform};
+use crate::error::{Error, ErrorKind, Result};
+use crate::utils;
+
+const API_URL: &str = "https://api-open-cater.meituan.com";
+const URL_PREPARE: &str = "/tuangou/coupon/prepare";
+const URL_CONSUME: &str = "/tuangou/coupon/consume";
+const URL_CANCEL: &str = "/tuangou/coupon/cancel";
+// 团购业务
+const BUSINESS_ID: &str = "1";
+const TIMEOUT: u64 = 15;
+// 核销人名称，显示在美团商家后台
+const OPERATOR_NAME: &str = "收银台";
+
+/// 美团开放平台团购券核销
+pub struct MeituanVoucher {
+    client: reqwest::Client,
+    base_url: String,
+    developer_id: String,
+    sign_key: String,
+    app_auth_token: String,
+}
+
+/// 签名：签名密钥拼接按键名排序的键值对后取 SHA1
+fn sign(sign_key: &str, params: &BTreeMap<&str, String>) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(sign_key.as_bytes());
+    for (key, value) in params {
+        hasher.update(key.as_bytes());
+        hasher.update(value.as_bytes());
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn amount(data: &Value, key: &str) -> f64 {
+    match &data[key] {
+        Value::String(s) => s.parse().unwrap_or_default(),
+        value => value.as_f64().unwrap_or_default(),
+    }
+}
+
+impl MeituanVoucher {
+    pub fn new(developer_id: &str, sign_key: &str, app_auth_token: &str) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(TIMEOUT))
+                .build()
+                .unwrap_or_default(),
+            base_url: API_URL.to_string(),
+            developer_id: developer_id.to_string(),
+            sign_key: sign_key.to_string(),
+            app_auth_token: app_auth_token.to_string(),
+        }
+    }
+
+    /// 改用其他接口地址，测试模式下指向本地模拟平台
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    async fn post(&self, path: &str, biz: Value) -> Result<Value> {
+        let mut params = BTreeMap::from([
+            ("appAuthToken", self.app_auth_token.clone()),
+            ("businessId", BUSINESS_ID.to_string()),
+            ("charset", "UTF-8".to_string()),
+            ("developerId", self.developer_id.clone()),
+            ("timestamp", (utils::get_timestamp() / 1000).to_string()),
+            ("version", "2".to_string()),
+            ("biz", biz.to_string()),
+        ]);
+        let signature = sign(&self.sign_key, &params);
+        params.insert("sign", signature);
+
+        let resp: Value = self
+            .client
+            .post(format!("{}{path}", self.base_url))
+            .form(&params)
+            .send()
+            .await?
+            .json()
+            .await?;
+        tracing::debug!("美团团购券接口 {} 返回: {}", path, resp);
+
+        if resp["code"].as_str() != Some("OP_SUCCESS") {
+            let msg = resp["msg"]
+                .as_str()
+                .or(resp["error"]["message"].as_str())
+                .unwrap_or("未知错误");
+            return Err(Error::with_details(
+                ErrorKind::BadRequest,
+                format!("美团团购券接口调用失败: {msg}"),
+            ));
+        }
+        Ok(resp)
+    }
+}
+
+#[async_trait]
+impl VoucherPlatform for MeituanVoucher {
+    async fn verify(&self, code: &str) -> Result<VoucherInfo> {
+        let resp = self
+            .post(URL_PREPARE, json!({ "couponCode": code }))
+            .await?;
+        let data = &resp["data"];
+        if data["count"].as_i64().unwrap_or_default() < 1 {
+            return Err(Error::bad_request("券码不可用"));
+        }
+        Ok(VoucherInfo {
+            code: code.to_string(),
+            title: data["dealTitle"].as_str().map(str::to_string),
+            face_amount: amount(data, "dealValue"),
+            settlement_amount: amount(data, "couponBuyPrice"),
+        })
+    }
+
+    async fn consume(&self, code: &str) -> Result<VoucherConsumption> {
+        // 核销结果不一定包含金额，先查询券信息
+        let info = self.verify(code).await?;
+        let resp = self
+            .post(
+                URL_CONSUME,
+                json!({
+                    "couponCode": code,
+                    "count": 1,
+                    "eId": uuid::Uuid::new_v4().simple().to_string(),
+                    "eName": OPERATOR_NAME,
+                }),
+            )
+            .await?;
+        let data = &resp["data"];
+        Ok(VoucherConsumption {
+            code: code.to_string(),
+            platform_order_id: match &data["orderId"] {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            },
+            certificate_id: None,
+            title: info.title,
+            face_amount: info.face_amount,
+            settlement_amount: info.settlement_amount,
+            raw_response: Some(resp.to_string()),
+        })
+    }
+
+    async fn cancel(&self, consumption: &VoucherConsumption) -> Result<()> {
+        self.post(
+            URL_CANCEL,
+            json!({
+                "couponCode": consumption.code,
+                "type": 1,
+                "eId": uuid::Uuid::new_v4().simple().to_string(),
+                "eName": OPERATOR_NAME,
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pay::voucher::mock;
+
+    #[test]
+    fn test_sign() {
+        let params = BTreeMap::from([
+            ("timestamp", "1700000000".to_string()),
+            ("developerId", "1001".to_string()),
+            ("charset", "UTF-8".to_string()),
+        ]);
+        // sha1("secretcharsetUTF-8developerId1001timestamp1700000000")
+        assert_eq!(
+            sign("secret", &params),
+            "22686cf40495cb05d0f226675bb8c295da78c2d4"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consume_and_cancel() {
+        let prepare = r#"{"code":"OP_SUCCESS","data":{"count":1,"dealTitle":"洗衣券","dealValue":59,"couponBuyPrice":"53.1"}}"#;
+        let (base_url, requests) = mock::serve(vec![
+            (URL_PREPARE, prepare.to_string()),
+            (
+                URL_CONSUME,
+                r#"{"code":"OP_SUCCESS","data":{"orderId":10086}}"#.to_string(),
+            ),
+            (URL_CANCEL, r#"{"code":"OP_SUCCESS","data":{}}"#.to_string()),
+        ])
+        .await;
+        let platform = MeituanVoucher::new("1001", "key", "token").with_base_url(&base_url);
+
+        let info = platform.verify("123456").await.unwrap();
+        assert_eq!(info.title.as_deref(), Some("洗衣券"));
+        assert_eq!(info.face_amount, 59.);
+        assert_eq!(info.settlement_amount, 53.1);
+
+        let consumption = platform.consume("123456").await.unwrap();
+        assert_eq!(consumption.platform_order_id.as_deref(), Some("10086"));
+        assert_eq!(consumption.face_amount, 59.);
+        platform.cancel(&consumption).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let paths = requests
+            .iter()
+            .map(|r| r.split(' ').nth(1).unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![URL_PREPARE, URL_PREPARE, URL_CONSUME, URL_CANCEL]
+        );
+        assert!(requests[3].contains("couponCode%22%3A%22123456"));
+    }
+
+    #[tokio::test]
+    async fn test_error_response() {
+        let (base_url, _) = mock::serve(vec![(
+            URL_PREPARE,
+            r#"{"code":"OP_FAILED","msg":"券码已使用"}"#.to_string(),
+        )])
+        .await;
+        let err = MeituanVoucher::new("1001", "key", "token")
+            .with_base_url(&base_url)
+            .consume("123456")
+            .await
+            .unwrap_err();
+        assert!(err.details().unwrap_or_default().contains("券码已使用"));
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 测试用的本地 HTTP 服务：按请求路径返回对应的 JSON，未配置的路径返回 404。
+/// 返回服务地址及依次收到的请求（方法 路径 请求体）
+pub async fn serve(routes: Vec<(&'static str, String)>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let Some((method, path, body)) = read_request(&mut stream).await else {
+                continue;
+            };
+            received
+                .lock()
+                .unwrap()
+                .push(format!("{method} {path} {body}"));
+            let resp = match routes.iter().find(|(route, _)| *route == path) {
+                Some((_, body)) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                ),
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string(),
+            };
+            let _ = stream.write_all(resp.as_bytes()).await;
+        }
+    });
+    (base_url, requests)
+}
+
+// 读取一个请求，返回方法、去掉查询参数的路径及请求体
+async fn read_request(stream: &mut TcpStream) -> Option<(String, String, String)> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    loop {
+        let n = stream.read(&mut buf).await.ok()?;
+        request.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&request);
+        if let Some(header_end) = text.find("\r\n\r\n") {
+            let content_length = text
+                .lines()
+                .find_map(|line| {
+                    line.to_ascii_lowercase()
+                        .strip_prefix("content-length:")
+                        .map(|len| len.trim().parse::<usize>().unwrap_or_default())
+                })
+                .unwrap_or_default();
+            if request.len() >= header_end + 4 + content_length {
+                let mut parts = text.split_whitespace();
+                let method = parts.next()?.to_string();
+                let path = parts.next()?.split('?').next()?.to_string();
+                let body = text[header_end + 4..].to_string();
+                return Some((method, path, body));
+            }
+        }
+        if n == 0 {
+            return None;
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use tauri::State;
+
+use crate::constants::PaymentMethod;
+use crate::db::configs::Config;
+use crate::db::{Curd, Validator};
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::utils;
+
+mod douyin;
+mod meituan;
+#[cfg(test)]
+mod mock;
+mod stub;
+
+pub use douyin::DouyinVoucher;
+pub use meituan::MeituanVoucher;
+pub use stub::StubVoucher;
+
+// 团购券核销设置在 configs 表中的键名前缀
+const CONFIG_PREFIX: &str = "voucher.";
+
+/// 券码查询结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoucherInfo {
+    pub code: String,
+    pub title: Option<String>,
+    /// 券面价值
+    pub face_amount: f64,
+    /// 用户实付金额，作为结算金额
+    pub settlement_amount: f64,
+}
+
+/// 核销结果，撤销核销时原样传回
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoucherConsumption {
+    pub code: String,
+    /// 平台核销单号
+    pub platform_order_id: Option<String>,
+    /// 抖音券 ID
+    pub certificate_id: Option<String>,
+    pub title: Option<String>,
+    pub face_amount: f64,
+    pub settlement_amount: f64,
+    pub raw_response: Option<String>,
+}
+
+/// 第三方平台团购券核销接口
+#[async_trait]
+pub trait VoucherPlatform: Send + Sync {
+    /// 查询券码是否可用及金额，不核销
+    async fn verify(&self, code: &str) -> Result<VoucherInfo>;
+
+    /// 核销券码
+    async fn consume(&self, code: &str) -> Result<VoucherConsumption>;
+
+    /// 撤销核销，平台一般只允许在核销后的一段时间内撤销
+    async fn cancel(&self, consumption: &VoucherConsumption) -> Result<()>;
+}
+
+/// 美团、抖音团购券核销设置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct VoucherSettings {
+    /// 测试模式，美团、抖音接口改为调用模拟平台
+    pub test_mode: bool,
+    /// 本地模拟平台地址，如 http://127.0.0.1:18080，为空时使用内置模拟平台
+    pub test_base_url: String,
+    pub meituan_developer_id: String,
+    pub meituan_sign_key: String,
+    pub meituan_app_auth_token: String,
+    pub douyin_client_key: String,
+    pub douyin_client_secret: String,
+    /// 抖音门店 ID
+    pub douyin_poi_id: String,
+}
+
+impl Validator for VoucherSettings {
+    fn validate(&self) -> Result<()> {
+        let filled = |fields: &[&String]| {
+            let count = fields.iter().filter(|f| !f.trim().is_empty()).count();
+            count == 0 || count == fields.len()
+        };
+        if !filled(&[
+            &self.meituan_developer_id,
+            &self.meituan_sign_key,
+            &self.meituan_app_auth_token,
+        ]) {
+            return Err(Error::bad_request("美团接口参数不完整"));
+        }
+        if !filled(&[
+            &self.douyin_client_key,
+            &self.douyin_client_secret,
+            &self.douyin_poi_id,
+        ]) {
+            return Err(Error::bad_request("抖音接口参数不完整"));
+        }
+        let test_url = self.test_base_url.trim();
+        if !test_url.is_empty() && !test_url.starts_with("http") {
+            return Err(Error::bad_request("本地模拟平台地址格式不正确"));
+        }
+        Ok(())
+    }
+}
+
+impl VoucherSettings {
+    // (键名, 参数名称, 参数值)
+    fn entries(&self) -> Vec<(&'static str, &'static str, String)> {
+        vec![
+            ("testMode", "团购券-测试模式", self.test_mode.to_string()),
+            (
+                "testBaseUrl",
+                "团购券-本地模拟平台地址",
+                self.test_base_url.clone(),
+            ),
+            (
+                "meituanDeveloperId",
+                "团购券-美团开发者 ID",
+                self.meituan_developer_id.clone(),
+            ),
+            (
+                "meituanSignKey",
+                "团购券-美团签名密钥",
+                self.meituan_sign_key.clone(),
+            ),
+            (
+                "meituanAppAuthToken",
+                "团购券-美团门店授权令牌",
+                self.meituan_app_auth_token.clone(),
+            ),
+            (
+                "douyinClientKey",
+                "团购券-抖音应用 Key",
+                self.douyin_client_key.clone(),
+            ),
+            (
+                "douyinClientSecret",
+                "团购券-抖音应用密钥",
+                self.douyin_client_secret.clone(),
+            ),
+            (
+                "douyinPoiId",
+                "团购券-抖音门店 ID",
+                self.douyin_poi_id.clone(),
+            ),
+        ]
+    }
+
+    fn apply(&mut self, key: &str, value: &str) {
+        let value = value.trim().to_string();
+        match key {
+            "testMode" => self.test_mode = utils::to_bool(value),
+            "testBaseUrl" => self.test_base_url = value,
+            "meituanDeveloperId" => self.meituan_developer_id = value,
+            "meituanSignKey" => self.meituan_sign_key = value,
+            "meituanAppAuthToken" => self.meituan_app_auth_token = value,
+            "douyinClientKey" => self.douyin_client_key = value,
+            "douyinClientSecret" => self.douyin_client_secret = value,
+            "douyinPoiId" => self.douyin_poi_id = value,
+            _ => {}
+        }
+    }
+
+    pub async fn load(pool: &Pool<Sqlite>) -> Result<Self> {
+        let configs = Config {
+            config_key: Some(CONFIG_PREFIX.to_string()),
+            ..Default::default()
+        }
+        .get_all(pool)
+        .await?;
+
+        let mut settings = Self::default();
+        for config in configs {
+            if let Some(key) = config
+                .config_key
+                .as_deref()
+                .and_then(|key| key.strip_prefix(CONFIG_PREFIX))
+            {
+                settings.apply(key, &config.config_value.unwrap_or_default());
+            }
+        }
+        Ok(settings)
+    }
+
+    pub async fn save(&self, pool: &Pool<Sqlite>) -> Result<()> {
+        for (key, name, value) in self.entries() {
+            Config::set_value(pool, &format!("{CONFIG_PREFIX}{key}"), name, value).await?;
+        }
+        Ok(())
+    }
+
+    /// 按支付方式获取核销平台。测试模式下未配置模拟平台地址时使用内置模拟平台，
+    /// 否则接口地址改为本地模拟平台，未配置的接口参数用测试值
+    pub fn platform(&self, method: &PaymentMethod) -> Result<Box<dyn VoucherPlatform>> {
+        if !matches!(method, PaymentMethod::Meituan | PaymentMethod::Douyin) {
+            return Err(Error::bad_request("该支付方式不支持团购券核销"));
+        }
+        let test_url = self.test_base_url.trim();
+        if self.test_mode && test_url.is_empty() {
+            return Ok(Box::new(StubVoucher));
+        }
+        let param = |value: &String| match value.as_str() {
+            "" if self.test_mode => "test".to_string(),
+            value => value.to_string(),
+        };
+        match method {
+            PaymentMethod::Meituan => {
+                if self.meituan_developer_id.is_empty() && !self.test_mode {
+                    return Err(Error::bad_request("未配置美团团购券接口"));
+                }
+                let platform = MeituanVoucher::new(
+                    &param(&self.meituan_developer_id),
+                    &param(&self.meituan_sign_key),
+                    &param(&self.meituan_app_auth_token),
+                );
+                Ok(Box::new(if self.test_mode {
+                    platform.with_base_url(test_url)
+                } else {
+                    platform
+                }))
+            }
+            _ => {
+                if self.douyin_client_key.is_empty() && !self.test_mode {
+                    return Err(Error::bad_request("未配置抖音团购券接口"));
+                }
+                let platform = DouyinVoucher::new(
+                    &param(&self.douyin_client_key),
+                    &param(&self.douyin_client_secret),
+                    &param(&self.douyin_poi_id),
+                );
+                Ok(Box::new(if self.test_mode {
+                    platform.with_base_url(test_url)
+                } else {
+                    platform
+                }))
+            }
+        }
+    }
+}
+
+/// 去除券码首尾空白及空券码，同一券码不能重复使用
+pub fn normalize_codes(codes: Vec<String>) -> Result<Vec<String>> {
+    let mut result: Vec<String> = Vec::with_capacity(codes.len());
+    for code in codes {
+        let code = code.trim().to_string();
+        if code.is_empty() {
+            continue;
+        }
+        if result.contains(&code) {
+            return Err(Error::bad_request(format!("券码 {code} 重复")));
+        }
+        result.push(code);
+    }
+    Ok(result)
+}
+
+/// 依次核销多张券码，任一张失败时撤销已核销的券码
+pub async fn consume_all(
+    platform: &dyn VoucherPlatform,
+    codes: &[String],
+) -> Result<Vec<VoucherConsumption>> {
+    let mut consumed = Vec::with_capacity(codes.len());
+    for code in codes {
+        match platform.consume(code).await {
+            Ok(consumption) => consumed.push(consumption),
+            Err(e) => {
+                cancel_all(platform, &consumed).await;
+                return Err(e);
+            }
+        }
+    }
+    Ok(consumed)
+}
+
+/// 收银核销：团购券按券面价值抵扣，先确认券面合计足以支付应付金额再依次核销
+pub async fn consume_for_payment(
+    platform: &dyn VoucherPlatform,
+    codes: &[String],
+    payable_amount: f64,
+) -> Result<Vec<VoucherConsumption>> {
+    let mut face_amount = 0.0;
+    for code in codes {
+        face_amount += platform.verify(code).await?.face_amount;
+    }
+    let shortfall = ((payable_amount - face_amount) * 100.0).round() / 100.0;
+    if shortfall > 0.0 {
+        return Err(Error::bad_request(format!(
+            "团购券金额不足，还差 {shortfall} 元，请先用其他方式支付差额"
+        )));
+    }
+    consume_all(platform, codes).await
+}
+
+/// 撤销核销，失败时只记录日志，返回撤销失败的券码及原因
+pub async fn cancel_all(
+    platform: &dyn VoucherPlatform,
+    consumed: &[VoucherConsumption],
+) -> Vec<(String, String)> {
+    let mut failed = Vec::new();
+    for consumption in consumed {
+        if let Err(e) = platform.cancel(consumption).await {
+            tracing::error!("撤销团购券核销失败 {}: {}", consumption.code, e);
+            failed.push((consumption.code.clone(), e.to_string()));
+        }
+    }
+    failed
+}
+
+#[tauri::command]
+pub async fn get_voucher_settings(state: State<'_, AppState>) -> Result<VoucherSettings> {
+    VoucherSettings::load(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn save_voucher_settings(
+    state: State<'_, AppState>,
+    settings: VoucherSettings,
+) -> Result<()> {
+    settings.validate()?;
+    settings.save(&state.pool).await
+}
+
+/// 收银前查询团购券，确认券码可用及金额
+#[tauri::command]
+pub async fn verify_voucher(
+    state: State<'_, AppState>,
+    payment_method: PaymentMethod,
+    code: String,
+) -> Result<VoucherInfo> {
+    let settings = VoucherSettings::load(&state.pool).await?;
+    settings
+        .platform(&payment_method)?
+        .verify(code.trim())
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_validate() {
+        let mut settings = VoucherSettings::default();
+        assert!(settings.validate().is_ok());
+
+        settings.meituan_developer_id = "1001".to_string();
+        assert!(settings.validate().is_err());
+
+        settings.meituan_sign_key = "key".to_string();
+        settings.meituan_app_auth_token = "token".to_string();
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_normalize_codes() {
+        let codes = vec![" 123 ".to_string(), "".to_string(), "456".to_string()];
+        assert_eq!(normalize_codes(codes).unwrap(), vec!["123", "456"]);
+        assert!(normalize_codes(vec!["123".to_string(), "123 ".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_platform_requires_voucher_method() {
+        let mut settings = VoucherSettings {
+            test_mode: true,
+            ..Default::default()
+        };
+        assert!(settings.platform(&PaymentMethod::Cash).is_err());
+        // 未配置模拟平台地址时使用内置模拟平台
+        assert!(settings.validate().is_ok());
+        assert!(settings.platform(&PaymentMethod::Meituan).is_ok());
+        settings.test_base_url = "127.0.0.1:18080".to_string();
+        assert!(settings.validate().is_err());
+        settings.test_base_url = "http://127.0.0.1:18080".to_string();
+        assert!(settings.validate().is_ok());
+        assert!(settings.platform(&PaymentMethod::Meituan).is_ok());
+        assert!(settings.platform(&PaymentMethod::Douyin).is_ok());
+        assert!(
+            VoucherSettings::default()
+                .platform(&PaymentMethod::Douyin)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consume_all_rolls_back() {
+        let stub = StubVoucher;
+        let codes = vec!["030000000101".to_string(), "030000000102".to_string()];
+        let consumed = consume_all(&stub, &codes).await.unwrap();
+        assert_eq!(consumed.len(), 2);
+        assert!(cancel_all(&stub, &consumed).await.is_empty());
+
+        // 第二张券码无效时撤销已核销的第一张
+        let codes = vec!["030000000103".to_string(), "invalid".to_string()];
+        assert!(consume_all(&stub, &codes).await.is_err());
+        assert!(stub.verify("030000000103").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_payment_rollback() {
+        let stub = StubVoucher;
+        let codes = vec!["040000000201".to_string(), "020000000202".to_string()];
+
+        // 券面合计 60 元不足以支付 80 元，不核销任何券码
+        assert!(consume_for_payment(&stub, &codes, 80.).await.is_err());
+        assert!(stub.verify("040000000201").await.is_ok());
+
+        // 核销后本地记账失败时，支付流程撤销已核销的券码，券码可以再次使用
+        let consumed = consume_for_payment(&stub, &codes, 55.).await.unwrap();
+        assert_eq!(consumed.len(), 2);
+        assert!(stub.verify("040000000201").await.is_err());
+        assert!(cancel_all(&stub, &consumed).await.is_empty());
+        for code in &codes {
+            assert!(stub.verify(code).await.is_ok());
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+
+use super::{VoucherConsumption, VoucherInfo, VoucherPlatform};
+use crate::error::{Error, Result};
+
+const CODE_LEN: usize = 12;
+// 模拟平台抽佣后的结算比例
+const SETTLEMENT_RATE: f64 = 0.9;
+
+// 已核销的券码及核销单号，进程内有效
+static CONSUMED: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 内置模拟核销平台，测试模式下未配置模拟平台地址时代替美团、抖音接口。
+/// 券码为 12 位数字，前 3 位为券面价值（元），如 059000000001 为 59 元券
+pub struct StubVoucher;
+
+impl StubVoucher {
+    fn parse(code: &str) -> Result<VoucherInfo> {
+        if code.len() != CODE_LEN || !code.chars().all(|c| c.is_ascii_digit()) {
+            return Err(Error::bad_request("券码不存在"));
+        }
+        let face_amount: f64 = code[..3].parse().unwrap_or_default();
+        if face_amount <= 0.0 {
+            return Err(Error::bad_request("券码不存在"));
+        }
+        Ok(VoucherInfo {
+            code: code.to_string(),
+            title: Some(format!("测试团购券 {face_amount} 元")),
+            face_amount,
+            settlement_amount: (face_amount * SETTLEMENT_RATE * 100.0).round() / 100.0,
+        })
+    }
+}
+
+#[async_trait]
+impl VoucherPlatform for StubVoucher {
+    async fn verify(&self, code: &str) -> Result<VoucherInfo> {
+        let info = Self::parse(code)?;
+        if CONSUMED.lock().unwrap().contains_key(code) {
+            return Err(Error::bad_request("券码已核销"));
+        }
+        Ok(info)
+    }
+
+    async fn consume(&self, code: &str) -> Result<VoucherConsumption> {
+        let info = Self::parse(code)?;
+        let mut consumed = CONSUMED.lock().unwrap();
+        if consumed.contains_key(code) {
+            return Err(Error::bad_request("券码已核销"));
+        }
+        let order_id = format!("STUB{}", uuid::Uuid::new_v4().simple());
+        consumed.insert(code.to_string(), order_id.clone());
+        Ok(VoucherConsumption {
+            code: info.code,
+            platform_order_id: Some(order_id),
+            certificate_id: None,
+            title: info.title,
+            face_amount: info.face_amount,
+            settlement_amount: info.settlement_amount,
+            raw_response: None,
+        })
+    }
+
+    async fn cancel(&self, consumption: &VoucherConsumption) -> Result<()> {
+        let mut consumed = CONSUMED.lock().unwrap();
+        match consumed.get(&consumption.code) {
+            Some(order_id) if Some(order_id) == consumption.platform_order_id.as_ref() => {
+                consumed.remove(&consumption.code);
+                Ok(())
+            }
+            _ => Err(Error::bad_request("核销记录不存在")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_consume_and_cancel() {
+        let stub = StubVoucher;
+        assert!(stub.verify("000000000001").await.is_err());
+        assert!(stub.verify("59").await.is_err());
+
+        let code = "059000000001";
+        let info = stub.verify(code).await.unwrap();
+        assert_eq!(info.face_amount, 59.0);
+        assert_eq!(info.settlement_amount, 53.1);
+
+        let consumption = stub.consume(code).await.unwrap();
+        assert!(stub.verify(code).await.is_err());
+        assert!(stub.consume(code).await.is_err());
+
+        stub.cancel(&consumption).await.unwrap();
+        assert!(stub.cancel(&consumption).await.is_err());
+        assert!(stub.consume(code).await.is_ok());
+    }
+}
@@ -0,0 +1,213 @@
+use image::GrayImage;
+
+use super::raw::encode_gbk;
+
+const ESC: u8 = 0x1B;
+const GS: u8 = 0x1D;
+const LF: u8 = 0x0A;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left = 0,
+    Center = 1,
+    Right = 2,
+}
+
+/// ESC/POS 指令构造器
+#[derive(Debug, Default)]
+pub struct EscPos {
+    buf: Vec<u8>,
+}
+
+impl EscPos {
+    pub fn new() -> Self {
+        let mut pos = Self::default();
+        // ESC @ 初始化打印机
+        pos.buf.extend_from_slice(&[ESC, b'@']);
+        pos
+    }
+
+    pub fn align(&mut self, align: Align) -> &mut Self {
+        self.buf.extend_from_slice(&[ESC, b'a', align as u8]);
+        self
+    }
+
+    pub fn bold(&mut self, on: bool) -> &mut Self {
+        self.buf.extend_from_slice(&[ESC, b'E', on as u8]);
+        self
+    }
+
+    /// 字符放大倍数，取值 1-8
+    pub fn size(&mut self, width: u8, height: u8) -> &mut Self {
+        let w = width.clamp(1, 8) - 1;
+        let h = height.clamp(1, 8) - 1;
+        self.buf.extend_from_slice(&[GS, b'!', (w << 4) | h]);
+        self
+    }
+
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        self.buf.extend(encode_gbk(text));
+        self
+    }
+
+    pub fn line(&mut self, text: &str) -> &mut Self {
+        self.text(text).feed(1)
+    }
+
+    pub fn feed(&mut self, lines: u8) -> &mut Self {
+        for _ in 0..lines {
+            self.buf.push(LF);
+        }
+        self
+    }
+
+    /// 左右两端对齐输出一行
+    pub fn line_lr(&mut self, left: &str, right: &str, columns: usize) -> &mut Self {
+        let gap = columns
+            .saturating_sub(text_width(left) + text_width(right))
+            .max(1);
+        self.line(&format!("{left}{}{right}", " ".repeat(gap)))
+    }
+
+    /// 按打印列数自动换行
+    pub fn wrap(&mut self, text: &str, columns: usize) -> &mut Self {
+        for line in wrap_lines(text, columns) {
+            self.line(&line);
+        }
+        self
+    }
+
+    pub fn separator(&mut self, columns: usize) -> &mut Self {
+        self.line(&"-".repeat(columns))
+    }
+
+    /// CODE128 条码，文字显示在条码下方
+    pub fn barcode128(&mut self, data: &str) -> &mut Self {
+        // 使用 CODE B 字符集
+        let mut payload = b"{B".to_vec();
+        payload.extend(data.bytes());
+        self.buf.extend_from_slice(&[GS, b'h', 60]); // 高度
+        self.buf.extend_from_slice(&[GS, b'w', 2]); // 模块宽度
+        self.buf.extend_from_slice(&[GS, b'H', 2]); // HRI 位置
+        self.buf
+            .extend_from_slice(&[GS, b'k', 73, payload.len().min(255) as u8]);
+        self.buf.extend(payload.into_iter().take(255));
+        self.feed(1)
+    }
+
+    /// 二维码，size 为模块大小 1-16
+    pub fn qr_code(&mut self, data: &str, size: u8) -> &mut Self {
+        let bytes = data.as_bytes();
+        let len = bytes.len() + 3;
+        let (pl, ph) = ((len % 256) as u8, (len / 256) as u8);
+        // 模型 2
+        self.buf
+            .extend_from_slice(&[GS, b'(', b'k', 4, 0, 49, 65, 50, 0]);
+        // 模块大小
+        self.buf
+            .extend_from_slice(&[GS, b'(', b'k', 3, 0, 49, 67, size.clamp(1, 16)]);
+        // 纠错等级 M
+        self.buf
+            .extend_from_slice(&[GS, b'(', b'k', 3, 0, 49, 69, 49]);
+        // 存储数据
+        self.buf
+            .extend_from_slice(&[GS, b'(', b'k', pl, ph, 49, 80, 48]);
+        self.buf.extend_from_slice(bytes);
+        // 打印
+        self.buf
+            .extend_from_slice(&[GS, b'(', b'k', 3, 0, 49, 81, 48]);
+        self.feed(1)
+    }
+
+    /// 光栅位图（GS v 0），灰度低于 128 的像素打印为黑点
+    pub fn image(&mut self, img: &GrayImage) -> &mut Self {
+        let width_bytes = img.width().div_ceil(8);
+        let height = img.height();
+        self.buf.extend_from_slice(&[
+            GS,
+            b'v',
+            b'0',
+            0,
+            (width_bytes % 256) as u8,
+            (width_bytes / 256) as u8,
+            (height % 256) as u8,
+            (height / 256) as u8,
+        ]);
+        for y in 0..height {
+            for bx in 0..width_bytes {
+                let mut byte = 0u8;
+                for bit in 0..8 {
+                    let x = bx * 8 + bit;
+                    if x < img.width() && img.get_pixel(x, y).0[0] < 128 {
+                        byte |= 0x80 >> bit;
+                    }
+                }
+                self.buf.push(byte);
+            }
+        }
+        self.feed(1)
+    }
+
+    /// 走纸后半切
+    pub fn cut(&mut self) -> &mut Self {
+        self.buf.extend_from_slice(&[GS, b'V', 66, 0]);
+        self
+    }
+
+    /// 钱箱脉冲（引脚 2）
+    pub fn open_drawer(&mut self) -> &mut Self {
+        self.buf.extend_from_slice(&[ESC, b'p', 0, 25, 250]);
+        self
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+// 中文等全角字符在热敏打印机上占两列
+fn char_width(c: char) -> usize {
+    if c.is_ascii() { 1 } else { 2 }
+}
+
+pub fn text_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// 按打印列数拆分为多行
+pub fn wrap_lines(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut width = 0;
+    for c in text.chars() {
+        let w = char_width(c);
+        if width + w > columns && !current.is_empty() {
+            lines.push(std::mem::take(&mut current));
+            width = 0;
+        }
+        current.push(c);
+        width += w;
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_width() {
+        assert_eq!(text_width("abc"), 3);
+        assert_eq!(text_width("瑕疵: 1"), 8);
+    }
+
+    #[test]
+    fn test_wrap_by_columns() {
+        let mut pos = EscPos::default();
+        pos.wrap("一二三四五", 4);
+        assert_eq!(pos.build().iter().filter(|b| **b == LF).count(), 3);
+    }
+}
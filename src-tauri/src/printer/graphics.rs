@@ -0,0 +1,219 @@
+use std::io::Cursor;
+
+use barcoders::sym::code39::Code39;
+use barcoders::sym::code128::Code128;
+use datamatrix::{DataMatrix, SymbolList};
+use image::{GrayImage, Luma};
+use printpdf::image_crate::ImageDecoder;
+use printpdf::{Image, ImageTransform, Mm, PdfLayerReference, image_crate};
+use qrcode::{Color, QrCode};
+
+use super::label::DOTS_PER_MM;
+use crate::constants::BarcodeType;
+use crate::error::{Error, Result};
+
+// 嵌入 PDF 的图片按此 dpi 计算原始尺寸
+const PDF_IMAGE_DPI: f32 = 300.0;
+// 扫码枪可稳定识别的最小模块宽度（点），一维码与二维码分别计算
+const MIN_LINEAR_MODULE_DOTS: u32 = 2;
+const MIN_MATRIX_MODULE_DOTS: u32 = 3;
+
+/// 条码模块矩阵，一维码高度为 1
+#[derive(Debug)]
+pub struct BarcodeMatrix {
+    pub width: u32,
+    pub height: u32,
+    // 四周静区的模块数
+    quiet: u32,
+    modules: Vec<bool>,
+}
+
+impl BarcodeMatrix {
+    fn linear(bits: Vec<u8>) -> Self {
+        Self {
+            width: bits.len() as u32,
+            height: 1,
+            quiet: 10,
+            modules: bits.into_iter().map(|b| b == 1).collect(),
+        }
+    }
+
+    pub fn is_linear(&self) -> bool {
+        self.height == 1
+    }
+
+    fn is_dark(&self, x: u32, y: u32) -> bool {
+        self.modules[(y * self.width + x) as usize]
+    }
+
+    /// 含静区的模块数
+    pub fn total_width(&self) -> u32 {
+        self.width + self.quiet * 2
+    }
+
+    pub fn total_height(&self) -> u32 {
+        if self.is_linear() {
+            1
+        } else {
+            self.height + self.quiet * 2
+        }
+    }
+
+    /// 可稳定扫描的最小打印尺寸(mm)，一维码只限制宽度
+    pub fn min_size(&self) -> (f32, f32) {
+        if self.is_linear() {
+            let dots = self.total_width() * MIN_LINEAR_MODULE_DOTS;
+            (dots as f32 / DOTS_PER_MM, 0.)
+        } else {
+            let dots = MIN_MATRIX_MODULE_DOTS as f32 / DOTS_PER_MM;
+            (
+                self.total_width() as f32 * dots,
+                self.total_height() as f32 * dots,
+            )
+        }
+    }
+
+    /// 渲染为灰度图，module 为每个模块的像素数，一维码高度为 bar_height
+    pub fn to_image(&self, module: u32, bar_height: u32) -> GrayImage {
+        let module = module.max(1);
+        let height = if self.is_linear() {
+            bar_height.max(1)
+        } else {
+            self.total_height() * module
+        };
+        GrayImage::from_fn(self.total_width() * module, height, |px, py| {
+            let mx = (px / module).checked_sub(self.quiet);
+            let my = if self.is_linear() {
+                Some(0)
+            } else {
+                (py / module).checked_sub(self.quiet)
+            };
+            let dark = match (mx, my) {
+                (Some(x), Some(y)) if x < self.width && y < self.height => self.is_dark(x, y),
+                _ => false,
+            };
+            Luma([if dark { 0 } else { 255 }])
+        })
+    }
+}
+
+/// 按条码类型编码，内容不符合该类型字符集时返回错误
+pub fn encode_barcode(barcode_type: BarcodeType, data: &str) -> Result<BarcodeMatrix> {
+    let err = |e: String| Error::bad_request(format!("无法生成{barcode_type}条码: {e}"));
+    match barcode_type {
+        BarcodeType::Code39 => {
+            let barcode = Code39::new(data).map_err(|e| err(e.to_string()))?;
+            Ok(BarcodeMatrix::linear(barcode.encode()))
+        }
+        BarcodeType::Code128 => {
+            // 偶数位纯数字使用 C 字符集，条码长度约为 B 字符集的一半
+            let charset = if !data.is_empty()
+                && data.len() % 2 == 0
+                && data.chars().all(|c| c.is_ascii_digit())
+            {
+                'Ć'
+            } else {
+                'Ɓ'
+            };
+            let barcode =
+                Code128::new(format!("{charset}{data}")).map_err(|e| err(e.to_string()))?;
+            Ok(BarcodeMatrix::linear(barcode.encode()))
+        }
+        BarcodeType::QrCode => {
+            let code = QrCode::new(data.as_bytes()).map_err(|e| err(e.to_string()))?;
+            let width = code.width() as u32;
+            Ok(BarcodeMatrix {
+                width,
+                height: width,
+                quiet: 4,
+                modules: code
+                    .to_colors()
+                    .into_iter()
+                    .map(|c| c == Color::Dark)
+                    .collect(),
+            })
+        }
+        BarcodeType::DataMatrix => {
+            let code = DataMatrix::encode(data.as_bytes(), SymbolList::default())
+                .map_err(|e| err(format!("{e:?}")))?;
+            let bitmap = code.bitmap();
+            let (width, height) = (bitmap.width() as u32, bitmap.height() as u32);
+            let mut modules = vec![false; (width * height) as usize];
+            for (x, y) in bitmap.pixels() {
+                modules[y * width as usize + x] = true;
+            }
+            Ok(BarcodeMatrix {
+                width,
+                height,
+                quiet: 1,
+                modules,
+            })
+        }
+    }
+}
+
+/// 读取图片文件并转为灰度图
+pub fn load_gray(path: &str) -> Result<GrayImage> {
+    let img = image::open(path).map_err(|e| Error::bad_request(e.to_string()))?;
+    Ok(img.to_luma8())
+}
+
+pub fn encode_png(img: &GrayImage) -> Result<Vec<u8>> {
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, image::ImageFormat::Png)
+        .map_err(|e| Error::internal(e.to_string()))?;
+    Ok(buffer.into_inner())
+}
+
+/// 将 PNG 图片缩放到指定尺寸(mm)后绘制到 PDF，(x, y) 为左下角坐标
+pub fn add_png_to_layer(
+    layer: &PdfLayerReference,
+    png: &[u8],
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+) -> Result<()> {
+    let decoder = image_crate::codecs::png::PngDecoder::new(Cursor::new(png))?;
+    let (px_width, px_height) = decoder.dimensions();
+    let image = Image::try_from(decoder)?;
+
+    // 图片在给定 dpi 下的原始尺寸(mm)，据此缩放到指定大小
+    let natural_width = px_width as f32 / PDF_IMAGE_DPI * 25.4;
+    let natural_height = px_height as f32 / PDF_IMAGE_DPI * 25.4;
+    image.add_to_layer(
+        layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(x)),
+            translate_y: Some(Mm(y)),
+            scale_x: Some(width / natural_width),
+            scale_y: Some(height / natural_height),
+            dpi: Some(PDF_IMAGE_DPI),
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code128_numeric_is_shorter() {
+        let numeric = encode_barcode(BarcodeType::Code128, "250101123456").unwrap();
+        let code39 = encode_barcode(BarcodeType::Code39, "250101123456").unwrap();
+        assert!(numeric.is_linear());
+        assert!(numeric.width < code39.width);
+    }
+
+    #[test]
+    fn test_matrix_codes_are_square_images() {
+        for barcode_type in [BarcodeType::QrCode, BarcodeType::DataMatrix] {
+            let matrix = encode_barcode(barcode_type, "250101123456").unwrap();
+            let img = matrix.to_image(2, 0);
+            assert_eq!(img.width(), matrix.total_width() * 2);
+            assert_eq!(img.height(), matrix.total_height() * 2);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+
+use printpdf::{Mm, PdfDocument};
+use regex::{Captures, Regex};
+use rusttype::{Font, Scale, point};
+
+use super::{Item, graphics};
+use crate::constants::BarcodeType;
+use crate::db::label_template::{LabelFieldType, LabelTemplate};
+use crate::error::{Error, Result};
+
+// 预览图及原始指令分辨率，8 点/mm 与常见 203dpi 热敏打印机一致
+pub const DOTS_PER_MM: f32 = 8.0;
+// 条码图片像素高度
+const BARCODE_HEIGHT: u32 = 80;
+pub const PT_TO_MM: f32 = 25.4 / 72.0;
+
+/// 变量替换后的标签元素，坐标含义与 `LabelField` 相同
+#[derive(Debug, Clone)]
+pub enum LabelElement {
+    Text {
+        text: String,
+        x: f32,
+        y: f32,
+        font_size: f32,
+    },
+    Barcode {
+        barcode_type: BarcodeType,
+        code: String,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+}
+
+impl Item {
+    fn variables(&self, store_name: &str) -> HashMap<&'static str, String> {
+        HashMap::from([
+            ("storeName", store_name.to_string()),
+            ("clothCode", self.code.clone()),
+            ("clothName", self.cloth_name.clone()),
+            ("clothColor", self.cloth_color.to_string()),
+            (
+                "flaws",
+                self.cloth_flaw
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            ),
+            ("sum", self.sum.to_string()),
+            ("num", self.num.to_string()),
+            ("receiveTime", self.time.clone()),
+            ("dueDate", self.due_date.clone().unwrap_or_default()),
+            ("customerName", self.client.name.clone()),
+            ("customerPhone", self.client.phone.clone()),
+            ("rackName", self.shelf.name.clone()),
+            ("rackPosition", self.shelf.position.to_string()),
+        ])
+    }
+}
+
+/// 替换内容中的 `{变量名}`，未知变量原样保留
+pub fn render_text(content: &str, vars: &HashMap<&'static str, String>) -> String {
+    let re = Regex::new(r"\{(\w+)\}").unwrap();
+    re.replace_all(content, |caps: &Captures| {
+        vars.get(&caps[1])
+            .cloned()
+            .unwrap_or_else(|| caps[0].to_string())
+    })
+    .into_owned()
+}
+
+/// 根据模板和衣物信息计算需要绘制的元素
+pub fn layout(template: &LabelTemplate, store_name: &str, item: &Item) -> Vec<LabelElement> {
+    let vars = item.variables(store_name);
+    template
+        .fields
+        .iter()
+        .filter_map(|field| {
+            let content = render_text(&field.content, &vars);
+            match field.field_type {
+                LabelFieldType::Text => Some(LabelElement::Text {
+                    text: content,
+                    x: field.x,
+                    y: field.y,
+                    font_size: field.font_size.unwrap_or(template.font_size),
+                }),
+                // 条码内容为空时跳过
+                LabelFieldType::Barcode if content.trim().is_empty() => None,
+                LabelFieldType::Barcode => Some(LabelElement::Barcode {
+                    barcode_type: template.barcode_type,
+                    code: content,
+                    x: field.x,
+                    y: field.y,
+                    width: field.width.unwrap_or_default(),
+                    height: field.height.unwrap_or_default(),
+                }),
+            }
+        })
+        .collect()
+}
+
+// 生成条码图片及实际绘制尺寸，二维码在区域内保持正方形
+fn barcode_image(
+    barcode_type: BarcodeType,
+    code: &str,
+    width: f32,
+    height: f32,
+) -> Result<(image::GrayImage, f32, f32)> {
+    let matrix = graphics::encode_barcode(barcode_type, code)?;
+    if matrix.is_linear() {
+        Ok((matrix.to_image(2, BARCODE_HEIGHT), width, height))
+    } else {
+        let size = width.min(height);
+        Ok((matrix.to_image(4, 0), size, size))
+    }
+}
+
+/// 按模板生成标签 PDF，返回文件名
+pub fn gen_label_pdf(template: &LabelTemplate, store_name: &str, item: &Item) -> Result<String> {
+    let (doc, page1, layer1) = PdfDocument::new(
+        "Label",
+        Mm(template.page_width),
+        Mm(template.page_height),
+        "Layer 1",
+    );
+    let current_layer = doc.get_page(page1).get_layer(layer1);
+    let font = doc.add_external_font(File::open(template.font_path())?)?;
+
+    for element in layout(template, store_name, item) {
+        match element {
+            LabelElement::Text {
+                text,
+                x,
+                y,
+                font_size,
+            } => current_layer.use_text(text, font_size, Mm(x), Mm(y), &font),
+            LabelElement::Barcode {
+                barcode_type,
+                code,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let (img, width, height) = barcode_image(barcode_type, &code, width, height)?;
+                let bytes = graphics::encode_png(&img)?;
+                graphics::add_png_to_layer(&current_layer, &bytes, x, y, width, height)?;
+            }
+        }
+    }
+
+    let pdf_file_name = format!("{}.pdf", item.code);
+    doc.save(&mut BufWriter::new(File::create(&pdf_file_name)?))?;
+    Ok(pdf_file_name)
+}
+
+/// 按模板生成标签预览图，返回 PNG 数据
+pub fn gen_label_png(template: &LabelTemplate, store_name: &str, item: &Item) -> Result<Vec<u8>> {
+    let width = (template.page_width * DOTS_PER_MM).round() as u32;
+    let height = (template.page_height * DOTS_PER_MM).round() as u32;
+    let mut canvas = image::GrayImage::from_pixel(width, height, image::Luma([255u8]));
+
+    let font_data = std::fs::read(template.font_path())?;
+    let font = Font::try_from_vec(font_data).ok_or(Error::internal("字体文件加载失败"))?;
+
+    for element in layout(template, store_name, item) {
+        match element {
+            LabelElement::Text {
+                text,
+                x,
+                y,
+                font_size,
+            } => {
+                let scale = Scale::uniform(font_size * PT_TO_MM * DOTS_PER_MM);
+                let start = point(x * DOTS_PER_MM, (template.page_height - y) * DOTS_PER_MM);
+                for glyph in font.layout(&text, scale, start) {
+                    if let Some(bb) = glyph.pixel_bounding_box() {
+                        glyph.draw(|gx, gy, v| {
+                            let px = gx as i32 + bb.min.x;
+                            let py = gy as i32 + bb.min.y;
+                            if px >= 0 && py >= 0 && (px as u32) < width && (py as u32) < height {
+                                let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+                                let intensity = ((1.0 - v) * 255.0) as u8;
+                                pixel.0[0] = pixel.0[0].min(intensity);
+                            }
+                        });
+                    }
+                }
+            }
+            LabelElement::Barcode {
+                barcode_type,
+                code,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let (barcode, bar_width, bar_height) =
+                    barcode_image(barcode_type, &code, width, height)?;
+                let resized = image::imageops::resize(
+                    &barcode,
+                    ((bar_width * DOTS_PER_MM) as u32).max(1),
+                    ((bar_height * DOTS_PER_MM) as u32).max(1),
+                    image::imageops::FilterType::Nearest,
+                );
+                image::imageops::overlay(
+                    &mut canvas,
+                    &resized,
+                    (x * DOTS_PER_MM) as i64,
+                    ((template.page_height - y - bar_height) * DOTS_PER_MM) as i64,
+                );
+            }
+        }
+    }
+
+    graphics::encode_png(&canvas)
+}
@@ -0,0 +1,262 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use super::{Item, PrintReceiptReq};
+use crate::constants::PrintJobType;
+use crate::db::Curd;
+use crate::db::orders::Order;
+use crate::db::print_job::PrintJob;
+use crate::db::printer::PrinterConfiguration;
+use crate::db::user_coupons::UserCoupon;
+use crate::error::{Error, ErrorKind, Result};
+use crate::local_users::LocalUser;
+use crate::state::AppState;
+use crate::utils;
+
+// 队列轮询间隔（秒）
+const POLL_INTERVAL: u64 = 5;
+// 重试基础间隔（毫秒），按已尝试次数线性递增
+const RETRY_DELAY: i64 = 30 * 1000;
+
+/// 标签打印任务内容
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelPayload {
+    pub items: Vec<Item>,
+    pub template_id: Option<i64>,
+}
+
+fn printer_type(job_type: PrintJobType) -> &'static str {
+    match job_type {
+        PrintJobType::Label => "business",
+        PrintJobType::Receipt => "receipt",
+    }
+}
+
+// 打印机未配置时重试没有意义，直接返回错误，不进入队列。
+// 补打时沿用原任务的打印机配置
+async fn enqueue(
+    pool: &Pool<Sqlite>,
+    store: &LocalUser,
+    job_type: PrintJobType,
+    ref_no: String,
+    payload: String,
+    printer: Option<PrinterConfiguration>,
+) -> Result<PrintJob> {
+    let store_id = store.id.ok_or(Error::unauthorized())?;
+    let printer = match printer {
+        Some(printer) => printer,
+        None => PrinterConfiguration::get_by_type(pool, printer_type(job_type))
+            .await?
+            .ok_or(Error::with_kind(ErrorKind::PrinterNotSet))?,
+    };
+    PrintJob::new(store_id, job_type, ref_no, &printer, payload)
+        .create(pool)
+        .await
+}
+
+/// 标签入队并立即打印一次
+pub async fn submit_labels(
+    pool: &Pool<Sqlite>,
+    store: &LocalUser,
+    payload: LabelPayload,
+    printer: Option<PrinterConfiguration>,
+) -> Result<PrintJob> {
+    if payload.items.is_empty() {
+        return Err(Error::bad_request("没有需要打印的衣物"));
+    }
+    let ref_no = payload
+        .items
+        .iter()
+        .map(|item| item.code.as_str())
+        .collect::<Vec<&str>>()
+        .join(",");
+    let payload = serde_json::to_string(&payload)?;
+    let job = enqueue(pool, store, PrintJobType::Label, ref_no, payload, printer).await?;
+    run_job(pool, store, job).await
+}
+
+/// 小票入队并立即打印一次
+pub async fn submit_receipt(
+    pool: &Pool<Sqlite>,
+    store: &LocalUser,
+    mut order: PrintReceiptReq,
+    printer: Option<PrinterConfiguration>,
+) -> Result<PrintJob> {
+    // 补打时沿用原小票记录的余额
+    if order.balance.is_none() {
+        if let (Some(store_id), Some(user_id)) = (store.id, order.order.user_id) {
+            order.balance = Some(UserCoupon::stored_value_balance(pool, store_id, user_id).await?);
+        }
+    }
+    if order.breakdown.is_none() {
+        if let Some(order_id) = order.order.order_id {
+            order.breakdown = match Order::get_price_breakdown(pool, order_id).await? {
+                Some(breakdown) => Some(breakdown),
+                None => Some(Order::price_breakdown(pool, &order.order, &order.clothes).await?),
+            };
+        }
+    }
+    let ref_no = order.order.order_number.clone().unwrap_or_default();
+    let payload = serde_json::to_string(&order)?;
+    let job = enqueue(pool, store, PrintJobType::Receipt, ref_no, payload, printer).await?;
+    run_job(pool, store, job).await
+}
+
+async fn execute(pool: &Pool<Sqlite>, store: &LocalUser, job: &PrintJob) -> Result<()> {
+    match job.job_type.unwrap_or_default() {
+        PrintJobType::Label => {
+            let payload: LabelPayload = serde_json::from_str(&job.payload)?;
+            super::print_labels(pool, store, job, &payload.items, payload.template_id).await
+        }
+        PrintJobType::Receipt => {
+            let order: PrintReceiptReq = serde_json::from_str(&job.payload)?;
+            super::print_receipt_order(pool, store, order, job).await
+        }
+    }
+}
+
+/// 执行一次打印任务并更新状态，失败且未超过最大次数时安排下次重试
+pub async fn run_job(pool: &Pool<Sqlite>, store: &LocalUser, job: PrintJob) -> Result<PrintJob> {
+    let job_id = job.job_id.ok_or(Error::bad_request("job_id is required"))?;
+
+    // 任务已被其他流程领取或已结束，直接返回当前状态
+    if PrintJob::claim(pool, job_id).await? {
+        let attempts = job.attempts + 1;
+        match execute(pool, store, &job).await {
+            Ok(()) => PrintJob::complete(pool, job_id).await?,
+            Err(e) => {
+                let error = format!("{:?}: {}", e.kind(), e.details().unwrap_or_default());
+                let next_retry_time = (attempts < job.max_attempts)
+                    .then(|| utils::get_timestamp() + RETRY_DELAY * attempts);
+                tracing::warn!(
+                    "print job {} failed ({} attempts): {}",
+                    job_id,
+                    attempts,
+                    error
+                );
+                PrintJob::fail(pool, job_id, &error, next_retry_time).await?;
+            }
+        }
+    }
+
+    PrintJob::get_by_id(pool, job_id)
+        .await?
+        .ok_or(Error::not_found("打印任务不存在"))
+}
+
+/// 打印队列后台任务，定时执行到期的待打印任务
+#[derive(Debug, Clone)]
+pub struct PrintQueueManager {
+    task_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl PrintQueueManager {
+    pub fn new() -> Self {
+        Self {
+            task_handle: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// 启动打印队列任务
+    pub async fn start<R: Runtime>(&self, app_handle: AppHandle<R>) -> Result<()> {
+        let state = app_handle.state::<AppState>();
+        let pool = state.pool.clone();
+        let store = state.get_user_info().await.ok_or(Error::unauthorized())?;
+        let store_id = store.id.ok_or(Error::unauthorized())?;
+        let task_handle = self.task_handle.clone();
+
+        // 先停止已存在的任务
+        self.stop().await;
+
+        let count = PrintJob::reset_interrupted(&pool, store_id).await?;
+        if count > 0 {
+            tracing::info!("{} interrupted print jobs requeued", count);
+        }
+
+        let handle = tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(tokio::time::Duration::from_secs(POLL_INTERVAL));
+            loop {
+                interval.tick().await;
+                let jobs = match PrintJob::list_due(&pool, store_id).await {
+                    Ok(jobs) => jobs,
+                    Err(e) => {
+                        tracing::error!("获取打印任务失败: {}", e);
+                        continue;
+                    }
+                };
+                for job in jobs {
+                    match run_job(&pool, &store, job).await {
+                        Ok(job) => {
+                            if let Err(e) = app_handle.emit("app://print-job", &job) {
+                                tracing::error!("Failed to emit print job event: {:?}", e);
+                            }
+                        }
+                        Err(e) => tracing::error!("执行打印任务失败: {}", e),
+                    }
+                }
+            }
+        });
+
+        // 保存任务句柄
+        let mut handle_guard = task_handle.lock().await;
+        *handle_guard = Some(handle);
+
+        Ok(())
+    }
+
+    /// 停止打印队列任务
+    pub async fn stop(&self) {
+        let mut handle_guard = self.task_handle.lock().await;
+        if let Some(handle) = handle_guard.take() {
+            handle.abort();
+        }
+    }
+}
+
+/// 失败或已取消的任务重新打印
+#[tauri::command]
+pub async fn retry_print_job(state: State<'_, AppState>, id: i64) -> Result<PrintJob> {
+    let store = state.get_user_info().await.ok_or(Error::unauthorized())?;
+    let store_id = store.id.ok_or(Error::unauthorized())?;
+    if !PrintJob::requeue(&state.pool, store_id, id).await? {
+        return Err(Error::bad_request("只有失败或已取消的任务可以重试"));
+    }
+    let job = PrintJob::get_for_store(&state.pool, store_id, id).await?;
+    run_job(&state.pool, &store, job).await
+}
+
+/// 按历史任务补打，codes 不为空时仅补打标签任务中指定编码的衣物
+#[tauri::command]
+pub async fn reprint_print_job(
+    state: State<'_, AppState>,
+    id: i64,
+    codes: Option<Vec<String>>,
+) -> Result<PrintJob> {
+    let store = state.get_user_info().await.ok_or(Error::unauthorized())?;
+    let store_id = store.id.ok_or(Error::unauthorized())?;
+    let job = PrintJob::get_for_store(&state.pool, store_id, id).await?;
+    let job_type = job.job_type.unwrap_or_default();
+    let printer =
+        PrinterConfiguration::get_for_job(&state.pool, printer_type(job_type), &job).await?;
+
+    match job_type {
+        PrintJobType::Label => {
+            let mut payload: LabelPayload = serde_json::from_str(&job.payload)?;
+            if let Some(codes) = codes.filter(|codes| !codes.is_empty()) {
+                payload.items.retain(|item| codes.contains(&item.code));
+            }
+            submit_labels(&state.pool, &store, payload, Some(printer)).await
+        }
+        PrintJobType::Receipt => {
+            let order: PrintReceiptReq = serde_json::from_str(&job.payload)?;
+            submit_receipt(&state.pool, &store, order, Some(printer)).await
+        }
+    }
+}
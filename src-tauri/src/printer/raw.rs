@@ -0,0 +1,48 @@
+use tokio::io::AsyncWriteExt;
+
+use crate::db::printer::PrinterConfiguration;
+use crate::error::{Error, ErrorKind, Result};
+
+/// 热敏打印机中文字库均为 GB18030/GBK 编码
+pub fn encode_gbk(text: &str) -> Vec<u8> {
+    let (bytes, _, _) = encoding_rs::GB18030.encode(text);
+    bytes.into_owned()
+}
+
+/// 将原始指令发送到打印机配置的输出目标
+pub async fn send(config: &PrinterConfiguration, bytes: &[u8]) -> Result<()> {
+    let target = config.target.as_deref().unwrap_or_default().trim();
+
+    if let Some(addr) = target.strip_prefix("tcp://") {
+        tracing::debug!("send {} bytes to {}", bytes.len(), addr);
+        let mut stream = tokio::net::TcpStream::connect(addr)
+            .await
+            .map_err(|e| Error::with_details(ErrorKind::PrinterNotFound, e.to_string()))?;
+        stream.write_all(bytes).await?;
+        stream.flush().await?;
+        return Ok(());
+    }
+
+    if let Some(path) = target.strip_prefix("file://") {
+        tracing::debug!("write {} bytes to {}", bytes.len(), path);
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(bytes).await?;
+        return Ok(());
+    }
+
+    let name = if target.is_empty() {
+        config.name.as_str()
+    } else {
+        target
+    };
+    let printer =
+        printers::get_printer_by_name(name).ok_or(Error::with_kind(ErrorKind::PrinterNotFound))?;
+    printer
+        .print(bytes, None)
+        .map_err(|e| Error::with_details(ErrorKind::PrintError, e))?;
+    Ok(())
+}
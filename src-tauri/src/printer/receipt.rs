@@ -0,0 +1,749 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::BufWriter;
+
+use image::GrayImage;
+use printpdf::{Mm, PdfDocument};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use tauri::State;
+
+use super::escpos::{Align, EscPos, text_width, wrap_lines};
+use super::label::PT_TO_MM;
+use super::{PrintReceiptReq, graphics};
+use crate::constants::BarcodeType;
+use crate::db::configs::Config;
+use crate::db::pricing_rules::PriceLine;
+use crate::db::printer::PrinterConfiguration;
+use crate::db::{Curd, Validator};
+use crate::drying_rack::DryingRack;
+use crate::error::{Error, Result};
+use crate::local_users::LocalUser;
+use crate::order_clothes::OrderCloth;
+use crate::state::AppState;
+use crate::tags::Tag;
+use crate::utils;
+
+// 小票设置在 configs 表中的键名前缀
+const CONFIG_PREFIX: &str = "receipt.";
+const RECEIPT_FONT_PATH: &str = "MSYH.TTC";
+// PDF 小票页边距(mm)
+const MARGIN: f32 = 4.0;
+// 条码高度(mm)及像素高度
+const BARCODE_HEIGHT: f32 = 10.0;
+const BARCODE_PX_HEIGHT: u32 = 80;
+// 二维码、logo 最多占可打印宽度的比例
+const QR_RATIO: f32 = 0.5;
+const LOGO_RATIO: f32 = 0.6;
+
+/// 小票纸张规格
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PaperSize {
+    #[default]
+    #[serde(rename = "58mm")]
+    Mm58,
+    #[serde(rename = "80mm")]
+    Mm80,
+    A5,
+}
+
+impl PaperSize {
+    fn parse(value: &str) -> Self {
+        match value.trim() {
+            "80mm" | "80" => Self::Mm80,
+            "A5" | "a5" => Self::A5,
+            _ => Self::Mm58,
+        }
+    }
+
+    /// 纸张宽度(mm)
+    pub fn width(self) -> f32 {
+        match self {
+            Self::Mm58 => 58.0,
+            Self::Mm80 => 80.0,
+            Self::A5 => 148.0,
+        }
+    }
+
+    /// 热敏打印机每行可打印的半角字符数，A5 仅用于 PDF，热敏机按 80mm 处理
+    pub fn columns(self) -> usize {
+        match self {
+            Self::Mm58 => 32,
+            Self::Mm80 | Self::A5 => 48,
+        }
+    }
+
+    /// 热敏打印机可打印宽度(点)
+    pub fn dots(self) -> u32 {
+        match self {
+            Self::Mm58 => 384,
+            Self::Mm80 | Self::A5 => 576,
+        }
+    }
+
+    fn font_size(self) -> f32 {
+        match self {
+            Self::Mm58 | Self::Mm80 => 8.0,
+            Self::A5 => 10.0,
+        }
+    }
+}
+
+impl Display for PaperSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaperSize::Mm58 => write!(f, "58mm"),
+            PaperSize::Mm80 => write!(f, "80mm"),
+            PaperSize::A5 => write!(f, "A5"),
+        }
+    }
+}
+
+/// 小票设置，每项保存为 configs 表中 `receipt.` 开头的一条参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(default)]
+pub struct ReceiptSettings {
+    pub paper_size: PaperSize,
+    /// 页眉，多行以换行分隔
+    pub header: String,
+    /// 页脚，多行以换行分隔
+    pub footer: String,
+    /// 门店 logo 图片路径
+    pub logo: String,
+    pub show_flaws: bool,
+    /// 订单状态查询链接，`{orderNumber}` 替换为订单号，为空时不打印二维码
+    pub qr_url: String,
+    /// 打印会员支付后的储值余额
+    pub show_balance: bool,
+    /// 大字号打印取件码
+    pub show_pickup_code: bool,
+}
+
+impl Default for ReceiptSettings {
+    fn default() -> Self {
+        Self {
+            paper_size: PaperSize::default(),
+            header: String::new(),
+            footer: String::new(),
+            logo: String::new(),
+            show_flaws: true,
+            qr_url: String::new(),
+            show_balance: false,
+            show_pickup_code: false,
+        }
+    }
+}
+
+impl Validator for ReceiptSettings {
+    fn validate(&self) -> Result<()> {
+        if !self.logo.is_empty() && !std::path::Path::new(&self.logo).exists() {
+            return Err(Error::bad_request("logo 图片不存在"));
+        }
+
+        if !self.qr_url.is_empty() && !self.qr_url.starts_with("http") {
+            return Err(Error::bad_request("二维码链接需以 http 开头"));
+        }
+
+        Ok(())
+    }
+}
+
+impl ReceiptSettings {
+    // (键名, 参数名称, 参数值)
+    fn entries(&self) -> Vec<(&'static str, &'static str, String)> {
+        vec![
+            ("paperSize", "小票-纸张规格", self.paper_size.to_string()),
+            ("header", "小票-页眉", self.header.clone()),
+            ("footer", "小票-页脚", self.footer.clone()),
+            ("logo", "小票-门店logo", self.logo.clone()),
+            ("showFlaws", "小票-打印瑕疵", self.show_flaws.to_string()),
+            ("qrUrl", "小票-订单二维码链接", self.qr_url.clone()),
+            (
+                "showBalance",
+                "小票-打印会员余额",
+                self.show_balance.to_string(),
+            ),
+            (
+                "showPickupCode",
+                "小票-大字号取件码",
+                self.show_pickup_code.to_string(),
+            ),
+        ]
+    }
+
+    pub async fn load(pool: &Pool<Sqlite>) -> Result<Self> {
+        let configs = Config {
+            config_key: Some(CONFIG_PREFIX.to_string()),
+            ..Default::default()
+        }
+        .get_all(pool)
+        .await?;
+
+        let mut settings = Self::default();
+        for config in configs {
+            let value = config.config_value.unwrap_or_default();
+            match config
+                .config_key
+                .as_deref()
+                .and_then(|key| key.strip_prefix(CONFIG_PREFIX))
+            {
+                Some("paperSize") => settings.paper_size = PaperSize::parse(&value),
+                Some("header") => settings.header = value,
+                Some("footer") => settings.footer = value,
+                Some("logo") => settings.logo = value,
+                Some("showFlaws") => settings.show_flaws = utils::to_bool(value),
+                Some("qrUrl") => settings.qr_url = value,
+                Some("showBalance") => settings.show_balance = utils::to_bool(value),
+                Some("showPickupCode") => settings.show_pickup_code = utils::to_bool(value),
+                _ => {}
+            }
+        }
+        Ok(settings)
+    }
+
+    pub async fn save(&self, pool: &Pool<Sqlite>) -> Result<()> {
+        for (key, name, value) in self.entries() {
+            Config::set_value(pool, &format!("{CONFIG_PREFIX}{key}"), name, value).await?;
+        }
+        Ok(())
+    }
+}
+
+// 小票中每件衣物的展示信息
+pub struct ClothDetail {
+    color: String,
+    service_type: String,
+    service_requirement: String,
+    flaw_names: Vec<String>,
+    hanger_info: String,
+}
+
+// 查询小票需要的颜色、瑕疵、衣挂名称
+pub async fn load_cloth_details(
+    pool: &Pool<Sqlite>,
+    clothes: &[OrderCloth],
+) -> Result<Vec<ClothDetail>> {
+    let mut cloth_details = Vec::with_capacity(clothes.len());
+    for cloth in clothes {
+        let color = cloth.clothing_color.unwrap_or_default();
+        let color = Tag::get_by_id(pool, color)
+            .await?
+            .unwrap_or_default()
+            .tag_name
+            .unwrap_or_default();
+        let service_type = cloth
+            .service_type
+            .as_ref()
+            .map_or(String::new(), |s| s.to_string());
+        let service_requirement = cloth
+            .service_requirement
+            .as_ref()
+            .map_or(String::new(), |s| s.to_string());
+
+        // 获取瑕疵名称
+        let mut flaw_names = Vec::new();
+        if let Some(flaw) = &cloth.clothing_flaw {
+            for flaw_id in flaw.split(',') {
+                if let Ok(id) = flaw_id.trim().parse::<i64>() {
+                    if let Ok(Some(flaw_tag)) = Tag::get_by_id(pool, id).await {
+                        if let Some(name) = flaw_tag.tag_name {
+                            flaw_names.push(name);
+                        }
+                    }
+                }
+            }
+        }
+
+        // 获取衣挂信息
+        let hanger_info = if cloth.hanger_name.is_none()
+            || cloth.hanger_name.as_deref().unwrap_or("").is_empty()
+        {
+            if let Some(location_code) = cloth.hang_location_code {
+                // 根据location_code查询衣挂信息
+                if let Ok(Some(rack)) = DryingRack::get_by_id(pool, location_code).await {
+                    rack.name.unwrap_or_default()
+                } else {
+                    String::new()
+                }
+            } else {
+                String::new()
+            }
+        } else {
+            cloth.hanger_name.as_deref().unwrap_or("").to_string()
+        };
+
+        cloth_details.push(ClothDetail {
+            color,
+            service_type,
+            service_requirement,
+            flaw_names,
+            hanger_info,
+        });
+    }
+    Ok(cloth_details)
+}
+
+fn cloth_name(cloth: &OrderCloth) -> String {
+    cloth
+        .cloth_info
+        .as_ref()
+        .and_then(|info| info.title.as_deref())
+        .unwrap_or("未知")
+        .to_string()
+}
+
+/// 小票中的一行内容，PDF 与 ESC/POS 共用同一份排版
+enum ReceiptLine {
+    /// size 为放大倍数
+    Text {
+        text: String,
+        align: Align,
+        size: u8,
+        bold: bool,
+    },
+    /// 超出列数时自动换行
+    Wrap(String),
+    /// 左右两端对齐
+    LeftRight(String, String),
+    Separator,
+    Barcode(String),
+    QrCode(String),
+    Image(GrayImage),
+}
+
+impl ReceiptLine {
+    fn text(text: impl Into<String>) -> Self {
+        Self::Text {
+            text: text.into(),
+            align: Align::Left,
+            size: 1,
+            bold: false,
+        }
+    }
+
+    fn center(text: impl Into<String>, size: u8, bold: bool) -> Self {
+        Self::Text {
+            text: text.into(),
+            align: Align::Center,
+            size,
+            bold,
+        }
+    }
+}
+
+// 根据设置生成小票内容
+fn build_lines(
+    settings: &ReceiptSettings,
+    store: &LocalUser,
+    order: &PrintReceiptReq,
+    cloth_details: &[ClothDetail],
+) -> Vec<ReceiptLine> {
+    let mut lines = Vec::new();
+    let order_number = order.order.order_number.as_deref().unwrap_or("");
+
+    // logo 加载失败不影响打印
+    if !settings.logo.is_empty() {
+        match graphics::load_gray(&settings.logo) {
+            Ok(logo) => lines.push(ReceiptLine::Image(logo)),
+            Err(e) => tracing::warn!("load receipt logo failed: {}", e),
+        }
+    }
+
+    lines.push(ReceiptLine::center(
+        store.store_name.as_deref().unwrap_or_default(),
+        2,
+        true,
+    ));
+    for header in settings.header.lines().filter(|l| !l.trim().is_empty()) {
+        lines.push(ReceiptLine::center(header, 1, false));
+    }
+
+    lines.push(ReceiptLine::text(format!("订单号: {}", order_number)));
+    let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    lines.push(ReceiptLine::text(format!("打印时间: {}", now)));
+
+    if settings.show_pickup_code {
+        if let Some(code) = order.order.pickup_code.as_deref().filter(|c| !c.is_empty()) {
+            lines.push(ReceiptLine::center(format!("取件码: {}", code), 2, true));
+        }
+    }
+
+    if !order_number.is_empty() {
+        lines.push(ReceiptLine::Barcode(order_number.to_string()));
+    }
+
+    lines.push(ReceiptLine::Separator);
+    for (cloth, detail) in order.clothes.iter().zip(cloth_details) {
+        let hanger = format!(
+            "{}-{}",
+            detail.hanger_info,
+            cloth.hanger_number.unwrap_or_default()
+        );
+        lines.push(ReceiptLine::LeftRight(
+            format!("{} {}", cloth_name(cloth), detail.color),
+            hanger,
+        ));
+        lines.push(ReceiptLine::text(format!(
+            "衣物编码: {}",
+            cloth.hang_cloth_code.as_deref().unwrap_or("")
+        )));
+        if settings.show_flaws && !detail.flaw_names.is_empty() {
+            lines.push(ReceiptLine::Wrap(format!(
+                "瑕疵: {}",
+                detail.flaw_names.join("、")
+            )));
+        }
+        if !detail.service_type.is_empty() {
+            lines.push(ReceiptLine::text(format!(
+                "服务类型: {}",
+                detail.service_type
+            )));
+        }
+        if !detail.service_requirement.is_empty() {
+            lines.push(ReceiptLine::text(format!(
+                "服务要求: {}",
+                detail.service_requirement
+            )));
+        }
+        let price = cloth
+            .price_value
+            .map(|p| format!("¥{:.2}", p))
+            .unwrap_or_default();
+        lines.push(ReceiptLine::LeftRight("洗护价:".to_string(), price));
+        let item = order.breakdown.as_ref().and_then(|breakdown| {
+            breakdown
+                .items
+                .iter()
+                .find(|item| item.cloth_id.is_some() && item.cloth_id == cloth.cloth_id)
+        });
+        if let Some(item) = item {
+            for line in &item.lines {
+                lines.push(price_line(line));
+            }
+        }
+        lines.push(ReceiptLine::Separator);
+    }
+
+    if let Some(breakdown) = &order.breakdown {
+        for line in &breakdown.lines {
+            lines.push(price_line(line));
+        }
+    }
+
+    lines.push(ReceiptLine::LeftRight(
+        format!("总金额: ¥{:.2}", order.mount),
+        format!("总件数:{}", order.clothes.len()),
+    ));
+    lines.push(ReceiptLine::text(format!(
+        "付款方式: {}",
+        order.payment_method.as_deref().unwrap_or("未知")
+    )));
+    if settings.show_balance {
+        if let Some(balance) = order.balance {
+            lines.push(ReceiptLine::text(format!("会员余额: ¥{:.2}", balance)));
+        }
+    }
+    lines.push(ReceiptLine::text(format!(
+        "客户: {}",
+        order.order.nick_name.as_deref().unwrap_or("")
+    )));
+    lines.push(ReceiptLine::text(format!(
+        "电话: {}",
+        order.order.phonenumber.as_deref().unwrap_or("")
+    )));
+    lines.push(ReceiptLine::Separator);
+    lines.push(ReceiptLine::text(format!(
+        "服务热线: {}",
+        store.owner_phone.as_deref().unwrap_or("")
+    )));
+
+    if !settings.qr_url.is_empty() && !order_number.is_empty() {
+        lines.push(ReceiptLine::QrCode(
+            settings.qr_url.replace("{orderNumber}", order_number),
+        ));
+        lines.push(ReceiptLine::center("扫码查看订单进度", 1, false));
+    }
+
+    for footer in settings.footer.lines().filter(|l| !l.trim().is_empty()) {
+        lines.push(ReceiptLine::center(footer, 1, false));
+    }
+
+    lines
+}
+
+fn price_line(line: &PriceLine) -> ReceiptLine {
+    let sign = if line.amount < 0.0 { "-" } else { "+" };
+    ReceiptLine::LeftRight(
+        format!("{}:", line.label),
+        format!("{}¥{:.2}", sign, line.amount.abs()),
+    )
+}
+
+// PDF 小票的排版参数
+struct PdfMetrics {
+    width: f32,
+    font_size: f32,
+    // 半角字符宽度(mm)
+    char_width: f32,
+    line_height: f32,
+    columns: usize,
+}
+
+impl PdfMetrics {
+    fn new(paper: PaperSize) -> Self {
+        let width = paper.width();
+        let font_size = paper.font_size();
+        let char_width = font_size * PT_TO_MM * 0.5;
+        Self {
+            width,
+            font_size,
+            char_width,
+            line_height: font_size * PT_TO_MM * 1.6,
+            columns: ((width - MARGIN * 2.0) / char_width) as usize,
+        }
+    }
+
+    fn printable_width(&self) -> f32 {
+        self.width - MARGIN * 2.0
+    }
+
+    fn qr_size(&self) -> f32 {
+        (self.printable_width() * QR_RATIO).min(30.0)
+    }
+
+    fn image_size(&self, img: &GrayImage) -> (f32, f32) {
+        let width = (self.printable_width() * LOGO_RATIO).min(img.width() as f32 / 8.0);
+        (
+            width,
+            width * img.height() as f32 / img.width().max(1) as f32,
+        )
+    }
+
+    fn height(&self, line: &ReceiptLine) -> f32 {
+        match line {
+            ReceiptLine::Text { size, .. } => self.line_height * *size as f32,
+            ReceiptLine::Wrap(text) => {
+                self.line_height * wrap_lines(text, self.columns).len().max(1) as f32
+            }
+            ReceiptLine::LeftRight(..) | ReceiptLine::Separator => self.line_height,
+            ReceiptLine::Barcode(_) => BARCODE_HEIGHT + self.line_height,
+            ReceiptLine::QrCode(_) => self.qr_size() + self.line_height * 0.5,
+            ReceiptLine::Image(img) => self.image_size(img).1 + self.line_height * 0.5,
+        }
+    }
+
+    fn text_x(&self, text: &str, align: Align, size: u8) -> f32 {
+        let text_mm = text_width(text) as f32 * self.char_width * size as f32;
+        match align {
+            Align::Left => MARGIN,
+            Align::Center => ((self.width - text_mm) / 2.0).max(0.0),
+            Align::Right => (self.width - MARGIN - text_mm).max(0.0),
+        }
+    }
+}
+
+/// 生成小票 PDF，返回文件名
+pub fn gen_receipt_pdf(
+    settings: &ReceiptSettings,
+    store: &LocalUser,
+    order: &PrintReceiptReq,
+    cloth_details: &[ClothDetail],
+) -> Result<String> {
+    let lines = build_lines(settings, store, order, cloth_details);
+    let metrics = PdfMetrics::new(settings.paper_size);
+    let height = lines.iter().map(|line| metrics.height(line)).sum::<f32>() + MARGIN * 2.0;
+
+    let (doc, page1, layer1) =
+        PdfDocument::new("Receipt", Mm(metrics.width), Mm(height), "Layer 1");
+    let current_layer = doc.get_page(page1).get_layer(layer1);
+    let font = doc.add_external_font(File::open(RECEIPT_FONT_PATH)?)?;
+    let font_size = metrics.font_size;
+
+    // y 为当前行顶部位置，自上而下排版
+    let mut y = height - MARGIN;
+    for line in &lines {
+        let line_height = metrics.height(line);
+        // 文字基线位于行底部稍上方
+        let baseline = y - line_height + metrics.line_height * 0.3;
+        match line {
+            ReceiptLine::Text {
+                text, align, size, ..
+            } => {
+                let x = metrics.text_x(text, *align, *size);
+                current_layer.use_text(text, font_size * *size as f32, Mm(x), Mm(baseline), &font);
+            }
+            ReceiptLine::Wrap(text) => {
+                let mut line_y = y - metrics.line_height + metrics.line_height * 0.3;
+                for part in wrap_lines(text, metrics.columns) {
+                    current_layer.use_text(part, font_size, Mm(MARGIN), Mm(line_y), &font);
+                    line_y -= metrics.line_height;
+                }
+            }
+            ReceiptLine::LeftRight(left, right) => {
+                current_layer.use_text(left, font_size, Mm(MARGIN), Mm(baseline), &font);
+                let x = metrics.text_x(right, Align::Right, 1);
+                current_layer.use_text(right, font_size, Mm(x), Mm(baseline), &font);
+            }
+            ReceiptLine::Separator => {
+                current_layer.use_text(
+                    "-".repeat(metrics.columns),
+                    font_size,
+                    Mm(MARGIN),
+                    Mm(baseline),
+                    &font,
+                );
+            }
+            ReceiptLine::Barcode(code) => {
+                // 与 ESC/POS 小票一致使用 Code128
+                let matrix = graphics::encode_barcode(BarcodeType::Code128, code)?;
+                let png = graphics::encode_png(&matrix.to_image(2, BARCODE_PX_HEIGHT))?;
+                let width = metrics.printable_width() * 0.8;
+                graphics::add_png_to_layer(
+                    &current_layer,
+                    &png,
+                    (metrics.width - width) / 2.0,
+                    y - BARCODE_HEIGHT,
+                    width,
+                    BARCODE_HEIGHT,
+                )?;
+            }
+            ReceiptLine::QrCode(data) => {
+                let matrix = graphics::encode_barcode(BarcodeType::QrCode, data)?;
+                let png = graphics::encode_png(&matrix.to_image(8, 0))?;
+                let size = metrics.qr_size();
+                graphics::add_png_to_layer(
+                    &current_layer,
+                    &png,
+                    (metrics.width - size) / 2.0,
+                    y - size,
+                    size,
+                    size,
+                )?;
+            }
+            ReceiptLine::Image(img) => {
+                let png = graphics::encode_png(img)?;
+                let (width, img_height) = metrics.image_size(img);
+                graphics::add_png_to_layer(
+                    &current_layer,
+                    &png,
+                    (metrics.width - width) / 2.0,
+                    y - img_height,
+                    width,
+                    img_height,
+                )?;
+            }
+        }
+        y -= line_height;
+    }
+
+    let pdf_file_name = format!(
+        "receipt_{}.pdf",
+        order.order.order_number.as_deref().unwrap_or("")
+    );
+    doc.save(&mut BufWriter::new(File::create(&pdf_file_name)?))?;
+    Ok(pdf_file_name)
+}
+
+/// 生成 ESC/POS 小票指令
+pub fn gen_receipt_escpos(
+    settings: &ReceiptSettings,
+    store: &LocalUser,
+    order: &PrintReceiptReq,
+    cloth_details: &[ClothDetail],
+    printer: &PrinterConfiguration,
+) -> Vec<u8> {
+    let paper = settings.paper_size;
+    let columns = paper.columns();
+    let mut pos = EscPos::new();
+
+    for line in build_lines(settings, store, order, cloth_details) {
+        match line {
+            ReceiptLine::Text {
+                text,
+                align,
+                size,
+                bold,
+            } => {
+                pos.align(align)
+                    .size(size, size)
+                    .bold(bold)
+                    .line(&text)
+                    .bold(false)
+                    .size(1, 1)
+                    .align(Align::Left);
+            }
+            ReceiptLine::Wrap(text) => {
+                pos.wrap(&text, columns);
+            }
+            ReceiptLine::LeftRight(left, right) => {
+                pos.line_lr(&left, &right, columns);
+            }
+            ReceiptLine::Separator => {
+                pos.separator(columns);
+            }
+            ReceiptLine::Barcode(code) => {
+                pos.align(Align::Center)
+                    .barcode128(&code)
+                    .align(Align::Left);
+            }
+            ReceiptLine::QrCode(data) => {
+                pos.align(Align::Center)
+                    .qr_code(&data, 6)
+                    .align(Align::Left);
+            }
+            ReceiptLine::Image(img) => {
+                // 按纸张宽度等比缩放
+                let max_width = (paper.dots() as f32 * LOGO_RATIO) as u32;
+                let img = if img.width() > max_width {
+                    let height = img.height() * max_width / img.width().max(1);
+                    image::imageops::resize(
+                        &img,
+                        max_width,
+                        height.max(1),
+                        image::imageops::FilterType::Triangle,
+                    )
+                } else {
+                    img
+                };
+                pos.align(Align::Center).image(&img).align(Align::Left);
+            }
+        }
+    }
+    pos.feed(3);
+
+    if printer.cut_paper {
+        pos.cut();
+    }
+    if printer.open_drawer {
+        pos.open_drawer();
+    }
+    pos.build()
+}
+
+#[tauri::command]
+pub async fn get_receipt_settings(state: State<'_, AppState>) -> Result<ReceiptSettings> {
+    ReceiptSettings::load(&state.pool).await
+}
+
+#[tauri::command]
+pub async fn save_receipt_settings(
+    state: State<'_, AppState>,
+    settings: ReceiptSettings,
+) -> Result<()> {
+    settings.validate()?;
+    settings.save(&state.pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paper_size_round_trip() {
+        for paper in [PaperSize::Mm58, PaperSize::Mm80, PaperSize::A5] {
+            assert_eq!(PaperSize::parse(&paper.to_string()), paper);
+        }
+        assert_eq!(PaperSize::parse(""), PaperSize::Mm58);
+    }
+}
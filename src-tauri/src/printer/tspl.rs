@@ -0,0 +1,102 @@
+use super::graphics;
+use super::label::{DOTS_PER_MM, LabelElement, PT_TO_MM};
+use super::raw::encode_gbk;
+use crate::constants::BarcodeType;
+use crate::db::label_template::LabelTemplate;
+use crate::error::Result;
+
+// TSS24.BF2 为简体中文字库，字高 24 点
+const FONT_NAME: &str = "TSS24.BF2";
+const FONT_DOTS: f32 = 24.0;
+
+fn escape(text: &str) -> String {
+    text.replace('"', "\\[\"]")
+}
+
+/// 生成 TSPL 标签指令，坐标由 mm（左下角原点）换算为点（左上角原点）
+pub fn gen_label(template: &LabelTemplate, elements: &[LabelElement]) -> Result<Vec<u8>> {
+    let mut buf = format!(
+        "SIZE {} mm,{} mm\r\nGAP 2 mm,0 mm\r\nDIRECTION 1\r\nCLS\r\n",
+        template.page_width, template.page_height
+    )
+    .into_bytes();
+
+    for element in elements {
+        let cmd = match element {
+            LabelElement::Text {
+                text,
+                x,
+                y,
+                font_size,
+            } => {
+                let multiple = (font_size * PT_TO_MM * DOTS_PER_MM / FONT_DOTS)
+                    .round()
+                    .max(1.) as u32;
+                let top = ((template.page_height - y) * DOTS_PER_MM - FONT_DOTS * multiple as f32)
+                    .max(0.);
+                format!(
+                    "TEXT {},{},\"{FONT_NAME}\",0,{multiple},{multiple},\"{}\"\r\n",
+                    (x * DOTS_PER_MM) as u32,
+                    top as u32,
+                    escape(text)
+                )
+            }
+            LabelElement::Barcode {
+                barcode_type,
+                code,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let matrix = graphics::encode_barcode(*barcode_type, code)?;
+                let left = (x * DOTS_PER_MM) as u32;
+                match barcode_type {
+                    BarcodeType::Code39 | BarcodeType::Code128 => {
+                        let top = ((template.page_height - y - height) * DOTS_PER_MM).max(0.);
+                        let (symbology, modules) = match barcode_type {
+                            // Code39 宽窄比为 3:1，每个字符约 16 个窄条宽度
+                            BarcodeType::Code39 => ("39", (code.chars().count() + 2) * 16),
+                            _ => ("128", matrix.width as usize),
+                        };
+                        let narrow = ((width * DOTS_PER_MM) as usize / modules).max(1);
+                        let wide = if *barcode_type == BarcodeType::Code39 {
+                            narrow * 3
+                        } else {
+                            narrow
+                        };
+                        format!(
+                            "BARCODE {left},{},\"{symbology}\",{},0,0,{narrow},{wide},\"{}\"\r\n",
+                            top as u32,
+                            (height * DOTS_PER_MM) as u32,
+                            escape(code)
+                        )
+                    }
+                    BarcodeType::QrCode | BarcodeType::DataMatrix => {
+                        let size = width.min(*height);
+                        let top = ((template.page_height - y - size) * DOTS_PER_MM).max(0.);
+                        let dots = (size * DOTS_PER_MM) as u32;
+                        let cell = (dots / matrix.total_width()).clamp(1, 10);
+                        if *barcode_type == BarcodeType::QrCode {
+                            format!(
+                                "QRCODE {left},{},M,{cell},A,0,\"{}\"\r\n",
+                                top as u32,
+                                escape(code)
+                            )
+                        } else {
+                            format!(
+                                "DMATRIX {left},{},{dots},{dots},x{cell},\"{}\"\r\n",
+                                top as u32,
+                                escape(code)
+                            )
+                        }
+                    }
+                }
+            }
+        };
+        buf.extend(encode_gbk(&cmd));
+    }
+
+    buf.extend_from_slice(b"PRINT 1,1\r\n");
+    Ok(buf)
+}
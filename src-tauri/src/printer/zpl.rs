@@ -0,0 +1,93 @@
+use super::graphics;
+use super::label::{DOTS_PER_MM, LabelElement, PT_TO_MM};
+use crate::constants::BarcodeType;
+use crate::db::label_template::LabelTemplate;
+use crate::error::Result;
+
+// ^ 和 ~ 为 ZPL 控制符，不能出现在字段数据中
+fn escape(text: &str) -> String {
+    text.replace(['^', '~'], " ")
+}
+
+/// 生成 ZPL 标签指令，使用 UTF-8 编码（^CI28），中文需打印机已安装对应字库
+pub fn gen_label(template: &LabelTemplate, elements: &[LabelElement]) -> Result<Vec<u8>> {
+    let mut cmd = format!(
+        "^XA^CI28^PW{}^LL{}\n",
+        (template.page_width * DOTS_PER_MM) as u32,
+        (template.page_height * DOTS_PER_MM) as u32
+    );
+
+    for element in elements {
+        match element {
+            LabelElement::Text {
+                text,
+                x,
+                y,
+                font_size,
+            } => {
+                let dots = (font_size * PT_TO_MM * DOTS_PER_MM).max(1.);
+                let top = ((template.page_height - y) * DOTS_PER_MM - dots).max(0.);
+                cmd.push_str(&format!(
+                    "^FO{},{}^A0N,{},{}^FD{}^FS\n",
+                    (x * DOTS_PER_MM) as u32,
+                    top as u32,
+                    dots as u32,
+                    dots as u32,
+                    escape(text)
+                ));
+            }
+            LabelElement::Barcode {
+                barcode_type,
+                code,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let matrix = graphics::encode_barcode(*barcode_type, code)?;
+                let left = (x * DOTS_PER_MM) as u32;
+                let code = escape(code);
+                match barcode_type {
+                    BarcodeType::Code39 | BarcodeType::Code128 => {
+                        let modules = match barcode_type {
+                            BarcodeType::Code39 => (code.chars().count() + 2) * 16,
+                            _ => matrix.width as usize,
+                        };
+                        let narrow = ((width * DOTS_PER_MM) as usize / modules).clamp(1, 10);
+                        let top = ((template.page_height - y - height) * DOTS_PER_MM).max(0.);
+                        let bar_height = (height * DOTS_PER_MM) as u32;
+                        let symbol = if *barcode_type == BarcodeType::Code39 {
+                            format!("^B3N,N,{bar_height},N,N")
+                        } else {
+                            format!("^BCN,{bar_height},N,N,N")
+                        };
+                        cmd.push_str(&format!(
+                            "^FO{left},{}^BY{narrow},3,{bar_height}{symbol}^FD{code}^FS\n",
+                            top as u32
+                        ));
+                    }
+                    BarcodeType::QrCode | BarcodeType::DataMatrix => {
+                        let size = width.min(*height);
+                        let top = ((template.page_height - y - size) * DOTS_PER_MM).max(0.);
+                        let cell =
+                            ((size * DOTS_PER_MM) as u32 / matrix.total_width()).clamp(1, 10);
+                        if *barcode_type == BarcodeType::QrCode {
+                            cmd.push_str(&format!(
+                                "^FO{left},{}^BQN,2,{cell}^FDMA,{code}^FS\n",
+                                top as u32
+                            ));
+                        } else {
+                            cmd.push_str(&format!(
+                                "^FO{left},{}^BXN,{cell},200^FD{code}^FS\n",
+                                top as u32
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    cmd.push_str("^XZ\n");
+    Ok(cmd.into_bytes())
+}
@@ -10,7 +10,10 @@ use tokio::{task::JoinHandle, time::sleep};
 
 use crate::{
     error::Error,
+    notice::scheduler::NoticeSchedulerManager,
     orders::TimeWarningManager,
+    printer::queue::PrintQueueManager,
+    segments::SegmentManager,
     utils::{
         self,
         request::{HttpClient, Token},
@@ -34,6 +37,9 @@ pub struct AppState {
     pub token: Arc<TokioMutex<Option<Token>>>,
     pub token_refresh_handle: Arc<TokioMutex<Option<JoinHandle<()>>>>,
     pub time_warning_check_handle: TimeWarningManager,
+    pub print_queue_handle: PrintQueueManager,
+    pub notice_scheduler_handle: NoticeSchedulerManager,
+    pub segment_refresh_handle: SegmentManager,
     pub last_activity_time: Arc<Mutex<i64>>,
 }
 
@@ -45,6 +51,9 @@ impl AppState {
             token: Arc::new(TokioMutex::new(None)),
             token_refresh_handle: Arc::new(TokioMutex::new(None)),
             time_warning_check_handle: TimeWarningManager::new(),
+            print_queue_handle: PrintQueueManager::new(),
+            notice_scheduler_handle: NoticeSchedulerManager::new(),
+            segment_refresh_handle: SegmentManager::new(),
             last_activity_time: Arc::new(Mutex::new(utils::get_timestamp())),
         }
     }
@@ -113,6 +122,13 @@ impl AppState {
         self.time_warning_check_handle
             .start(app_handle.clone())
             .await?;
+        self.print_queue_handle.start(app_handle.clone()).await?;
+        self.notice_scheduler_handle
+            .start(app_handle.clone())
+            .await?;
+        self.segment_refresh_handle
+            .start(app_handle.clone())
+            .await?;
         Ok(())
     }
 
@@ -216,6 +232,9 @@ impl AppState {
         let mut token = self.token.lock().await;
         *token = None; // 将 token 置为 None
         self.time_warning_check_handle.stop().await;
+        self.print_queue_handle.stop().await;
+        self.notice_scheduler_handle.stop().await;
+        self.segment_refresh_handle.stop().await;
     }
 
     pub async fn get_user_info(&self) -> Option<LocalUser> {